@@ -45,6 +45,9 @@ pub enum DatabaseError {
   #[error("Import data failed: {0}")]
   ImportData(String),
 
+  #[error(transparent)]
+  CollabError(#[from] collab::error::CollabError),
+
   #[error("Internal failure: {0}")]
   Internal(#[from] anyhow::Error),
 }
@@ -59,6 +62,9 @@ impl From<CollabValidateError> for DatabaseError {
   fn from(error: CollabValidateError) -> Self {
     match error {
       CollabValidateError::NoRequiredData(data) => DatabaseError::NoRequiredData(data),
+      CollabValidateError::SchemaViolations(violations) => {
+        DatabaseError::CollabError(collab::error::CollabError::SchemaViolations(violations))
+      },
     }
   }
 }
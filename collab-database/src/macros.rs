@@ -60,27 +60,6 @@ macro_rules! impl_i64_update {
   };
 }
 
-#[macro_export]
-macro_rules! impl_i32_update {
-  ($setter1: ident, $setter2: ident, $key: expr) => {
-    pub fn $setter1(self, value: i32) -> Self {
-      self
-        .map_ref
-        .insert(self.txn, $key, Any::BigInt(value as i64));
-      self
-    }
-
-    pub fn $setter2(self, value: Option<i32>) -> Self {
-      if let Some(value) = value {
-        self
-          .map_ref
-          .insert(self.txn, $key, Any::BigInt(value as i64));
-      }
-      self
-    }
-  };
-}
-
 #[macro_export]
 macro_rules! impl_u8_update {
   ($setter1: ident, $setter2: ident, $key: expr) => {
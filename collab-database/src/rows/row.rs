@@ -1,5 +1,6 @@
 use collab::preclude::{
-  Any, ArrayRef, Collab, FillRef, Map, MapExt, MapRef, ReadTxn, ToJson, TransactionMut, YrsValue,
+  Any, ArrayRef, Collab, FillRef, Map, MapExt, MapRef, ReadTxn, Subscription, ToJson,
+  TransactionMut, YrsValue,
 };
 use std::borrow::{Borrow, BorrowMut};
 use std::collections::HashMap;
@@ -22,7 +23,6 @@ use crate::rows::{
 
 use crate::util::encoded_collab;
 use crate::views::{OrderObjectPosition, RowOrder};
-use crate::{impl_bool_update, impl_i32_update, impl_i64_update};
 use collab::core::collab::CollabOptions;
 use collab::core::origin::CollabOrigin;
 use collab::entity::EncodedCollab;
@@ -79,9 +79,10 @@ impl DatabaseRow {
     mut collab: Collab,
     change_tx: Option<RowChangeSender>,
   ) -> Result<Self, DatabaseError> {
-    let body = DatabaseRowBody::open(row_id.clone(), &mut collab)?;
+    let mut body = DatabaseRowBody::open(row_id.clone(), &mut collab)?;
     if let Some(change_tx) = change_tx {
-      subscribe_row_data_change(row_id.clone(), &body.data, change_tx);
+      let subscription = subscribe_row_data_change(row_id.clone(), &collab, &body.data, change_tx);
+      body.change_subscription = Some(subscription);
     }
     Ok(Self {
       row_id,
@@ -96,9 +97,10 @@ impl DatabaseRow {
     change_tx: Option<RowChangeSender>,
     row: Row,
   ) -> Self {
-    let body = DatabaseRowBody::create(row_id.clone(), &mut collab, row);
+    let mut body = DatabaseRowBody::create(row_id.clone(), &mut collab, row);
     if let Some(change_tx) = change_tx {
-      subscribe_row_data_change(row_id.clone(), &body.data, change_tx);
+      let subscription = subscribe_row_data_change(row_id.clone(), &collab, &body.data, change_tx);
+      body.change_subscription = Some(subscription);
     }
     Self {
       row_id,
@@ -213,6 +215,9 @@ pub struct DatabaseRowBody {
   meta: MapRef,
   #[allow(dead_code)]
   comments: ArrayRef,
+  /// Sends the [crate::rows::RowChange]s of the row while it's alive.
+  #[allow(dead_code)]
+  change_subscription: Option<Subscription>,
 }
 
 impl DatabaseRowBody {
@@ -250,6 +255,7 @@ impl DatabaseRowBody {
       data,
       meta,
       comments,
+      change_subscription: None,
     }
   }
 
@@ -513,6 +519,18 @@ impl<'a, 'b> RowBuilder<'a, 'b> {
   pub fn done(self) {}
 }
 
+/// The values of a row that are stored as plain keys of its map, see [ROW_ID] and the other
+/// `ROW_*` constants. [RowUpdate] writes them with the update builder generated for this struct.
+#[derive(collab_derive::Collab)]
+struct RowProperties {
+  id: String,
+  database_id: String,
+  visibility: bool,
+  height: i32,
+  created_at: i64,
+  last_modified: i64,
+}
+
 /// It used to update a [Row]
 pub struct RowUpdate<'a, 'b> {
   map_ref: MapRef,
@@ -529,26 +547,69 @@ impl<'a, 'b> RowUpdate<'a, 'b> {
     }
   }
 
-  impl_bool_update!(set_visibility, set_visibility_if_not_none, ROW_VISIBILITY);
-  impl_i32_update!(set_height, set_height_at_if_not_none, ROW_HEIGHT);
-  impl_i64_update!(set_created_at, set_created_at_if_not_none, CREATED_AT);
-  impl_i64_update!(
-    set_last_modified,
-    set_last_modified_if_not_none,
-    LAST_MODIFIED
-  );
+  fn properties(&mut self) -> RowPropertiesUpdate<'_, 'b> {
+    RowPropertiesUpdate::new(self.txn, &self.map_ref)
+  }
+
+  pub fn set_visibility(mut self, value: bool) -> Self {
+    self.properties().set_visibility(value);
+    self
+  }
+
+  pub fn set_visibility_if_not_none(self, value: Option<bool>) -> Self {
+    match value {
+      Some(value) => self.set_visibility(value),
+      None => self,
+    }
+  }
+
+  pub fn set_height(mut self, value: i32) -> Self {
+    self.properties().set_height(value);
+    self
+  }
+
+  pub fn set_height_at_if_not_none(self, value: Option<i32>) -> Self {
+    match value {
+      Some(value) => self.set_height(value),
+      None => self,
+    }
+  }
+
+  pub fn set_created_at(mut self, value: i64) -> Self {
+    self.properties().set_created_at(value);
+    self
+  }
+
+  pub fn set_created_at_if_not_none(self, value: Option<i64>) -> Self {
+    match value {
+      Some(value) => self.set_created_at(value),
+      None => self,
+    }
+  }
+
+  pub fn set_last_modified(mut self, value: i64) -> Self {
+    self.properties().set_last_modified(value);
+    self
+  }
+
+  pub fn set_last_modified_if_not_none(self, value: Option<i64>) -> Self {
+    match value {
+      Some(value) => self.set_last_modified(value),
+      None => self,
+    }
+  }
 
-  pub fn set_database_id(self, database_id: String) -> Self {
-    self.map_ref.insert(self.txn, ROW_DATABASE_ID, database_id);
+  pub fn set_database_id(mut self, database_id: String) -> Self {
+    self.properties().set_database_id(database_id);
     self
   }
 
-  pub fn set_row_id(self, new_row_id: RowId) -> Self {
+  pub fn set_row_id(mut self, new_row_id: RowId) -> Self {
     let old_row_id = match row_id_from_map_ref(self.txn, &self.map_ref) {
       Some(row_id) => row_id,
       None => {
         // no row id found, so we just insert the new id
-        self.map_ref.insert(self.txn, ROW_ID, new_row_id.as_str());
+        self.properties().set_id(new_row_id.as_str());
         return self;
       },
     };
@@ -568,7 +629,7 @@ impl<'a, 'b> RowUpdate<'a, 'b> {
     };
 
     // update to new row id
-    self.map_ref.insert(self.txn, ROW_ID, new_row_id.as_str());
+    self.properties().set_id(new_row_id.as_str());
 
     // update meta key derived from new row id
     // this exhaustively iterates over all meta keys
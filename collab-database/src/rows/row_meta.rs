@@ -1,3 +1,4 @@
+use collab::core::map_binding::{log_update_error, write_value};
 use collab::preclude::{Map, MapExt, MapRef, ReadTxn, TransactionMut};
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
@@ -8,11 +9,11 @@ use crate::{
   rows::{RowMetaKey, meta_id_from_row_id},
 };
 
+/// Updates the meta of a row. The keys of the meta are derived from the id of the row, see
+/// [meta_id_from_row_id], so they can't be bound with `#[derive(Collab)]`, but the values are
+/// written with the same functions as the generated update builders.
 pub struct RowMetaUpdate<'a, 'b> {
-  #[allow(dead_code)]
   map_ref: MapRef,
-
-  #[allow(dead_code)]
   txn: &'a mut TransactionMut<'b>,
 
   row_id: Uuid,
@@ -59,34 +60,25 @@ impl<'a, 'b> RowMetaUpdate<'a, 'b> {
   }
 
   pub fn insert_icon(self, icon_url: &str) -> Self {
-    let icon_id = meta_id_from_row_id(&self.row_id, RowMetaKey::IconId);
-    self.map_ref.insert(self.txn, icon_id, icon_url);
-    self
+    self.write(RowMetaKey::IconId, icon_url)
   }
 
   pub fn insert_cover(self, cover: &RowCover) -> Self {
-    let cover_id = meta_id_from_row_id(&self.row_id, RowMetaKey::CoverId);
-    self.map_ref.insert(
-      self.txn,
-      cover_id,
-      serde_json::to_string(cover).unwrap_or_default(),
-    );
-    self
+    let cover = serde_json::to_string(cover).unwrap_or_default();
+    self.write(RowMetaKey::CoverId, &cover)
   }
 
   pub fn update_is_document_empty(self, is_document_empty: bool) -> Self {
-    let is_document_empty_id = meta_id_from_row_id(&self.row_id, RowMetaKey::IsDocumentEmpty);
-    self
-      .map_ref
-      .insert(self.txn, is_document_empty_id, is_document_empty);
-    self
+    self.write(RowMetaKey::IsDocumentEmpty, &is_document_empty)
   }
 
   pub fn update_attachment_count(self, attachment_count: i64) -> Self {
-    let attachment_count_id = meta_id_from_row_id(&self.row_id, RowMetaKey::AttachmentCount);
-    self
-      .map_ref
-      .insert(self.txn, attachment_count_id, attachment_count);
+    self.write(RowMetaKey::AttachmentCount, &attachment_count)
+  }
+
+  fn write<V: Serialize + ?Sized>(self, key: RowMetaKey, value: &V) -> Self {
+    let key = meta_id_from_row_id(&self.row_id, key);
+    log_update_error(&key, write_value(self.txn, &self.map_ref, &key, value));
     self
   }
 }
@@ -0,0 +1,113 @@
+#![cfg(feature = "lock_diagnostics")]
+
+use std::any::type_name;
+use std::sync::Arc;
+
+use collab::lock::RwLock;
+use collab::lock::diagnostics;
+use collab::preclude::Collab;
+use collab_database::rows::{CreateRowParams, DatabaseRow, RowId};
+use uuid::Uuid;
+
+use crate::database_test::helper::{DatabaseTest, create_database};
+
+async fn create_database_with_rows(database_id: &str) -> (DatabaseTest, Vec<RowId>) {
+  let mut database_test = create_database(1, database_id);
+  let mut row_ids = vec![];
+  for _ in 0..3 {
+    let row_id = Uuid::new_v4();
+    database_test
+      .create_row_in_view("v1", CreateRowParams::new(row_id, database_id.to_string()))
+      .await
+      .unwrap();
+    row_ids.push(RowId::from(row_id));
+  }
+  (database_test, row_ids)
+}
+
+#[tokio::test]
+async fn database_and_row_lock_order_test() {
+  let database_id = Uuid::new_v4().to_string();
+  let (database_test, row_ids) = create_database_with_rows(&database_id).await;
+
+  // Every task locks the database, then one of its rows.
+  let database = Arc::new(RwLock::new(database_test));
+  let tasks = row_ids
+    .into_iter()
+    .map(|row_id| {
+      let database = database.clone();
+      diagnostics::spawn(async move {
+        let database = database.read().await;
+        let row = database.get_database_row(&row_id).await.unwrap();
+        let row = row.read().await;
+        assert!(row.get_row().is_some());
+      })
+    })
+    .collect::<Vec<_>>();
+  for task in tasks {
+    task.await.unwrap();
+  }
+
+  let classes = [
+    type_name::<DatabaseTest>(),
+    type_name::<DatabaseRow>(),
+    type_name::<Collab>(),
+  ];
+  let inversions = diagnostics::lock_inversions()
+    .into_iter()
+    .filter(|inversion| classes.contains(&inversion.first) && classes.contains(&inversion.second))
+    .collect::<Vec<_>>();
+  assert!(inversions.is_empty(), "{:?}", inversions);
+}
+
+#[tokio::test]
+async fn block_and_row_lock_order_test() {
+  let database_id = Uuid::new_v4().to_string();
+  let (database_test, row_ids) = create_database_with_rows(&database_id).await;
+  // The registry is shared by the tests of this binary, so the block gets its own lock class
+  let block = Arc::new(RwLock::with_class(
+    database_test.body.block.clone(),
+    "block_and_row_lock_order_test::Block",
+  ));
+
+  // Every task locks the block, then one of its rows.
+  let tasks = row_ids
+    .iter()
+    .cloned()
+    .map(|row_id| {
+      let block = block.clone();
+      diagnostics::spawn(async move {
+        let block = block.read().await;
+        let row = block.get_database_row(&row_id).await.unwrap();
+        let row = row.read().await;
+        assert!(row.get_row().is_some());
+      })
+    })
+    .collect::<Vec<_>>();
+  for task in tasks {
+    task.await.unwrap();
+  }
+  let inversions_of_block = || {
+    diagnostics::lock_inversions()
+      .into_iter()
+      .filter(|inversion| inversion.second == block.class())
+      .collect::<Vec<_>>()
+  };
+  assert!(inversions_of_block().is_empty());
+
+  // Locking the block while holding a row inverts the order recorded above
+  let row = block
+    .read()
+    .await
+    .get_database_row(&row_ids[0])
+    .await
+    .unwrap();
+  diagnostics::instrument(async {
+    let _row = row.write().await;
+    let _block = block.write().await;
+  })
+  .await;
+  let inversions = inversions_of_block();
+  assert_eq!(inversions.len(), 1, "{:?}", inversions);
+  assert_eq!(inversions[0].first, type_name::<DatabaseRow>());
+}
@@ -9,9 +9,11 @@ mod filter_test;
 mod group_test;
 pub mod helper;
 mod layout_test;
+mod lock_order_test;
 // mod restore_test;
 mod row_observe_test;
 mod row_test;
+mod simulation_test;
 mod sort_test;
 mod type_option_test;
 mod view_observe_test;
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use collab::core::collab_simulation::{Simulation, SimulationConfig};
+use collab::error::CollabError;
+use collab_database::database::{Database, DatabaseBody, DatabaseContext};
+use collab_database::fields::Field;
+use uuid::Uuid;
+
+use crate::database_test::helper::create_database;
+use crate::helper::make_rocks_db;
+use crate::user_test::helper::TestUserDatabaseServiceImpl;
+
+fn database_simulation(config: SimulationConfig) -> Simulation<Database> {
+  let database_id = Uuid::new_v4().to_string();
+  let database_test = create_database(1, &database_id);
+  let encoded_collab = database_test
+    .collab
+    .encode_collab_v1(|_| Ok::<_, CollabError>(()))
+    .unwrap();
+  let config = config
+    .with_object_id(&database_id)
+    .with_doc_state(encoded_collab.doc_state.to_vec());
+
+  let collab_service = Arc::new(TestUserDatabaseServiceImpl::new(
+    1,
+    database_test.workspace_id.clone(),
+    make_rocks_db(),
+    database_test.client_id,
+  ));
+  let context = DatabaseContext::new(collab_service.clone(), collab_service.clone());
+  Simulation::with_peers(config, |collab| {
+    let (body, collab) = DatabaseBody::open(collab, context.clone()).unwrap();
+    Database {
+      collab,
+      body,
+      collab_service: collab_service.clone(),
+    }
+  })
+  .unwrap()
+}
+
+/// Inserts a field, or renames one of the fields.
+fn random_edit(database: &mut Database, rng: &mut fastrand::Rng) {
+  let fields = database.get_all_fields();
+  if fields.is_empty() || rng.bool() {
+    let field = Field::new(
+      format!("field_{}", rng.u32(..)),
+      "new field".to_string(),
+      0,
+      false,
+    );
+    database.insert_field(field);
+  } else {
+    let field_id = fields[rng.usize(..fields.len())].id.clone();
+    let name = format!("field {}", rng.u32(..));
+    database.update_field(&field_id, |update| {
+      update.set_name(name);
+    });
+  }
+}
+
+/// The fields are stored in a map, so they are sorted to compare the peers.
+fn sorted_fields(database: &Database) -> Vec<Field> {
+  let mut fields = database.get_all_fields();
+  fields.sort_by(|a, b| a.id.cmp(&b.id));
+  fields
+}
+
+#[test]
+fn concurrent_database_edits_converge_test() {
+  for seed in 0..10 {
+    let config = SimulationConfig::new(3, seed)
+      .with_reorder(true)
+      .with_drop_rate(0.2);
+    let mut simulation = database_simulation(config);
+
+    simulation.run(50, random_edit);
+    simulation.converge();
+    simulation.assert_converged();
+
+    let fields = sorted_fields(simulation.peer(0));
+    for database in simulation.peers() {
+      assert_eq!(sorted_fields(database), fields);
+    }
+  }
+}
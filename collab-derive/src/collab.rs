@@ -1,5 +1,5 @@
 use crate::internal::{ASTContainer, ASTResult};
-use crate::yrs_token::make_yrs_token_steam;
+use crate::map_binding::make_map_binding_token_stream;
 use proc_macro2::TokenStream;
 
 pub fn expand_derive(input: &syn::DeriveInput) -> Result<TokenStream, Vec<syn::Error>> {
@@ -10,8 +10,8 @@ pub fn expand_derive(input: &syn::DeriveInput) -> Result<TokenStream, Vec<syn::E
   };
 
   let mut token_stream: TokenStream = TokenStream::default();
-  if let Some(yrs_token_stream) = make_yrs_token_steam(&ast_result, &cont) {
-    token_stream.extend(yrs_token_stream);
+  if let Some(map_binding_token_stream) = make_map_binding_token_stream(&ast_result, &cont) {
+    token_stream.extend(map_binding_token_stream);
   }
 
   ast_result.check()?;
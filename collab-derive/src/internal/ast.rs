@@ -9,13 +9,15 @@ use std::fmt;
 use std::fmt::Display;
 use syn::Meta::{List, NameValue};
 use syn::NestedMeta::Meta;
-use syn::{self, punctuated::Punctuated, Fields, LitStr, Path, Token};
+use syn::{self, Fields, Path, Token, punctuated::Punctuated};
 
 pub struct ASTContainer<'a> {
   /// The struct or enum name (without generics).
   pub ident: syn::Ident,
 
-  pub path: Option<String>,
+  pub vis: syn::Visibility,
+
+  pub generics: &'a syn::Generics,
 
   /// The contents of the struct or enum.
   pub data: ASTData<'a>,
@@ -39,9 +41,12 @@ impl<'a> ASTContainer<'a> {
       },
     };
 
-    let ident = ast.ident.clone();
-    let path = get_key(ast_result, &ident, &ast.attrs);
-    let item = ASTContainer { ident, path, data };
+    let item = ASTContainer {
+      ident: ast.ident.clone(),
+      vis: ast.vis.clone(),
+      generics: &ast.generics,
+      data,
+    };
     Some(item)
   }
 }
@@ -64,17 +69,13 @@ impl<'a> ASTData<'a> {
 
 /// A variant of an enum.
 pub struct ASTEnumVariant<'a> {
-  pub ident: syn::Ident,
-  pub style: ASTStyle,
   pub fields: Vec<ASTField<'a>>,
-  pub original: &'a syn::Variant,
 }
 
 pub struct ASTField<'a> {
   pub member: syn::Member,
   pub ty: &'a syn::Type,
-  pub yrs_attr: YrsAttribute,
-  pub original: &'a syn::Field,
+  pub collab_attr: CollabAttribute,
 }
 
 impl<'a> ASTField<'a> {
@@ -85,49 +86,111 @@ impl<'a> ASTField<'a> {
         None => syn::Member::Unnamed(index.into()),
       },
       ty: &field.ty,
-      yrs_attr: YrsAttribute::from_ast(ast_result, field),
-      original: field,
+      collab_attr: CollabAttribute::from_ast(ast_result, field),
     })
   }
 }
 
-pub const YRS: Symbol = Symbol("yrs");
-pub const PRS_TY: Symbol = Symbol("ty");
-pub struct YrsAttribute {
-  #[allow(dead_code)]
-  ty: Option<LitStr>,
+pub const COLLAB: Symbol = Symbol("collab");
+/// The attribute used before `#[collab(key = "x")]`, kept as an alias of it.
+pub const COLLAB_KEY: Symbol = Symbol("collab_key");
+pub const FIELD_KEY: Symbol = Symbol("key");
+pub const MAP: Symbol = Symbol("map");
+pub const ARRAY: Symbol = Symbol("array");
+pub const DEFAULT: Symbol = Symbol("default");
+pub const SKIP: Symbol = Symbol("skip");
+pub const SKIP_SETTER: Symbol = Symbol("skip_setter");
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FieldKind {
+  /// Serialized with serde and stored as an Any.
+  Value,
+  /// A struct deriving Collab, stored in a nested map.
+  Map,
+  /// A Vec of structs deriving Collab, stored in a nested array of maps.
+  Array,
+}
+
+pub struct CollabAttribute {
+  /// The key of the field in the map, the name of the field by default.
+  pub key: Option<String>,
+  pub kind: FieldKind,
+  /// Use the default value when the field is missing instead of failing to read the struct.
+  pub default: bool,
+  /// The field is not stored in the map.
+  pub skip: bool,
+  /// The update builder has no setter for the field, so that it can be written by hand.
+  pub skip_setter: bool,
 }
 
-impl YrsAttribute {
-  /// Extract out the `#[yrs(...)]` attributes from a struct field.
+impl CollabAttribute {
+  /// Extract out the `#[collab(...)]` attributes from a struct field.
   pub fn from_ast(ast_result: &ASTResult, field: &syn::Field) -> Self {
-    let mut ty = ASTFieldAttr::none(ast_result, PRS_TY);
+    let mut key = ASTFieldAttr::none(ast_result, FIELD_KEY);
+    let mut kind = ASTFieldAttr::none(ast_result, MAP);
+    let mut default = false;
+    let mut skip = false;
+    let mut skip_setter = false;
+    // Parse '#[collab_key = "x"]'
+    for attr in field.attrs.iter().filter(|attr| attr.path == COLLAB_KEY) {
+      match attr.parse_meta() {
+        Ok(NameValue(syn::MetaNameValue {
+          lit: syn::Lit::Str(lit),
+          ..
+        })) => key.set(&attr.path, lit.value()),
+        _ => ast_result.error_spanned_by(
+          attr,
+          "collab_key must be a string, e.g. #[collab_key = \"x\"]",
+        ),
+      }
+    }
     for meta_item in field
       .attrs
       .iter()
-      .flat_map(|attr| get_yrs_nested_meta(ast_result, attr))
+      .flat_map(|attr| get_collab_nested_meta(ast_result, attr))
       .flatten()
     {
       match &meta_item {
-        // Parse '#[yrs(ty = x)]'
-        Meta(NameValue(m)) if m.path == PRS_TY => {
+        // Parse '#[collab(key = "x")]'
+        Meta(NameValue(m)) if m.path == FIELD_KEY => {
           if let syn::Lit::Str(lit) = &m.lit {
-            ty.set(&m.path, lit.clone());
+            key.set(&m.path, lit.value());
+          } else {
+            ast_result
+              .error_spanned_by(&m.lit, "key must be a string, e.g. #[collab(key = \"x\")]");
           }
         },
-
+        // Parse '#[collab(map)]'
+        Meta(syn::Meta::Path(word)) if word == MAP => kind.set(word, FieldKind::Map),
+        // Parse '#[collab(array)]'
+        Meta(syn::Meta::Path(word)) if word == ARRAY => kind.set(word, FieldKind::Array),
+        // Parse '#[collab(default)]'
+        Meta(syn::Meta::Path(word)) if word == DEFAULT => default = true,
+        // Parse '#[collab(skip)]'
+        Meta(syn::Meta::Path(word)) if word == SKIP => skip = true,
+        // Parse '#[collab(skip_setter)]'
+        Meta(syn::Meta::Path(word)) if word == SKIP_SETTER => skip_setter = true,
         _ => {
           ast_result.error_spanned_by(meta_item, "unexpected meta in field attribute");
         },
       }
     }
-    YrsAttribute { ty: ty.get() }
+    CollabAttribute {
+      key: key.get(),
+      kind: kind.get().unwrap_or(FieldKind::Value),
+      default,
+      skip,
+      skip_setter,
+    }
   }
 }
 
-fn get_yrs_nested_meta(cx: &ASTResult, attr: &syn::Attribute) -> Result<Vec<syn::NestedMeta>, ()> {
+fn get_collab_nested_meta(
+  cx: &ASTResult,
+  attr: &syn::Attribute,
+) -> Result<Vec<syn::NestedMeta>, ()> {
   // Only handle the attribute that we have defined
-  if attr.path != YRS {
+  if attr.path != COLLAB {
     return Ok(vec![]);
   }
 
@@ -135,7 +198,10 @@ fn get_yrs_nested_meta(cx: &ASTResult, attr: &syn::Attribute) -> Result<Vec<syn:
     Ok(List(meta)) => Ok(meta.nested.into_iter().collect()),
     Ok(_) => Ok(vec![]),
     Err(err) => {
-      cx.error_spanned_by(attr, "attribute must be str, e.g. #[yrs(xx = \"xxx\")]");
+      cx.error_spanned_by(
+        attr,
+        "attribute must be a list, e.g. #[collab(key = \"x\")]",
+      );
       cx.syn_error(err);
       Err(())
     },
@@ -203,13 +269,8 @@ fn enum_from_ast<'a>(
   variants
     .iter()
     .flat_map(|variant| {
-      let (style, fields) = struct_from_ast(cx, &variant.fields);
-      Some(ASTEnumVariant {
-        ident: variant.ident.clone(),
-        style,
-        fields,
-        original: variant,
-      })
+      let (_, fields) = struct_from_ast(cx, &variant.fields);
+      Some(ASTEnumVariant { fields })
     })
     .collect()
 }
@@ -257,27 +318,3 @@ impl Display for Symbol {
     formatter.write_str(self.0)
   }
 }
-
-pub const KEY: Symbol = Symbol("collab_key");
-
-fn get_key(
-  ast_result: &ASTResult,
-  struct_name: &Ident,
-  attrs: &[syn::Attribute],
-) -> Option<String> {
-  let mut key = None;
-  attrs
-    .iter()
-    .filter(|attr| attr.path.segments.iter().any(|s| s.ident == KEY))
-    .for_each(|attr| {
-      if let Ok(NameValue(named_value)) = attr.parse_meta() {
-        if key.is_some() {
-          ast_result.error_spanned_by(struct_name, "Duplicate key type definition");
-        }
-        if let syn::Lit::Str(s) = named_value.lit {
-          key = Some(s.value());
-        }
-      }
-    });
-  key
-}
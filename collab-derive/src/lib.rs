@@ -1,6 +1,6 @@
 mod collab;
 mod internal;
-mod yrs_token;
+mod map_binding;
 
 #[macro_use]
 extern crate quote;
@@ -8,9 +8,20 @@ extern crate proc_macro;
 
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::parse_macro_input;
 use syn::DeriveInput;
+use syn::parse_macro_input;
 
+/// Binds the fields of a struct to the keys of a `MapRef`, see `collab::core::map_binding`.
+///
+/// Field attributes:
+/// * `#[collab(key = "x")]`: the key of the field, the name of the field by default.
+///   `#[collab_key = "x"]` is an alias of it.
+/// * `#[collab(map)]`: the field derives `Collab` too and is stored in a nested map.
+/// * `#[collab(array)]`: the field is a `Vec` of structs deriving `Collab`, stored in a nested
+///   array.
+/// * `#[collab(default)]`: a missing field is read as its default value instead of failing.
+/// * `#[collab(skip)]`: the field is not stored.
+/// * `#[collab(skip_setter)]`: the update builder has no setter for the field.
 #[proc_macro_derive(Collab, attributes(collab, collab_key))]
 pub fn derive_collab(input: TokenStream) -> TokenStream {
   let input = parse_macro_input!(input as DeriveInput);
@@ -0,0 +1,359 @@
+use crate::internal::{ASTContainer, ASTData, ASTField, ASTResult, ASTStyle, FieldKind};
+use proc_macro2::{Ident, TokenStream};
+
+use syn::{AngleBracketedGenericArguments, PathArguments, Type};
+
+/// Generates, for a struct with named fields:
+/// * an implementation of `collab::core::map_binding::MapBinding`, which provides the
+///   `from_map_ref`/`fill_map_ref` pair.
+/// * an update builder named `<Struct>Update` with a `set_<field>` method per field, and an
+///   `update_<field>` method per nested map.
+/// * an `observe_<field>` function per field.
+pub fn make_map_binding_token_stream(
+  ast_result: &ASTResult,
+  ast: &ASTContainer,
+) -> Option<TokenStream> {
+  match &ast.data {
+    ASTData::Struct(ASTStyle::Struct, _) => {},
+    _ => {
+      ast_result.error_spanned_by(
+        &ast.ident,
+        "Collab can only be derived for structs with named fields",
+      );
+      return None;
+    },
+  }
+  if !ast.generics.params.is_empty() {
+    ast_result.error_spanned_by(ast.generics, "Collab can't be derived for generic structs");
+    return None;
+  }
+
+  let fields = ast
+    .data
+    .all_fields()
+    .flat_map(|field| BindingField::from_ast(ast_result, field))
+    .collect::<Vec<_>>();
+
+  let struct_name = &ast.ident;
+  let vis = &ast.vis;
+  let update_name = format_ident!("{}Update", struct_name.to_string());
+  let readers = fields.iter().map(|field| field.reader());
+  let fillers = fields.iter().map(|field| field.filler());
+  let setters = fields.iter().map(|field| field.setters());
+  let observers = fields.iter().map(|field| field.observer());
+  // A private struct is usually bound for its update builder only, its observers would be
+  // reported as dead code.
+  let allow_dead_observers = match vis {
+    syn::Visibility::Public(_) => quote! {},
+    _ => quote! { #[allow(dead_code)] },
+  };
+
+  Some(quote! {
+      impl collab::core::map_binding::MapBinding for #struct_name {
+          fn from_map_ref<T: collab::preclude::ReadTxn>(
+              txn: &T,
+              map_ref: &collab::preclude::MapRef,
+          ) -> Option<Self> {
+              Some(Self {
+                  #(#readers)*
+              })
+          }
+
+          fn fill_map_ref(
+              self,
+              txn: &mut collab::preclude::TransactionMut,
+              map_ref: &collab::preclude::MapRef,
+          ) -> Result<(), collab::error::CollabError> {
+              #(#fillers)*
+              Ok(())
+          }
+      }
+
+      #vis struct #update_name<'a, 'b> {
+          map_ref: &'a collab::preclude::MapRef,
+          txn: &'a mut collab::preclude::TransactionMut<'b>,
+      }
+
+      impl<'a, 'b> #update_name<'a, 'b> {
+          pub fn new(
+              txn: &'a mut collab::preclude::TransactionMut<'b>,
+              map_ref: &'a collab::preclude::MapRef,
+          ) -> Self {
+              Self { map_ref, txn }
+          }
+
+          #(#setters)*
+      }
+
+      #allow_dead_observers
+      impl #struct_name {
+          #(#observers)*
+      }
+  })
+}
+
+struct BindingField<'a> {
+  ident: &'a Ident,
+  key: String,
+  ty: &'a Type,
+  kind: FieldKind,
+  /// The type wrapped by the Option, if the field is optional.
+  optional: Option<&'a Type>,
+  default: bool,
+  skip: bool,
+  skip_setter: bool,
+}
+
+impl<'a> BindingField<'a> {
+  fn from_ast(ast_result: &ASTResult, field: &'a ASTField<'a>) -> Option<Self> {
+    let ident = get_member_ident(ast_result, &field.member)?;
+    let attr = &field.collab_attr;
+    let optional = bracketed_type(field.ty, "Option");
+    if attr.kind == FieldKind::Array
+      && bracketed_type(optional.unwrap_or(field.ty), "Vec").is_none()
+    {
+      ast_result.error_spanned_by(field.ty, "#[collab(array)] requires a Vec field");
+      return None;
+    }
+    if attr.default && optional.is_some() {
+      ast_result.error_spanned_by(
+        field.ty,
+        "#[collab(default)] is implied for the Option fields",
+      );
+      return None;
+    }
+    Some(Self {
+      ident,
+      key: attr.key.clone().unwrap_or_else(|| ident.to_string()),
+      ty: field.ty,
+      kind: attr.kind,
+      optional,
+      default: attr.default,
+      skip: attr.skip,
+      skip_setter: attr.skip_setter,
+    })
+  }
+
+  /// The type of the stored value, without the Option.
+  fn value_ty(&self) -> &'a Type {
+    self.optional.unwrap_or(self.ty)
+  }
+
+  /// An expression that reads the value of the field as an Option from `map_ref`.
+  fn read_expr(&self, map_ref: TokenStream) -> TokenStream {
+    let key = &self.key;
+    let value_ty = self.value_ty();
+    match self.kind {
+      FieldKind::Value => quote! {
+          collab::core::map_binding::read_value::<_, #value_ty>(txn, #map_ref, #key)
+      },
+      FieldKind::Map => quote! {
+          collab::core::map_binding::read_map::<_, #value_ty>(txn, #map_ref, #key)
+      },
+      FieldKind::Array => {
+        let item_ty = bracketed_type(value_ty, "Vec").unwrap();
+        quote! {
+            collab::core::map_binding::read_array::<_, #item_ty>(txn, #map_ref, #key)
+        }
+      },
+    }
+  }
+
+  /// A statement that writes `value`, of the type of the stored value, to `map_ref`.
+  fn write_expr(&self, map_ref: TokenStream, value: TokenStream) -> TokenStream {
+    let key = &self.key;
+    match self.kind {
+      FieldKind::Value => quote! {
+          collab::core::map_binding::write_value(txn, #map_ref, #key, &#value)
+      },
+      FieldKind::Map => quote! {
+          collab::core::map_binding::write_map(txn, #map_ref, #key, #value)
+      },
+      FieldKind::Array => quote! {
+          collab::core::map_binding::write_array(txn, #map_ref, #key, #value)
+      },
+    }
+  }
+
+  fn reader(&self) -> TokenStream {
+    let ident = self.ident;
+    if self.skip {
+      return quote! { #ident: Default::default(), };
+    }
+    let read = self.read_expr(quote!(map_ref));
+    if self.optional.is_some() {
+      quote! { #ident: #read, }
+    } else if self.default {
+      quote! { #ident: #read.unwrap_or_default(), }
+    } else {
+      quote! { #ident: #read?, }
+    }
+  }
+
+  fn filler(&self) -> TokenStream {
+    if self.skip {
+      return quote! {};
+    }
+    let ident = self.ident;
+    let key = &self.key;
+    if self.optional.is_some() {
+      let write = self.write_expr(quote!(map_ref), quote!(value));
+      quote! {
+          match self.#ident {
+              Some(value) => #write?,
+              None => collab::core::map_binding::remove_key(txn, map_ref, #key),
+          }
+      }
+    } else {
+      let write = self.write_expr(quote!(map_ref), quote!(self.#ident));
+      quote! { #write?; }
+    }
+  }
+
+  fn setters(&self) -> TokenStream {
+    if self.skip || self.skip_setter {
+      return quote! {};
+    }
+    let ident = self.ident;
+    let key = &self.key;
+    let ty = self.ty;
+    let setter = format_ident!("set_{}", ident.to_string());
+    let write = self.write_expr(quote!(self.map_ref), quote!(value));
+    let set = match (self.kind, self.optional) {
+      (FieldKind::Value, None) => quote! {
+          pub fn #setter<V: Into<#ty>>(self, value: V) -> Self {
+              let value: #ty = value.into();
+              let txn = &mut *self.txn;
+              collab::core::map_binding::log_update_error(#key, #write);
+              self
+          }
+      },
+      (_, None) => quote! {
+          pub fn #setter(self, value: #ty) -> Self {
+              let txn = &mut *self.txn;
+              collab::core::map_binding::log_update_error(#key, #write);
+              self
+          }
+      },
+      (_, Some(value_ty)) => quote! {
+          pub fn #setter(self, value: Option<#value_ty>) -> Self {
+              let txn = &mut *self.txn;
+              match value {
+                  Some(value) => collab::core::map_binding::log_update_error(#key, #write),
+                  None => collab::core::map_binding::remove_key(txn, self.map_ref, #key),
+              }
+              self
+          }
+      },
+    };
+
+    if self.kind != FieldKind::Map {
+      return set;
+    }
+    // The fields of a nested map can be updated one by one with the update builder of the
+    // nested struct.
+    let updater = format_ident!("update_{}", ident.to_string());
+    let nested_update = match update_type_of(self.value_ty()) {
+      Some(nested_update) => nested_update,
+      None => return set,
+    };
+    quote! {
+        #set
+
+        pub fn #updater<F>(self, f: F) -> Self
+        where
+            F: FnOnce(#nested_update),
+        {
+            let nested = collab::preclude::MapExt::get_or_init_map(self.map_ref, self.txn, #key);
+            f(#nested_update::new(self.txn, &nested));
+            self
+        }
+    }
+  }
+
+  fn observer(&self) -> TokenStream {
+    if self.skip {
+      return quote! {};
+    }
+    let key = &self.key;
+    let value_ty = self.value_ty();
+    let observer = format_ident!("observe_{}", self.ident.to_string());
+    let read = self.read_expr(quote!(&observed));
+    let doc = format!(
+      "Invokes `callback` with the new value of `{}` every time it changes in `map_ref`.",
+      self.ident
+    );
+    quote! {
+        #[doc = #doc]
+        pub fn #observer<F>(
+            map_ref: &collab::preclude::MapRef,
+            callback: F,
+        ) -> collab::preclude::Subscription
+        where
+            F: Fn(&collab::preclude::TransactionMut, Option<#value_ty>) + Send + Sync + 'static,
+        {
+            let observed = map_ref.clone();
+            collab::preclude::DeepObservable::observe_deep(map_ref, move |txn, events| {
+                if collab::core::map_binding::is_key_changed(txn, events, #key) {
+                    callback(txn, #read);
+                }
+            })
+        }
+    }
+  }
+}
+
+/// Returns the path of the update builder generated for `ty`, i.e. `<Type>Update`.
+fn update_type_of(ty: &Type) -> Option<syn::Path> {
+  if let Type::Path(p) = ty {
+    let mut path = p.path.clone();
+    let last = path.segments.last_mut()?;
+    last.ident = format_ident!("{}Update", last.ident.to_string());
+    last.arguments = PathArguments::None;
+    return Some(path);
+  }
+  None
+}
+
+pub(crate) fn get_member_ident<'a>(
+  ast_result: &ASTResult,
+  member: &'a syn::Member,
+) -> Option<&'a syn::Ident> {
+  if let syn::Member::Named(ident) = member {
+    Some(ident)
+  } else {
+    ast_result.error_spanned_by(
+      member,
+      "Unsupported member, shouldn't be self.0".to_string(),
+    );
+    None
+  }
+}
+
+/// Returns `T` if `ty` is `wrapper<T>`, e.g. `Option<T>`.
+fn bracketed_type<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+  if let Type::Path(p) = ty {
+    let seg = p.path.segments.last()?;
+    if seg.ident != wrapper {
+      return None;
+    }
+    if let PathArguments::AngleBracketed(bracketed) = &seg.arguments {
+      return parse_bracketed(bracketed).first().copied();
+    }
+  }
+  None
+}
+
+fn parse_bracketed(bracketed: &AngleBracketedGenericArguments) -> Vec<&Type> {
+  bracketed
+    .args
+    .iter()
+    .flat_map(|arg| {
+      if let syn::GenericArgument::Type(ty_in_bracket) = arg {
+        Some(ty_in_bracket)
+      } else {
+        None
+      }
+    })
+    .collect::<Vec<&syn::Type>>()
+}
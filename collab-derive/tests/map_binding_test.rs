@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use collab::core::map_binding::MapBinding;
+use collab::preclude::{Any, Doc, Map, MapRef, Out, Transact};
+use collab_derive::Collab;
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+#[derive(Collab, Debug, Clone, Default, PartialEq)]
+pub struct Icon {
+  ty: i64,
+  value: String,
+}
+
+#[derive(Collab, Debug, Clone, PartialEq)]
+pub struct Item {
+  #[collab_key = "item_id"]
+  id: String,
+  #[collab(default)]
+  done: bool,
+}
+
+#[derive(Collab, Debug, Clone, PartialEq)]
+pub struct Page {
+  id: String,
+  #[collab(key = "title")]
+  name: String,
+  created_at: i64,
+  tags: Vec<String>,
+  extra: HashMap<String, String>,
+  description: Option<String>,
+  #[collab(map)]
+  icon: Icon,
+  #[collab(map)]
+  cover: Option<Icon>,
+  #[collab(array)]
+  items: Vec<Item>,
+  #[collab(skip)]
+  cached_len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize_repr, Deserialize_repr)]
+#[repr(u8)]
+pub enum Layout {
+  Document = 0,
+  Grid = 1,
+}
+
+#[derive(Collab, Debug, Clone, PartialEq)]
+pub struct Cell {
+  height: i32,
+  created_at: i64,
+  layout: Layout,
+}
+
+fn page() -> Page {
+  Page {
+    id: "p1".to_string(),
+    name: "Getting started".to_string(),
+    created_at: 1700000000,
+    tags: vec!["a".to_string(), "b".to_string()],
+    extra: HashMap::from([("color".to_string(), "red".to_string())]),
+    description: Some("hello".to_string()),
+    icon: Icon {
+      ty: 0,
+      value: "📄".to_string(),
+    },
+    cover: None,
+    items: vec![
+      Item {
+        id: "i1".to_string(),
+        done: true,
+      },
+      Item {
+        id: "i2".to_string(),
+        done: false,
+      },
+    ],
+    cached_len: 0,
+  }
+}
+
+fn page_map(doc: &Doc) -> MapRef {
+  let map = doc.get_or_insert_map("page");
+  page().fill_map_ref(&mut doc.transact_mut(), &map).unwrap();
+  map
+}
+
+#[test]
+fn fill_and_read_map_ref_test() {
+  let doc = Doc::new();
+  let map = page_map(&doc);
+
+  let txn = doc.transact();
+  assert_eq!(Page::from_map_ref(&txn, &map), Some(page()));
+  assert!(map.get(&txn, "title").is_some());
+  assert!(map.get(&txn, "name").is_none());
+  assert!(map.get(&txn, "cover").is_none());
+  assert!(map.get(&txn, "cached_len").is_none());
+}
+
+#[test]
+fn skipped_field_is_not_stored_test() {
+  let doc = Doc::new();
+  let map = doc.get_or_insert_map("page");
+  let mut page = page();
+  page.cached_len = 42;
+  page.fill_map_ref(&mut doc.transact_mut(), &map).unwrap();
+
+  let read = Page::from_map_ref(&doc.transact(), &map).unwrap();
+  assert_eq!(read.cached_len, 0);
+}
+
+#[test]
+fn missing_fields_test() {
+  let doc = Doc::new();
+  let map = doc.get_or_insert_map("item");
+  let mut txn = doc.transact_mut();
+  // The required id is missing
+  assert_eq!(Item::from_map_ref(&txn, &map), None);
+
+  // The done field falls back to its default value
+  map.insert(&mut txn, "item_id", "i1");
+  assert_eq!(
+    Item::from_map_ref(&txn, &map),
+    Some(Item {
+      id: "i1".to_string(),
+      done: false,
+    })
+  );
+}
+
+#[test]
+fn update_builder_test() {
+  let doc = Doc::new();
+  let map = page_map(&doc);
+  {
+    let mut txn = doc.transact_mut();
+    PageUpdate::new(&mut txn, &map)
+      .set_name("Renamed")
+      .set_created_at(1800000000)
+      .set_description(None)
+      .update_icon(|icon| {
+        icon.set_value("🚀");
+      })
+      .set_cover(Some(Icon {
+        ty: 1,
+        value: "https://cover".to_string(),
+      }))
+      .set_items(vec![Item {
+        id: "i3".to_string(),
+        done: true,
+      }]);
+  }
+
+  let page = Page::from_map_ref(&doc.transact(), &map).unwrap();
+  assert_eq!(page.name, "Renamed");
+  assert_eq!(page.created_at, 1800000000);
+  assert_eq!(page.description, None);
+  assert_eq!(
+    page.icon,
+    Icon {
+      ty: 0,
+      value: "🚀".to_string(),
+    }
+  );
+  assert_eq!(page.cover.unwrap().value, "https://cover");
+  assert_eq!(page.items.len(), 1);
+  assert_eq!(page.items[0].id, "i3");
+}
+
+#[test]
+fn field_observer_test() {
+  let doc = Doc::new();
+  let map = page_map(&doc);
+  let names = Arc::new(Mutex::new(vec![]));
+  let icons = Arc::new(Mutex::new(vec![]));
+  let _name_sub = {
+    let names = names.clone();
+    Page::observe_name(&map, move |_, name| names.lock().unwrap().push(name))
+  };
+  let _icon_sub = {
+    let icons = icons.clone();
+    Page::observe_icon(&map, move |_, icon| icons.lock().unwrap().push(icon))
+  };
+
+  {
+    let mut txn = doc.transact_mut();
+    PageUpdate::new(&mut txn, &map).set_name("Renamed");
+  }
+  {
+    // A change nested in the icon map is reported for the icon only.
+    let mut txn = doc.transact_mut();
+    PageUpdate::new(&mut txn, &map).update_icon(|icon| {
+      icon.set_ty(2);
+    });
+  }
+
+  assert_eq!(*names.lock().unwrap(), vec![Some("Renamed".to_string())]);
+  assert_eq!(
+    *icons.lock().unwrap(),
+    vec![Some(Icon {
+      ty: 2,
+      value: "📄".to_string(),
+    })]
+  );
+}
+
+#[test]
+fn integer_fields_are_stored_as_big_int_test() {
+  let doc = Doc::new();
+  let map = doc.get_or_insert_map("cell");
+  let cell = Cell {
+    height: 60,
+    created_at: 1700000000,
+    layout: Layout::Grid,
+  };
+  cell
+    .clone()
+    .fill_map_ref(&mut doc.transact_mut(), &map)
+    .unwrap();
+  {
+    let txn = doc.transact();
+    assert_eq!(map.get(&txn, "height"), Some(Out::Any(Any::BigInt(60))));
+    assert_eq!(
+      map.get(&txn, "created_at"),
+      Some(Out::Any(Any::BigInt(1700000000)))
+    );
+    assert_eq!(map.get(&txn, "layout"), Some(Out::Any(Any::BigInt(1))));
+    assert_eq!(Cell::from_map_ref(&txn, &map), Some(cell));
+  }
+
+  {
+    let mut txn = doc.transact_mut();
+    CellUpdate::new(&mut txn, &map)
+      .set_height(80)
+      .set_layout(Layout::Document);
+  }
+  let txn = doc.transact();
+  assert_eq!(map.get(&txn, "height"), Some(Out::Any(Any::BigInt(80))));
+  assert_eq!(map.get(&txn, "layout"), Some(Out::Any(Any::BigInt(0))));
+}
@@ -369,9 +369,9 @@ impl Document {
   }
 
   /// Set the local state of the awareness.
-  /// It will override the previous state.
+  /// It will override the previous state, except the presence of the collab.
   pub fn set_awareness_local_state(&self, state: DocumentAwarenessState) {
-    if let Err(e) = self.collab.get_awareness().set_local_state(state) {
+    if let Err(e) = self.collab.set_local_awareness_state(state) {
       tracing::error!("Failed to serialize DocumentAwarenessState, state: {}", e);
     }
   }
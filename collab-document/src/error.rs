@@ -64,6 +64,9 @@ impl From<CollabValidateError> for DocumentError {
   fn from(error: CollabValidateError) -> Self {
     match error {
       CollabValidateError::NoRequiredData(_) => DocumentError::NoRequiredData,
+      CollabValidateError::SchemaViolations(violations) => {
+        DocumentError::CollabError(collab::error::CollabError::SchemaViolations(violations))
+      },
     }
   }
 }
@@ -0,0 +1,63 @@
+use collab::core::collab::default_client_id;
+use collab::core::collab_simulation::{Simulation, SimulationConfig};
+use collab_document::blocks::Block;
+use collab_document::document::Document;
+use collab_document::document_data::default_document_collab_data;
+
+const DOC_ID: &str = "simulation_document";
+
+fn document_simulation(config: SimulationConfig) -> Simulation<Document> {
+  let encoded_collab = default_document_collab_data(DOC_ID, default_client_id()).unwrap();
+  let config = config
+    .with_object_id(DOC_ID)
+    .with_doc_state(encoded_collab.doc_state.to_vec());
+  Simulation::with_peers(config, |collab| Document::open(collab).unwrap()).unwrap()
+}
+
+/// Inserts a paragraph in the page, or some text at the start of the first paragraph.
+fn random_edit(document: &mut Document, rng: &mut fastrand::Rng, text_id: &str) {
+  if rng.bool() {
+    let block = Block {
+      id: format!("block_{}", rng.u32(..)),
+      ty: "paragraph".to_string(),
+      parent: document.get_page_id().unwrap(),
+      children: "".to_string(),
+      external_id: None,
+      external_type: None,
+      data: Default::default(),
+    };
+    document.insert_block(block, None).unwrap();
+  } else {
+    let delta = format!(r#"[{{"insert": "{}"}}]"#, rng.alphanumeric());
+    document.apply_text_delta(text_id, delta);
+  }
+}
+
+#[test]
+fn concurrent_document_edits_converge_test() {
+  for seed in 0..10 {
+    let config = SimulationConfig::new(3, seed)
+      .with_reorder(true)
+      .with_drop_rate(0.2);
+    let mut simulation = document_simulation(config);
+    let text_id = simulation
+      .peer(0)
+      .get_document_data()
+      .unwrap()
+      .meta
+      .text_map
+      .unwrap()
+      .into_keys()
+      .next()
+      .unwrap();
+
+    simulation.run(50, |document, rng| random_edit(document, rng, &text_id));
+    simulation.converge();
+    simulation.assert_converged();
+
+    let data = simulation.peer(0).get_document_data().unwrap();
+    for document in simulation.peers() {
+      assert_eq!(document.get_document_data().unwrap(), data);
+    }
+  }
+}
@@ -7,6 +7,9 @@ use crate::define::{
   DOCUMENT_ROOT, FOLDER, FOLDER_META, FOLDER_WORKSPACE_ID, USER_AWARENESS, WORKSPACE_DATABASES,
 };
 use crate::proto;
+use collab::core::collab_schema::{
+  CollabSchema, MapSchema, SchemaViolation, ValueSchema, display_violations,
+};
 use collab::preclude::{ArrayRef, Collab, MapExt, MapRef};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
@@ -34,6 +37,9 @@ pub enum CollabType {
 pub enum CollabValidateError {
   #[error("No required data: {0}")]
   NoRequiredData(String),
+
+  #[error("Schema validation failed: {}", display_violations(.0))]
+  SchemaViolations(Vec<SchemaViolation>),
 }
 
 impl CollabType {
@@ -41,8 +47,15 @@ impl CollabType {
     *self as i32
   }
 
+  /// Whether the awareness of the collab is synced with the peers. The presence of
+  /// `collab::core::collab_presence` is carried by the awareness, so it's enabled for every type
+  /// that has presence: the cursors of a document, the viewed row of a database and the viewed
+  /// view of a folder.
   pub fn awareness_enabled(&self) -> bool {
-    matches!(self, CollabType::Document)
+    matches!(
+      self,
+      CollabType::Document | CollabType::Database | CollabType::DatabaseRow | CollabType::Folder
+    )
   }
 
   pub fn indexed_enabled(&self) -> bool {
@@ -139,6 +152,58 @@ impl CollabType {
       CollabType::Unknown => Ok(()),
     }
   }
+
+  /// Returns the [CollabSchema] of the data section for this type, or `None` for
+  /// [CollabType::Unknown]. The schemas describe the keys and the kinds of values that
+  /// [CollabType::validate_require_data] looks for, and allow unknown keys. They are not a
+  /// replacement for it: a [ValueSchema::Map] also accepts a map that is not a shared type, and a
+  /// [ValueSchema::String] accepts an empty id, e.g. an empty [FOLDER_WORKSPACE_ID].
+  pub fn schema(&self) -> Option<CollabSchema> {
+    let data = match self {
+      CollabType::Document => MapSchema::new().required(DOCUMENT_ROOT, MapSchema::new()),
+      CollabType::Database => MapSchema::new().required(
+        DATABASE,
+        MapSchema::new()
+          .required(DATABASE_ID, ValueSchema::String)
+          .required(
+            DATABASE_METAS,
+            MapSchema::new().required(DATABASE_INLINE_VIEW, ValueSchema::String),
+          ),
+      ),
+      CollabType::WorkspaceDatabase => {
+        MapSchema::new().required(WORKSPACE_DATABASES, ValueSchema::array_of(ValueSchema::Any))
+      },
+      CollabType::Folder => MapSchema::new().required(
+        FOLDER,
+        MapSchema::new().required(
+          FOLDER_META,
+          MapSchema::new().required(FOLDER_WORKSPACE_ID, ValueSchema::String),
+        ),
+      ),
+      CollabType::DatabaseRow => MapSchema::new().required(
+        DATABASE_ROW_DATA,
+        MapSchema::new().required(DATABASE_ROW_ID, ValueSchema::String),
+      ),
+      CollabType::UserAwareness => MapSchema::new().required(USER_AWARENESS, MapSchema::new()),
+      CollabType::Unknown => return None,
+    };
+    Some(CollabSchema::new(data))
+  }
+
+  /// Validates the collab against [CollabType::schema] and returns every violation at once.
+  pub fn validate_schema(&self, collab: &Collab) -> Result<(), CollabValidateError> {
+    match self.schema() {
+      None => Ok(()),
+      Some(schema) => {
+        let violations = schema.validate(collab);
+        if violations.is_empty() {
+          Ok(())
+        } else {
+          Err(CollabValidateError::SchemaViolations(violations))
+        }
+      },
+    }
+  }
   pub fn from_proto(proto: &proto::CollabType) -> Self {
     match proto {
       proto::CollabType::Unknown => CollabType::Unknown,
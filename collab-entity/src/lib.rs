@@ -2,6 +2,7 @@ pub use collab_object::*;
 
 mod collab_object;
 pub mod define;
+pub mod migration;
 pub mod proto;
 pub mod reminder;
 
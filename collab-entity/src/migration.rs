@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use collab::core::collab_migration::{CollabMigration, CollabMigrations};
+use collab::error::CollabError;
+
+use crate::CollabType;
+
+/// The migrations of all the collab types, see [CollabMigration].
+#[derive(Clone, Default)]
+pub struct MigrationRegistry {
+  migrations: HashMap<CollabType, CollabMigrations>,
+}
+
+impl MigrationRegistry {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn register<M: CollabMigration>(
+    &mut self,
+    collab_type: CollabType,
+    migration: M,
+  ) -> Result<(), CollabError> {
+    self
+      .migrations
+      .entry(collab_type)
+      .or_default()
+      .push(Arc::new(migration))
+  }
+
+  /// Returns the migrations of the given collab type, an empty list if none is registered.
+  pub fn migrations_for(&self, collab_type: CollabType) -> CollabMigrations {
+    self
+      .migrations
+      .get(&collab_type)
+      .cloned()
+      .unwrap_or_default()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use collab::core::collab_migration::CollabMigration;
+  use collab::error::CollabError;
+  use collab::preclude::{MapRef, TransactionMut};
+
+  use crate::CollabType;
+  use crate::migration::MigrationRegistry;
+
+  struct Noop(u32);
+
+  impl CollabMigration for Noop {
+    fn source_version(&self) -> u32 {
+      self.0
+    }
+
+    fn target_version(&self) -> u32 {
+      self.0 + 1
+    }
+
+    fn migrate(&self, _txn: &mut TransactionMut, _data: &MapRef) -> Result<(), CollabError> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn migrations_are_registered_by_collab_type() {
+    let mut registry = MigrationRegistry::new();
+    registry.register(CollabType::Document, Noop(0)).unwrap();
+    registry.register(CollabType::Document, Noop(1)).unwrap();
+    assert!(registry.register(CollabType::Document, Noop(1)).is_err());
+
+    let migrations = registry.migrations_for(CollabType::Document);
+    assert_eq!(migrations.latest_version(), 2);
+    assert!(registry.migrations_for(CollabType::Folder).is_empty());
+  }
+}
@@ -4,20 +4,25 @@ use std::sync::Arc;
 
 use anyhow::Result;
 use collab::preclude::{Any, In, Map, MapExt, MapPrelim, MapRef, Out, ReadTxn, TransactionMut};
+use collab_derive::Collab;
 use serde::{Deserialize, Serialize};
 use serde_repr::*;
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Collab, Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct Reminder {
   #[serde(rename = "id")]
   pub id: String,
   pub scheduled_at: i64,
   pub is_ack: bool,
+  #[collab(default)]
   pub is_read: bool,
   pub ty: ObjectType,
+  #[collab(default)]
   pub title: String,
+  #[collab(default)]
   pub message: String,
   /// The meta field is used to store arbitrary key-value pairs.
+  #[collab(default, skip_setter)]
   pub meta: ReminderMeta,
   /// The object_id field is used to store the id of the object that the reminder is associated with.
   pub object_id: String,
@@ -82,6 +87,19 @@ impl Reminder {
   }
 }
 
+impl ReminderUpdate<'_, '_> {
+  pub fn set_type<T: Into<i64>>(self, value: T) -> Self {
+    self.set_ty(ObjectType::from(value.into()))
+  }
+
+  pub fn set_meta(self, value: HashMap<String, String>) -> Self {
+    let result =
+      collab::core::map_binding::write_value(self.txn, self.map_ref, REMINDER_META, &value);
+    collab::core::map_binding::log_update_error(REMINDER_META, result);
+    self
+  }
+}
+
 impl<T> TryFrom<(&T, MapRef)> for Reminder
 where
   T: ReadTxn,
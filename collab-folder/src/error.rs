@@ -16,6 +16,9 @@ impl From<CollabValidateError> for FolderError {
   fn from(error: CollabValidateError) -> Self {
     match error {
       CollabValidateError::NoRequiredData(data) => FolderError::NoRequiredData(data),
+      CollabValidateError::SchemaViolations(violations) => {
+        FolderError::CollabError(collab::error::CollabError::SchemaViolations(violations))
+      },
     }
   }
 }
@@ -252,6 +252,27 @@ impl Folder {
     self.body.views.update_view(&mut txn, view_id, f, uid)
   }
 
+  /// Like [Folder::update_view], but the change goes through the
+  /// [collab::preclude::CollabPlugin::before_commit] hooks and the permission of the collab. A
+  /// rejected change is reverted and the error is returned, e.g.
+  /// [collab::error::CollabError::PermissionDenied] for a read-only folder.
+  pub fn try_update_view<F>(
+    &mut self,
+    view_id: &str,
+    f: F,
+    uid: i64,
+  ) -> Result<Option<Arc<View>>, FolderError>
+  where
+    F: FnOnce(ViewUpdate) -> Option<View>,
+  {
+    let views = &self.body.views;
+    let view = self
+      .collab
+      .context
+      .with_txn(|txn| views.update_view(txn, view_id, f, uid))?;
+    Ok(view)
+  }
+
   pub fn delete_views<T: AsRef<str>>(&mut self, views: Vec<T>) {
     let mut txn = self.collab.transact_mut();
     self.body.views.delete_views(&mut txn, views);
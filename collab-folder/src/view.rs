@@ -3,9 +3,11 @@ use std::sync::Arc;
 
 use anyhow::bail;
 use collab::core::collab::IndexContentSender;
+use collab::core::collab_permission::CollabPermission;
 use collab::preclude::{
   Any, Map, MapExt, MapPrelim, MapRef, ReadTxn, Subscription, TransactionMut, YrsValue,
 };
+use collab_derive::Collab;
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
 use serde_repr::*;
@@ -14,15 +16,14 @@ use tracing::{instrument, trace};
 
 use crate::folder_observe::ViewChangeSender;
 
+use crate::UserId;
 use crate::section::{Section, SectionItem, SectionMap};
 use crate::space_info::SpaceInfo;
 use crate::{ParentChildRelations, RepeatedViewIdentifier, ViewIdentifier, subscribe_view_change};
-use crate::{UserId, impl_any_update, impl_i64_update, impl_option_i64_update, impl_str_update};
 
 pub(crate) const FOLDER_VIEW_ID: &str = "id";
 pub(crate) const FOLDER_VIEW_NAME: &str = "name";
 const VIEW_PARENT_ID: &str = "bid";
-const VIEW_DESC: &str = "desc";
 const VIEW_LAYOUT: &str = "layout";
 const VIEW_CREATE_AT: &str = "created_at";
 const VIEW_CREATED_BY: &str = "created_by";
@@ -551,6 +552,23 @@ impl<'a, 'b> ViewBuilder<'a, 'b> {
   }
 }
 
+/// The values of a view that are stored as plain keys of its map. The keys match the `VIEW_*`
+/// constants. [ViewUpdate] writes them with the update builder generated for this struct.
+#[derive(Collab)]
+struct ViewProperties {
+  name: String,
+  bid: String,
+  desc: String,
+  layout: ViewLayout,
+  created_at: i64,
+  created_by: i64,
+  last_edited_time: i64,
+  last_edited_by: i64,
+  icon: String,
+  is_locked: bool,
+  extra: String,
+}
+
 pub struct ViewUpdate<'a, 'b, 'c> {
   #[allow(dead_code)]
   uid: UserId,
@@ -562,20 +580,6 @@ pub struct ViewUpdate<'a, 'b, 'c> {
 }
 
 impl<'a, 'b, 'c> ViewUpdate<'a, 'b, 'c> {
-  impl_str_update!(set_name, set_name_if_not_none, FOLDER_VIEW_NAME);
-  impl_str_update!(set_bid, set_bid_if_not_none, VIEW_PARENT_ID);
-  impl_str_update!(set_desc, set_desc_if_not_none, VIEW_DESC);
-  impl_any_update!(set_layout, set_layout_if_not_none, VIEW_LAYOUT, ViewLayout);
-  impl_i64_update!(set_created_at, set_created_at_if_not_none, VIEW_CREATE_AT);
-  impl_option_i64_update!(set_created_by, VIEW_CREATED_BY);
-  impl_i64_update!(
-    set_last_edited_time,
-    set_last_edited_time_if_not_none,
-    VIEW_LAST_EDITED_TIME
-  );
-  impl_option_i64_update!(set_last_edited_by, VIEW_LAST_EDITED_BY);
-  impl_str_update!(set_extra, set_extra_if_not_none, VIEW_EXTRA);
-
   pub fn new(
     uid: UserId,
     view_id: &'a str,
@@ -594,6 +598,108 @@ impl<'a, 'b, 'c> ViewUpdate<'a, 'b, 'c> {
     }
   }
 
+  fn properties(&mut self) -> ViewPropertiesUpdate<'_, 'b> {
+    ViewPropertiesUpdate::new(self.txn, self.map_ref)
+  }
+
+  pub fn set_name<T: AsRef<str>>(mut self, value: T) -> Self {
+    self.properties().set_name(value.as_ref());
+    self
+  }
+
+  pub fn set_name_if_not_none<T: AsRef<str>>(self, value: Option<T>) -> Self {
+    match value {
+      Some(value) => self.set_name(value),
+      None => self,
+    }
+  }
+
+  pub fn set_bid<T: AsRef<str>>(mut self, value: T) -> Self {
+    self.properties().set_bid(value.as_ref());
+    self
+  }
+
+  pub fn set_bid_if_not_none<T: AsRef<str>>(self, value: Option<T>) -> Self {
+    match value {
+      Some(value) => self.set_bid(value),
+      None => self,
+    }
+  }
+
+  pub fn set_desc<T: AsRef<str>>(mut self, value: T) -> Self {
+    self.properties().set_desc(value.as_ref());
+    self
+  }
+
+  pub fn set_desc_if_not_none<T: AsRef<str>>(self, value: Option<T>) -> Self {
+    match value {
+      Some(value) => self.set_desc(value),
+      None => self,
+    }
+  }
+
+  pub fn set_layout(mut self, value: ViewLayout) -> Self {
+    self.properties().set_layout(value);
+    self
+  }
+
+  pub fn set_layout_if_not_none(self, value: Option<ViewLayout>) -> Self {
+    match value {
+      Some(value) => self.set_layout(value),
+      None => self,
+    }
+  }
+
+  pub fn set_created_at(mut self, value: i64) -> Self {
+    self.properties().set_created_at(value);
+    self
+  }
+
+  pub fn set_created_at_if_not_none(self, value: Option<i64>) -> Self {
+    match value {
+      Some(value) => self.set_created_at(value),
+      None => self,
+    }
+  }
+
+  pub fn set_created_by(mut self, value: Option<i64>) -> Self {
+    if let Some(value) = value {
+      self.properties().set_created_by(value);
+    }
+    self
+  }
+
+  pub fn set_last_edited_time(mut self, value: i64) -> Self {
+    self.properties().set_last_edited_time(value);
+    self
+  }
+
+  pub fn set_last_edited_time_if_not_none(self, value: Option<i64>) -> Self {
+    match value {
+      Some(value) => self.set_last_edited_time(value),
+      None => self,
+    }
+  }
+
+  pub fn set_last_edited_by(mut self, value: Option<i64>) -> Self {
+    if let Some(value) = value {
+      self.properties().set_last_edited_by(value);
+    }
+    self
+  }
+
+  pub fn set_extra<T: AsRef<str>>(mut self, value: T) -> Self {
+    self.properties().set_extra(value.as_ref());
+    self
+  }
+
+  pub fn set_extra_if_not_none<T: AsRef<str>>(self, value: Option<T>) -> Self {
+    match value {
+      Some(value) => self.set_extra(value),
+      None => self,
+    }
+  }
+
   pub fn set_children(self, children: RepeatedViewIdentifier) -> Self {
     let array = self
       .children_map
@@ -603,18 +709,17 @@ impl<'a, 'b, 'c> ViewUpdate<'a, 'b, 'c> {
     self
   }
 
-  pub fn set_icon(self, icon: Option<ViewIcon>) -> Self {
+  pub fn set_icon(mut self, icon: Option<ViewIcon>) -> Self {
     let icon_str = icon
       .and_then(|icon| serde_json::to_string(&icon).ok())
       .unwrap_or_default();
-    self.map_ref.insert(self.txn, VIEW_ICON, icon_str);
-
+    self.properties().set_icon(icon_str);
     self
   }
 
-  pub fn set_is_locked(self, is_locked: Option<bool>) -> Self {
+  pub fn set_is_locked(mut self, is_locked: Option<bool>) -> Self {
     if let Some(is_locked) = is_locked {
-      self.map_ref.insert(self.txn, VIEW_IS_LOCKED, is_locked);
+      self.properties().set_is_locked(is_locked);
     }
     self
   }
@@ -705,8 +810,8 @@ impl<'a, 'b, 'c> ViewUpdate<'a, 'b, 'c> {
     self
   }
 
-  pub fn set_page_lock_status(self, is_locked: bool) -> Self {
-    self.map_ref.insert(self.txn, VIEW_IS_LOCKED, is_locked);
+  pub fn set_page_lock_status(mut self, is_locked: bool) -> Self {
+    self.properties().set_is_locked(is_locked);
     self
   }
 
@@ -796,6 +901,16 @@ impl View {
       extra: None,
     }
   }
+
+  /// The permission to open the collab of the view with: a locked view is read-only.
+  pub fn collab_permission(&self) -> CollabPermission {
+    if self.is_locked.unwrap_or(false) {
+      CollabPermission::ReadOnly
+    } else {
+      CollabPermission::ReadWrite
+    }
+  }
+
   pub fn space_info(&self) -> Option<SpaceInfo> {
     let extra = self.extra.as_ref()?;
     serde_json::from_str::<SpaceInfo>(extra).ok()
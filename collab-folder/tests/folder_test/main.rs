@@ -4,6 +4,7 @@ mod favorite_test;
 mod load_disk;
 mod recent_views_test;
 mod serde_test;
+mod simulation_test;
 mod space_info_test;
 mod trash_test;
 mod util;
@@ -0,0 +1,58 @@
+use collab::core::collab::{CollabOptions, default_client_id};
+use collab::core::collab_simulation::{Simulation, SimulationConfig};
+use collab::core::origin::CollabOrigin;
+use collab::preclude::Collab;
+use collab_folder::{Folder, FolderData, Workspace};
+
+use crate::util::make_test_view;
+
+const WORKSPACE_ID: &str = "simulation_workspace";
+const UID: i64 = 1;
+
+fn folder_simulation(config: SimulationConfig) -> Simulation<Folder> {
+  let options = CollabOptions::new(WORKSPACE_ID.to_string(), default_client_id());
+  let collab = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  let workspace = Workspace::new(WORKSPACE_ID.to_string(), "".to_string(), UID);
+  let folder = Folder::create(collab, None, FolderData::new(UID, workspace));
+  let encoded_collab = folder.encode_collab().unwrap();
+  let config = config
+    .with_object_id(WORKSPACE_ID)
+    .with_doc_state(encoded_collab.doc_state.to_vec());
+  Simulation::with_peers(config, |collab| Folder::open(collab, None).unwrap()).unwrap()
+}
+
+/// Inserts a view in the workspace, or renames one of the views.
+fn random_edit(folder: &mut Folder, rng: &mut fastrand::Rng) {
+  let views = folder.get_views_belong_to(WORKSPACE_ID, UID);
+  if views.is_empty() || rng.bool() {
+    let view = make_test_view(&format!("view_{}", rng.u32(..)), WORKSPACE_ID, vec![]);
+    folder.insert_view(view, None, UID);
+  } else {
+    let view_id = views[rng.usize(..views.len())].id.clone();
+    let name = format!("view {}", rng.u32(..));
+    folder.update_view(&view_id, |update| update.set_name(name).done(), UID);
+  }
+}
+
+#[test]
+fn concurrent_folder_edits_converge_test() {
+  for seed in 0..10 {
+    let config = SimulationConfig::new(3, seed)
+      .with_reorder(true)
+      .with_drop_rate(0.2);
+    let mut simulation = folder_simulation(config);
+
+    simulation.run(50, random_edit);
+    simulation.converge();
+    simulation.assert_converged();
+
+    let data = simulation
+      .peer(0)
+      .get_folder_data(WORKSPACE_ID, UID)
+      .unwrap();
+    assert!(!data.views.is_empty());
+    for folder in simulation.peers() {
+      assert_eq!(folder.get_folder_data(WORKSPACE_ID, UID).unwrap(), data);
+    }
+  }
+}
@@ -8,7 +8,7 @@ use collab::preclude::Collab;
 use collab_entity::CollabType;
 use collab_folder::*;
 use collab_plugins::CollabKVDB;
-use collab_plugins::local_storage::rocksdb::rocksdb_plugin::RocksdbDiskPlugin;
+use collab_plugins::local_storage::disk::rocksdb_plugin::RocksdbDiskPlugin;
 use tempfile::TempDir;
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::fmt::Subscriber;
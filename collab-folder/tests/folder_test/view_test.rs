@@ -1,7 +1,15 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
 use crate::util::{create_folder_with_workspace, make_test_view, setup_log};
 use collab::core::collab::{IndexContent, default_client_id};
+use collab::core::collab_permission::CollabPermission;
+use collab::core::collab_plugin::CollabPluginType;
+use collab::error::CollabError;
+use collab::preclude::{Any, CollabPlugin, Map, MapExt, MapRef, ReadTxn, ToJson, TransactionMut};
+use collab_folder::error::FolderError;
 use collab_folder::folder_diff::FolderViewChange;
-use collab_folder::{IconType, UserId, ViewIcon, ViewIndexContent, timestamp};
+use collab_folder::{IconType, UserId, View, ViewIcon, ViewIndexContent, timestamp};
 
 #[test]
 fn create_view_test() {
@@ -694,3 +702,135 @@ fn compare_diff_view_test() {
     view_id: "v2".to_string(),
   }));
 }
+
+/// Rejects the renaming of the views locked with [collab_folder::View::is_locked].
+#[derive(Clone, Default)]
+struct LockedViewPlugin {
+  /// The names of the locked views, as of the last transaction.
+  names: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl LockedViewPlugin {
+  fn locked_names<T: ReadTxn>(txn: &T) -> HashMap<String, String> {
+    let views: Option<MapRef> = txn
+      .get_map("data")
+      .and_then(|data| data.get_with_path(txn, ["folder", "views"]));
+    let mut names = HashMap::new();
+    for (view_id, view) in views.iter().flat_map(|views| views.iter(txn)) {
+      let Any::Map(view) = view.to_json(txn) else {
+        continue;
+      };
+      if let (Some(Any::Bool(true)), Some(Any::String(name))) =
+        (view.get("is_locked"), view.get("name"))
+      {
+        names.insert(view_id.to_string(), name.to_string());
+      }
+    }
+    names
+  }
+}
+
+impl CollabPlugin for LockedViewPlugin {
+  fn before_commit(&self, _object_id: &str, txn: &TransactionMut) -> Result<(), CollabError> {
+    let current = Self::locked_names(txn);
+    for (view_id, name) in self.names.lock().unwrap().iter() {
+      if current.get(view_id).is_some_and(|current| current != name) {
+        return Err(CollabError::TransactionRejected(format!(
+          "view {} is locked",
+          view_id
+        )));
+      }
+    }
+    Ok(())
+  }
+
+  fn has_commit_hook(&self) -> bool {
+    true
+  }
+
+  fn after_transaction(&self, _object_id: &str, txn: &mut TransactionMut) {
+    *self.names.lock().unwrap() = Self::locked_names(txn);
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::Other("LockedViewPlugin".to_string())
+  }
+}
+
+#[test]
+fn locked_view_rejects_changes_test() {
+  let uid = UserId::from(1);
+  let mut folder_test = create_folder_with_workspace(uid.clone(), "w1");
+  folder_test
+    .folder
+    .collab
+    .add_plugin(Box::new(LockedViewPlugin::default()));
+  let mut view = make_test_view("v1", "w1", vec![]);
+  view.name = "my page".to_string();
+  folder_test.insert_view(view, None, uid.as_i64());
+  folder_test
+    .try_update_view(
+      "v1",
+      |update| update.set_page_lock_status(true).done(),
+      uid.as_i64(),
+    )
+    .unwrap();
+
+  let result = folder_test.try_update_view(
+    "v1",
+    |update| update.set_name("renamed").done(),
+    uid.as_i64(),
+  );
+  assert!(matches!(
+    result,
+    Err(FolderError::CollabError(CollabError::TransactionRejected(
+      _
+    )))
+  ));
+  let view = folder_test.get_view("v1", uid.as_i64()).unwrap();
+  assert_eq!(view.name, "my page");
+  assert_eq!(view.is_locked, Some(true));
+
+  folder_test
+    .try_update_view(
+      "v1",
+      |update| update.set_page_lock_status(false).done(),
+      uid.as_i64(),
+    )
+    .unwrap();
+  folder_test
+    .try_update_view(
+      "v1",
+      |update| update.set_name("renamed").done(),
+      uid.as_i64(),
+    )
+    .unwrap();
+  let view = folder_test.get_view("v1", uid.as_i64()).unwrap();
+  assert_eq!(view.name, "renamed");
+}
+
+#[test]
+fn read_only_folder_rejects_changes_test() {
+  let uid = UserId::from(1);
+  let mut folder_test = create_folder_with_workspace(uid.clone(), "w1");
+  let mut view = make_test_view("v1", "w1", vec![]);
+  view.name = "my page".to_string();
+  folder_test.insert_view(view, None, uid.as_i64());
+  folder_test
+    .folder
+    .collab
+    .context
+    .set_permission(CollabPermission::ReadOnly);
+
+  let result = folder_test.try_update_view(
+    "v1",
+    |update| update.set_name("renamed").done(),
+    uid.as_i64(),
+  );
+  assert!(matches!(
+    result,
+    Err(FolderError::CollabError(CollabError::PermissionDenied(_)))
+  ));
+  let view = folder_test.get_view("v1", uid.as_i64()).unwrap();
+  assert_eq!(view.name, "my page");
+}
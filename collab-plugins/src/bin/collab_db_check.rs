@@ -0,0 +1,114 @@
+//! Checks the integrity of a collab database, and optionally repairs it.
+//!
+//! Usage: `collab_db_check <db path> [--repair] [--key-file <path> [--allow-plaintext]]`
+//!
+//! With `--allow-plaintext`, the values that are not sealed with the key file are read as they
+//! are instead of being reported, e.g. for a store that is being encrypted.
+//!
+//! The database must not be opened by another process. The exit code is 0 if the database has no
+//! problem left, 1 otherwise.
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+  if let Err(err) = native::run(std::env::args().skip(1)) {
+    eprintln!("{}", err);
+    std::process::exit(2);
+  }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+  use std::path::PathBuf;
+
+  use collab_plugins::CollabKVDB;
+  use collab_plugins::local_storage::kv::encryption::CollabCipher;
+  use collab_plugins::local_storage::kv::integrity::{repair, verify};
+  use collab_plugins::local_storage::kv::key_file::LocalKeyFile;
+  use collab_plugins::local_storage::kv::{KVTransactionDB, PersistenceError};
+
+  const USAGE: &str =
+    "usage: collab_db_check <db path> [--repair] [--key-file <path> [--allow-plaintext]]";
+
+  pub fn run(mut args: impl Iterator<Item = String>) -> Result<(), PersistenceError> {
+    let mut path = None;
+    let mut key_file = None;
+    let mut should_repair = false;
+    let mut allow_plaintext = false;
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "--repair" => should_repair = true,
+        "--allow-plaintext" => allow_plaintext = true,
+        "--key-file" => key_file = Some(args.next().ok_or_else(usage)?),
+        _ if path.is_none() && !arg.starts_with("--") => path = Some(PathBuf::from(arg)),
+        _ => return Err(usage()),
+      }
+    }
+    let path = path.ok_or_else(usage)?;
+
+    let mut db = CollabKVDB::open(&path)?;
+    match key_file {
+      Some(key_file) => {
+        let mut cipher = CollabCipher::new(LocalKeyFile::open(key_file)?);
+        if allow_plaintext {
+          cipher = cipher.allow_plaintext();
+        }
+        db = db.with_cipher(cipher);
+      },
+      None if allow_plaintext => return Err(usage()),
+      None => {},
+    }
+
+    let report = verify(&db.read_txn())?;
+    println!(
+      "{}: {} docs, {} updates",
+      path.display(),
+      report.docs,
+      report.updates
+    );
+    for doc in &report.unreadable_docs {
+      println!("unreadable without its encryption key: {}", doc);
+    }
+    for problem in &report.problems {
+      println!("problem: {}", problem);
+    }
+    if report.is_ok() {
+      println!("no problem found");
+      return Ok(());
+    }
+    if !should_repair {
+      println!(
+        "{} problems found, run with --repair to fix them",
+        report.problems.len()
+      );
+      std::process::exit(1);
+    }
+
+    let repair_report = db.with_write_txn(repair)?;
+    db.flush()?;
+    println!(
+      "quarantined {} entries, rebuilt {} docs",
+      repair_report.quarantined, repair_report.rebuilt_docs
+    );
+    for key in &repair_report.pending_updates {
+      println!("quarantined the update {:?}: it can't be integrated", key);
+    }
+    for doc in &repair_report.skipped_docs {
+      println!("skipped: {}", doc);
+    }
+    let report = verify(&db.read_txn())?;
+    for problem in &report.problems {
+      println!("problem left: {}", problem);
+    }
+    if !report.is_ok() {
+      std::process::exit(1);
+    }
+    Ok(())
+  }
+
+  fn usage() -> PersistenceError {
+    PersistenceError::InvalidData(USAGE.to_string())
+  }
+}
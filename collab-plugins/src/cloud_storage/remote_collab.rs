@@ -6,8 +6,9 @@ use std::time::{Duration, SystemTime};
 
 use anyhow::{Error, anyhow};
 use async_trait::async_trait;
-use collab::core::collab::{DataSource, TransactionMutExt};
+use collab::core::collab::DataSource;
 use collab::core::collab_state::SyncState;
+use collab::core::collab_stream::DocStateChunkReader;
 use collab::core::origin::CollabOrigin;
 use collab::lock::RwLock;
 use collab::preclude::Collab;
@@ -92,8 +93,7 @@ impl RemoteCollab {
             match Update::decode_v1(&update) {
               Ok(update) => {
                 let mut collab = local_collab.write().await;
-                let mut txn = collab.transact_mut();
-                if let Err(e) = txn.try_apply_update(update) {
+                if let Err(e) = collab.apply_update(update) {
                   tracing::error!("apply remote update failed: {:?}", e);
                 }
               },
@@ -207,6 +207,7 @@ impl RemoteCollab {
   /// Return the update of the remote collab.
   /// If the remote collab contains any updates, it will return None.
   /// Otherwise, it will merge the updates into one and return the merged update.
+  /// A [DataSource::DocStateV1Stream] is applied chunk by chunk and an empty update is returned.
   #[allow(dead_code)]
   pub async fn sync(&self, local_collab: Weak<RwLock<Collab>>) -> Result<Vec<u8>, Error> {
     let mut remote_update = vec![];
@@ -218,13 +219,12 @@ impl RemoteCollab {
     let collab_doc_state = self.storage.get_doc_state(&self.object).await?;
     {
       let mut remote_collab = self.collab.write().await;
-      let mut txn = remote_collab.transact_mut();
 
       match collab_doc_state {
         DataSource::Disk { .. } => {},
         DataSource::DocStateV1(doc_state) => {
           if let Ok(update) = Update::decode_v1(&doc_state) {
-            if let Err(e) = txn.try_apply_update(update) {
+            if let Err(e) = remote_collab.apply_update(update) {
               tracing::error!("apply update failed: {:?}", e);
             }
           } else {
@@ -234,7 +234,7 @@ impl RemoteCollab {
         },
         DataSource::DocStateV2(doc_state) => {
           if let Ok(update) = Update::decode_v2(&doc_state) {
-            if let Err(e) = txn.try_apply_update(update) {
+            if let Err(e) = remote_collab.apply_update(update) {
               tracing::error!("apply update failed: {:?}", e);
             }
           } else {
@@ -242,6 +242,20 @@ impl RemoteCollab {
           }
           remote_update = doc_state;
         },
+        DataSource::DocStateV1Stream(reader) => {
+          // The chunks are applied one at a time and never merged, so the streamed doc state is
+          // not returned.
+          for update in DocStateChunkReader::new(reader) {
+            match update {
+              Ok(update) => {
+                if let Err(e) = remote_collab.apply_update(update) {
+                  tracing::error!("apply update failed: {:?}", e);
+                }
+              },
+              Err(e) => tracing::error!("🔴decode update failed: {:?}", e),
+            }
+          }
+        },
       }
 
       let _ = self.sync_state.send(SyncState::InitSyncBegin);
@@ -299,7 +313,7 @@ impl RemoteCollab {
       );
 
       // Apply the update to the remote collab and send the update to the remote.
-      remote_lock.transact_mut().apply_update(decode_update)?;
+      remote_lock.apply_update(decode_update)?;
       drop(remote_lock);
 
       self.sink.queue_msg(|msg_id| Message {
@@ -313,11 +327,7 @@ impl RemoteCollab {
 
   pub fn push_update(&self, update: &[u8]) -> Result<(), Error> {
     if let Ok(decode_update) = Update::decode_v1(update) {
-      self
-        .collab
-        .blocking_write()
-        .transact_mut()
-        .apply_update(decode_update)?;
+      self.collab.blocking_write().apply_update(decode_update)?;
 
       self.sink.queue_msg(|msg_id| Message {
         object: self.object.clone(),
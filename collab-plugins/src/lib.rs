@@ -20,10 +20,26 @@ macro_rules! if_wasm {
 pub mod cloud_storage;
 pub mod connect_state;
 
+// The native platforms use either the RocksDB or the SQLite backend
 if_native! {
+    #[cfg(feature = "rocksdb")]
     pub type CollabKVDB = local_storage::rocksdb::kv_impl::KVTransactionDBRocksdbImpl;
+
+    #[cfg(feature = "sqlite")]
+    pub type CollabKVDB = local_storage::sqlite::kv_impl::KVTransactionDBSqliteImpl;
 }
 
+#[cfg(all(
+  not(target_arch = "wasm32"),
+  not(any(feature = "rocksdb", feature = "sqlite"))
+))]
+compile_error!("either the rocksdb or the sqlite feature must be enabled");
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "rocksdb", feature = "sqlite"))]
+compile_error!(
+  "the rocksdb and sqlite features are mutually exclusive, build with `default-features = false` to use sqlite"
+);
+
 if_wasm! {
     pub type CollabKVDB = local_storage::indexeddb::CollabIndexeddb;
 }
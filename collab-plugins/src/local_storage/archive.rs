@@ -0,0 +1,377 @@
+//! Export and import of the whole local collab store, e.g. to back up the data of a user or to
+//! move it to another machine.
+//!
+//! An archive is written by [export_collabs] from a read transaction and restored by
+//! [import_collabs] within a write transaction, of any [crate::local_storage::kv::KVTransactionDB].
+//! It starts with [ARCHIVE_MAGIC] and a bincode encoded header, followed by one [ArchivedCollab]
+//! per object. The updates of an object are merged into a single doc state, so the archive doesn't
+//! depend on the key layout of the store. The doc state is written as an [EncodedCollab] frame,
+//! checksummed and, with the `zstd` feature, compressed. The archive is never encrypted, so the
+//! export of an encrypted store must be allowed explicitly.
+//!
+//! The named versions are not archived, [ExportReport::dropped_versions] counts them.
+
+use std::io::{Read, Write};
+
+use collab::core::collab::{default_client_id, make_yrs_doc};
+use collab::entity::{Compression, EncodedCollab, EncoderVersion};
+use collab_entity::CollabType;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{Doc, ReadTxn, StateVector, Transact, Update};
+
+use crate::local_storage::kv::doc::CollabKVAction;
+use crate::local_storage::kv::keys::make_snapshot_update_key;
+use crate::local_storage::kv::snapshot::{CollabSnapshot, SnapshotAction, seal_snapshot};
+use crate::local_storage::kv::version::VersionAction;
+use crate::local_storage::kv::{KVStore, PersistenceError, create_update_key};
+
+pub const ARCHIVE_MAGIC: [u8; 4] = *b"CLBA";
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[cfg(feature = "zstd")]
+const ARCHIVE_COMPRESSION: Compression = Compression::Zstd;
+#[cfg(not(feature = "zstd"))]
+const ARCHIVE_COMPRESSION: Compression = Compression::None;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveHeader {
+  version: u32,
+  created_at: i64,
+  object_count: u64,
+}
+
+/// An object of the archive.
+#[derive(Serialize, Deserialize)]
+pub struct ArchivedCollab {
+  pub uid: i64,
+  pub workspace_id: String,
+  pub object_id: String,
+  pub collab_type: CollabType,
+  pub encoded_collab: EncodedCollab,
+  pub snapshots: Vec<CollabSnapshot>,
+}
+
+/// How an [ArchivedCollab] is written in the archive.
+#[derive(Serialize, Deserialize)]
+struct ArchiveEntry {
+  uid: i64,
+  workspace_id: String,
+  object_id: String,
+  collab_type: CollabType,
+  /// The [EncodedCollab] of the object, encoded with [EncodedCollab::encode_to_frame].
+  frame: Vec<u8>,
+  snapshots: Vec<CollabSnapshot>,
+}
+
+impl TryFrom<ArchiveEntry> for ArchivedCollab {
+  type Error = PersistenceError;
+
+  fn try_from(entry: ArchiveEntry) -> Result<Self, Self::Error> {
+    let (_, encoded_collab) = EncodedCollab::decode_frame(&entry.frame)?;
+    Ok(Self {
+      uid: entry.uid,
+      workspace_id: entry.workspace_id,
+      object_id: entry.object_id,
+      collab_type: entry.collab_type,
+      encoded_collab,
+      snapshots: entry.snapshots,
+    })
+  }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExportReport {
+  pub objects: usize,
+  pub snapshots: usize,
+  /// The number of named versions of the exported objects, which are not archived.
+  pub dropped_versions: usize,
+}
+
+/// Writes all the objects of the user to the archive.
+///
+/// The store doesn't record the type of the objects, `collab_type` returns it for the given
+/// workspace id and object id, or [CollabType::Unknown] if it's not known.
+///
+/// The archive is written in plaintext. If the store is encrypted, it returns
+/// [PersistenceError::PlaintextExportNotAllowed] unless `allow_plaintext` is true.
+pub fn export_collabs<'a, S, W>(
+  store: &S,
+  uid: i64,
+  mut writer: W,
+  collab_type: impl Fn(&str, &str) -> CollabType,
+  allow_plaintext: bool,
+) -> Result<ExportReport, PersistenceError>
+where
+  S: KVStore<'a> + 'a,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+  W: Write,
+{
+  if store.cipher().is_some() && !allow_plaintext {
+    return Err(PersistenceError::PlaintextExportNotAllowed);
+  }
+  let mut workspace_ids = store.get_all_workspace_ids()?;
+  workspace_ids.sort();
+  let mut objects = vec![];
+  for workspace_id in workspace_ids {
+    let mut object_ids = store
+      .get_all_object_ids(uid, &workspace_id)?
+      .collect::<Vec<_>>();
+    object_ids.sort();
+    objects.extend(
+      object_ids
+        .into_iter()
+        .map(|object_id| (workspace_id.clone(), object_id)),
+    );
+  }
+
+  writer.write_all(&ARCHIVE_MAGIC).map_err(io_error)?;
+  let header = ArchiveHeader {
+    version: ARCHIVE_VERSION,
+    created_at: chrono::Utc::now().timestamp(),
+    object_count: objects.len() as u64,
+  };
+  bincode::serialize_into(&mut writer, &header)?;
+
+  let mut report = ExportReport::default();
+  for (workspace_id, object_id) in objects {
+    // The doc is only read, so the client id is never used
+    let doc = make_yrs_doc(&object_id, false, default_client_id());
+    store.load_doc(uid, &workspace_id, &object_id, &doc)?;
+    let encoded_collab = {
+      let txn = doc.transact();
+      EncodedCollab::new_v1(
+        txn.state_vector().encode_v1(),
+        txn.encode_state_as_update_v1(&StateVector::default()),
+      )
+    };
+    let snapshots = store.get_snapshots(uid, &workspace_id, &object_id);
+    report.objects += 1;
+    report.snapshots += snapshots.len();
+    report.dropped_versions += store.number_of_versions(uid, &workspace_id, &object_id)?;
+
+    let collab_type = collab_type(&workspace_id, &object_id);
+    let entry = ArchiveEntry {
+      uid,
+      frame: encoded_collab.encode_to_frame(&collab_type.to_string(), ARCHIVE_COMPRESSION)?,
+      collab_type,
+      workspace_id,
+      object_id,
+      snapshots,
+    };
+    bincode::serialize_into(&mut writer, &entry)?;
+  }
+  writer.flush().map_err(io_error)?;
+  if report.dropped_versions > 0 {
+    warn!(
+      "{} named versions of user:{} are not exported",
+      report.dropped_versions, uid
+    );
+  }
+  info!(
+    "exported {} objects and {} snapshots of user:{}",
+    report.objects, report.snapshots, uid
+  );
+  Ok(report)
+}
+
+/// Reads the objects of an archive one by one.
+pub struct CollabArchiveReader<R> {
+  reader: R,
+  header: ArchiveHeader,
+  read: u64,
+}
+
+impl<R: Read> CollabArchiveReader<R> {
+  pub fn new(mut reader: R) -> Result<Self, PersistenceError> {
+    let mut magic = [0; ARCHIVE_MAGIC.len()];
+    reader.read_exact(&mut magic).map_err(io_error)?;
+    if magic != ARCHIVE_MAGIC {
+      return Err(PersistenceError::InvalidData(
+        "not a collab archive".to_string(),
+      ));
+    }
+    let header: ArchiveHeader = bincode::deserialize_from(&mut reader)?;
+    if header.version != ARCHIVE_VERSION {
+      return Err(PersistenceError::InvalidData(format!(
+        "unsupported collab archive version: {}",
+        header.version
+      )));
+    }
+    Ok(Self {
+      reader,
+      header,
+      read: 0,
+    })
+  }
+
+  /// The unix timestamp of the export, in seconds.
+  pub fn created_at(&self) -> i64 {
+    self.header.created_at
+  }
+
+  /// The number of objects in the archive.
+  pub fn object_count(&self) -> u64 {
+    self.header.object_count
+  }
+}
+
+impl<R: Read> Iterator for CollabArchiveReader<R> {
+  type Item = Result<ArchivedCollab, PersistenceError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.read >= self.header.object_count {
+      return None;
+    }
+    self.read += 1;
+    let entry = bincode::deserialize_from::<_, ArchiveEntry>(&mut self.reader);
+    Some(entry.map_err(Into::into).and_then(ArchivedCollab::try_from))
+  }
+}
+
+/// What [import_collabs] does with an object that already exists in the store.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportConflictPolicy {
+  /// Keep the existing object.
+  #[default]
+  Skip,
+  /// Replace the existing object, and its snapshots, with the archived one.
+  Overwrite,
+  /// Merge the archived object into the existing one. Both are versions of the same document, so
+  /// none of their changes are lost. The archived snapshots are added to the existing ones.
+  Merge,
+}
+
+/// An archived object that already exists in the store.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportConflict {
+  pub uid: i64,
+  pub workspace_id: String,
+  pub object_id: String,
+  pub collab_type: CollabType,
+  /// How the conflict was resolved.
+  pub resolution: ImportConflictPolicy,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+  /// The number of objects written to the store, including the overwritten and merged ones.
+  pub objects: usize,
+  pub snapshots: usize,
+  pub conflicts: Vec<ImportConflict>,
+}
+
+/// Restores the archive into the store. Call it within
+/// [crate::local_storage::kv::KVTransactionDB::with_write_txn], for the archive to be imported
+/// atomically.
+pub fn import_collabs<'a, S, R>(
+  store: &S,
+  reader: R,
+  policy: ImportConflictPolicy,
+) -> Result<ImportReport, PersistenceError>
+where
+  S: KVStore<'a> + 'a,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+  R: Read,
+{
+  let mut report = ImportReport::default();
+  for collab in CollabArchiveReader::new(reader)? {
+    let collab = collab?;
+    let ArchivedCollab {
+      uid,
+      workspace_id,
+      object_id,
+      ..
+    } = &collab;
+
+    if store.is_exist(*uid, workspace_id, object_id) {
+      warn!(
+        "archived object {}/{} already exists, resolution: {:?}",
+        workspace_id, object_id, policy
+      );
+      report.conflicts.push(ImportConflict {
+        uid: *uid,
+        workspace_id: workspace_id.clone(),
+        object_id: object_id.clone(),
+        collab_type: collab.collab_type,
+        resolution: policy,
+      });
+      match policy {
+        ImportConflictPolicy::Skip => continue,
+        ImportConflictPolicy::Overwrite => store.delete_doc(*uid, workspace_id, object_id)?,
+        ImportConflictPolicy::Merge => {
+          let (_, doc_state) = encode_v1(&collab)?;
+          store.push_update(*uid, workspace_id, object_id, &doc_state)?;
+          report.objects += 1;
+          report.snapshots += insert_snapshots(store, &collab)?;
+          continue;
+        },
+      }
+    }
+
+    let (state_vector, doc_state) = encode_v1(&collab)?;
+    store.flush_doc(*uid, workspace_id, object_id, state_vector, doc_state)?;
+    report.objects += 1;
+    report.snapshots += insert_snapshots(store, &collab)?;
+  }
+  info!(
+    "imported {} objects and {} snapshots, {} conflicts",
+    report.objects,
+    report.snapshots,
+    report.conflicts.len()
+  );
+  Ok(report)
+}
+
+/// Returns the state vector and the doc state of the archived object, encoded with v1.
+fn encode_v1(collab: &ArchivedCollab) -> Result<(Vec<u8>, Vec<u8>), PersistenceError> {
+  let encoded_collab = &collab.encoded_collab;
+  if encoded_collab.version == EncoderVersion::V1 {
+    return Ok((
+      encoded_collab.state_vector.to_vec(),
+      encoded_collab.doc_state.to_vec(),
+    ));
+  }
+  let doc = Doc::new();
+  let mut txn = doc.transact_mut();
+  txn.apply_update(Update::decode_v2(&encoded_collab.doc_state)?)?;
+  Ok((
+    txn.state_vector().encode_v1(),
+    txn.encode_state_as_update_v1(&StateVector::default()),
+  ))
+}
+
+/// Inserts the archived snapshots, with their original creation time.
+fn insert_snapshots<'a, S>(store: &S, collab: &ArchivedCollab) -> Result<usize, PersistenceError>
+where
+  S: KVStore<'a> + 'a,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  if collab.snapshots.is_empty() {
+    return Ok(0);
+  }
+  let snapshot_id = store.create_snapshot_id(collab.uid, &collab.object_id)?;
+  for snapshot in &collab.snapshots {
+    let key = create_update_key(
+      snapshot_id,
+      store,
+      &collab.object_id,
+      make_snapshot_update_key,
+    )?;
+    let value = seal_snapshot(
+      store,
+      collab.uid,
+      &collab.workspace_id,
+      &collab.object_id,
+      snapshot,
+    )?;
+    store.insert(key, value)?;
+  }
+  Ok(collab.snapshots.len())
+}
+
+fn io_error(err: std::io::Error) -> PersistenceError {
+  PersistenceError::Internal(err.into())
+}
@@ -0,0 +1,135 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+
+use collab::core::collab_compact::CompactionReport;
+
+/// Decides when the update log of a document is merged into its doc state, see
+/// [crate::local_storage::CollabPersistenceConfig::compaction].
+///
+/// Every update of a document is stored under its own key, and they are all applied when the
+/// document is loaded. Once a threshold is reached, the updates are merged into the doc state in
+/// the background, which keeps the loading time of the hot documents bounded.
+#[derive(Clone, Debug)]
+pub struct CompactionPolicy {
+  /// Compact when the document has at least this many updates. Default is 1000.
+  pub max_updates: usize,
+  /// Compact when the updates received since the document was opened, or last compacted, take at
+  /// least this many bytes. Default is 4MB.
+  pub max_update_bytes: usize,
+  /// After a failed compaction, retry once the document received this many more updates.
+  /// Default is 100.
+  pub retry_after_updates: usize,
+  metrics: Arc<CompactionMetrics>,
+}
+
+impl CompactionPolicy {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn max_updates(mut self, max_updates: usize) -> Self {
+    debug_assert!(max_updates > 0);
+    self.max_updates = max_updates;
+    self
+  }
+
+  pub fn max_update_bytes(mut self, max_update_bytes: usize) -> Self {
+    debug_assert!(max_update_bytes > 0);
+    self.max_update_bytes = max_update_bytes;
+    self
+  }
+
+  pub fn retry_after_updates(mut self, retry_after_updates: usize) -> Self {
+    self.retry_after_updates = retry_after_updates;
+    self
+  }
+
+  /// The metrics of the compactions run with this policy. They are shared by all the documents
+  /// opened with it.
+  pub fn metrics(&self) -> &Arc<CompactionMetrics> {
+    &self.metrics
+  }
+
+  pub(crate) fn should_compact(&self, updates: usize, update_bytes: usize) -> bool {
+    updates >= self.max_updates || update_bytes >= self.max_update_bytes
+  }
+}
+
+impl Default for CompactionPolicy {
+  fn default() -> Self {
+    Self {
+      max_updates: 1000,
+      max_update_bytes: 4 * 1024 * 1024,
+      retry_after_updates: 100,
+      metrics: Default::default(),
+    }
+  }
+}
+
+/// The result of [crate::local_storage::kv::doc::CollabKVAction::compact_updates].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpdateCompaction {
+  /// The number of updates merged into the doc state.
+  pub merged_updates: usize,
+  /// The size of the merged updates, before they were encrypted.
+  pub merged_update_bytes: usize,
+  pub report: CompactionReport,
+}
+
+#[derive(Debug, Default)]
+pub struct CompactionMetrics {
+  compactions: AtomicU64,
+  failures: AtomicU64,
+  merged_updates: AtomicU64,
+  bytes_before: AtomicU64,
+  bytes_after: AtomicU64,
+}
+
+impl CompactionMetrics {
+  pub(crate) fn record(&self, compaction: &UpdateCompaction) {
+    self.compactions.fetch_add(1, Relaxed);
+    self
+      .merged_updates
+      .fetch_add(compaction.merged_updates as u64, Relaxed);
+    self
+      .bytes_before
+      .fetch_add(compaction.report.before as u64, Relaxed);
+    self
+      .bytes_after
+      .fetch_add(compaction.report.after as u64, Relaxed);
+  }
+
+  pub(crate) fn record_failure(&self) {
+    self.failures.fetch_add(1, Relaxed);
+  }
+
+  pub fn stats(&self) -> CompactionStats {
+    CompactionStats {
+      compactions: self.compactions.load(Relaxed),
+      failures: self.failures.load(Relaxed),
+      merged_updates: self.merged_updates.load(Relaxed),
+      bytes_before: self.bytes_before.load(Relaxed),
+      bytes_after: self.bytes_after.load(Relaxed),
+    }
+  }
+}
+
+/// A snapshot of the [CompactionMetrics].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+  /// The number of successful compactions.
+  pub compactions: u64,
+  pub failures: u64,
+  pub merged_updates: u64,
+  /// The total size of the doc states and updates before they were compacted.
+  pub bytes_before: u64,
+  /// The total size of the compacted doc states.
+  pub bytes_after: u64,
+}
+
+impl CompactionStats {
+  pub fn saved_bytes(&self) -> u64 {
+    self.bytes_before.saturating_sub(self.bytes_after)
+  }
+}
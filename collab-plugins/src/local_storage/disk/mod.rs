@@ -0,0 +1,4 @@
+pub mod rocksdb_plugin;
+// pub mod snapshot_plugin;
+pub mod util;
+pub mod version_storage;
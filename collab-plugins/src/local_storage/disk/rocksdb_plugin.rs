@@ -0,0 +1,342 @@
+use crate::CollabKVDB;
+use crate::local_storage::CollabPersistenceConfig;
+use crate::local_storage::kv::KVTransactionDB;
+use crate::local_storage::kv::doc::CollabKVAction;
+
+use std::ops::Deref;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use collab::entity::EncodedCollab;
+use collab::preclude::{Collab, CollabPlugin};
+use collab_entity::CollabType;
+use tracing::{error, info, warn};
+
+use collab::core::collab_plugin::{AsyncCollabPlugin, CollabPluginType};
+use yrs::TransactionMut;
+
+pub trait RocksdbBackup: Send + Sync {
+  fn save_doc(&self, uid: i64, object_id: &str, data: EncodedCollab) -> Result<(), anyhow::Error>;
+  fn get_doc(&self, uid: i64, object_id: &str) -> Result<EncodedCollab, anyhow::Error>;
+}
+
+/// Persists the updates of a [Collab] in the [CollabKVDB].
+///
+/// Added as a [CollabPlugin], the updates are written on the editing thread. Wrap it in a
+/// [collab::core::collab_plugin::AsyncPluginAdapter] to write them on a blocking task instead,
+/// in the order they were committed.
+#[derive(Clone)]
+pub struct RocksdbDiskPlugin {
+  uid: i64,
+  #[allow(dead_code)]
+  workspace_id: String,
+  object_id: String,
+  collab_type: CollabType,
+  collab_db: Weak<CollabKVDB>,
+  did_init: Arc<AtomicBool>,
+  update_count: Arc<AtomicU32>,
+  /// The size of the updates received since the doc was opened, or last compacted.
+  update_bytes: Arc<AtomicUsize>,
+  compaction: Arc<CompactionTask>,
+  config: CollabPersistenceConfig,
+}
+
+impl Deref for RocksdbDiskPlugin {
+  type Target = Weak<CollabKVDB>;
+
+  fn deref(&self) -> &Self::Target {
+    &self.collab_db
+  }
+}
+
+impl RocksdbDiskPlugin {
+  pub fn new_with_config(
+    uid: i64,
+    workspace_id: String,
+    object_id: String,
+    collab_type: CollabType,
+    collab_db: Weak<CollabKVDB>,
+    config: CollabPersistenceConfig,
+  ) -> Self {
+    let update_count = Arc::new(AtomicU32::new(0));
+    let did_init = Arc::new(AtomicBool::new(false));
+    Self {
+      workspace_id,
+      object_id,
+      collab_type,
+      collab_db,
+      uid,
+      did_init,
+      update_count,
+      update_bytes: Default::default(),
+      compaction: Default::default(),
+      config,
+    }
+  }
+
+  pub fn new(
+    uid: i64,
+    workspace_id: String,
+    object_id: String,
+    collab_type: CollabType,
+    collab_db: Weak<CollabKVDB>,
+  ) -> Self {
+    Self::new_with_config(
+      uid,
+      workspace_id,
+      object_id,
+      collab_type,
+      collab_db,
+      CollabPersistenceConfig::default(),
+    )
+  }
+
+  fn increase_count(&self) {
+    let _update_count = self.update_count.fetch_add(1, SeqCst);
+  }
+
+  /// Blocks until the running compaction, if any, is done.
+  pub fn wait_for_compaction(&self) {
+    self.compaction.state.wait();
+  }
+
+  /// Merges the update log into the doc state once a threshold of the
+  /// [crate::local_storage::CompactionPolicy] is reached. The compaction runs on the blocking
+  /// threads of the current tokio runtime, or on the calling thread outside of a runtime. Only
+  /// one compaction of the doc runs at a time, and a failed one is only retried after
+  /// [crate::local_storage::CompactionPolicy::retry_after_updates] more updates.
+  fn compact_if_needed(&self, object_id: &str) {
+    let Some(policy) = self.config.compaction.clone() else {
+      return;
+    };
+    let update_count = self.update_count.load(SeqCst) as usize;
+    if !policy.should_compact(update_count, self.update_bytes.load(SeqCst)) {
+      return;
+    }
+    if update_count < self.compaction.state.retry_at.load(SeqCst) {
+      return;
+    }
+    if !self.compaction.state.start() {
+      return;
+    }
+
+    let uid = self.uid;
+    let workspace_id = self.workspace_id.clone();
+    let object_id = object_id.to_string();
+    let collab_db = self.collab_db.clone();
+    let update_count = self.update_count.clone();
+    let update_bytes = self.update_bytes.clone();
+    let state = self.compaction.state.clone();
+    let compact = move || {
+      // The db is only held while compacting, it may be closed before the task starts
+      if let Some(db) = collab_db.upgrade() {
+        let result =
+          db.with_write_txn(|w_db_txn| w_db_txn.compact_updates(uid, &workspace_id, &object_id));
+        match result {
+          Ok(compaction) => {
+            let merged = compaction.merged_updates as u32;
+            let _ =
+              update_count.fetch_update(SeqCst, SeqCst, |count| Some(count.saturating_sub(merged)));
+            // The updates pushed during the compaction are not merged, keep their size
+            let merged_bytes = compaction.merged_update_bytes;
+            let _ = update_bytes.fetch_update(SeqCst, SeqCst, |bytes| {
+              Some(bytes.saturating_sub(merged_bytes))
+            });
+            state.retry_at.store(0, SeqCst);
+            policy.metrics().record(&compaction);
+          },
+          Err(err) => {
+            let retry_at =
+              (update_count.load(SeqCst) as usize).saturating_add(policy.retry_after_updates);
+            state.retry_at.store(retry_at, SeqCst);
+            policy.metrics().record_failure();
+            error!(
+              "[Rocksdb Plugin]: compact updates of {} failed: {}",
+              object_id, err
+            );
+          },
+        }
+      }
+      state.finish();
+    };
+    match tokio::runtime::Handle::try_current() {
+      Ok(runtime) => {
+        runtime.spawn_blocking(compact);
+      },
+      Err(_) => compact(),
+    }
+  }
+
+  fn write_to_disk(&self, collab: &Collab) {
+    if let Some(collab_db) = self.collab_db.upgrade() {
+      let rocksdb_read = collab_db.read_txn();
+      if !rocksdb_read.is_exist(self.uid, &self.workspace_id, &self.object_id) {
+        // The schema doesn't check that the maps are shared types nor that the ids are not empty
+        let result = self
+          .collab_type
+          .validate_require_data(collab)
+          .and_then(|_| self.collab_type.validate_schema(collab));
+        match result {
+          Ok(_) => {
+            let txn = collab.transact();
+            if let Err(err) = collab_db.with_write_txn(|w_db_txn| {
+              w_db_txn.create_new_doc(self.uid, &self.workspace_id, &self.object_id, &txn)?;
+              info!(
+                "[Rocksdb Plugin]: created new doc {}, collab_type:{}",
+                self.object_id, self.collab_type
+              );
+              Ok(())
+            }) {
+              error!(
+                "[Rocksdb Plugin]: create doc:{} failed: {}",
+                self.object_id, err
+              );
+            }
+          },
+          Err(err) => {
+            warn!(
+              "[Rocksdb Plugin]: validate collab:{}, collab_type:{}, failed: {}",
+              self.object_id, self.collab_type, err
+            );
+          },
+        }
+      }
+    }
+  }
+
+  fn init_doc(&self, collab: &Collab) {
+    self.did_init.store(true, SeqCst);
+    self.write_to_disk(collab);
+    if self.config.compaction.is_some() {
+      if let Some(db) = self.collab_db.upgrade() {
+        let update_count =
+          db.read_txn()
+            .number_of_updates(self.uid, &self.workspace_id, &self.object_id);
+        self.update_count.store(update_count as u32, SeqCst);
+      }
+    }
+  }
+
+  fn push_update(&self, object_id: &str, update: &[u8]) {
+    // Only push update if the doc is loaded
+    if !self.did_init.load(SeqCst) {
+      return;
+    }
+    if let Some(db) = self.collab_db.upgrade() {
+      self.increase_count();
+      self.update_bytes.fetch_add(update.len(), SeqCst);
+      // Acquire a write transaction to ensure consistency. It's retried if it conflicts with
+      // a running compaction, or the update would be lost
+      let result = db.with_retried_write_txn(|w_db_txn| {
+        let _ = w_db_txn.push_update(self.uid, self.workspace_id.as_str(), object_id, update)?;
+        use yrs::updates::decoder::Decode;
+        tracing::trace!(
+          "[Rocksdb Plugin]: Collab {} {} persisting update: {:#?}",
+          object_id,
+          self.collab_type,
+          yrs::Update::decode_v1(update).unwrap()
+        );
+        Ok(())
+      });
+
+      match result {
+        Ok(_) => self.compact_if_needed(object_id),
+        Err(err) => error!(
+          "[Rocksdb Plugin]: {}:{} save update failed: {:?}",
+          object_id, self.collab_type, err
+        ),
+      }
+    } else {
+      tracing::warn!("[Rocksdb Plugin]: collab_db is dropped");
+    };
+  }
+}
+
+/// Whether a compaction of the doc is running.
+#[derive(Default)]
+struct CompactionState {
+  running: Mutex<bool>,
+  done: Condvar,
+  /// The number of updates from which a compaction is retried after a failure.
+  retry_at: AtomicUsize,
+}
+
+impl CompactionState {
+  /// Returns false if a compaction is already running.
+  fn start(&self) -> bool {
+    let mut running = self.running.lock().unwrap();
+    !std::mem::replace(&mut *running, true)
+  }
+
+  fn finish(&self) {
+    *self.running.lock().unwrap() = false;
+    self.done.notify_all();
+  }
+
+  fn wait(&self) {
+    let mut running = self.running.lock().unwrap();
+    while *running {
+      running = self.done.wait(running).unwrap();
+    }
+  }
+}
+
+/// Shared by the clones of a [RocksdbDiskPlugin]. Dropped with the last one, it waits for the
+/// running compaction so that it doesn't outlive the plugin. A thread of a tokio runtime is never
+/// blocked: the compaction keeps running on its blocking thread, and
+/// [AsyncCollabPlugin::flush] is the way to wait for it.
+#[derive(Default)]
+struct CompactionTask {
+  state: Arc<CompactionState>,
+}
+
+impl Drop for CompactionTask {
+  fn drop(&mut self) {
+    if tokio::runtime::Handle::try_current().is_err() {
+      self.state.wait();
+    }
+  }
+}
+
+impl CollabPlugin for RocksdbDiskPlugin {
+  fn did_init(&self, collab: &Collab, _object_id: &str) {
+    self.init_doc(collab);
+  }
+
+  fn receive_update(&self, object_id: &str, _txn: &TransactionMut, update: &[u8]) {
+    self.push_update(object_id, update);
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::Other("RocksdbDiskPlugin".to_string())
+  }
+}
+
+#[async_trait]
+impl AsyncCollabPlugin for RocksdbDiskPlugin {
+  fn did_init(&self, collab: &Collab, _object_id: &str) {
+    self.init_doc(collab);
+  }
+
+  async fn receive_update(&self, object_id: &str, update: Bytes) {
+    let plugin = self.clone();
+    let object_id = object_id.to_string();
+    // Awaited, so the updates are written in order
+    let result = tokio::task::spawn_blocking(move || plugin.push_update(&object_id, &update)).await;
+    if let Err(err) = result {
+      error!("[Rocksdb Plugin]: save update failed: {}", err);
+    }
+  }
+
+  async fn flush(&self) {
+    let plugin = self.clone();
+    let _ = tokio::task::spawn_blocking(move || plugin.wait_for_compaction()).await;
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::Other("RocksdbDiskPlugin".to_string())
+  }
+}
@@ -0,0 +1,68 @@
+use crate::CollabKVDB;
+use crate::local_storage::kv::KVTransactionDB;
+use crate::local_storage::kv::version::VersionAction;
+use anyhow::anyhow;
+use collab::core::collab_version::{CollabVersion, CollabVersionStorage};
+use collab::error::CollabError;
+use std::sync::Weak;
+
+/// Stores the named versions of the collabs in the [CollabKVDB], so the version history is
+/// available offline.
+pub struct RocksdbVersionStorage {
+  uid: i64,
+  workspace_id: String,
+  collab_db: Weak<CollabKVDB>,
+}
+
+impl RocksdbVersionStorage {
+  pub fn new(uid: i64, workspace_id: String, collab_db: Weak<CollabKVDB>) -> Self {
+    Self {
+      uid,
+      workspace_id,
+      collab_db,
+    }
+  }
+
+  fn collab_db(&self) -> Result<std::sync::Arc<CollabKVDB>, CollabError> {
+    self
+      .collab_db
+      .upgrade()
+      .ok_or_else(|| CollabError::Internal(anyhow!("collab_db is dropped")))
+  }
+}
+
+impl CollabVersionStorage for RocksdbVersionStorage {
+  fn save_version(&self, object_id: &str, version: &CollabVersion) -> Result<(), CollabError> {
+    self
+      .collab_db()?
+      .with_write_txn(|txn| txn.insert_version(self.uid, &self.workspace_id, object_id, version))
+      .map_err(|err| CollabError::Internal(err.into()))
+  }
+
+  fn get_version(
+    &self,
+    object_id: &str,
+    version_id: &str,
+  ) -> Result<Option<CollabVersion>, CollabError> {
+    self
+      .collab_db()?
+      .read_txn()
+      .get_version(self.uid, &self.workspace_id, object_id, version_id)
+      .map_err(|err| CollabError::Internal(err.into()))
+  }
+
+  fn list_versions(&self, object_id: &str) -> Result<Vec<CollabVersion>, CollabError> {
+    self
+      .collab_db()?
+      .read_txn()
+      .get_versions(self.uid, &self.workspace_id, object_id)
+      .map_err(|err| CollabError::Internal(err.into()))
+  }
+
+  fn delete_version(&self, object_id: &str, version_id: &str) -> Result<(), CollabError> {
+    self
+      .collab_db()?
+      .with_write_txn(|txn| txn.delete_version(self.uid, &self.workspace_id, object_id, version_id))
+      .map_err(|err| CollabError::Internal(err.into()))
+  }
+}
@@ -4,9 +4,9 @@ use std::ops::RangeBounds;
 use std::sync::Arc;
 
 use crate::local_storage::kv::PersistenceError;
+use crate::local_storage::kv::encryption::CollabCipher;
 use crate::local_storage::kv::keys::*;
 use crate::local_storage::kv::oid::{DocIDGen, OID};
-use crate::local_storage::kv::snapshot::CollabSnapshot;
 use smallvec::SmallVec;
 use yrs::{TransactionMut, Update};
 
@@ -28,9 +28,34 @@ pub trait KVTransactionDB: Send + Sync + 'static {
   where
     'b: 'a;
 
+  /// Like [KVTransactionDB::with_write_txn], but `f` runs again in a new transaction when the
+  /// commit conflicts with another transaction, e.g. a compaction of the same doc. It gives up
+  /// after [MAX_WRITE_ATTEMPTS] attempts.
+  fn with_retried_write_txn<'a, 'b, Output>(
+    &'b self,
+    f: impl Fn(&Self::TransactionAction<'a>) -> Result<Output, PersistenceError>,
+  ) -> Result<Output, PersistenceError>
+  where
+    'b: 'a,
+  {
+    let mut attempts = 1;
+    loop {
+      match self.with_write_txn(&f) {
+        Err(err) if err.is_write_conflict() && attempts < MAX_WRITE_ATTEMPTS => {
+          tracing::debug!("retry the write transaction: {}", err);
+          attempts += 1;
+        },
+        result => return result,
+      }
+    }
+  }
+
   fn flush(&self) -> Result<(), PersistenceError>;
 }
 
+/// The number of times [KVTransactionDB::with_retried_write_txn] runs a conflicting transaction.
+pub const MAX_WRITE_ATTEMPTS: usize = 5;
+
 pub trait KVStore<'a> {
   type Range: Iterator<Item = Self::Entry>;
   type Entry: KVEntry;
@@ -55,6 +80,11 @@ pub trait KVStore<'a> {
 
   /// Return the entry prior to the given key
   fn next_back_entry(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error>;
+
+  /// Return the cipher used to encrypt the documents, see [crate::local_storage::kv::encryption].
+  fn cipher(&self) -> Option<&CollabCipher> {
+    None
+  }
 }
 
 impl<T> KVStore<'static> for Arc<T>
@@ -89,22 +119,27 @@ where
   fn next_back_entry(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
     (**self).next_back_entry(key)
   }
+
+  fn cipher(&self) -> Option<&CollabCipher> {
+    (**self).cipher()
+  }
 }
 
+/// Inserts the stored value of a snapshot, see
+/// [crate::local_storage::kv::snapshot::SnapshotAction::create_snapshot_with_data].
 pub fn insert_snapshot_update<'a, K, S>(
   store: &S,
   snapshot_id: SnapshotID,
   object_id: &K,
-  data: Vec<u8>,
+  value: Vec<u8>,
 ) -> Result<(), PersistenceError>
 where
   K: AsRef<[u8]> + ?Sized + Debug,
   S: KVStore<'a>,
   PersistenceError: From<<S as KVStore<'a>>::Error>,
 {
-  let snapshot = CollabSnapshot::new(data).to_vec();
   let update_key = create_update_key(snapshot_id, store, object_id, make_snapshot_update_key)?;
-  store.insert(update_key, snapshot)?;
+  store.insert(update_key, value)?;
   Ok(())
 }
 
@@ -143,7 +178,7 @@ where
   Ok(make_update_key(id, last_clock))
 }
 
-fn create_update_key<'a, F, K, S>(
+pub(crate) fn create_update_key<'a, F, K, S>(
   id: OID,
   store: &S,
   _object_id: &K,
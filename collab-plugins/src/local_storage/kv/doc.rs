@@ -1,6 +1,12 @@
+use crate::local_storage::UpdateCompaction;
+use crate::local_storage::kv::encryption::{ValueKind, ValueScope, decrypt_value, encrypt_value};
 use crate::local_storage::kv::keys::*;
-use crate::local_storage::kv::snapshot::SnapshotAction;
+use crate::local_storage::kv::snapshot::{SnapshotAction, get_snapshot_id};
+use crate::local_storage::kv::version::{VersionAction, get_version_space_id};
 use crate::local_storage::kv::*;
+use collab::core::collab::{default_client_id, make_yrs_doc};
+use collab::core::collab_compact::{CompactedCollab, CompactionReport, compact_doc_state};
+use collab::error::CollabError;
 use smallvec::{SmallVec, smallvec};
 use std::collections::HashSet;
 use tracing::{error, info};
@@ -32,6 +38,11 @@ where
     let sv_key = make_state_vector_key(doc_id);
 
     info!("new doc:{:?}, doc state len:{}", object_id, doc_state.len());
+    let doc_state = encrypt_value(
+      self.cipher(),
+      &ValueScope::new(uid, workspace_id, object_id, ValueKind::DocState),
+      &doc_state,
+    )?;
     self.insert(doc_state_key, doc_state)?;
     self.insert(sv_key, sv)?;
 
@@ -55,6 +66,11 @@ where
     let sv_key = make_state_vector_key(doc_id);
 
     info!("new doc:{:?}, doc state len:{}", object_id, doc_state.len());
+    let doc_state = encrypt_value(
+      self.cipher(),
+      &ValueScope::new(uid, workspace_id, object_id, ValueKind::DocState),
+      &doc_state,
+    )?;
     self.insert(doc_state_key, doc_state)?;
     self.insert(sv_key, state_vector)?;
 
@@ -86,11 +102,224 @@ where
     let doc_state_key = make_doc_state_key(doc_id);
     let sv_key = make_state_vector_key(doc_id);
     // Insert new doc state and state vector
+    let doc_state = encrypt_value(
+      self.cipher(),
+      &ValueScope::new(uid, workspace_id, object_id, ValueKind::DocState),
+      &doc_state,
+    )?;
     self.insert(doc_state_key, doc_state)?;
     self.insert(sv_key, state_vector)?;
     Ok(())
   }
 
+  /// Compacts the stored document: its doc state and updates are replaced by a single doc state
+  /// with garbage collection applied, see
+  /// [collab::preclude::Collab::compact]. Call it on a write transaction, e.g.
+  /// within [KVTransactionDB::with_write_txn], for the updates to be replaced atomically, then
+  /// reopen the live collab from the returned [CompactedCollab::encoded_collab].
+  ///
+  /// The named versions of the document need the deleted items, so
+  /// [CollabError::CompactionWithVersions] is returned if the document has any. Use
+  /// [CollabKVAction::compact_updates] to shrink the update log of a document with versions.
+  fn compact_doc(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &str,
+  ) -> Result<CompactedCollab, PersistenceError> {
+    let doc_id = get_doc_id(uid, self, workspace_id, object_id).ok_or_else(|| {
+      PersistenceError::RecordNotFound(format!(
+        "doc with given object id: {:?} is not found",
+        object_id
+      ))
+    })?;
+    if self.has_versions(uid, workspace_id, object_id)? {
+      return Err(CollabError::CompactionWithVersions(object_id.to_string()).into());
+    }
+    let start = make_doc_start_key(doc_id);
+    let end = make_doc_end_key(doc_id);
+    let before = self
+      .range(start.as_ref()..end.as_ref())?
+      .map(|entry| entry.value().len())
+      .sum();
+
+    let doc = Doc::new();
+    self.load_doc(uid, workspace_id, object_id, &doc)?;
+    let encoded_collab = compact_doc_state(&doc.transact())?.encoded_collab;
+    let after = encoded_collab.doc_state.len() + encoded_collab.state_vector.len();
+    self.flush_doc(
+      uid,
+      workspace_id,
+      object_id,
+      encoded_collab.state_vector.to_vec(),
+      encoded_collab.doc_state.to_vec(),
+    )?;
+    info!(
+      "compacted doc:{:?}, size: {} -> {}",
+      object_id, before, after
+    );
+    Ok(CompactedCollab {
+      encoded_collab,
+      report: CompactionReport { before, after },
+    })
+  }
+
+  /// Merges the updates of the document into its doc state, then deletes them with
+  /// [CollabKVAction::delete_updates_to]. Unlike [CollabKVAction::compact_doc], the content of the
+  /// deleted items is kept, so the versions of the document can still be restored.
+  ///
+  /// Call it on a write transaction, e.g. within [KVTransactionDB::with_write_txn], for the doc
+  /// state to be replaced atomically. The updates pushed by other transactions in the meantime
+  /// are not merged, and kept.
+  fn compact_updates(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &str,
+  ) -> Result<UpdateCompaction, PersistenceError> {
+    let not_found = || {
+      PersistenceError::RecordNotFound(format!(
+        "doc with given object id: {:?} is not found",
+        object_id
+      ))
+    };
+    let doc_id = get_doc_id(uid, self, workspace_id, object_id).ok_or_else(not_found)?;
+    let doc_state_key = make_doc_state_key(doc_id);
+    let doc_state = self.get(doc_state_key.as_ref())?.ok_or_else(not_found)?;
+    let start = make_doc_update_key(doc_id, 0);
+    let end = make_doc_update_key(doc_id, Clock::MAX);
+    let updates = self
+      .range(start.as_ref()..end.as_ref())?
+      .collect::<Vec<_>>();
+    let Some(last_update) = updates.last() else {
+      return Ok(UpdateCompaction::default());
+    };
+
+    // Keep the deleted items, the client id is never used as the doc is only read
+    let doc = make_yrs_doc(object_id, true, default_client_id());
+    let mut before = doc_state.as_ref().len();
+    let mut merged_update_bytes = 0;
+    {
+      let mut txn = doc.transact_mut();
+      let doc_state = decrypt_value(
+        self.cipher(),
+        &ValueScope::new(uid, workspace_id, object_id, ValueKind::DocState),
+        doc_state.as_ref(),
+      )?;
+      txn.try_apply_update(Update::decode_v1(&doc_state)?)?;
+      for encoded_update in &updates {
+        before += encoded_update.value().len();
+        let update = decrypt_value(
+          self.cipher(),
+          &ValueScope::new(uid, workspace_id, object_id, ValueKind::Update),
+          encoded_update.value(),
+        )?;
+        merged_update_bytes += update.len();
+        txn.try_apply_update(Update::decode_v1(&update)?)?;
+      }
+    }
+
+    let txn = doc.transact();
+    let doc_state = txn.encode_state_as_update_v1(&StateVector::default());
+    let doc_state = encrypt_value(
+      self.cipher(),
+      &ValueScope::new(uid, workspace_id, object_id, ValueKind::DocState),
+      &doc_state,
+    )?;
+    let sv = txn.state_vector().encode_v1();
+    let after = doc_state.len() + sv.len();
+    self.insert(doc_state_key, doc_state)?;
+    self.insert(make_state_vector_key(doc_id), sv)?;
+
+    // Only the merged updates are deleted, up to the last one included
+    let clock = Clock::from_be_bytes(clock_from_key(last_update.key()).try_into().unwrap());
+    let merged_end = make_doc_update_key(doc_id, clock + 1);
+    self.delete_updates_to(uid, workspace_id, object_id, merged_end.as_ref())?;
+    info!(
+      "merged {} updates of doc:{:?}, size: {} -> {}",
+      updates.len(),
+      object_id,
+      before,
+      after
+    );
+    Ok(UpdateCompaction {
+      merged_updates: updates.len(),
+      merged_update_bytes,
+      report: CompactionReport { before, after },
+    })
+  }
+
+  /// Re-encrypts the doc state, the updates, the snapshots and the versions of the document that
+  /// are not sealed with the current key of the workspace, see
+  /// [crate::local_storage::kv::encryption]. Call it on a write transaction after a key rotation,
+  /// before the previous key is discarded.
+  ///
+  /// Return the number of re-encrypted values.
+  fn rotate_doc_key(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &str,
+  ) -> Result<usize, PersistenceError> {
+    let cipher = self.cipher().ok_or_else(|| {
+      PersistenceError::MissingEncryptionKey("the store has no cipher".to_string())
+    })?;
+    let Some(doc_id) = get_doc_id(uid, self, workspace_id, object_id) else {
+      return Ok(0);
+    };
+
+    let mut stale = vec![];
+    let doc_state_key = make_doc_state_key(doc_id);
+    if let Some(doc_state) = self.get(doc_state_key.as_ref())? {
+      if cipher.needs_rotation(workspace_id, doc_state.as_ref()) {
+        let value = doc_state.as_ref().to_vec();
+        stale.push((doc_state_key.to_vec(), value, ValueKind::DocState));
+      }
+    }
+    let start = make_doc_update_key(doc_id, 0);
+    let end = make_doc_update_key(doc_id, Clock::MAX);
+    let mut ranges = vec![(start.to_vec(), end.to_vec(), ValueKind::Update)];
+    if let Some(snapshot_id) = get_snapshot_id(uid, self, object_id) {
+      let start = make_snapshot_update_key(snapshot_id, 0);
+      let end = make_snapshot_update_key(snapshot_id, Clock::MAX);
+      ranges.push((start.to_vec(), end.to_vec(), ValueKind::Snapshot));
+    }
+    if let Some(version_space_id) = get_version_space_id(uid, self, workspace_id, object_id) {
+      let start = make_version_key(version_space_id, 0);
+      let end = make_version_key(version_space_id, Clock::MAX);
+      ranges.push((start.to_vec(), end.to_vec(), ValueKind::Version));
+    }
+    for (start, end, kind) in ranges {
+      for entry in self.range(start.as_slice()..end.as_slice())? {
+        if cipher.needs_rotation(workspace_id, entry.value()) {
+          stale.push((entry.key().to_vec(), entry.value().to_vec(), kind));
+        }
+      }
+    }
+
+    for (key, value, kind) in &stale {
+      let scope = ValueScope::new(uid, workspace_id, object_id, *kind);
+      let plaintext = cipher.decrypt(&scope, value)?;
+      self.insert(key, cipher.encrypt(&scope, &plaintext)?)?;
+    }
+    if !stale.is_empty() {
+      info!("re-encrypted {} values of doc:{:?}", stale.len(), object_id);
+    }
+    Ok(stale.len())
+  }
+
+  /// Re-encrypts all the documents of the workspace, see [CollabKVAction::rotate_doc_key].
+  fn rotate_workspace_key(&self, uid: i64, workspace_id: &str) -> Result<usize, PersistenceError> {
+    let object_ids = self
+      .get_all_object_ids(uid, workspace_id)?
+      .collect::<Vec<_>>();
+    let mut count = 0;
+    for object_id in object_ids {
+      count += self.rotate_doc_key(uid, workspace_id, &object_id)?;
+    }
+    Ok(count)
+  }
+
   fn is_exist(&self, uid: i64, workspace_id: &str, object_id: &str) -> bool {
     get_doc_id(uid, self, workspace_id, object_id).is_some()
   }
@@ -114,8 +343,12 @@ where
       let doc_state_key = make_doc_state_key(doc_id);
       if let Some(doc_state) = self.get(doc_state_key.as_ref())? {
         // Load the doc state
-
-        match Update::decode_v1(doc_state.as_ref()) {
+        let doc_state = decrypt_value(
+          self.cipher(),
+          &ValueScope::new(uid, workspace_id, object_id, ValueKind::DocState),
+          doc_state.as_ref(),
+        )?;
+        match Update::decode_v1(&doc_state) {
           Ok(update) => {
             txn.try_apply_update(update)?;
           },
@@ -132,9 +365,16 @@ where
         // Load the updates
         let encoded_updates = self.range(update_start.as_ref()..update_end.as_ref())?;
         for encoded_update in encoded_updates {
+          // A missing key is not a corruption, so the error is returned before the update is
+          // checked.
+          let update = decrypt_value(
+            self.cipher(),
+            &ValueScope::new(uid, workspace_id, object_id, ValueKind::Update),
+            encoded_update.value(),
+          )?;
           // Decode the update and apply it to the transaction. If the update is invalid, we will
           // remove the update and the following updates.
-          if let Err(e) = Update::decode_v1(encoded_update.value())
+          if let Err(e) = Update::decode_v1(&update)
             .map_err(PersistenceError::Yrs)
             .and_then(|update| {
               // trace!("apply update: {:#?}", update);
@@ -196,7 +436,14 @@ where
           object_id
         )))
       },
-      Some(doc_id) => insert_doc_update(self, doc_id, object_id, update.to_vec()),
+      Some(doc_id) => {
+        let update = encrypt_value(
+          self.cipher(),
+          &ValueScope::new(uid, workspace_id, object_id, ValueKind::Update),
+          update,
+        )?;
+        insert_doc_update(self, doc_id, object_id, update)
+      },
     }
   }
 
@@ -246,6 +493,11 @@ where
     let sv_key = make_state_vector_key(doc_id);
 
     // Insert new doc state and state vector
+    let doc_state = encrypt_value(
+      self.cipher(),
+      &ValueScope::new(uid, workspace_id, object_id, ValueKind::DocState),
+      &doc_state,
+    )?;
     self.insert(doc_state_key, doc_state)?;
     self.insert(sv_key, sv)?;
     Ok(())
@@ -261,9 +513,10 @@ where
       let start = make_doc_update_key(doc_id, 0);
       let end = make_doc_update_key(doc_id, Clock::MAX);
       let range = self.range(start.as_ref()..end.as_ref())?;
+      let scope = ValueScope::new(uid, workspace_id, object_id, ValueKind::Update);
       let mut updates = vec![];
       for update in range {
-        updates.push(update.value().to_vec());
+        updates.push(decrypt_value(self.cipher(), &scope, update.value())?);
       }
       Ok(updates)
     } else {
@@ -301,6 +554,9 @@ where
 
       // Delete the snapshot
       self.delete_all_snapshots(uid, object_id)?;
+
+      // Delete the named versions
+      self.delete_all_versions(uid, workspace_id, object_id)?;
     }
     Ok(())
   }
@@ -329,8 +585,10 @@ where
     from_vec.extend_from_slice(workspace_bytes);
     let from = Key(from_vec);
 
-    // Construct the `to` key by appending 0xFF to cover the full range of keys with the same prefix
-    let to_vec: SmallVec<[u8; 24]> = smallvec![DOC_SPACE, DOC_SPACE_OBJECT_KEY];
+    // Construct the `to` key by appending 0xFF to cover the full range of keys with the same prefix.
+    // The object ids are utf8, so they never start with 0xFF
+    let mut to_vec = from.0.clone();
+    to_vec.push(TERMINATOR_HI_WATERMARK);
     let to = Key(to_vec);
 
     let iter = self.range(from.as_ref()..to.as_ref())?;
@@ -341,6 +599,10 @@ where
     }))
   }
 
+  /// Returns the workspace ids of the docs of every user. Only the uuid workspace ids are
+  /// returned: the key of a doc doesn't separate the workspace id from the object id, so another
+  /// workspace id can't be read back, and the keys of the v0 layout have no workspace id. The
+  /// objects of such a workspace are still returned by [Self::get_all_object_ids].
   fn get_all_workspace_ids(&self) -> Result<Vec<String>, PersistenceError> {
     let from = Key::from_const([DOC_SPACE, DOC_SPACE_OBJECT]);
     let to = Key::from_const([DOC_SPACE, DOC_SPACE_OBJECT_KEY]);
@@ -350,8 +612,8 @@ where
     // Iterate over the keys and extract workspace IDs
     for entry in iter {
       let key_bytes = entry.key();
-      if let Some(workspace_id) = extract_uuid_from_key(key_bytes) {
-        workspace_ids.insert(Uuid::from_bytes(workspace_id).to_string());
+      if let Some(workspace_id) = extract_workspace_id_from_key(key_bytes) {
+        workspace_ids.insert(workspace_id.to_string());
       }
     }
 
@@ -372,7 +634,12 @@ where
       let mut updates = vec![];
       if let Ok(encoded_updates) = self.range(start.as_ref()..=end.as_ref()) {
         for encoded_update in encoded_updates {
-          updates.push(Update::decode_v1(encoded_update.value())?);
+          let update = decrypt_value(
+            self.cipher(),
+            &ValueScope::new(uid, workspace_id, object_id, ValueKind::Update),
+            encoded_update.value(),
+          )?;
+          updates.push(Update::decode_v1(&update)?);
         }
       }
       Ok(updates)
@@ -478,20 +745,25 @@ where
     Some(String::from_utf8_lossy(content).to_string())
   }
 }
-fn extract_uuid_from_key(key: &[u8]) -> Option<[u8; 16]> {
-  // Start index is set to skip DOC_SPACE and DOC_SPACE_OBJECT (2 bytes)
-  let start_index = 2;
-  let end_index = start_index + 16;
-  // Ensure the key has enough length for extracting a 16-byte UUID
-  if key.len() >= end_index {
-    let mut uuid_bytes = [0u8; 16];
-    uuid_bytes.copy_from_slice(&key[start_index..end_index]);
-    Some(uuid_bytes)
-  } else {
-    None
+/// Extract the workspace id from a v1 doc id key: `[DOC_SPACE, DOC_SPACE_OBJECT, uid (8 bytes),
+/// workspace id, object id, TERMINATOR]`. The workspace id is a uuid string, which is what
+/// separates it from the object id.
+pub(crate) fn extract_workspace_id_from_key(key: &[u8]) -> Option<&str> {
+  // Skip DOC_SPACE, DOC_SPACE_OBJECT and the uid
+  let start_index = 2 + 8;
+  let end_index = start_index + UUID_STR_LEN;
+  // The object id can't be empty, so a v0 key whose object id is a uuid is skipped
+  if key.len() <= end_index + 1 {
+    return None;
   }
+  let workspace_id = std::str::from_utf8(&key[start_index..end_index]).ok()?;
+  Uuid::parse_str(workspace_id).ok()?;
+  Some(workspace_id)
 }
 
+/// The length of a hyphenated uuid string.
+const UUID_STR_LEN: usize = 36;
+
 pub fn migrate_old_keys<'a, S>(store: &'a S, workspace_id: &str) -> Result<(), PersistenceError>
 where
   S: KVStore<'a>,
@@ -0,0 +1,285 @@
+//! Encryption at rest of the stored documents.
+//!
+//! When a [CollabCipher] is attached to the store, e.g. with [crate::CollabKVDB::with_cipher], the
+//! doc states and the updates written by [crate::local_storage::kv::doc::CollabKVAction], the
+//! snapshots and the named versions are sealed with XChaCha20-Poly1305, using the key of their
+//! workspace. The [ValueScope] of a value is bound to its ciphertext, so a value copied to another
+//! document, user or workspace, or read as another kind of value, can't be opened.
+//!
+//! A sealed value starts with a header that records the id of its key:
+//! `MAGIC | VERSION | key id (u32, big endian) | nonce (24 bytes) | ciphertext`. New values are
+//! always sealed with the current key of the workspace, while the values sealed with an older key
+//! can still be opened as long as the [EncryptionKeyProvider] knows it. Rotating a key is done by
+//! making a new key current, then re-encrypting the stored values with
+//! [crate::local_storage::kv::doc::CollabKVAction::rotate_workspace_key]. The
+//! [crate::local_storage::kv::key_file::LocalKeyFile] provider keeps the keys in a local file.
+//!
+//! A value that is not sealed is rejected, otherwise anyone with access to the disk could replace
+//! a sealed value with a plaintext one. The values stored before the encryption was enabled are
+//! migrated with a cipher created with [CollabCipher::allow_plaintext], which reads them as they
+//! are until they are sealed by the rotation. The state vectors are kept in plaintext: they only
+//! contain client ids and clocks.
+
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+
+use crate::local_storage::kv::PersistenceError;
+
+pub type KeyId = u32;
+
+pub const KEY_LEN: usize = 32;
+const MAGIC: [u8; 3] = [0xC0, 0x11, 0xAB];
+const VERSION: u8 = 1;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + 1 + size_of::<KeyId>() + NONCE_LEN;
+
+/// A 256-bit key. Its bytes are never printed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptionKey([u8; KEY_LEN]);
+
+impl EncryptionKey {
+  /// Generates a random key with the OS random number generator.
+  pub fn generate() -> Self {
+    let mut bytes = [0; KEY_LEN];
+    bytes.copy_from_slice(&XChaCha20Poly1305::generate_key(&mut OsRng));
+    Self(bytes)
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<Self, PersistenceError> {
+    let bytes = bytes.try_into().map_err(|_| {
+      PersistenceError::InvalidData(format!(
+        "encryption key must be {} bytes, got {}",
+        KEY_LEN,
+        bytes.len()
+      ))
+    })?;
+    Ok(Self(bytes))
+  }
+
+  pub fn as_bytes(&self) -> &[u8; KEY_LEN] {
+    &self.0
+  }
+}
+
+impl Debug for EncryptionKey {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.write_str("EncryptionKey(..)")
+  }
+}
+
+/// The kind of a sealed value, see [ValueScope].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ValueKind {
+  DocState = 0,
+  Update = 1,
+  Snapshot = 2,
+  Version = 3,
+}
+
+/// Identifies a stored value. The scope is the associated data of the ciphertext: a value can
+/// only be opened with the scope it was sealed with.
+#[derive(Debug, Clone, Copy)]
+pub struct ValueScope<'a> {
+  pub uid: i64,
+  pub workspace_id: &'a str,
+  pub object_id: &'a [u8],
+  pub kind: ValueKind,
+}
+
+impl<'a> ValueScope<'a> {
+  pub fn new<K: AsRef<[u8]> + ?Sized>(
+    uid: i64,
+    workspace_id: &'a str,
+    object_id: &'a K,
+    kind: ValueKind,
+  ) -> Self {
+    Self {
+      uid,
+      workspace_id,
+      object_id: object_id.as_ref(),
+      kind,
+    }
+  }
+
+  /// `kind | uid | workspace id length | workspace id | object id`, the lengths and the uid are
+  /// big endian.
+  fn associated_data(&self) -> Vec<u8> {
+    let workspace_id = self.workspace_id.as_bytes();
+    let mut aad =
+      Vec::with_capacity(1 + 2 * size_of::<u64>() + workspace_id.len() + self.object_id.len());
+    aad.push(self.kind as u8);
+    aad.extend_from_slice(&self.uid.to_be_bytes());
+    aad.extend_from_slice(&(workspace_id.len() as u64).to_be_bytes());
+    aad.extend_from_slice(workspace_id);
+    aad.extend_from_slice(self.object_id);
+    aad
+  }
+}
+
+/// Provides the keys of the workspaces, e.g. from the OS keychain or a key management service.
+pub trait EncryptionKeyProvider: Send + Sync {
+  /// Returns the key used to seal the new values of the workspace, with its id.
+  fn current_key(&self, workspace_id: &str) -> Option<(KeyId, EncryptionKey)>;
+
+  /// Returns the key with the given id, which might not be the current one.
+  fn key(&self, workspace_id: &str, key_id: KeyId) -> Option<EncryptionKey>;
+}
+
+/// Seals and opens the stored values with the keys of an [EncryptionKeyProvider].
+#[derive(Clone)]
+pub struct CollabCipher {
+  provider: Arc<dyn EncryptionKeyProvider>,
+  allow_plaintext: bool,
+}
+
+impl CollabCipher {
+  pub fn new<P: EncryptionKeyProvider + 'static>(provider: P) -> Self {
+    Self::from_provider(Arc::new(provider))
+  }
+
+  pub fn from_provider(provider: Arc<dyn EncryptionKeyProvider>) -> Self {
+    Self {
+      provider,
+      allow_plaintext: false,
+    }
+  }
+
+  /// Reads the values that are not sealed as they are. Only use it to migrate a store written
+  /// before the encryption was enabled: seal its values with
+  /// [crate::local_storage::kv::doc::CollabKVAction::rotate_workspace_key], then reopen the store
+  /// with a cipher that rejects them.
+  pub fn allow_plaintext(mut self) -> Self {
+    self.allow_plaintext = true;
+    self
+  }
+
+  /// Seals the value with the current key of the workspace of the scope.
+  pub fn encrypt(&self, scope: &ValueScope, plaintext: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+    let workspace_id = scope.workspace_id;
+    let (key_id, key) = self
+      .provider
+      .current_key(workspace_id)
+      .ok_or_else(|| missing_key(workspace_id, None))?;
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let aad = scope.associated_data();
+    let payload = Payload {
+      msg: plaintext,
+      aad: &aad,
+    };
+    let ciphertext = XChaCha20Poly1305::new(Key::from_slice(key.as_bytes()))
+      .encrypt(&nonce, payload)
+      .map_err(|_| {
+        PersistenceError::Encryption(format!("failed to seal a value of {}", workspace_id))
+      })?;
+
+    let mut sealed = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    sealed.extend_from_slice(&MAGIC);
+    sealed.push(VERSION);
+    sealed.extend_from_slice(&key_id.to_be_bytes());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+  }
+
+  /// Opens a value sealed with the same scope. The values that are not sealed are rejected, unless
+  /// the cipher was created with [CollabCipher::allow_plaintext].
+  pub fn decrypt(&self, scope: &ValueScope, data: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+    let workspace_id = scope.workspace_id;
+    let Some(key_id) = sealed_key_id(data) else {
+      if self.allow_plaintext {
+        return Ok(data.to_vec());
+      }
+      return Err(PersistenceError::Encryption(format!(
+        "a value of {} is not sealed",
+        workspace_id
+      )));
+    };
+    let key = self
+      .provider
+      .key(workspace_id, key_id)
+      .ok_or_else(|| missing_key(workspace_id, Some(key_id)))?;
+    let nonce = XNonce::from_slice(&data[HEADER_LEN - NONCE_LEN..HEADER_LEN]);
+    let aad = scope.associated_data();
+    let payload = Payload {
+      msg: &data[HEADER_LEN..],
+      aad: &aad,
+    };
+    XChaCha20Poly1305::new(Key::from_slice(key.as_bytes()))
+      .decrypt(nonce, payload)
+      .map_err(|_| {
+        PersistenceError::Encryption(format!(
+          "failed to open a value of {} with key {}: wrong key, wrong scope or corrupted data",
+          workspace_id, key_id
+        ))
+      })
+  }
+
+  /// Returns true if the value is not sealed with the current key of the workspace.
+  pub fn needs_rotation(&self, workspace_id: &str, data: &[u8]) -> bool {
+    let current = self.provider.current_key(workspace_id).map(|(id, _)| id);
+    sealed_key_id(data) != current
+  }
+}
+
+impl Debug for CollabCipher {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.write_str("CollabCipher")
+  }
+}
+
+/// Returns true if the value was sealed by a [CollabCipher].
+pub fn is_encrypted(data: &[u8]) -> bool {
+  sealed_key_id(data).is_some()
+}
+
+fn sealed_key_id(data: &[u8]) -> Option<KeyId> {
+  if data.len() < HEADER_LEN || data[..MAGIC.len()] != MAGIC || data[MAGIC.len()] != VERSION {
+    return None;
+  }
+  let start = MAGIC.len() + 1;
+  let bytes = data[start..start + size_of::<KeyId>()].try_into().ok()?;
+  Some(KeyId::from_be_bytes(bytes))
+}
+
+fn missing_key(workspace_id: &str, key_id: Option<KeyId>) -> PersistenceError {
+  let msg = match key_id {
+    None => format!("no encryption key for workspace {}", workspace_id),
+    Some(key_id) => format!(
+      "no encryption key with id {} for workspace {}",
+      key_id, workspace_id
+    ),
+  };
+  PersistenceError::MissingEncryptionKey(msg)
+}
+
+/// Seals the value if the store has a cipher.
+pub(crate) fn encrypt_value(
+  cipher: Option<&CollabCipher>,
+  scope: &ValueScope,
+  value: &[u8],
+) -> Result<Vec<u8>, PersistenceError> {
+  match cipher {
+    None => Ok(value.to_vec()),
+    Some(cipher) => cipher.encrypt(scope, value),
+  }
+}
+
+/// Opens the value if it's sealed. A sealed value can't be read from a store without a cipher.
+pub(crate) fn decrypt_value(
+  cipher: Option<&CollabCipher>,
+  scope: &ValueScope,
+  value: &[u8],
+) -> Result<Vec<u8>, PersistenceError> {
+  match cipher {
+    Some(cipher) => cipher.decrypt(scope, value),
+    None if is_encrypted(value) => Err(PersistenceError::MissingEncryptionKey(format!(
+      "the data of workspace {} is encrypted, but the store has no cipher",
+      scope.workspace_id
+    ))),
+    None => Ok(value.to_vec()),
+  }
+}
@@ -18,6 +18,10 @@ pub enum PersistenceError {
   #[error("{0}")]
   RocksdbIOError(String),
 
+  #[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+  #[error(transparent)]
+  Sqlite(#[from] rusqlite::Error),
+
   #[error(transparent)]
   Bincode(#[from] bincode::Error),
 
@@ -42,12 +46,27 @@ pub enum PersistenceError {
   #[error("Duplicate update key")]
   DuplicateUpdateKey,
 
+  #[error("Write conflict: {0}")]
+  WriteConflict(String),
+
   #[error("Can't find the latest update key")]
   LatestUpdateKeyNotExist,
 
+  #[error("Missing encryption key: {0}")]
+  MissingEncryptionKey(String),
+
+  #[error("Encryption error: {0}")]
+  Encryption(String),
+
+  #[error("The store is encrypted, its plaintext export must be allowed explicitly")]
+  PlaintextExportNotAllowed,
+
   #[error(transparent)]
   Collab(#[from] collab::error::CollabError),
 
+  #[error(transparent)]
+  EncodedCollab(#[from] collab::error::EncodedCollabError),
+
   #[error(transparent)]
   Internal(#[from] anyhow::Error),
 }
@@ -56,6 +75,17 @@ impl PersistenceError {
   pub fn is_record_not_found(&self) -> bool {
     matches!(self, PersistenceError::RecordNotFound(_))
   }
+
+  /// Whether the transaction failed because another one changed the same keys, and can be run
+  /// again.
+  pub fn is_write_conflict(&self) -> bool {
+    match self {
+      PersistenceError::WriteConflict(_) => true,
+      #[cfg(not(target_arch = "wasm32"))]
+      PersistenceError::RocksdbBusy(_) => true,
+      _ => false,
+    }
+  }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -65,7 +95,7 @@ impl From<indexed_db_futures::web_sys::DomException> for PersistenceError {
   }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(all(feature = "rocksdb", not(target_arch = "wasm32")))]
 impl From<rocksdb::Error> for PersistenceError {
   fn from(value: rocksdb::Error) -> Self {
     match value.kind() {
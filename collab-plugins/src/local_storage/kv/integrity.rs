@@ -0,0 +1,483 @@
+//! Logical integrity check of the store, over the key layout of [crate::local_storage::kv::keys].
+//!
+//! The storage engine detects its own corruptions, e.g.
+//! [PersistenceError::RocksdbCorruption], but not the entries that don't make sense together.
+//! [verify] walks the doc space and reports the doc ids without a doc state, the entries of unknown
+//! doc ids and the doc states or updates that can't be decoded. [repair] moves the bad entries to
+//! the [QUARANTINE_SPACE], where they can still be inspected, and rebuilds the doc states of the
+//! affected documents. The updates that can't be integrated into the rebuilt doc state, e.g.
+//! because they depend on a quarantined update, are quarantined too.
+//!
+//! The values encrypted with a key that the store doesn't have are not checked, and the documents
+//! they belong to are never rebuilt.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter};
+
+use collab::core::collab::{default_client_id, make_yrs_doc};
+use tracing::{info, warn};
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{Doc, ReadTxn, StateVector, Transact, Update};
+
+use crate::local_storage::kv::doc::extract_workspace_id_from_key;
+use crate::local_storage::kv::encryption::{
+  CollabCipher, ValueKind, ValueScope, decrypt_value, encrypt_value,
+};
+use crate::local_storage::kv::keys::*;
+use crate::local_storage::kv::*;
+
+/// A document, as referred to by its doc id key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocRef {
+  pub doc_id: DocID,
+  pub uid: i64,
+  /// None for the keys written before the workspace id was part of them.
+  pub workspace_id: Option<String>,
+  pub object_id: String,
+}
+
+impl Display for DocRef {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{} (uid: {}, workspace: {}, doc id: {})",
+      self.object_id,
+      self.uid,
+      self.workspace_id.as_deref().unwrap_or("-"),
+      self.doc_id
+    )
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityProblem {
+  /// The value of a doc id key is not a doc id.
+  InvalidDocId {
+    key: Vec<u8>,
+  },
+  /// A key of the doc space that doesn't match the layout.
+  InvalidDocKey {
+    key: Vec<u8>,
+  },
+  /// An entry of a doc id that no document refers to.
+  OrphanDocEntry {
+    doc_id: DocID,
+    key: Vec<u8>,
+  },
+  /// The document can't be loaded without its doc state.
+  MissingDocState {
+    doc: DocRef,
+  },
+  MissingStateVector {
+    doc: DocRef,
+  },
+  UndecodableDocState {
+    doc: DocRef,
+    error: String,
+  },
+  UndecodableUpdate {
+    doc: DocRef,
+    key: Vec<u8>,
+    error: String,
+  },
+}
+
+impl Display for IntegrityProblem {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      IntegrityProblem::InvalidDocId { key } => write!(f, "invalid doc id for key {:?}", key),
+      IntegrityProblem::InvalidDocKey { key } => write!(f, "invalid doc key {:?}", key),
+      IntegrityProblem::OrphanDocEntry { doc_id, key } => {
+        write!(f, "orphan entry {:?} of unknown doc id {}", key, doc_id)
+      },
+      IntegrityProblem::MissingDocState { doc } => write!(f, "missing doc state of {}", doc),
+      IntegrityProblem::MissingStateVector { doc } => {
+        write!(f, "missing state vector of {}", doc)
+      },
+      IntegrityProblem::UndecodableDocState { doc, error } => {
+        write!(f, "undecodable doc state of {}: {}", doc, error)
+      },
+      IntegrityProblem::UndecodableUpdate { doc, key, error } => {
+        write!(f, "undecodable update {:?} of {}: {}", key, doc, error)
+      },
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+  pub docs: usize,
+  pub updates: usize,
+  pub problems: Vec<IntegrityProblem>,
+  /// The documents with values encrypted with a key that the store doesn't have.
+  pub unreadable_docs: Vec<DocRef>,
+}
+
+impl IntegrityReport {
+  pub fn is_ok(&self) -> bool {
+    self.problems.is_empty()
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+  /// The problems found before the repair.
+  pub problems: Vec<IntegrityProblem>,
+  /// The number of entries moved to the quarantine space.
+  pub quarantined: usize,
+  pub rebuilt_docs: usize,
+  /// The keys of the updates that couldn't be integrated into the rebuilt doc states, e.g.
+  /// because they depend on a quarantined update. They are quarantined too.
+  pub pending_updates: Vec<Vec<u8>>,
+  /// The documents that need to be rebuilt, but have unreadable values.
+  pub skipped_docs: Vec<DocRef>,
+}
+
+/// Checks the entries of the doc space, see the [module documentation](self).
+pub fn verify<'a, S>(store: &S) -> Result<IntegrityReport, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let mut report = IntegrityReport::default();
+
+  // The doc id keys
+  let mut docs = BTreeMap::new();
+  let from = Key::from_const([DOC_SPACE, DOC_SPACE_OBJECT]);
+  let to = Key::from_const([DOC_SPACE, DOC_SPACE_OBJECT_KEY]);
+  for entry in store.range(from.as_ref()..to.as_ref())? {
+    let key = entry.key();
+    let Ok(doc_id) = <[u8; DOC_ID_LEN]>::try_from(entry.value()).map(DocID::from_be_bytes) else {
+      report
+        .problems
+        .push(IntegrityProblem::InvalidDocId { key: key.to_vec() });
+      continue;
+    };
+    match parse_doc_ref(key, doc_id) {
+      Some(doc) => {
+        docs.insert(doc_id, (doc, DocEntries::default()));
+      },
+      None => report
+        .problems
+        .push(IntegrityProblem::InvalidDocKey { key: key.to_vec() }),
+    }
+  }
+  report.docs = docs.len();
+
+  // The entries of the documents
+  let from = Key::from_const([DOC_SPACE, DOC_SPACE_OBJECT_KEY]);
+  let to = Key::from_const([DOC_SPACE, DOC_SPACE_OBJECT_KEY + 1]);
+  for entry in store.range(from.as_ref()..to.as_ref())? {
+    let key = entry.key();
+    let doc_key_len = 2 + DOC_ID_LEN + 1;
+    if key.len() < doc_key_len {
+      report
+        .problems
+        .push(IntegrityProblem::InvalidDocKey { key: key.to_vec() });
+      continue;
+    }
+    let doc_id = DocID::from_be_bytes(key[2..2 + DOC_ID_LEN].try_into().unwrap());
+    let Some((doc, entries)) = docs.get_mut(&doc_id) else {
+      report.problems.push(IntegrityProblem::OrphanDocEntry {
+        doc_id,
+        key: key.to_vec(),
+      });
+      continue;
+    };
+
+    let tag = key[2 + DOC_ID_LEN];
+    if key.len() == doc_key_len && tag == DOC_STATE {
+      entries.state = true;
+      match check_value(store.cipher(), doc, ValueKind::DocState, entry.value()) {
+        Checked::Valid => {},
+        Checked::Unreadable => entries.unreadable = true,
+        Checked::Invalid(error) => report.problems.push(IntegrityProblem::UndecodableDocState {
+          doc: doc.clone(),
+          error,
+        }),
+      }
+    } else if key.len() == doc_key_len && tag == DOC_STATE_VEC {
+      entries.state_vector = true;
+    } else if key.len() == doc_key_len && tag == REMOTE_DOC_STATE_VEC {
+      // Only used by the sync
+    } else if key.len() == DOC_UPDATE_KEY_LEN
+      && tag == DOC_UPDATE
+      && key[key.len() - 1] == TERMINATOR
+    {
+      report.updates += 1;
+      match check_value(store.cipher(), doc, ValueKind::Update, entry.value()) {
+        Checked::Valid => {},
+        Checked::Unreadable => entries.unreadable = true,
+        Checked::Invalid(error) => report.problems.push(IntegrityProblem::UndecodableUpdate {
+          doc: doc.clone(),
+          key: key.to_vec(),
+          error,
+        }),
+      }
+    } else {
+      report
+        .problems
+        .push(IntegrityProblem::InvalidDocKey { key: key.to_vec() });
+    }
+  }
+
+  for (doc, entries) in docs.into_values() {
+    if !entries.state {
+      report
+        .problems
+        .push(IntegrityProblem::MissingDocState { doc: doc.clone() });
+    }
+    if !entries.state_vector {
+      report
+        .problems
+        .push(IntegrityProblem::MissingStateVector { doc: doc.clone() });
+    }
+    if entries.unreadable {
+      report.unreadable_docs.push(doc);
+    }
+  }
+  Ok(report)
+}
+
+/// Moves the entries reported by [verify] to the quarantine space, then rebuilds the doc states of
+/// the affected documents from what is left. Call it within
+/// [crate::local_storage::kv::KVTransactionDB::with_write_txn], for the store to be repaired
+/// atomically.
+pub fn repair<'a, S>(store: &S) -> Result<RepairReport, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let report = verify(store)?;
+  let mut quarantine = vec![];
+  let mut rebuild = BTreeMap::new();
+  for problem in &report.problems {
+    match problem {
+      IntegrityProblem::InvalidDocId { key }
+      | IntegrityProblem::InvalidDocKey { key }
+      | IntegrityProblem::OrphanDocEntry { key, .. } => quarantine.push(key.clone()),
+      IntegrityProblem::MissingDocState { doc } | IntegrityProblem::MissingStateVector { doc } => {
+        rebuild.insert(doc.doc_id, doc);
+      },
+      IntegrityProblem::UndecodableDocState { doc, .. } => {
+        quarantine.push(make_doc_state_key(doc.doc_id).to_vec());
+        rebuild.insert(doc.doc_id, doc);
+      },
+      IntegrityProblem::UndecodableUpdate { doc, key, .. } => {
+        quarantine.push(key.clone());
+        rebuild.insert(doc.doc_id, doc);
+      },
+    }
+  }
+
+  let mut repair_report = RepairReport::default();
+  for key in quarantine {
+    if move_to_quarantine(store, &key)? {
+      repair_report.quarantined += 1;
+    }
+  }
+
+  let unreadable = report
+    .unreadable_docs
+    .iter()
+    .map(|doc| doc.doc_id)
+    .collect::<HashSet<_>>();
+  for doc in rebuild.into_values() {
+    if unreadable.contains(&doc.doc_id) {
+      warn!("skip rebuilding the unreadable doc {}", doc);
+      repair_report.skipped_docs.push(doc.clone());
+      continue;
+    }
+    let pending_updates = rebuild_doc_state(store, doc)?;
+    repair_report.quarantined += pending_updates.len();
+    repair_report.pending_updates.extend(pending_updates);
+    repair_report.rebuilt_docs += 1;
+  }
+
+  info!(
+    "repaired {} problems: {} entries quarantined, {} docs rebuilt",
+    report.problems.len(),
+    repair_report.quarantined,
+    repair_report.rebuilt_docs
+  );
+  repair_report.problems = report.problems;
+  Ok(repair_report)
+}
+
+fn move_to_quarantine<'a, S>(store: &S, key: &[u8]) -> Result<bool, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  match store.get(key)? {
+    Some(value) => {
+      store.insert(make_quarantine_key(key), value)?;
+      store.remove(key)?;
+      Ok(true)
+    },
+    None => Ok(false),
+  }
+}
+
+/// Merges the doc state, if any, and the updates of the document into a new doc state. Returns
+/// the keys of the updates that couldn't be integrated, which are moved to the quarantine space.
+fn rebuild_doc_state<'a, S>(store: &S, doc: &DocRef) -> Result<Vec<Vec<u8>>, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let workspace_id = doc.workspace_id.as_deref().unwrap_or_default();
+  let scope = |kind| ValueScope::new(doc.uid, workspace_id, &doc.object_id, kind);
+  let doc_state_key = make_doc_state_key(doc.doc_id);
+  let start = make_doc_update_key(doc.doc_id, 0);
+  let end = make_doc_update_key(doc.doc_id, Clock::MAX);
+
+  let doc_state = match store.get(doc_state_key.as_ref())? {
+    Some(doc_state) => Some(decrypt_value(
+      store.cipher(),
+      &scope(ValueKind::DocState),
+      doc_state.as_ref(),
+    )?),
+    None => None,
+  };
+  let mut keys = vec![];
+  let mut updates = vec![];
+  for entry in store.range(start.as_ref()..end.as_ref())? {
+    updates.push(decrypt_value(
+      store.cipher(),
+      &scope(ValueKind::Update),
+      entry.value(),
+    )?);
+    keys.push(entry.key().to_vec());
+  }
+  let last_clock = keys
+    .last()
+    .map(|key| Clock::from_be_bytes(clock_from_key(key).try_into().unwrap()));
+
+  let (ydoc, integrated) = integrate_updates(&doc.object_id, doc_state.as_deref(), &updates)?;
+  let mut pending_updates = vec![];
+  for (key, integrated) in keys.into_iter().zip(integrated) {
+    if !integrated {
+      warn!(
+        "quarantine the update {:?} of {}: it can't be integrated",
+        key, doc
+      );
+      move_to_quarantine(store, &key)?;
+      pending_updates.push(key);
+    }
+  }
+
+  let txn = ydoc.transact();
+  let doc_state = txn.encode_state_as_update_v1(&StateVector::default());
+  // The values of the documents without a workspace id are never encrypted
+  let doc_state = match &doc.workspace_id {
+    Some(_) => encrypt_value(store.cipher(), &scope(ValueKind::DocState), &doc_state)?,
+    None => doc_state,
+  };
+  store.insert(doc_state_key, doc_state)?;
+  store.insert(
+    make_state_vector_key(doc.doc_id),
+    txn.state_vector().encode_v1(),
+  )?;
+  if let Some(clock) = last_clock {
+    let merged_end = make_doc_update_key(doc.doc_id, clock + 1);
+    store.remove_range(start.as_ref(), merged_end.as_ref())?;
+  }
+  info!("rebuilt the doc state of {}", doc);
+  Ok(pending_updates)
+}
+
+/// Applies the doc state and the updates to a new doc, and returns which updates were integrated.
+///
+/// An update that depends on a missing update is not integrated: yrs keeps it pending in the
+/// store of the doc, and it would be lost with the doc state. All the updates are applied in one
+/// pass, so an update that only depends on a later one is integrated once that one is applied.
+/// An update is then integrated when the state vector of the doc covers its blocks and its
+/// deleted ranges; the others are what remains pending.
+fn integrate_updates(
+  object_id: &str,
+  doc_state: Option<&[u8]>,
+  updates: &[Vec<u8>],
+) -> Result<(Doc, Vec<bool>), PersistenceError> {
+  // Keep the deleted items, the client id is never used as the doc is only read
+  let ydoc = make_yrs_doc(object_id, true, default_client_id());
+  let mut applied = Vec::with_capacity(updates.len());
+  {
+    let mut txn = ydoc.transact_mut();
+    if let Some(doc_state) = doc_state {
+      txn.try_apply_update(Update::decode_v1(doc_state)?)?;
+    }
+    for update in updates {
+      let update = Update::decode_v1(update)?;
+      applied.push((update.state_vector(), update.delete_set().clone()));
+      txn.try_apply_update(update)?;
+    }
+  }
+
+  let state_vector = ydoc.transact().state_vector();
+  let integrated = applied
+    .iter()
+    .map(|(update_state_vector, delete_set)| {
+      update_state_vector
+        .iter()
+        .all(|(client, clock)| state_vector.get(client) >= *clock)
+        && delete_set.iter().all(|(client, ranges)| {
+          ranges
+            .iter()
+            .all(|range| range.end <= state_vector.get(client))
+        })
+    })
+    .collect();
+  Ok((ydoc, integrated))
+}
+
+#[derive(Default)]
+struct DocEntries {
+  state: bool,
+  state_vector: bool,
+  unreadable: bool,
+}
+
+enum Checked {
+  Valid,
+  Unreadable,
+  Invalid(String),
+}
+
+fn check_value(
+  cipher: Option<&CollabCipher>,
+  doc: &DocRef,
+  kind: ValueKind,
+  value: &[u8],
+) -> Checked {
+  let workspace_id = doc.workspace_id.as_deref().unwrap_or_default();
+  let scope = ValueScope::new(doc.uid, workspace_id, &doc.object_id, kind);
+  let value = match decrypt_value(cipher, &scope, value) {
+    Ok(value) => value,
+    Err(PersistenceError::MissingEncryptionKey(_)) => return Checked::Unreadable,
+    Err(err) => return Checked::Invalid(err.to_string()),
+  };
+  match Update::decode_v1(&value) {
+    Ok(_) => Checked::Valid,
+    Err(err) => Checked::Invalid(err.to_string()),
+  }
+}
+
+/// Parses a doc id key, see [make_doc_id_key_v1] and [make_doc_id_key_v0].
+fn parse_doc_ref(key: &[u8], doc_id: DocID) -> Option<DocRef> {
+  let prefix_len = 2 + size_of::<i64>();
+  if key.len() <= prefix_len || key[key.len() - 1] != TERMINATOR {
+    return None;
+  }
+  let uid = i64::from_be_bytes(key[2..prefix_len].try_into().unwrap());
+  let workspace_id = extract_workspace_id_from_key(key);
+  let object_start = prefix_len + workspace_id.map(str::len).unwrap_or_default();
+  let object_id = std::str::from_utf8(&key[object_start..key.len() - 1]).ok()?;
+  Some(DocRef {
+    doc_id,
+    uid,
+    workspace_id: workspace_id.map(str::to_string),
+    object_id: object_id.to_string(),
+  })
+}
@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::local_storage::kv::PersistenceError;
+use crate::local_storage::kv::encryption::{EncryptionKey, EncryptionKeyProvider, KeyId};
+
+/// An [EncryptionKeyProvider] backed by a JSON file on the local disk. It works offline, which
+/// makes it suitable for tests and for the devices that don't have a keychain.
+pub struct LocalKeyFile {
+  path: PathBuf,
+  content: RwLock<KeyFileContent>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct KeyFileContent {
+  workspaces: HashMap<String, WorkspaceKeys>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WorkspaceKeys {
+  current: KeyId,
+  /// The hex encoded keys.
+  keys: BTreeMap<KeyId, String>,
+}
+
+impl LocalKeyFile {
+  /// Opens the key file at the given path. The file is created by the first
+  /// [LocalKeyFile::rotate_key] if it doesn't exist.
+  pub fn open(path: impl Into<PathBuf>) -> Result<Self, PersistenceError> {
+    let path = path.into();
+    let content = match std::fs::read(&path) {
+      Ok(bytes) => serde_json::from_slice(&bytes)
+        .map_err(|err| PersistenceError::InvalidData(format!("invalid key file: {}", err)))?,
+      Err(err) if err.kind() == std::io::ErrorKind::NotFound => KeyFileContent::default(),
+      Err(err) => return Err(PersistenceError::Internal(err.into())),
+    };
+    Ok(Self {
+      path,
+      content: RwLock::new(content),
+    })
+  }
+
+  /// Generates a new key for the workspace and makes it the current one. The previous keys are
+  /// kept, so the values sealed with them can still be opened until they are rotated.
+  pub fn rotate_key(&self, workspace_id: &str) -> Result<KeyId, PersistenceError> {
+    self.insert_key(workspace_id, EncryptionKey::generate())
+  }
+
+  /// Adds the key to the workspace and makes it the current one.
+  pub fn insert_key(
+    &self,
+    workspace_id: &str,
+    key: EncryptionKey,
+  ) -> Result<KeyId, PersistenceError> {
+    let mut content = self.content.write().unwrap();
+    let workspace = content
+      .workspaces
+      .entry(workspace_id.to_string())
+      .or_insert_with(|| WorkspaceKeys {
+        current: 0,
+        keys: BTreeMap::new(),
+      });
+    let key_id = workspace.keys.keys().next_back().map_or(1, |id| id + 1);
+    workspace.keys.insert(key_id, encode_hex(key.as_bytes()));
+    workspace.current = key_id;
+    self.save(&content)?;
+    Ok(key_id)
+  }
+
+  /// Removes a key that is not current anymore. The values still sealed with it can't be opened.
+  pub fn remove_key(&self, workspace_id: &str, key_id: KeyId) -> Result<(), PersistenceError> {
+    let mut content = self.content.write().unwrap();
+    if let Some(workspace) = content.workspaces.get_mut(workspace_id) {
+      if workspace.current == key_id {
+        return Err(PersistenceError::InvalidData(format!(
+          "key {} is the current key of workspace {}",
+          key_id, workspace_id
+        )));
+      }
+      workspace.keys.remove(&key_id);
+    }
+    self.save(&content)
+  }
+
+  /// Writes the file atomically, only readable by its owner.
+  fn save(&self, content: &KeyFileContent) -> Result<(), PersistenceError> {
+    let internal = |err: std::io::Error| PersistenceError::Internal(err.into());
+    let bytes =
+      serde_json::to_vec_pretty(content).map_err(|err| PersistenceError::Internal(err.into()))?;
+    let tmp_path = self.path.with_extension("tmp");
+    // A file left by an interrupted save keeps its permissions, the keys are never written to it
+    match std::fs::remove_file(&tmp_path) {
+      Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(internal(err)),
+      _ => {},
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    // The file is created with the permissions, so it's never readable by other users
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::OpenOptionsExt;
+      options.mode(0o600);
+    }
+    let mut file = options.open(&tmp_path).map_err(internal)?;
+    file.write_all(&bytes).map_err(internal)?;
+    file.sync_all().map_err(internal)?;
+    std::fs::rename(&tmp_path, &self.path).map_err(internal)?;
+    Ok(())
+  }
+}
+
+impl EncryptionKeyProvider for LocalKeyFile {
+  fn current_key(&self, workspace_id: &str) -> Option<(KeyId, EncryptionKey)> {
+    let current = self
+      .content
+      .read()
+      .unwrap()
+      .workspaces
+      .get(workspace_id)?
+      .current;
+    Some((current, self.key(workspace_id, current)?))
+  }
+
+  fn key(&self, workspace_id: &str, key_id: KeyId) -> Option<EncryptionKey> {
+    let content = self.content.read().unwrap();
+    let hex = content.workspaces.get(workspace_id)?.keys.get(&key_id)?;
+    let key = decode_hex(hex).and_then(|bytes| EncryptionKey::from_bytes(&bytes).ok());
+    if key.is_none() {
+      tracing::error!(
+        "🔴invalid key {} of workspace {} in key file",
+        key_id,
+        workspace_id
+      );
+    }
+    key
+  }
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+  if hex.len() % 2 != 0 {
+    return None;
+  }
+  (0..hex.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+    .collect()
+}
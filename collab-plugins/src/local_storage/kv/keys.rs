@@ -21,6 +21,14 @@ use smallvec::{SmallVec, smallvec};
 // SNAPSHOT_SPACE
 //     SNAPSHOT_SPACE_OBJECT        object_id       TERMINATOR
 //     SNAPSHOT_SPACE_OBJECT_KEY    snapshot_id     SNAPSHOT_UPDATE(snapshot)
+//
+// VERSION_SPACE
+//     VERSION_SPACE_OBJECT         uid   workspace_id   object_id   TERMINATOR
+//     VERSION_SPACE_OBJECT_KEY     version_space_id   VERSION_UPDATE clock TERMINATOR (version)
+//     VERSION_SPACE_VERSION_ID     version_space_id   version_id   TERMINATOR (version key)
+//
+// QUARANTINE_SPACE
+//     QUARANTINE_SPACE_OBJECT      original key
 
 /// Prefix byte used for all of the yrs object entries.
 pub const DOC_SPACE: u8 = 1;
@@ -57,6 +65,25 @@ pub const SNAPSHOT_UPDATE: u8 = 1;
 pub const COLLAB_SPACE: u8 = 3;
 pub const COLLAB_SPACE_OBJECT: u8 = 0;
 
+/// Prefix byte used for all of the named version entries.
+pub const VERSION_SPACE: u8 = 4;
+
+/// Prefix byte used for object id -> [VersionSpaceID] mapping index key space.
+pub const VERSION_SPACE_OBJECT: u8 = 0;
+
+/// Prefix byte used for version key space.
+pub const VERSION_SPACE_OBJECT_KEY: u8 = 1;
+
+/// Tag byte within [VERSION_SPACE_OBJECT_KEY] used to identify object's version entries.
+pub const VERSION_UPDATE: u8 = 1;
+
+/// Prefix byte used for version id -> version key mapping index key space.
+pub const VERSION_SPACE_VERSION_ID: u8 = 2;
+
+/// Prefix byte used for the entries moved aside by [crate::local_storage::kv::integrity::repair].
+pub const QUARANTINE_SPACE: u8 = 5;
+pub const QUARANTINE_SPACE_OBJECT: u8 = 0;
+
 pub type DocID = u64;
 pub const DOC_ID_LEN: usize = 8;
 pub const DOC_STATE_KEY_LEN: usize = DOC_ID_LEN + 4;
@@ -68,6 +95,9 @@ pub const SNAPSHOT_ID_LEN: usize = 8;
 pub const SNAPSHOT_UPDATE_KEY_LEN: usize = SNAPSHOT_ID_LEN + CLOCK_LEN + 4;
 pub const SNAPSHOT_UPDATE_KEY_PREFIX_LEN: usize = SNAPSHOT_ID_LEN + 4;
 
+pub type VersionSpaceID = u64;
+pub const VERSION_KEY_LEN: usize = DOC_ID_LEN + CLOCK_LEN + 4;
+
 pub type Clock = u32;
 pub const CLOCK_LEN: usize = 4;
 
@@ -192,6 +222,42 @@ pub fn make_snapshot_update_key_prefix(
   Key(v)
 }
 
+// [4,0, uid, workspace_id, object_id, 0]
+pub fn make_version_space_id_key(uid: &[u8], workspace_id: &[u8], object_id: &[u8]) -> Key<20> {
+  let mut v: SmallVec<[u8; 20]> = smallvec![VERSION_SPACE, VERSION_SPACE_OBJECT];
+  v.write_all(uid).unwrap();
+  v.write_all(workspace_id).unwrap();
+  v.write_all(object_id).unwrap();
+  v.push(TERMINATOR);
+  Key(v)
+}
+
+// [4,1,  0,0,0,0,0,0,0,0,  1   [0,0,0,0],  0]
+pub fn make_version_key(version_space_id: VersionSpaceID, clock: Clock) -> Key<VERSION_KEY_LEN> {
+  let mut v: SmallVec<[u8; VERSION_KEY_LEN]> = smallvec![VERSION_SPACE, VERSION_SPACE_OBJECT_KEY];
+  v.write_all(&version_space_id.to_be_bytes()).unwrap();
+  v.push(VERSION_UPDATE);
+  v.write_all(&clock.to_be_bytes()).unwrap();
+  v.push(TERMINATOR);
+  Key(v)
+}
+
+// [4,2,  0,0,0,0,0,0,0,0,  version_id,  0]
+pub fn make_version_id_key(version_space_id: VersionSpaceID, version_id: &[u8]) -> Key<48> {
+  let mut v: SmallVec<[u8; 48]> = smallvec![VERSION_SPACE, VERSION_SPACE_VERSION_ID];
+  v.write_all(&version_space_id.to_be_bytes()).unwrap();
+  v.write_all(version_id).unwrap();
+  v.push(TERMINATOR);
+  Key(v)
+}
+
+// [5,0, original key]
+pub fn make_quarantine_key(key: &[u8]) -> Key<32> {
+  let mut v: SmallVec<[u8; 32]> = smallvec![QUARANTINE_SPACE, QUARANTINE_SPACE_OBJECT];
+  v.write_all(key).unwrap();
+  Key(v)
+}
+
 pub fn make_collab_id_key(object_id: &[u8]) -> Key<20> {
   let mut v: SmallVec<[u8; 20]> = smallvec![COLLAB_SPACE, COLLAB_SPACE_OBJECT];
   v.write_all(object_id).unwrap();
@@ -4,8 +4,13 @@ pub use range::*;
 
 mod db;
 pub mod doc;
+pub mod encryption;
 pub mod error;
+pub mod integrity;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod key_file;
 pub mod keys;
 pub mod oid;
 mod range;
 pub mod snapshot;
+pub mod version;
@@ -2,6 +2,7 @@ use std::fmt::Debug;
 use std::panic;
 use std::panic::AssertUnwindSafe;
 
+use crate::local_storage::kv::encryption::{ValueKind, ValueScope, decrypt_value, encrypt_value};
 use crate::local_storage::kv::keys::*;
 use crate::local_storage::kv::*;
 use collab_entity::CollabType;
@@ -24,9 +25,13 @@ where
   /// The snapshot contains the updates prior to the given update_key. For example,
   /// if the update_key is 10, the snapshot will contain updates 0-9. So when restoring
   /// the document from a snapshot, it should apply the update from key:10.
+  ///
+  /// The snapshot is sealed with the key of the workspace if the store has a cipher, see
+  /// [crate::local_storage::kv::encryption].
   fn create_snapshot<K, T>(
     &self,
     uid: i64,
+    workspace_id: &str,
     object_id: &K,
     txn: &T,
     snapshot: Snapshot,
@@ -43,7 +48,9 @@ where
         }
         tracing::trace!("New snapshot for object:{:?}", object_id);
         let snapshot_id = self.create_snapshot_id(uid, object_id.as_ref())?;
-        insert_snapshot_update(self, snapshot_id, object_id, data)?;
+        let snapshot = CollabSnapshot::new(data);
+        let value = seal_snapshot(self, uid, workspace_id, object_id, &snapshot)?;
+        insert_snapshot_update(self, snapshot_id, object_id, value)?;
       },
       Err(e) => {
         tracing::error!(
@@ -59,6 +66,7 @@ where
   fn create_snapshot_with_data<K>(
     &self,
     uid: i64,
+    workspace_id: &str,
     object_id: &K,
     snapshot_data: Vec<u8>,
   ) -> Result<(), PersistenceError>
@@ -67,11 +75,18 @@ where
   {
     tracing::trace!("New snapshot for object:{:?}", object_id);
     let snapshot_id = self.create_snapshot_id(uid, object_id.as_ref())?;
-    insert_snapshot_update(self, snapshot_id, object_id, snapshot_data)?;
+    let snapshot = CollabSnapshot::new(snapshot_data);
+    let value = seal_snapshot(self, uid, workspace_id, object_id, &snapshot)?;
+    insert_snapshot_update(self, snapshot_id, object_id, value)?;
     Ok(())
   }
   /// Return list of snapshots for the given object id.
-  fn get_snapshots<K: AsRef<[u8]> + ?Sized>(&self, uid: i64, object_id: &K) -> Vec<CollabSnapshot> {
+  fn get_snapshots<K: AsRef<[u8]> + ?Sized>(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &K,
+  ) -> Vec<CollabSnapshot> {
     let mut snapshots = vec![];
     if let Some(snapshot_id) = get_snapshot_id(uid, self, object_id) {
       let start = make_snapshot_update_key(snapshot_id, 0);
@@ -79,8 +94,9 @@ where
 
       if let Ok(encoded_updates) = self.range(start.as_ref()..=end.as_ref()) {
         for encoded_snapshot in encoded_updates {
-          if let Ok(snapshot) = CollabSnapshot::try_from(encoded_snapshot.value()) {
-            snapshots.push(snapshot);
+          match open_snapshot(self, uid, workspace_id, object_id, encoded_snapshot.value()) {
+            Ok(snapshot) => snapshots.push(snapshot),
+            Err(err) => tracing::warn!("🟡failed to read a snapshot: {}", err),
           }
         }
       }
@@ -88,24 +104,26 @@ where
     snapshots
   }
 
-  fn get_last_snapshot_by_snapshot_id(&self, snapshot_id: SnapshotID) -> Option<CollabSnapshot> {
+  fn get_last_snapshot_by_snapshot_id<K: AsRef<[u8]> + ?Sized>(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &K,
+    snapshot_id: SnapshotID,
+  ) -> Option<CollabSnapshot> {
     let last_update_key = self.get_snapshot_last_update_key(snapshot_id)?;
-    self.get(last_update_key.as_ref()).ok()?.and_then(|value| {
-      if let Ok(snapshot) = CollabSnapshot::try_from(value.as_ref()) {
-        Some(snapshot)
-      } else {
-        None
-      }
-    })
+    let value = self.get(last_update_key.as_ref()).ok()??;
+    open_snapshot(self, uid, workspace_id, object_id, value.as_ref()).ok()
   }
 
   fn get_last_snapshot<K: AsRef<[u8]> + ?Sized>(
     &self,
     uid: i64,
+    workspace_id: &str,
     object_id: &K,
   ) -> Option<CollabSnapshot> {
     let snapshot_id = get_snapshot_id(uid, self, object_id)?;
-    self.get_last_snapshot_by_snapshot_id(snapshot_id)
+    self.get_last_snapshot_by_snapshot_id(uid, workspace_id, object_id, snapshot_id)
   }
 
   fn delete_last_snapshot_by_snapshot_id(&self, snapshot_id: SnapshotID) {
@@ -162,6 +180,37 @@ where
   get_id_for_key(store, key)
 }
 
+/// Serializes the snapshot, sealed with the key of the workspace if the store has a cipher.
+pub(crate) fn seal_snapshot<'a, K, S>(
+  store: &S,
+  uid: i64,
+  workspace_id: &str,
+  object_id: &K,
+  snapshot: &CollabSnapshot,
+) -> Result<Vec<u8>, PersistenceError>
+where
+  K: AsRef<[u8]> + ?Sized,
+  S: KVStore<'a>,
+{
+  let scope = ValueScope::new(uid, workspace_id, object_id, ValueKind::Snapshot);
+  encrypt_value(store.cipher(), &scope, &snapshot.to_vec())
+}
+
+fn open_snapshot<'a, K, S>(
+  store: &S,
+  uid: i64,
+  workspace_id: &str,
+  object_id: &K,
+  value: &[u8],
+) -> Result<CollabSnapshot, PersistenceError>
+where
+  K: AsRef<[u8]> + ?Sized,
+  S: KVStore<'a>,
+{
+  let scope = ValueScope::new(uid, workspace_id, object_id, ValueKind::Snapshot);
+  CollabSnapshot::try_from(decrypt_value(store.cipher(), &scope, value)?.as_slice())
+}
+
 pub fn try_encode_snapshot<T: ReadTxn>(
   txn: &T,
   snapshot: Snapshot,
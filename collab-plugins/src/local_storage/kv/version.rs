@@ -0,0 +1,194 @@
+use crate::local_storage::kv::encryption::{ValueKind, ValueScope, decrypt_value, encrypt_value};
+use crate::local_storage::kv::keys::*;
+use crate::local_storage::kv::*;
+use collab::core::collab_version::CollabVersion;
+
+impl<'a, T> VersionAction<'a> for T
+where
+  T: KVStore<'a>,
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+}
+
+/// Persists the named [CollabVersion]s of a collab. The versions of an object are stored in
+/// insertion order, so listing them returns the oldest version first. They are sealed with the key
+/// of the workspace if the store has a cipher, see [crate::local_storage::kv::encryption].
+///
+/// The key of each version is indexed by its version id, so a single version is read or deleted
+/// without decrypting the others.
+pub trait VersionAction<'a>: KVStore<'a> + Sized
+where
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+  fn insert_version(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &str,
+    version: &CollabVersion,
+  ) -> Result<(), PersistenceError> {
+    let version_space_id = get_or_create_version_space_id(uid, self, workspace_id, object_id)?;
+    let id_key = make_version_id_key(version_space_id, version.version_id.as_bytes());
+    // A version saved again with the same id replaces the previous one
+    if let Some(key) = self.get(id_key.as_ref())? {
+      self.remove(key.as_ref())?;
+    }
+    let key = create_update_key(version_space_id, self, object_id, make_version_key)?;
+    let scope = ValueScope::new(uid, workspace_id, object_id, ValueKind::Version);
+    let value = encrypt_value(self.cipher(), &scope, &bincode::serialize(version)?)?;
+    self.insert(key.as_ref(), value)?;
+    self.insert(id_key, key.as_ref())?;
+    Ok(())
+  }
+
+  fn get_versions(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &str,
+  ) -> Result<Vec<CollabVersion>, PersistenceError> {
+    let mut versions = vec![];
+    if let Some(version_space_id) = get_version_space_id(uid, self, workspace_id, object_id) {
+      let start = make_version_key(version_space_id, 0);
+      let end = make_version_key(version_space_id, Clock::MAX);
+      let scope = ValueScope::new(uid, workspace_id, object_id, ValueKind::Version);
+      for entry in self.range(start.as_ref()..end.as_ref())? {
+        let value = decrypt_value(self.cipher(), &scope, entry.value())?;
+        versions.push(bincode::deserialize::<CollabVersion>(&value)?);
+      }
+    }
+    Ok(versions)
+  }
+
+  fn has_versions(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &str,
+  ) -> Result<bool, PersistenceError> {
+    match get_version_space_id(uid, self, workspace_id, object_id) {
+      None => Ok(false),
+      Some(version_space_id) => {
+        let start = make_version_key(version_space_id, 0);
+        let end = make_version_key(version_space_id, Clock::MAX);
+        Ok(self.range(start.as_ref()..end.as_ref())?.next().is_some())
+      },
+    }
+  }
+
+  fn number_of_versions(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &str,
+  ) -> Result<usize, PersistenceError> {
+    match get_version_space_id(uid, self, workspace_id, object_id) {
+      None => Ok(0),
+      Some(version_space_id) => {
+        let start = make_version_key(version_space_id, 0);
+        let end = make_version_key(version_space_id, Clock::MAX);
+        Ok(self.range(start.as_ref()..end.as_ref())?.count())
+      },
+    }
+  }
+
+  fn get_version(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &str,
+    version_id: &str,
+  ) -> Result<Option<CollabVersion>, PersistenceError> {
+    let Some(version_space_id) = get_version_space_id(uid, self, workspace_id, object_id) else {
+      return Ok(None);
+    };
+    let id_key = make_version_id_key(version_space_id, version_id.as_bytes());
+    let Some(key) = self.get(id_key.as_ref())? else {
+      return Ok(None);
+    };
+    match self.get(key.as_ref())? {
+      None => Ok(None),
+      Some(value) => {
+        let scope = ValueScope::new(uid, workspace_id, object_id, ValueKind::Version);
+        let value = decrypt_value(self.cipher(), &scope, value.as_ref())?;
+        Ok(Some(bincode::deserialize::<CollabVersion>(&value)?))
+      },
+    }
+  }
+
+  fn delete_version(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &str,
+    version_id: &str,
+  ) -> Result<(), PersistenceError> {
+    if let Some(version_space_id) = get_version_space_id(uid, self, workspace_id, object_id) {
+      let id_key = make_version_id_key(version_space_id, version_id.as_bytes());
+      if let Some(key) = self.get(id_key.as_ref())? {
+        self.remove(key.as_ref())?;
+        self.remove(id_key.as_ref())?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Delete all versions for the given object id.
+  fn delete_all_versions(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &str,
+  ) -> Result<(), PersistenceError> {
+    if let Some(version_space_id) = get_version_space_id(uid, self, workspace_id, object_id) {
+      let start = make_version_key(version_space_id, 0);
+      let end = make_version_key(version_space_id, Clock::MAX);
+      self.remove_range(start.as_ref(), end.as_ref())?;
+      // The version ids are utf8, so they never start with the high watermark
+      let start = make_version_id_key(version_space_id, &[]);
+      let end = make_version_id_key(version_space_id, &[TERMINATOR_HI_WATERMARK]);
+      self.remove_range(start.as_ref(), end.as_ref())?;
+    }
+    Ok(())
+  }
+}
+
+pub(crate) fn get_version_space_id<'a, S>(
+  uid: i64,
+  store: &S,
+  workspace_id: &str,
+  object_id: &str,
+) -> Option<VersionSpaceID>
+where
+  S: KVStore<'a>,
+{
+  let key = make_version_space_id_key(
+    &uid.to_be_bytes(),
+    workspace_id.as_ref(),
+    object_id.as_ref(),
+  );
+  get_id_for_key(store, key)
+}
+
+fn get_or_create_version_space_id<'a, S>(
+  uid: i64,
+  store: &S,
+  workspace_id: &str,
+  object_id: &str,
+) -> Result<VersionSpaceID, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  match get_version_space_id(uid, store, workspace_id, object_id) {
+    Some(version_space_id) => Ok(version_space_id),
+    None => {
+      let key = make_version_space_id_key(
+        &uid.to_be_bytes(),
+        workspace_id.as_ref(),
+        object_id.as_ref(),
+      );
+      insert_doc_id_for_key(store, key)
+    },
+  }
+}
@@ -0,0 +1,277 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeBounds};
+use std::sync::{Arc, RwLock};
+
+use crate::local_storage::kv::doc::CollabKVAction;
+use crate::local_storage::kv::encryption::CollabCipher;
+use crate::local_storage::kv::{KVEntry, KVStore, KVTransactionDB, PersistenceError};
+
+type Data = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// Implementation of [KVTransactionDB] that keeps the data in memory, e.g. for the tests or for
+/// a session that must not write to the disk. The data is dropped with the last clone of the
+/// database.
+///
+/// A transaction reads from a snapshot of the data taken when it begins. The changes of a write
+/// transaction are only visible to itself until they are committed, and they are discarded if it's
+/// dropped without being committed. If another transaction committed a change to one of its keys
+/// in the meantime, e.g. both appended an update to the same document with the same clock, the
+/// commit fails with [PersistenceError::WriteConflict] and none of its changes are applied.
+#[derive(Clone, Default)]
+pub struct KVTransactionDBMemoryImpl {
+  data: Arc<RwLock<Arc<Data>>>,
+  cipher: Option<CollabCipher>,
+}
+
+impl KVTransactionDBMemoryImpl {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Encrypts the documents written from now on with the given cipher, and decrypts the
+  /// encrypted ones. See [crate::local_storage::kv::encryption].
+  pub fn with_cipher(mut self, cipher: CollabCipher) -> Self {
+    self.cipher = Some(cipher);
+    self
+  }
+
+  pub fn cipher(&self) -> Option<&CollabCipher> {
+    self.cipher.as_ref()
+  }
+
+  /// Return the number of committed entries.
+  pub fn len(&self) -> usize {
+    self.data.read().unwrap().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub async fn is_exist(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &str,
+  ) -> Result<bool, PersistenceError> {
+    let read_txn = self.read_txn();
+    Ok(read_txn.is_exist(uid, workspace_id, object_id))
+  }
+
+  pub async fn delete_doc(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    doc_id: &str,
+  ) -> Result<(), PersistenceError> {
+    self.with_write_txn(|txn| txn.delete_doc(uid, workspace_id, doc_id))?;
+    Ok(())
+  }
+
+  fn begin(&self) -> MemoryKVStoreImpl<'_> {
+    MemoryKVStoreImpl {
+      db: self,
+      snapshot: self.data.read().unwrap().clone(),
+      changes: Default::default(),
+    }
+  }
+}
+
+impl KVTransactionDB for KVTransactionDBMemoryImpl {
+  type TransactionAction<'a> = MemoryKVStoreImpl<'a>;
+
+  fn read_txn<'a, 'b>(&'b self) -> Self::TransactionAction<'a>
+  where
+    'b: 'a,
+  {
+    self.begin()
+  }
+
+  fn write_txn<'a, 'b>(&'b self) -> Self::TransactionAction<'a>
+  where
+    'b: 'a,
+  {
+    self.begin()
+  }
+
+  fn with_write_txn<'a, 'b, Output>(
+    &'b self,
+    f: impl FnOnce(&Self::TransactionAction<'a>) -> Result<Output, PersistenceError>,
+  ) -> Result<Output, PersistenceError>
+  where
+    'b: 'a,
+  {
+    let store = self.write_txn();
+    let result = f(&store)?;
+    store.commit_transaction()?;
+    Ok(result)
+  }
+
+  fn flush(&self) -> Result<(), PersistenceError> {
+    Ok(())
+  }
+}
+
+/// Implementation of [KVStore] for [KVTransactionDBMemoryImpl]. The changes are discarded when
+/// it's dropped without being committed.
+pub struct MemoryKVStoreImpl<'a> {
+  db: &'a KVTransactionDBMemoryImpl,
+  snapshot: Arc<Data>,
+  /// The changed keys, None if the key is removed.
+  changes: RefCell<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+}
+
+impl MemoryKVStoreImpl<'_> {
+  pub fn commit_transaction(self) -> Result<(), PersistenceError> {
+    let changes = self.changes.into_inner();
+    if changes.is_empty() {
+      return Ok(());
+    }
+    let mut data = self.db.data.write().unwrap();
+    // The snapshot is shared with the data until another transaction commits
+    if !Arc::ptr_eq(&*data, &self.snapshot) {
+      let conflict = changes
+        .keys()
+        .find(|key| data.get(*key) != self.snapshot.get(*key));
+      if let Some(key) = conflict {
+        return Err(PersistenceError::WriteConflict(format!(
+          "the key {:?} was changed by another transaction",
+          key
+        )));
+      }
+    }
+    // Only copied if a transaction still reads the current data
+    let data = Arc::make_mut(&mut data);
+    for (key, value) in changes {
+      match value {
+        Some(value) => data.insert(key, value),
+        None => data.remove(&key),
+      };
+    }
+    Ok(())
+  }
+}
+
+impl<'a> KVStore<'a> for MemoryKVStoreImpl<'a> {
+  type Range = std::vec::IntoIter<MemoryEntry>;
+  type Entry = MemoryEntry;
+  type Value = Vec<u8>;
+  type Error = PersistenceError;
+
+  fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Self::Value>, Self::Error> {
+    let key = key.as_ref();
+    match self.changes.borrow().get(key) {
+      Some(value) => Ok(value.clone()),
+      None => Ok(self.snapshot.get(key).cloned()),
+    }
+  }
+
+  fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<(), Self::Error> {
+    self
+      .changes
+      .borrow_mut()
+      .insert(key.as_ref().to_vec(), Some(value.as_ref().to_vec()));
+    Ok(())
+  }
+
+  fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+    self.changes.borrow_mut().insert(key.to_vec(), None);
+    Ok(())
+  }
+
+  fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+    let keys = self
+      .range(from..to)?
+      .map(|entry| entry.key)
+      .collect::<Vec<_>>();
+    let mut changes = self.changes.borrow_mut();
+    for key in keys {
+      changes.insert(key, None);
+    }
+    Ok(())
+  }
+
+  fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> Result<Self::Range, Self::Error> {
+    let start = match range.start_bound() {
+      Bound::Included(start) => Bound::Included(start.as_ref().to_vec()),
+      Bound::Excluded(start) => Bound::Excluded(start.as_ref().to_vec()),
+      Bound::Unbounded => Bound::Unbounded,
+    };
+    // Like RocksDB, the upper bound itself is never included
+    let end = match range.end_bound() {
+      Bound::Included(end) | Bound::Excluded(end) => Bound::Excluded(end.as_ref().to_vec()),
+      Bound::Unbounded => Bound::Unbounded,
+    };
+    // BTreeMap::range panics if the range is empty
+    if let (Bound::Included(start) | Bound::Excluded(start), Bound::Excluded(end)) = (&start, &end)
+    {
+      if start >= end {
+        return Ok(vec![].into_iter());
+      }
+    }
+
+    let bounds = (start, end);
+    let mut entries = self
+      .snapshot
+      .range::<Vec<u8>, _>(bounds.clone())
+      .map(|(key, value)| (key.clone(), Some(value.clone())))
+      .collect::<BTreeMap<_, _>>();
+    for (key, value) in self.changes.borrow().range::<Vec<u8>, _>(bounds) {
+      entries.insert(key.clone(), value.clone());
+    }
+    let entries = entries
+      .into_iter()
+      .filter_map(|(key, value)| Some(MemoryEntry::new(key, value?)))
+      .collect::<Vec<_>>();
+    Ok(entries.into_iter())
+  }
+
+  fn next_back_entry(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+    let changes = self.changes.borrow();
+    // The last committed entry that is not removed by this transaction
+    let committed = self
+      .snapshot
+      .range::<[u8], _>((Bound::Unbounded, Bound::Included(key)))
+      .rev()
+      .find(|(key, _)| !matches!(changes.get(*key), Some(None)));
+    let changed = changes
+      .range::<[u8], _>((Bound::Unbounded, Bound::Included(key)))
+      .rev()
+      .find_map(|(key, value)| Some((key, value.as_ref()?)));
+    let entry = match (committed, changed) {
+      (Some(committed), Some(changed)) => Some(if committed.0 > changed.0 {
+        committed
+      } else {
+        changed
+      }),
+      (committed, changed) => committed.or(changed),
+    };
+    Ok(entry.map(|(key, value)| MemoryEntry::new(key.clone(), value.clone())))
+  }
+
+  fn cipher(&self) -> Option<&CollabCipher> {
+    self.db.cipher.as_ref()
+  }
+}
+
+pub struct MemoryEntry {
+  key: Vec<u8>,
+  value: Vec<u8>,
+}
+
+impl MemoryEntry {
+  pub fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+    Self { key, value }
+  }
+}
+
+impl KVEntry for MemoryEntry {
+  fn key(&self) -> &[u8] {
+    self.key.as_ref()
+  }
+
+  fn value(&self) -> &[u8] {
+    self.value.as_ref()
+  }
+}
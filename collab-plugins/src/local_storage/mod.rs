@@ -1,11 +1,24 @@
 pub mod kv;
+pub mod memory;
 
+/// The plugins that persist the collabs in the [crate::CollabKVDB] of the native platforms,
+/// whichever backend it uses.
 #[cfg(not(target_arch = "wasm32"))]
+pub mod disk;
+
+#[cfg(all(feature = "rocksdb", not(target_arch = "wasm32")))]
 pub mod rocksdb;
 
+#[cfg(all(feature = "sqlite", not(target_arch = "wasm32")))]
+pub mod sqlite;
+
 #[cfg(target_arch = "wasm32")]
 pub mod indexeddb;
 
+mod archive;
+mod compaction;
 mod storage_config;
 
+pub use archive::*;
+pub use compaction::*;
 pub use storage_config::*;
@@ -4,6 +4,7 @@ use std::path::Path;
 use std::sync::Arc;
 
 use crate::local_storage::kv::doc::CollabKVAction;
+use crate::local_storage::kv::encryption::CollabCipher;
 
 use crate::local_storage::kv::{KVEntry, KVStore, KVTransactionDB, PersistenceError};
 use rocksdb::Direction::Forward;
@@ -16,6 +17,7 @@ use rocksdb::{
 #[derive(Clone)]
 pub struct KVTransactionDBRocksdbImpl {
   db: Arc<TransactionDB>,
+  cipher: Option<CollabCipher>,
 }
 
 impl KVTransactionDBRocksdbImpl {
@@ -109,7 +111,21 @@ impl KVTransactionDBRocksdbImpl {
       },
     }?;
 
-    Ok(Self { db: Arc::new(db) })
+    Ok(Self {
+      db: Arc::new(db),
+      cipher: None,
+    })
+  }
+
+  /// Encrypts the documents written from now on with the given cipher, and decrypts the
+  /// encrypted ones. See [crate::local_storage::kv::encryption].
+  pub fn with_cipher(mut self, cipher: CollabCipher) -> Self {
+    self.cipher = Some(cipher);
+    self
+  }
+
+  pub fn cipher(&self) -> Option<&CollabCipher> {
+    self.cipher.as_ref()
   }
 
   pub async fn is_exist(
@@ -149,7 +165,7 @@ impl KVTransactionDB for KVTransactionDBRocksdbImpl {
     let txn = self
       .db
       .transaction_opt(&WriteOptions::default(), &txn_options);
-    RocksdbKVStoreImpl::new(txn)
+    RocksdbKVStoreImpl::new(txn).with_cipher(self.cipher.clone())
   }
 
   fn write_txn<'a, 'b>(&'b self) -> Self::TransactionAction<'a>
@@ -160,7 +176,7 @@ impl KVTransactionDB for KVTransactionDBRocksdbImpl {
     let txn = self
       .db
       .transaction_opt(&WriteOptions::default(), &txn_options);
-    RocksdbKVStoreImpl::new(txn)
+    RocksdbKVStoreImpl::new(txn).with_cipher(self.cipher.clone())
   }
 
   fn with_write_txn<'a, 'b, Output>(
@@ -174,7 +190,7 @@ impl KVTransactionDB for KVTransactionDBRocksdbImpl {
     let txn = self
       .db
       .transaction_opt(&WriteOptions::default(), &txn_options);
-    let store = RocksdbKVStoreImpl::new(txn);
+    let store = RocksdbKVStoreImpl::new(txn).with_cipher(self.cipher.clone());
     let result = f(&store)?;
     store.0.commit()?;
     Ok(result)
@@ -187,13 +203,18 @@ impl KVTransactionDB for KVTransactionDBRocksdbImpl {
 
 /// Implementation of [KVStore] for [KVTransactionDBRocksdbImpl]. This is a wrapper around [Transaction].
 // pub struct RocksKVStoreImpl<'a, DB: Send + Sync>(Transaction<'a, DB>);
-pub struct RocksdbKVStoreImpl<'a, DB: Send>(Transaction<'a, DB>);
+pub struct RocksdbKVStoreImpl<'a, DB: Send>(Transaction<'a, DB>, Option<CollabCipher>);
 
 unsafe impl<DB: Send> Send for RocksdbKVStoreImpl<'_, DB> {}
 
 impl<'a, DB: Send + Sync> RocksdbKVStoreImpl<'a, DB> {
   pub fn new(txn: Transaction<'a, DB>) -> Self {
-    Self(txn)
+    Self(txn, None)
+  }
+
+  pub fn with_cipher(mut self, cipher: Option<CollabCipher>) -> Self {
+    self.1 = cipher;
+    self
   }
 
   pub fn commit_transaction(self) -> Result<(), PersistenceError> {
@@ -292,6 +313,10 @@ impl<'a, DB: Send + Sync> KVStore<'a> for RocksdbKVStoreImpl<'a, DB> {
       Ok(None)
     }
   }
+
+  fn cipher(&self) -> Option<&CollabCipher> {
+    self.1.as_ref()
+  }
 }
 
 impl<'a, DB: Send + Sync> From<Transaction<'a, DB>> for RocksdbKVStoreImpl<'a, DB> {
@@ -1,4 +1,4 @@
 pub mod kv_impl;
-pub mod rocksdb_plugin;
-// pub mod snapshot_plugin;
-pub mod util;
+
+// The plugins work with any backend of the CollabKVDB, they are kept here for the existing paths
+pub use crate::local_storage::disk::{rocksdb_plugin, util, version_storage};
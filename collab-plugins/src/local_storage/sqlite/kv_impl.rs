@@ -0,0 +1,316 @@
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use rusqlite::{Connection, OptionalExtension, params, params_from_iter};
+
+use crate::local_storage::kv::doc::CollabKVAction;
+use crate::local_storage::kv::encryption::CollabCipher;
+use crate::local_storage::kv::{KVEntry, KVStore, KVTransactionDB, PersistenceError};
+
+/// The name of the database file, in the directory passed to [KVTransactionDBSqliteImpl::open].
+pub const SQLITE_DB_FILE: &str = "collab.sqlite";
+
+/// Implementation of [KVTransactionDB] backed by SQLite.
+///
+/// The keys and values are stored as blobs in a single table. SQLite compares the blobs byte by
+/// byte, so the keys are ordered in the same way as in RocksDB and the key encoding of
+/// [crate::local_storage::kv::keys] works unchanged.
+///
+/// Every transaction runs on its own connection, taken from a pool. The database is opened in WAL
+/// mode: a read transaction sees a consistent snapshot of the data, and doesn't block the write
+/// transactions, which are serialized.
+#[derive(Clone)]
+pub struct KVTransactionDBSqliteImpl {
+  pool: Arc<ConnectionPool>,
+  cipher: Option<CollabCipher>,
+}
+
+impl KVTransactionDBSqliteImpl {
+  /// Open a new SQLite database in the given directory, like the RocksDB database.
+  pub fn open(path: impl AsRef<Path>) -> Result<Self, PersistenceError> {
+    std::fs::create_dir_all(path.as_ref()).map_err(|err| PersistenceError::Internal(err.into()))?;
+    let pool = ConnectionPool {
+      path: path.as_ref().join(SQLITE_DB_FILE),
+      idle: Mutex::new(vec![]),
+    };
+    let conn = pool.connect()?;
+    conn.execute_batch(
+      "CREATE TABLE IF NOT EXISTS collab_kv \
+       (key BLOB PRIMARY KEY, value BLOB NOT NULL) WITHOUT ROWID;",
+    )?;
+    pool.release(conn);
+    Ok(Self {
+      pool: Arc::new(pool),
+      cipher: None,
+    })
+  }
+
+  /// Encrypts the documents written from now on with the given cipher, and decrypts the
+  /// encrypted ones. See [crate::local_storage::kv::encryption].
+  pub fn with_cipher(mut self, cipher: CollabCipher) -> Self {
+    self.cipher = Some(cipher);
+    self
+  }
+
+  pub fn cipher(&self) -> Option<&CollabCipher> {
+    self.cipher.as_ref()
+  }
+
+  pub async fn is_exist(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    object_id: &str,
+  ) -> Result<bool, PersistenceError> {
+    let read_txn = self.read_txn();
+    Ok(read_txn.is_exist(uid, workspace_id, object_id))
+  }
+
+  pub async fn delete_doc(
+    &self,
+    uid: i64,
+    workspace_id: &str,
+    doc_id: &str,
+  ) -> Result<(), PersistenceError> {
+    self.with_write_txn(|txn| txn.delete_doc(uid, workspace_id, doc_id))?;
+    Ok(())
+  }
+
+  /// The write transactions take the database lock when they begin, so that two of them never
+  /// fail to upgrade a read lock. The read transactions take their snapshot when they begin, like
+  /// the RocksDB ones.
+  fn begin(&self, sql: &str) -> SqliteKVStoreImpl<'_> {
+    let conn = self.pool.acquire().and_then(|conn| {
+      conn.execute_batch(sql)?;
+      Ok(conn)
+    });
+    if let Err(err) = &conn {
+      tracing::error!("🔴begin sqlite transaction failed: {}", err);
+    }
+    SqliteKVStoreImpl {
+      pool: &self.pool,
+      conn: conn.ok().map(Mutex::new),
+      cipher: self.cipher.clone(),
+    }
+  }
+}
+
+impl KVTransactionDB for KVTransactionDBSqliteImpl {
+  type TransactionAction<'a> = SqliteKVStoreImpl<'a>;
+
+  fn read_txn<'a, 'b>(&'b self) -> Self::TransactionAction<'a>
+  where
+    'b: 'a,
+  {
+    // The snapshot of a deferred transaction is only taken by its first read
+    self.begin("BEGIN DEFERRED; SELECT 1 FROM collab_kv LIMIT 1;")
+  }
+
+  fn write_txn<'a, 'b>(&'b self) -> Self::TransactionAction<'a>
+  where
+    'b: 'a,
+  {
+    self.begin("BEGIN IMMEDIATE")
+  }
+
+  fn with_write_txn<'a, 'b, Output>(
+    &'b self,
+    f: impl FnOnce(&Self::TransactionAction<'a>) -> Result<Output, PersistenceError>,
+  ) -> Result<Output, PersistenceError>
+  where
+    'b: 'a,
+  {
+    let store = self.write_txn();
+    let result = f(&store)?;
+    store.commit_transaction()?;
+    Ok(result)
+  }
+
+  fn flush(&self) -> Result<(), PersistenceError> {
+    Ok(())
+  }
+}
+
+struct ConnectionPool {
+  path: PathBuf,
+  idle: Mutex<Vec<Connection>>,
+}
+
+impl ConnectionPool {
+  fn connect(&self) -> Result<Connection, PersistenceError> {
+    let conn = Connection::open(&self.path)?;
+    conn.busy_timeout(Duration::from_secs(5))?;
+    conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;")?;
+    Ok(conn)
+  }
+
+  fn acquire(&self) -> Result<Connection, PersistenceError> {
+    match self.idle.lock().unwrap().pop() {
+      Some(conn) => Ok(conn),
+      None => self.connect(),
+    }
+  }
+
+  fn release(&self, conn: Connection) {
+    self.idle.lock().unwrap().push(conn);
+  }
+}
+
+/// Implementation of [KVStore] for [KVTransactionDBSqliteImpl]. The transaction is rolled back
+/// when it's dropped without being committed.
+pub struct SqliteKVStoreImpl<'a> {
+  pool: &'a ConnectionPool,
+  /// None if the transaction couldn't begin, the error is returned by every operation.
+  conn: Option<Mutex<Connection>>,
+  cipher: Option<CollabCipher>,
+}
+
+impl SqliteKVStoreImpl<'_> {
+  pub fn commit_transaction(mut self) -> Result<(), PersistenceError> {
+    let conn = self
+      .conn
+      .take()
+      .ok_or_else(not_started)?
+      .into_inner()
+      .unwrap();
+    conn.execute_batch("COMMIT")?;
+    self.pool.release(conn);
+    Ok(())
+  }
+
+  fn conn(&self) -> Result<MutexGuard<'_, Connection>, PersistenceError> {
+    let conn = self.conn.as_ref().ok_or_else(not_started)?;
+    Ok(conn.lock().unwrap())
+  }
+}
+
+impl Drop for SqliteKVStoreImpl<'_> {
+  fn drop(&mut self) {
+    if let Some(conn) = self.conn.take() {
+      let conn = conn.into_inner().unwrap();
+      // A connection that is still in a transaction can't be reused
+      if conn.execute_batch("ROLLBACK").is_ok() {
+        self.pool.release(conn);
+      }
+    }
+  }
+}
+
+fn not_started() -> PersistenceError {
+  PersistenceError::Internal(anyhow::anyhow!("the sqlite transaction is not started"))
+}
+
+impl<'a> KVStore<'a> for SqliteKVStoreImpl<'a> {
+  type Range = std::vec::IntoIter<SqliteEntry>;
+  type Entry = SqliteEntry;
+  type Value = Vec<u8>;
+  type Error = PersistenceError;
+
+  fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Self::Value>, Self::Error> {
+    let value = self
+      .conn()?
+      .query_row(
+        "SELECT value FROM collab_kv WHERE key = ?1",
+        params![key.as_ref()],
+        |row| row.get(0),
+      )
+      .optional()?;
+    Ok(value)
+  }
+
+  fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<(), Self::Error> {
+    self.conn()?.execute(
+      "INSERT INTO collab_kv (key, value) VALUES (?1, ?2) \
+       ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+      params![key.as_ref(), value.as_ref()],
+    )?;
+    Ok(())
+  }
+
+  fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+    self
+      .conn()?
+      .execute("DELETE FROM collab_kv WHERE key = ?1", params![key])?;
+    Ok(())
+  }
+
+  fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+    self.conn()?.execute(
+      "DELETE FROM collab_kv WHERE key >= ?1 AND key < ?2",
+      params![from, to],
+    )?;
+    Ok(())
+  }
+
+  fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> Result<Self::Range, Self::Error> {
+    // Like RocksDB, the start key is always included and the end key never is
+    let start = match range.start_bound() {
+      Bound::Included(start) | Bound::Excluded(start) => Some(start.as_ref().to_vec()),
+      Bound::Unbounded => None,
+    };
+    let end = match range.end_bound() {
+      Bound::Included(end) | Bound::Excluded(end) => Some(end.as_ref().to_vec()),
+      Bound::Unbounded => None,
+    };
+    // Read within the transaction, the entries don't depend on the connection once returned
+    let mut sql = "SELECT key, value FROM collab_kv WHERE 1 = 1".to_string();
+    let mut bounds: Vec<&[u8]> = vec![];
+    if let Some(start) = &start {
+      sql.push_str(" AND key >= ?");
+      bounds.push(start);
+    }
+    if let Some(end) = &end {
+      sql.push_str(" AND key < ?");
+      bounds.push(end);
+    }
+    sql.push_str(" ORDER BY key");
+
+    let conn = self.conn()?;
+    let mut stmt = conn.prepare_cached(&sql)?;
+    let entries = stmt
+      .query_map(params_from_iter(bounds), |row| {
+        Ok(SqliteEntry::new(row.get(0)?, row.get(1)?))
+      })?
+      .collect::<Result<Vec<_>, _>>()?;
+    Ok(entries.into_iter())
+  }
+
+  fn next_back_entry(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+    let entry = self
+      .conn()?
+      .query_row(
+        "SELECT key, value FROM collab_kv WHERE key <= ?1 ORDER BY key DESC LIMIT 1",
+        params![key],
+        |row| Ok(SqliteEntry::new(row.get(0)?, row.get(1)?)),
+      )
+      .optional()?;
+    Ok(entry)
+  }
+
+  fn cipher(&self) -> Option<&CollabCipher> {
+    self.cipher.as_ref()
+  }
+}
+
+pub struct SqliteEntry {
+  key: Vec<u8>,
+  value: Vec<u8>,
+}
+
+impl SqliteEntry {
+  pub fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+    Self { key, value }
+  }
+}
+
+impl KVEntry for SqliteEntry {
+  fn key(&self) -> &[u8] {
+    self.key.as_ref()
+  }
+
+  fn value(&self) -> &[u8] {
+    self.value.as_ref()
+  }
+}
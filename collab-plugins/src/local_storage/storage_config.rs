@@ -1,3 +1,5 @@
+use crate::local_storage::CompactionPolicy;
+
 #[derive(Clone)]
 pub struct CollabPersistenceConfig {
   /// Enable snapshot. Default is [false].
@@ -5,6 +7,9 @@ pub struct CollabPersistenceConfig {
   /// Generate a snapshot every N updates
   /// Default is 100. The value must be greater than 0.
   pub snapshot_per_update: u32,
+  /// Merge the update log of the documents into their doc state, see [CompactionPolicy].
+  /// Default is [None].
+  pub compaction: Option<CompactionPolicy>,
 }
 
 impl CollabPersistenceConfig {
@@ -22,6 +27,11 @@ impl CollabPersistenceConfig {
     self.snapshot_per_update = snapshot_per_update;
     self
   }
+
+  pub fn compaction(mut self, compaction: CompactionPolicy) -> Self {
+    self.compaction = Some(compaction);
+    self
+  }
 }
 
 impl Default for CollabPersistenceConfig {
@@ -29,6 +39,7 @@ impl Default for CollabPersistenceConfig {
     Self {
       enable_snapshot: true,
       snapshot_per_update: 100,
+      compaction: None,
     }
   }
 }
@@ -0,0 +1,317 @@
+use bytes::Bytes;
+use collab::core::collab_version::CollabVersion;
+use collab::error::EncodedCollabError;
+use collab_entity::CollabType;
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+use collab_plugins::local_storage::kv::encryption::{CollabCipher, EncryptionKey};
+use collab_plugins::local_storage::kv::key_file::LocalKeyFile;
+use collab_plugins::local_storage::kv::keys::{make_doc_id_key_v0, make_doc_id_key_v1};
+use collab_plugins::local_storage::kv::snapshot::SnapshotAction;
+use collab_plugins::local_storage::kv::version::VersionAction;
+use collab_plugins::local_storage::kv::{KVStore, KVTransactionDB, PersistenceError};
+use collab_plugins::local_storage::memory::kv_impl::KVTransactionDBMemoryImpl;
+use collab_plugins::local_storage::{
+  CollabArchiveReader, ImportConflictPolicy, export_collabs, import_collabs,
+};
+use uuid::Uuid;
+use yrs::{Doc, GetString, Text, Transact};
+
+use crate::disk::util::rocks_db;
+
+const UID: i64 = 1;
+
+fn write_doc<'a, S>(store: &S, uid: i64, workspace_id: &str, object_id: &str, text: &str)
+where
+  S: KVStore<'a> + 'a,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let doc = Doc::new();
+  store
+    .create_new_doc(uid, workspace_id, object_id, &doc.transact())
+    .unwrap();
+  let content = doc.get_or_insert_text("text");
+  let mut txn = doc.transact_mut();
+  content.insert(&mut txn, 0, text);
+  let update = txn.encode_update_v1();
+  drop(txn);
+  store
+    .push_update(uid, workspace_id, object_id, &update)
+    .unwrap();
+}
+
+fn read_doc<'a, S>(store: &S, uid: i64, workspace_id: &str, object_id: &str) -> String
+where
+  S: KVStore<'a> + 'a,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let doc = Doc::new();
+  store.load_doc(uid, workspace_id, object_id, &doc).unwrap();
+  let text = doc.get_or_insert_text("text");
+  text.get_string(&doc.transact())
+}
+
+fn new_id() -> String {
+  Uuid::new_v4().to_string()
+}
+
+#[test]
+fn export_import_round_trip_test() {
+  let (_, db) = rocks_db();
+  let (w1, w2) = (new_id(), new_id());
+  let (o1, o2, o3) = (new_id(), new_id(), new_id());
+  db.with_write_txn(|txn| {
+    write_doc(txn, UID, &w1, &o1, "hello");
+    write_doc(txn, UID, &w1, &o2, "world");
+    write_doc(txn, UID, &w2, &o3, "folder");
+    txn.create_snapshot_with_data(UID, &w1, &o1, vec![1, 2, 3])
+  })
+  .unwrap();
+  // The objects of another user are not exported
+  db.with_write_txn(|txn| {
+    write_doc(txn, 2, &w1, &new_id(), "other user");
+    Ok(())
+  })
+  .unwrap();
+
+  let mut archive = vec![];
+  let report = export_collabs(
+    &db.read_txn(),
+    UID,
+    &mut archive,
+    |_, object_id| {
+      if object_id == o3 {
+        CollabType::Folder
+      } else {
+        CollabType::Document
+      }
+    },
+    false,
+  )
+  .unwrap();
+  assert_eq!(report.objects, 3);
+  assert_eq!(report.snapshots, 1);
+
+  let reader = CollabArchiveReader::new(archive.as_slice()).unwrap();
+  assert_eq!(reader.object_count(), 3);
+  let collabs = reader.collect::<Result<Vec<_>, _>>().unwrap();
+  let folder = collabs.iter().find(|c| c.object_id == o3).unwrap();
+  assert_eq!(folder.collab_type, CollabType::Folder);
+  assert_eq!(folder.workspace_id, w2);
+  assert_eq!(folder.uid, UID);
+
+  let imported = KVTransactionDBMemoryImpl::new();
+  let report = imported
+    .with_write_txn(|txn| import_collabs(txn, archive.as_slice(), ImportConflictPolicy::Skip))
+    .unwrap();
+  assert_eq!(report.objects, 3);
+  assert_eq!(report.snapshots, 1);
+  assert!(report.conflicts.is_empty());
+
+  let txn = imported.read_txn();
+  assert_eq!(read_doc(&txn, UID, &w1, &o1), "hello");
+  assert_eq!(read_doc(&txn, UID, &w1, &o2), "world");
+  assert_eq!(read_doc(&txn, UID, &w2, &o3), "folder");
+  // The merged doc state is stored without updates
+  assert_eq!(txn.number_of_updates(UID, &w1, &o1), 0);
+
+  let snapshots = txn.get_snapshots(UID, &w1, &o1);
+  let original = db.read_txn().get_snapshots(UID, &w1, &o1);
+  assert_eq!(snapshots.len(), 1);
+  assert_eq!(snapshots[0].data, vec![1, 2, 3]);
+  assert_eq!(snapshots[0].created_at, original[0].created_at);
+}
+
+#[test]
+fn import_conflict_test() {
+  let workspace_id = new_id();
+  let object_id = new_id();
+  let source = KVTransactionDBMemoryImpl::new();
+  source
+    .with_write_txn(|txn| {
+      write_doc(txn, UID, &workspace_id, &object_id, "archived");
+      Ok(())
+    })
+    .unwrap();
+  let mut archive = vec![];
+  export_collabs(
+    &source.read_txn(),
+    UID,
+    &mut archive,
+    |_, _| CollabType::Document,
+    false,
+  )
+  .unwrap();
+
+  let import = |policy: ImportConflictPolicy| {
+    let target = KVTransactionDBMemoryImpl::new();
+    target
+      .with_write_txn(|txn| {
+        write_doc(txn, UID, &workspace_id, &object_id, "local");
+        Ok(())
+      })
+      .unwrap();
+    let report = target
+      .with_write_txn(|txn| import_collabs(txn, archive.as_slice(), policy))
+      .unwrap();
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(report.conflicts[0].object_id, object_id);
+    assert_eq!(report.conflicts[0].resolution, policy);
+    let text = read_doc(&target.read_txn(), UID, &workspace_id, &object_id);
+    (report.objects, text)
+  };
+
+  assert_eq!(import(ImportConflictPolicy::Skip), (0, "local".to_string()));
+  assert_eq!(
+    import(ImportConflictPolicy::Overwrite),
+    (1, "archived".to_string())
+  );
+  let (objects, text) = import(ImportConflictPolicy::Merge);
+  assert_eq!(objects, 1);
+  assert!(text.contains("local") && text.contains("archived"));
+}
+
+#[test]
+fn import_invalid_archive_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  let result =
+    db.with_write_txn(|txn| import_collabs(txn, b"not an archive".as_slice(), Default::default()));
+  assert!(matches!(result, Err(PersistenceError::InvalidData(_))));
+  assert!(db.is_empty());
+}
+
+#[test]
+fn import_corrupted_archive_test() {
+  let source = KVTransactionDBMemoryImpl::new();
+  source
+    .with_write_txn(|txn| {
+      write_doc(txn, UID, &new_id(), &new_id(), "archived");
+      Ok(())
+    })
+    .unwrap();
+  let mut archive = vec![];
+  export_collabs(
+    &source.read_txn(),
+    UID,
+    &mut archive,
+    |_, _| CollabType::Document,
+    false,
+  )
+  .unwrap();
+
+  // The object ends with its frame, followed by the checksum and the empty list of snapshots
+  let index = archive.len() - 8 - 4 - 1;
+  archive[index] ^= 0xff;
+  let db = KVTransactionDBMemoryImpl::new();
+  let result = db.with_write_txn(|txn| import_collabs(txn, archive.as_slice(), Default::default()));
+  assert!(matches!(
+    result,
+    Err(PersistenceError::EncodedCollab(
+      EncodedCollabError::ChecksumMismatch { .. }
+    ))
+  ));
+  assert!(db.is_empty());
+}
+
+#[test]
+fn export_encrypted_store_test() {
+  let (path, db) = rocks_db();
+  drop(db);
+  let workspace_id = new_id();
+  let keys = LocalKeyFile::open(path.join("keys.json")).unwrap();
+  keys
+    .insert_key(&workspace_id, EncryptionKey::generate())
+    .unwrap();
+  let db = KVTransactionDBMemoryImpl::new().with_cipher(CollabCipher::new(keys));
+  let object_id = new_id();
+  db.with_write_txn(|txn| {
+    write_doc(txn, UID, &workspace_id, &object_id, "secret");
+    txn.insert_version(
+      UID,
+      &workspace_id,
+      &object_id,
+      &CollabVersion::new("v1".to_string(), Bytes::new()),
+    )
+  })
+  .unwrap();
+
+  // The archive is in plaintext, it must be allowed explicitly
+  let mut archive = vec![];
+  let result = export_collabs(
+    &db.read_txn(),
+    UID,
+    &mut archive,
+    |_, _| CollabType::Document,
+    false,
+  );
+  assert!(matches!(
+    result,
+    Err(PersistenceError::PlaintextExportNotAllowed)
+  ));
+  assert!(archive.is_empty());
+
+  let report = export_collabs(
+    &db.read_txn(),
+    UID,
+    &mut archive,
+    |_, _| CollabType::Document,
+    true,
+  )
+  .unwrap();
+  assert_eq!(report.objects, 1);
+  assert_eq!(report.dropped_versions, 1);
+
+  let imported = KVTransactionDBMemoryImpl::new();
+  imported
+    .with_write_txn(|txn| import_collabs(txn, archive.as_slice(), ImportConflictPolicy::Skip))
+    .unwrap();
+  let txn = imported.read_txn();
+  assert_eq!(read_doc(&txn, UID, &workspace_id, &object_id), "secret");
+}
+
+#[test]
+fn list_workspace_and_object_ids_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  let workspace_id = new_id();
+  let object_id = new_id();
+  let uid = UID.to_be_bytes();
+  db.with_write_txn(|txn| {
+    write_doc(txn, UID, &workspace_id, &object_id, "uuid");
+    write_doc(txn, UID, "workspace_1", "object_1", "not a uuid");
+    write_doc(txn, 2, &workspace_id, "object_2", "other user");
+    // A key of the v0 layout, without workspace id, whose object id is a uuid
+    txn.insert(
+      make_doc_id_key_v0(&uid, new_id().as_bytes()),
+      42_u64.to_be_bytes(),
+    )?;
+    Ok(())
+  })
+  .unwrap();
+
+  let txn = db.read_txn();
+  // Neither the non uuid workspace id nor the v0 key can be read back as a workspace id
+  let mut workspace_ids = txn.get_all_workspace_ids().unwrap();
+  workspace_ids.sort();
+  assert_eq!(workspace_ids, vec![workspace_id.clone()]);
+
+  let object_ids = txn
+    .get_all_object_ids(UID, &workspace_id)
+    .unwrap()
+    .collect::<Vec<_>>();
+  assert_eq!(object_ids, vec![object_id.clone()]);
+  let object_ids = txn
+    .get_all_object_ids(UID, "workspace_1")
+    .unwrap()
+    .collect::<Vec<_>>();
+  assert_eq!(object_ids, vec!["object_1".to_string()]);
+  let object_ids = txn
+    .get_all_object_ids(2, &workspace_id)
+    .unwrap()
+    .collect::<Vec<_>>();
+  assert_eq!(object_ids, vec!["object_2".to_string()]);
+  assert!(
+    txn
+      .get(make_doc_id_key_v1(&uid, workspace_id.as_bytes(), object_id.as_bytes()).as_ref())
+      .unwrap()
+      .is_some()
+  );
+}
@@ -0,0 +1,260 @@
+use crate::disk::script::{CollabPersistenceTest, disk_plugin_with_db};
+use assert_json_diff::assert_json_eq;
+use bytes::Bytes;
+
+use collab::core::collab::{CollabOptions, default_client_id};
+use collab::core::collab_version::CollabVersion;
+use collab::core::origin::CollabOrigin;
+use collab::error::CollabError;
+use collab::preclude::Collab;
+use collab_entity::CollabType;
+use collab_plugins::local_storage::disk::rocksdb_plugin::RocksdbDiskPlugin;
+use collab_plugins::local_storage::disk::util::KVDBCollabPersistenceImpl;
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+use collab_plugins::local_storage::kv::version::VersionAction;
+use collab_plugins::local_storage::kv::{KVTransactionDB, PersistenceError};
+use collab_plugins::local_storage::{CollabPersistenceConfig, CompactionPolicy, CompactionStats};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn compact_doc_test() {
+  let doc_id = "1".to_string();
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::new());
+  let disk_plugin = disk_plugin_with_db(
+    test.uid,
+    test.workspace_id.clone(),
+    test.db.clone(),
+    &doc_id,
+    CollabType::Unknown,
+  );
+  let options = CollabOptions::new(doc_id.to_string(), default_client_id())
+    .with_data_source(data_source(&test).into());
+  let mut collab = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  collab.add_plugin(Box::new(disk_plugin));
+  collab.initialize();
+
+  for i in 0..100 {
+    collab.insert("text", format!("{}{}", i, "a".repeat(256)));
+  }
+  let value = collab.to_json_value();
+  let updates = test
+    .db
+    .read_txn()
+    .get_all_updates(test.uid, &test.workspace_id, &doc_id)
+    .unwrap();
+  assert_eq!(updates.len(), 100);
+
+  let compacted = test
+    .db
+    .with_write_txn(|txn| txn.compact_doc(test.uid, &test.workspace_id, &doc_id))
+    .unwrap();
+  let report = compacted.report;
+  assert!(report.before > 100 * 256);
+  assert!(report.after < 1024);
+
+  let updates = test
+    .db
+    .read_txn()
+    .get_all_updates(test.uid, &test.workspace_id, &doc_id)
+    .unwrap();
+  assert!(updates.is_empty());
+
+  let options = CollabOptions::new(doc_id.to_string(), default_client_id())
+    .with_data_source(data_source(&test).into());
+  let reopened = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  assert_json_eq!(reopened.to_json_value(), value);
+
+  // The returned doc state is the stored one
+  let options = CollabOptions::new(doc_id.to_string(), default_client_id())
+    .with_data_source(compacted.encoded_collab.into());
+  let reopened = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  assert_json_eq!(reopened.to_json_value(), value);
+}
+
+#[tokio::test]
+async fn compact_doc_with_versions_test() {
+  let doc_id = "1".to_string();
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::new());
+  let mut collab = open_collab(&test, &doc_id, None);
+  collab.insert("title", "hello");
+  let version = CollabVersion::new("v1".to_string(), Bytes::new());
+  test
+    .db
+    .with_write_txn(|txn| txn.insert_version(test.uid, &test.workspace_id, &doc_id, &version))
+    .unwrap();
+  collab.remove("title");
+
+  let result = test
+    .db
+    .with_write_txn(|txn| txn.compact_doc(test.uid, &test.workspace_id, &doc_id));
+  assert!(matches!(
+    result,
+    Err(PersistenceError::Collab(
+      CollabError::CompactionWithVersions(_)
+    ))
+  ));
+  let read_txn = test.db.read_txn();
+  assert_eq!(
+    read_txn.number_of_updates(test.uid, &test.workspace_id, &doc_id),
+    2
+  );
+  drop(read_txn);
+
+  // Merging the updates keeps the deleted items the version needs
+  let compaction = test
+    .db
+    .with_write_txn(|txn| txn.compact_updates(test.uid, &test.workspace_id, &doc_id))
+    .unwrap();
+  assert_eq!(compaction.merged_updates, 2);
+
+  test
+    .db
+    .with_write_txn(|txn| txn.delete_all_versions(test.uid, &test.workspace_id, &doc_id))
+    .unwrap();
+  test
+    .db
+    .with_write_txn(|txn| txn.compact_doc(test.uid, &test.workspace_id, &doc_id))
+    .unwrap();
+}
+
+#[tokio::test]
+async fn compact_not_exist_doc_test() {
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::new());
+  let result = test
+    .db
+    .with_write_txn(|txn| txn.compact_doc(test.uid, &test.workspace_id, "not_exist"));
+  assert!(matches!(result, Err(PersistenceError::RecordNotFound(_))));
+}
+
+#[tokio::test]
+async fn compact_updates_test() {
+  let doc_id = "1".to_string();
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::new());
+  let mut collab = open_collab(&test, &doc_id, None);
+  collab.insert("title", "hello");
+  collab.insert("title", "world");
+  collab.remove("title");
+  collab.insert("text", "a");
+  let value = collab.to_json_value();
+
+  let compaction = test
+    .db
+    .with_write_txn(|txn| txn.compact_updates(test.uid, &test.workspace_id, &doc_id))
+    .unwrap();
+  assert_eq!(compaction.merged_updates, 4);
+  assert!(compaction.merged_update_bytes > 0);
+  let read_txn = test.db.read_txn();
+  assert_eq!(
+    read_txn.number_of_updates(test.uid, &test.workspace_id, &doc_id),
+    0
+  );
+  drop(read_txn);
+
+  // Nothing left to merge
+  let compaction = test
+    .db
+    .with_write_txn(|txn| txn.compact_updates(test.uid, &test.workspace_id, &doc_id))
+    .unwrap();
+  assert_eq!(compaction.merged_updates, 0);
+
+  let reopened = open_collab(&test, &doc_id, None);
+  assert_json_eq!(reopened.to_json_value(), value);
+}
+
+#[tokio::test]
+async fn auto_compaction_by_update_count_test() {
+  let doc_id = "1".to_string();
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::new());
+  let policy = CompactionPolicy::new().max_updates(10);
+  let plugin = disk_plugin(&test, &doc_id, policy.clone());
+  let mut collab = open_collab(&test, &doc_id, Some(plugin.clone()));
+
+  for i in 0..25 {
+    collab.insert("text", i.to_string());
+    plugin.wait_for_compaction();
+  }
+  let read_txn = test.db.read_txn();
+  assert_eq!(
+    read_txn.number_of_updates(test.uid, &test.workspace_id, &doc_id),
+    5
+  );
+  drop(read_txn);
+
+  let stats = policy.metrics().stats();
+  assert_eq!(stats.compactions, 2);
+  assert_eq!(stats.merged_updates, 20);
+  assert_eq!(stats.failures, 0);
+  assert!(stats.bytes_before > 0);
+
+  let reopened = open_collab(&test, &doc_id, None);
+  assert_json_eq!(reopened.to_json_value(), collab.to_json_value());
+}
+
+#[tokio::test]
+async fn auto_compaction_by_update_bytes_test() {
+  let doc_id = "1".to_string();
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::new());
+  let policy = CompactionPolicy::new().max_update_bytes(1024);
+  let plugin = disk_plugin(&test, &doc_id, policy.clone());
+  let mut collab = open_collab(&test, &doc_id, Some(plugin.clone()));
+
+  collab.insert("small", "a");
+  plugin.wait_for_compaction();
+  assert_eq!(policy.metrics().stats(), CompactionStats::default());
+
+  collab.insert("large", "a".repeat(2048));
+  plugin.wait_for_compaction();
+  let stats = policy.metrics().stats();
+  assert_eq!(stats.compactions, 1);
+  assert_eq!(stats.merged_updates, 2);
+  let read_txn = test.db.read_txn();
+  assert_eq!(
+    read_txn.number_of_updates(test.uid, &test.workspace_id, &doc_id),
+    0
+  );
+}
+
+fn disk_plugin(
+  test: &CollabPersistenceTest,
+  doc_id: &str,
+  policy: CompactionPolicy,
+) -> RocksdbDiskPlugin {
+  RocksdbDiskPlugin::new_with_config(
+    test.uid,
+    test.workspace_id.clone(),
+    doc_id.to_string(),
+    CollabType::Unknown,
+    Arc::downgrade(&test.db),
+    CollabPersistenceConfig::new().compaction(policy),
+  )
+}
+
+fn open_collab(
+  test: &CollabPersistenceTest,
+  doc_id: &str,
+  plugin: Option<RocksdbDiskPlugin>,
+) -> Collab {
+  let options = CollabOptions::new(doc_id.to_string(), default_client_id())
+    .with_data_source(data_source(test).into());
+  let mut collab = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  let plugin = plugin.unwrap_or_else(|| {
+    *disk_plugin_with_db(
+      test.uid,
+      test.workspace_id.clone(),
+      test.db.clone(),
+      doc_id,
+      CollabType::Unknown,
+    )
+  });
+  collab.add_plugin(Box::new(plugin));
+  collab.initialize();
+  collab
+}
+
+fn data_source(test: &CollabPersistenceTest) -> KVDBCollabPersistenceImpl {
+  KVDBCollabPersistenceImpl {
+    db: Arc::downgrade(&test.db),
+    uid: test.uid,
+    workspace_id: test.workspace_id.clone(),
+  }
+}
@@ -0,0 +1,325 @@
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use collab::core::collab_version::CollabVersion;
+use collab_plugins::CollabKVDB;
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+use collab_plugins::local_storage::kv::encryption::{
+  CollabCipher, EncryptionKey, ValueKind, ValueScope, is_encrypted,
+};
+use collab_plugins::local_storage::kv::key_file::LocalKeyFile;
+use collab_plugins::local_storage::kv::snapshot::SnapshotAction;
+use collab_plugins::local_storage::kv::version::VersionAction;
+use collab_plugins::local_storage::kv::{KVEntry, KVStore, KVTransactionDB, PersistenceError};
+use yrs::{Doc, GetString, Text, Transact};
+
+use crate::disk::util::rocks_db;
+
+const UID: i64 = 1;
+const WORKSPACE_ID: &str = "w1";
+const DOC_ID: &str = "d1";
+
+fn open_encrypted(path: &Path, key_file: &Path) -> CollabKVDB {
+  let cipher = CollabCipher::new(LocalKeyFile::open(key_file).unwrap());
+  CollabKVDB::open(path).unwrap().with_cipher(cipher)
+}
+
+fn key_file_path(path: &Path) -> PathBuf {
+  path.join("keys.json")
+}
+
+/// Creates the doc, then pushes an update that appends `text` to it.
+fn write_text(db: &CollabKVDB, text: &str) -> Result<(), PersistenceError> {
+  let doc = Doc::new();
+  if db.read_txn().is_exist(UID, WORKSPACE_ID, DOC_ID) {
+    db.read_txn().load_doc(UID, WORKSPACE_ID, DOC_ID, &doc)?;
+  } else {
+    db.with_write_txn(|txn| txn.create_new_doc(UID, WORKSPACE_ID, DOC_ID, &doc.transact()))?;
+  }
+  let content = doc.get_or_insert_text("text");
+  let mut txn = doc.transact_mut();
+  let len = content.len(&txn);
+  content.insert(&mut txn, len, text);
+  let update = txn.encode_update_v1();
+  drop(txn);
+  db.with_write_txn(|txn| txn.push_update(UID, WORKSPACE_ID, DOC_ID, &update))?;
+  Ok(())
+}
+
+fn read_text(db: &CollabKVDB) -> Result<String, PersistenceError> {
+  let doc = Doc::new();
+  db.read_txn().load_doc(UID, WORKSPACE_ID, DOC_ID, &doc)?;
+  let text = doc.get_or_insert_text("text");
+  Ok(text.get_string(&doc.transact()))
+}
+
+/// Returns all the values stored for the doc, as they are on the disk.
+fn raw_values(db: &CollabKVDB) -> Vec<Vec<u8>> {
+  let txn = db.read_txn();
+  let start: &[u8] = &[0];
+  let end: &[u8] = &[0xFF];
+  txn
+    .range(start..end)
+    .unwrap()
+    .map(|entry| entry.value().to_vec())
+    .collect()
+}
+
+#[test]
+fn encrypted_doc_round_trip_test() {
+  let (path, db) = rocks_db();
+  drop(db);
+  let key_file = key_file_path(&path);
+  LocalKeyFile::open(&key_file)
+    .unwrap()
+    .rotate_key(WORKSPACE_ID)
+    .unwrap();
+
+  let db = open_encrypted(&path, &key_file);
+  write_text(&db, "top secret").unwrap();
+  write_text(&db, " plan").unwrap();
+  assert_eq!(read_text(&db).unwrap(), "top secret plan");
+
+  let updates = db
+    .read_txn()
+    .get_all_updates(UID, WORKSPACE_ID, DOC_ID)
+    .unwrap();
+  assert_eq!(updates.len(), 2);
+  assert!(updates.iter().all(|update| !is_encrypted(update)));
+
+  let values = raw_values(&db);
+  assert!(values.iter().any(|value| is_encrypted(value)));
+  let needle = b"secret";
+  assert!(
+    values
+      .iter()
+      .all(|value| !value.windows(needle.len()).any(|window| window == needle))
+  );
+}
+
+#[test]
+fn missing_key_test() {
+  let (path, db) = rocks_db();
+  drop(db);
+  let key_file = key_file_path(&path);
+
+  // No key for the workspace
+  let db = open_encrypted(&path, &key_file);
+  assert!(matches!(
+    write_text(&db, "hello"),
+    Err(PersistenceError::MissingEncryptionKey(_))
+  ));
+  drop(db);
+
+  LocalKeyFile::open(&key_file)
+    .unwrap()
+    .rotate_key(WORKSPACE_ID)
+    .unwrap();
+  let db = open_encrypted(&path, &key_file);
+  write_text(&db, "hello").unwrap();
+  drop(db);
+
+  // The store is opened without a cipher, the updates must not be dropped as corrupted
+  let db = CollabKVDB::open(&path).unwrap();
+  assert!(matches!(
+    read_text(&db),
+    Err(PersistenceError::MissingEncryptionKey(_))
+  ));
+  assert_eq!(
+    db.read_txn().number_of_updates(UID, WORKSPACE_ID, DOC_ID),
+    1
+  );
+}
+
+#[test]
+fn key_rotation_test() {
+  let (path, db) = rocks_db();
+  drop(db);
+  let key_file = key_file_path(&path);
+  let keys = LocalKeyFile::open(&key_file).unwrap();
+  let first_key = keys.rotate_key(WORKSPACE_ID).unwrap();
+  drop(keys);
+
+  let db = open_encrypted(&path, &key_file);
+  write_text(&db, "hello").unwrap();
+  drop(db);
+
+  let keys = LocalKeyFile::open(&key_file).unwrap();
+  let second_key = keys.rotate_key(WORKSPACE_ID).unwrap();
+  assert_ne!(first_key, second_key);
+  drop(keys);
+
+  // The values sealed with the previous key can still be opened
+  let db = open_encrypted(&path, &key_file);
+  write_text(&db, " world").unwrap();
+  assert_eq!(read_text(&db).unwrap(), "hello world");
+
+  // The doc state and the first update were sealed with the previous key
+  let count = db
+    .with_write_txn(|txn| txn.rotate_workspace_key(UID, WORKSPACE_ID))
+    .unwrap();
+  assert_eq!(count, 2);
+  let count = db
+    .with_write_txn(|txn| txn.rotate_doc_key(UID, WORKSPACE_ID, DOC_ID))
+    .unwrap();
+  assert_eq!(count, 0);
+  drop(db);
+
+  LocalKeyFile::open(&key_file)
+    .unwrap()
+    .remove_key(WORKSPACE_ID, first_key)
+    .unwrap();
+  let db = open_encrypted(&path, &key_file);
+  assert_eq!(read_text(&db).unwrap(), "hello world");
+}
+
+#[test]
+fn encrypt_existing_plaintext_doc_test() {
+  let (path, db) = rocks_db();
+  write_text(&db, "hello").unwrap();
+  drop(db);
+
+  let key_file = key_file_path(&path);
+  LocalKeyFile::open(&key_file)
+    .unwrap()
+    .rotate_key(WORKSPACE_ID)
+    .unwrap();
+  // The plaintext values are only read by a cipher that allows them
+  let db = open_encrypted(&path, &key_file);
+  assert!(matches!(
+    read_text(&db),
+    Err(PersistenceError::Encryption(_))
+  ));
+  assert_eq!(
+    db.read_txn().number_of_updates(UID, WORKSPACE_ID, DOC_ID),
+    1
+  );
+  drop(db);
+
+  let cipher = CollabCipher::new(LocalKeyFile::open(&key_file).unwrap()).allow_plaintext();
+  let db = CollabKVDB::open(&path).unwrap().with_cipher(cipher);
+  assert_eq!(read_text(&db).unwrap(), "hello");
+  let count = db
+    .with_write_txn(|txn| txn.rotate_doc_key(UID, WORKSPACE_ID, DOC_ID))
+    .unwrap();
+  assert_eq!(count, 2);
+  drop(db);
+
+  let db = open_encrypted(&path, &key_file);
+  assert_eq!(read_text(&db).unwrap(), "hello");
+  drop(db);
+
+  let db = CollabKVDB::open(&path).unwrap();
+  assert!(matches!(
+    read_text(&db),
+    Err(PersistenceError::MissingEncryptionKey(_))
+  ));
+}
+
+#[test]
+fn value_bound_to_scope_test() {
+  let (path, db) = rocks_db();
+  drop(db);
+  let keys = LocalKeyFile::open(key_file_path(&path)).unwrap();
+  let key = EncryptionKey::generate();
+  keys.insert_key("w1", key.clone()).unwrap();
+  keys.insert_key("w2", key).unwrap();
+  let cipher = CollabCipher::new(keys);
+
+  let scope = ValueScope::new(UID, "w1", "d1", ValueKind::Update);
+  let sealed = cipher.encrypt(&scope, b"hello").unwrap();
+  assert_eq!(cipher.decrypt(&scope, &sealed).unwrap(), b"hello");
+  for other in [
+    ValueScope::new(UID, "w2", "d1", ValueKind::Update),
+    ValueScope::new(UID + 1, "w1", "d1", ValueKind::Update),
+    ValueScope::new(UID, "w1", "d2", ValueKind::Update),
+    ValueScope::new(UID, "w1", "d1", ValueKind::DocState),
+  ] {
+    assert!(matches!(
+      cipher.decrypt(&other, &sealed),
+      Err(PersistenceError::Encryption(_))
+    ));
+  }
+}
+
+#[test]
+fn encrypted_snapshots_and_versions_test() {
+  let (path, db) = rocks_db();
+  drop(db);
+  let key_file = key_file_path(&path);
+  let keys = LocalKeyFile::open(&key_file).unwrap();
+  let first_key = keys.rotate_key(WORKSPACE_ID).unwrap();
+  drop(keys);
+
+  let db = open_encrypted(&path, &key_file);
+  write_text(&db, "hello").unwrap();
+  let version = CollabVersion::new("top secret".to_string(), Bytes::from_static(b"snapshot"));
+  db.with_write_txn(|txn| {
+    txn.create_snapshot_with_data(UID, WORKSPACE_ID, DOC_ID, b"secret snapshot".to_vec())?;
+    txn.insert_version(UID, WORKSPACE_ID, DOC_ID, &version)
+  })
+  .unwrap();
+
+  let needle = b"secret";
+  assert!(
+    raw_values(&db)
+      .iter()
+      .all(|value| !value.windows(needle.len()).any(|window| window == needle))
+  );
+  let snapshots = db.read_txn().get_snapshots(UID, WORKSPACE_ID, DOC_ID);
+  assert_eq!(snapshots.len(), 1);
+  assert_eq!(snapshots[0].data, b"secret snapshot");
+  let versions = db
+    .read_txn()
+    .get_versions(UID, WORKSPACE_ID, DOC_ID)
+    .unwrap();
+  assert_eq!(versions, vec![version.clone()]);
+  drop(db);
+
+  // The snapshot and the version are re-encrypted with the doc state and the update
+  LocalKeyFile::open(&key_file)
+    .unwrap()
+    .rotate_key(WORKSPACE_ID)
+    .unwrap();
+  let db = open_encrypted(&path, &key_file);
+  let count = db
+    .with_write_txn(|txn| txn.rotate_doc_key(UID, WORKSPACE_ID, DOC_ID))
+    .unwrap();
+  assert_eq!(count, 4);
+  drop(db);
+  LocalKeyFile::open(&key_file)
+    .unwrap()
+    .remove_key(WORKSPACE_ID, first_key)
+    .unwrap();
+  let db = open_encrypted(&path, &key_file);
+  assert_eq!(
+    db.read_txn().get_snapshots(UID, WORKSPACE_ID, DOC_ID).len(),
+    1
+  );
+  assert_eq!(
+    db.read_txn()
+      .get_version(UID, WORKSPACE_ID, DOC_ID, &version.version_id)
+      .unwrap(),
+    Some(version)
+  );
+}
+
+#[cfg(unix)]
+#[test]
+fn key_file_is_only_readable_by_owner_test() {
+  use std::os::unix::fs::PermissionsExt;
+
+  let (path, db) = rocks_db();
+  drop(db);
+  let key_file = key_file_path(&path);
+  // A file left by an interrupted save doesn't leak its permissions to the key file
+  let tmp_path = key_file.with_extension("tmp");
+  std::fs::write(&tmp_path, b"").unwrap();
+  std::fs::set_permissions(&tmp_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+  let keys = LocalKeyFile::open(&key_file).unwrap();
+  keys.rotate_key(WORKSPACE_ID).unwrap();
+  let mode = std::fs::metadata(&key_file).unwrap().permissions().mode();
+  assert_eq!(mode & 0o777, 0o600);
+  assert!(!tmp_path.exists());
+}
@@ -3,13 +3,15 @@ use assert_json_diff::assert_json_eq;
 
 use anyhow::Error;
 use collab::core::collab::{CollabOptions, default_client_id};
+use collab::core::collab_plugin::{AsyncPluginAdapter, AsyncPluginConfig};
 use collab::core::origin::CollabOrigin;
-use collab::preclude::Collab;
+use collab::preclude::{Collab, Map, MapExt};
 use collab_entity::CollabType;
+use collab_entity::define::{FOLDER, FOLDER_META, FOLDER_WORKSPACE_ID};
 use collab_plugins::local_storage::CollabPersistenceConfig;
+use collab_plugins::local_storage::disk::util::KVDBCollabPersistenceImpl;
 use collab_plugins::local_storage::kv::KVTransactionDB;
 use collab_plugins::local_storage::kv::doc::CollabKVAction;
-use collab_plugins::local_storage::rocksdb::util::KVDBCollabPersistenceImpl;
 use std::sync::Arc;
 
 #[tokio::test]
@@ -87,6 +89,50 @@ async fn flush_test() {
   assert_json_eq!(before_flush_value, after_flush_value);
 }
 
+#[tokio::test]
+async fn insert_with_async_disk_plugin_test() {
+  let doc_id = "1".to_string();
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::new());
+  let disk_plugin = disk_plugin_with_db(
+    test.uid,
+    test.workspace_id.clone(),
+    test.db.clone(),
+    &doc_id,
+    CollabType::Unknown,
+  );
+  let adapter = AsyncPluginAdapter::new(*disk_plugin, AsyncPluginConfig::default()).unwrap();
+  let handle = adapter.handle();
+
+  let data_source = || KVDBCollabPersistenceImpl {
+    db: Arc::downgrade(&test.db),
+    uid: test.uid,
+    workspace_id: test.workspace_id.clone(),
+  };
+  let options = CollabOptions::new(doc_id.to_string(), default_client_id())
+    .with_data_source(data_source().into());
+  let mut collab = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  collab.add_plugin(Box::new(adapter));
+  collab.initialize();
+  for i in 0..10 {
+    collab.insert(&i.to_string(), i.to_string());
+  }
+  let value = collab.to_json_value();
+  drop(collab);
+  handle.flushed().await;
+
+  let updates = test
+    .db
+    .read_txn()
+    .get_all_updates(test.uid, &test.workspace_id, &doc_id)
+    .unwrap();
+  assert_eq!(updates.len(), 10);
+
+  let options = CollabOptions::new(doc_id.to_string(), default_client_id())
+    .with_data_source(data_source().into());
+  let reopened = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  assert_json_eq!(reopened.to_json_value(), value);
+}
+
 #[tokio::test]
 async fn insert_multiple_changes_and_restore_from_disk() {
   let mut test = CollabPersistenceTest::new(CollabPersistenceConfig::new());
@@ -144,3 +190,32 @@ async fn insert_multiple_docs() {
   test.create_document_with_collab_db(id_4, db.clone()).await;
   test.assert_ids(expected).await;
 }
+
+#[tokio::test]
+async fn invalid_new_doc_is_not_written_test() {
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::new());
+  for (doc_id, workspace_id) in [("1", ""), ("2", "w1")] {
+    let mut collab = Collab::new(test.uid, doc_id, "1", default_client_id());
+    collab
+      .context
+      .with_txn(|txn| {
+        let folder = collab.data.get_or_init_map(txn, FOLDER);
+        let meta = folder.get_or_init_map(txn, FOLDER_META);
+        meta.insert(txn, FOLDER_WORKSPACE_ID, workspace_id);
+      })
+      .unwrap();
+    collab.add_plugin(disk_plugin_with_db(
+      test.uid,
+      test.workspace_id.clone(),
+      test.db.clone(),
+      doc_id,
+      CollabType::Folder,
+    ));
+    collab.initialize();
+  }
+
+  // The folder without a workspace id matches the schema, but misses the required data
+  let read_txn = test.db.read_txn();
+  assert!(!read_txn.is_exist(test.uid, &test.workspace_id, "1"));
+  assert!(read_txn.is_exist(test.uid, &test.workspace_id, "2"));
+}
@@ -0,0 +1,228 @@
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+use collab_plugins::local_storage::kv::integrity::{IntegrityProblem, repair, verify};
+use collab_plugins::local_storage::kv::keys::{
+  Clock, DocID, make_doc_id_key_v1, make_doc_state_key, make_doc_update_key, make_quarantine_key,
+  make_state_vector_key,
+};
+use collab_plugins::local_storage::kv::{KVEntry, KVStore, KVTransactionDB, PersistenceError};
+use collab_plugins::local_storage::memory::kv_impl::KVTransactionDBMemoryImpl;
+use uuid::Uuid;
+use yrs::{Doc, GetString, Text, Transact};
+
+use crate::disk::util::rocks_db;
+
+const UID: i64 = 1;
+
+/// Creates the doc, then pushes one update per text.
+fn write_doc<'a, S>(store: &S, workspace_id: &str, object_id: &str, texts: &[&str])
+where
+  S: KVStore<'a> + 'a,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let doc = Doc::new();
+  store
+    .create_new_doc(UID, workspace_id, object_id, &doc.transact())
+    .unwrap();
+  let content = doc.get_or_insert_text("text");
+  for text in texts {
+    let mut txn = doc.transact_mut();
+    let len = content.len(&txn);
+    content.insert(&mut txn, len, text);
+    let update = txn.encode_update_v1();
+    drop(txn);
+    store
+      .push_update(UID, workspace_id, object_id, &update)
+      .unwrap();
+  }
+}
+
+fn read_doc<'a, S>(store: &S, workspace_id: &str, object_id: &str) -> String
+where
+  S: KVStore<'a> + 'a,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let doc = Doc::new();
+  store.load_doc(UID, workspace_id, object_id, &doc).unwrap();
+  let text = doc.get_or_insert_text("text");
+  text.get_string(&doc.transact())
+}
+
+fn doc_id<'a, S: KVStore<'a>>(store: &S, workspace_id: &str, object_id: &str) -> DocID {
+  let key = make_doc_id_key_v1(
+    &UID.to_be_bytes(),
+    workspace_id.as_bytes(),
+    object_id.as_bytes(),
+  );
+  let value = store.get(key).unwrap().unwrap();
+  DocID::from_be_bytes(value.as_ref().try_into().unwrap())
+}
+
+#[test]
+fn verify_healthy_store_test() {
+  let (_, db) = rocks_db();
+  let workspace_id = Uuid::new_v4().to_string();
+  db.with_write_txn(|txn| {
+    write_doc(txn, &workspace_id, "d1", &["a", "b"]);
+    write_doc(txn, &workspace_id, "d2", &["c"]);
+    Ok(())
+  })
+  .unwrap();
+
+  let report = verify(&db.read_txn()).unwrap();
+  assert!(report.is_ok(), "{:?}", report.problems);
+  assert_eq!(report.docs, 2);
+  assert_eq!(report.updates, 3);
+}
+
+#[test]
+fn repair_corrupted_store_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  let workspace_id = Uuid::new_v4().to_string();
+  db.with_write_txn(|txn| {
+    write_doc(txn, &workspace_id, "d1", &["hello", " world"]);
+    write_doc(txn, &workspace_id, "d2", &["foo"]);
+    Ok(())
+  })
+  .unwrap();
+
+  let (d1, d2) = {
+    let txn = db.read_txn();
+    (
+      doc_id(&txn, &workspace_id, "d1"),
+      doc_id(&txn, &workspace_id, "d2"),
+    )
+  };
+  let bad_update_key = make_doc_update_key(d1, 10);
+  let orphan_key = make_doc_update_key(42, 1);
+  db.with_write_txn(|txn| {
+    txn.insert(bad_update_key.as_ref(), [0xFF, 0xFF, 0xFF])?;
+    txn.insert(orphan_key.as_ref(), [1, 2, 3])?;
+    txn.remove(make_doc_state_key(d2).as_ref())?;
+    txn.remove(make_state_vector_key(d2).as_ref())
+  })
+  .unwrap();
+
+  let report = verify(&db.read_txn()).unwrap();
+  assert_eq!(report.problems.len(), 4, "{:?}", report.problems);
+  assert!(report.problems.iter().any(|problem| matches!(
+    problem,
+    IntegrityProblem::UndecodableUpdate { doc, key, .. }
+      if doc.doc_id == d1 && doc.object_id == "d1" && key.as_slice() == bad_update_key.as_ref()
+  )));
+  assert!(
+    report
+      .problems
+      .iter()
+      .any(|problem| matches!(problem, IntegrityProblem::OrphanDocEntry { doc_id: 42, .. }))
+  );
+  assert!(report.problems.iter().any(|problem| matches!(
+    problem,
+    IntegrityProblem::MissingDocState { doc } if doc.doc_id == d2
+  )));
+  assert!(report.problems.iter().any(|problem| matches!(
+    problem,
+    IntegrityProblem::MissingStateVector { doc }
+      if doc.workspace_id.as_deref() == Some(workspace_id.as_str())
+  )));
+
+  let repair_report = db.with_write_txn(repair).unwrap();
+  assert_eq!(repair_report.problems.len(), 4);
+  assert_eq!(repair_report.quarantined, 2);
+  assert_eq!(repair_report.rebuilt_docs, 2);
+  assert!(repair_report.skipped_docs.is_empty());
+
+  let txn = db.read_txn();
+  assert!(verify(&txn).unwrap().is_ok());
+  assert_eq!(read_doc(&txn, &workspace_id, "d1"), "hello world");
+  // The doc state of d2 is rebuilt from its update
+  assert_eq!(read_doc(&txn, &workspace_id, "d2"), "foo");
+  assert_eq!(txn.number_of_updates(UID, &workspace_id, "d2"), 0);
+  // The bad entries are kept aside
+  let quarantined = txn
+    .get(make_quarantine_key(bad_update_key.as_ref()))
+    .unwrap();
+  assert_eq!(quarantined.unwrap(), vec![0xFF, 0xFF, 0xFF]);
+  assert!(txn.get(orphan_key.as_ref()).unwrap().is_none());
+}
+
+#[test]
+fn repair_quarantines_dependent_updates_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  let workspace_id = Uuid::new_v4().to_string();
+  // Each update appends to the text of the previous one, so it depends on it
+  db.with_write_txn(|txn| {
+    write_doc(txn, &workspace_id, "d1", &["hello", " world", "!"]);
+    Ok(())
+  })
+  .unwrap();
+
+  let d1 = doc_id(&db.read_txn(), &workspace_id, "d1");
+  let keys = {
+    let txn = db.read_txn();
+    let start = make_doc_update_key(d1, 0);
+    let end = make_doc_update_key(d1, Clock::MAX);
+    txn
+      .range(start.as_ref()..end.as_ref())
+      .unwrap()
+      .map(|entry| entry.key().to_vec())
+      .collect::<Vec<_>>()
+  };
+  assert_eq!(keys.len(), 3);
+  db.with_write_txn(|txn| txn.insert(&keys[1], [0xFF, 0xFF, 0xFF]))
+    .unwrap();
+
+  let repair_report = db.with_write_txn(repair).unwrap();
+  assert_eq!(repair_report.problems.len(), 1);
+  // The update that depends on the undecodable one can't be integrated
+  assert_eq!(repair_report.pending_updates, vec![keys[2].clone()]);
+  assert_eq!(repair_report.quarantined, 2);
+
+  let txn = db.read_txn();
+  assert!(verify(&txn).unwrap().is_ok());
+  assert_eq!(read_doc(&txn, &workspace_id, "d1"), "hello");
+  assert_eq!(txn.number_of_updates(UID, &workspace_id, "d1"), 0);
+  for key in &keys[1..] {
+    assert!(txn.get(make_quarantine_key(key)).unwrap().is_some());
+  }
+}
+
+#[test]
+fn repair_integrates_updates_stored_out_of_order_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  let workspace_id = Uuid::new_v4().to_string();
+  db.with_write_txn(|txn| {
+    write_doc(txn, &workspace_id, "d1", &["hello", " world", "!"]);
+    Ok(())
+  })
+  .unwrap();
+
+  let d1 = doc_id(&db.read_txn(), &workspace_id, "d1");
+  let entries = {
+    let txn = db.read_txn();
+    let start = make_doc_update_key(d1, 0);
+    let end = make_doc_update_key(d1, Clock::MAX);
+    txn
+      .range(start.as_ref()..end.as_ref())
+      .unwrap()
+      .map(|entry| (entry.key().to_vec(), entry.value().to_vec()))
+      .collect::<Vec<_>>()
+  };
+  assert_eq!(entries.len(), 3);
+  // The second update is stored after the third one, which depends on it
+  db.with_write_txn(|txn| {
+    txn.insert(&entries[1].0, &entries[2].1)?;
+    txn.insert(&entries[2].0, &entries[1].1)?;
+    txn.remove(make_state_vector_key(d1).as_ref())
+  })
+  .unwrap();
+
+  let repair_report = db.with_write_txn(repair).unwrap();
+  assert_eq!(repair_report.rebuilt_docs, 1);
+  assert!(repair_report.pending_updates.is_empty());
+  assert_eq!(repair_report.quarantined, 0);
+
+  let txn = db.read_txn();
+  assert!(verify(&txn).unwrap().is_ok());
+  assert_eq!(read_doc(&txn, &workspace_id, "d1"), "hello world!");
+  assert_eq!(txn.number_of_updates(UID, &workspace_id, "d1"), 0);
+}
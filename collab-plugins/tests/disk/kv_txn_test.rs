@@ -0,0 +1,131 @@
+use collab_plugins::local_storage::kv::{KVEntry, KVStore, KVTransactionDB, PersistenceError};
+
+use crate::disk::util::rocks_db;
+
+#[test]
+fn failed_write_txn_is_rolled_back_test() {
+  let (_, db) = rocks_db();
+  let result: Result<(), PersistenceError> = db.with_write_txn(|txn| {
+    txn.insert([1, 2, 3], [1])?;
+    Err(PersistenceError::InvalidData("abort".to_string()))
+  });
+  assert!(result.is_err());
+  assert!(db.read_txn().get([1, 2, 3]).unwrap().is_none());
+
+  // A write transaction that is not committed is discarded
+  let txn = db.write_txn();
+  txn.insert([1, 2, 3], [1]).unwrap();
+  drop(txn);
+  assert!(db.read_txn().get([1, 2, 3]).unwrap().is_none());
+}
+
+#[test]
+fn read_txn_does_not_block_write_txn_test() {
+  let (_, db) = rocks_db();
+  db.with_write_txn(|txn| txn.insert([1], [1])).unwrap();
+
+  // e.g. a doc is created while the loaded doc is read
+  let read_txn = db.read_txn();
+  assert_eq!(read_txn.get([1]).unwrap().unwrap(), vec![1]);
+  db.with_write_txn(|txn| {
+    txn.insert([1], [2])?;
+    txn.insert([2], [2])
+  })
+  .unwrap();
+  drop(read_txn);
+
+  let read_txn = db.read_txn();
+  assert_eq!(read_txn.get([1]).unwrap().unwrap(), vec![2]);
+  assert_eq!(read_txn.get([2]).unwrap().unwrap(), vec![2]);
+}
+
+#[test]
+fn range_bounds_test() {
+  let (_, db) = rocks_db();
+  db.with_write_txn(|txn| {
+    for i in 0..5u8 {
+      txn.insert([1, i], [i])?;
+    }
+    txn.insert([2], [9])
+  })
+  .unwrap();
+
+  let read_txn = db.read_txn();
+  assert_eq!(
+    values(read_txn.range([1, 1]..[1, 4]).unwrap()),
+    vec![1, 2, 3]
+  );
+  // The upper bound is never included
+  assert_eq!(
+    values(read_txn.range([1, 1]..=[1, 4]).unwrap()),
+    vec![1, 2, 3]
+  );
+  let entry = read_txn.next_back_entry(&[1, 9]).unwrap().unwrap();
+  assert_eq!(entry.value(), &[4]);
+  let entry = read_txn.next_back_entry(&[1, 2]).unwrap().unwrap();
+  assert_eq!(entry.value(), &[2]);
+  drop(read_txn);
+
+  db.with_write_txn(|txn| txn.remove_range(&[1, 0], &[1, 3]))
+    .unwrap();
+  let read_txn = db.read_txn();
+  assert_eq!(values(read_txn.range([1, 0]..[2]).unwrap()), vec![3, 4]);
+}
+
+#[test]
+fn large_range_test() {
+  let (_, db) = rocks_db();
+  db.with_write_txn(|txn| {
+    for i in 0..1000u16 {
+      txn.insert([1, (i >> 8) as u8, i as u8], i.to_be_bytes())?;
+    }
+    Ok(())
+  })
+  .unwrap();
+
+  // The entries removed while iterating don't shift the rest of the range
+  db.with_write_txn(|txn| {
+    let mut count = 0u16;
+    for entry in txn.range([1]..[2])? {
+      assert_eq!(entry.value(), &count.to_be_bytes());
+      txn.remove(entry.key())?;
+      count += 1;
+    }
+    assert_eq!(count, 1000);
+    Ok(())
+  })
+  .unwrap();
+  assert_eq!(db.read_txn().range([1]..[2]).unwrap().count(), 0);
+}
+
+fn values<E: KVEntry>(range: impl Iterator<Item = E>) -> Vec<u8> {
+  range.map(|entry| entry.value()[0]).collect()
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_file_test() {
+  use collab_plugins::local_storage::sqlite::kv_impl::SQLITE_DB_FILE;
+  let (path, _db) = rocks_db();
+  assert!(path.join(SQLITE_DB_FILE).exists());
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+fn sqlite_range_is_read_within_txn_test() {
+  let (_, db) = rocks_db();
+  db.with_write_txn(|txn| {
+    for i in 0..3u8 {
+      txn.insert([1, i], [i])?;
+    }
+    Ok(())
+  })
+  .unwrap();
+
+  let read_txn = db.read_txn();
+  let range = read_txn.range([1]..[2]).unwrap();
+  drop(read_txn);
+  // Written once the read transaction is over, it's not part of the range
+  db.with_write_txn(|txn| txn.insert([1, 9], [9])).unwrap();
+  assert_eq!(values(range), vec![0, 1, 2]);
+}
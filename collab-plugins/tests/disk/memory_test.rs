@@ -0,0 +1,245 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::SeqCst;
+
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+use collab_plugins::local_storage::kv::snapshot::SnapshotAction;
+use collab_plugins::local_storage::kv::{KVEntry, KVStore, KVTransactionDB, PersistenceError};
+use collab_plugins::local_storage::memory::kv_impl::KVTransactionDBMemoryImpl;
+use yrs::{Doc, GetString, Map, Text, Transact};
+
+const UID: i64 = 1;
+const WORKSPACE_ID: &str = "w1";
+
+#[test]
+fn memory_txn_commit_and_rollback_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  db.with_write_txn(|txn| txn.insert([1], [1])).unwrap();
+  assert_eq!(db.len(), 1);
+
+  let result: Result<(), PersistenceError> = db.with_write_txn(|txn| {
+    txn.insert([2], [2])?;
+    txn.remove(&[1])?;
+    Err(PersistenceError::InvalidData("abort".to_string()))
+  });
+  assert!(result.is_err());
+  let read_txn = db.read_txn();
+  assert_eq!(read_txn.get([1]).unwrap().unwrap(), vec![1]);
+  assert!(read_txn.get([2]).unwrap().is_none());
+  drop(read_txn);
+
+  // A write transaction sees its own changes before they are committed
+  let txn = db.write_txn();
+  txn.insert([2], [2]).unwrap();
+  txn.remove(&[1]).unwrap();
+  assert_eq!(txn.get([2]).unwrap().unwrap(), vec![2]);
+  assert!(txn.get([1]).unwrap().is_none());
+  assert!(db.read_txn().get([2]).unwrap().is_none());
+  txn.commit_transaction().unwrap();
+
+  let read_txn = db.read_txn();
+  assert!(read_txn.get([1]).unwrap().is_none());
+  assert_eq!(read_txn.get([2]).unwrap().unwrap(), vec![2]);
+}
+
+#[test]
+fn memory_read_txn_snapshot_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  db.with_write_txn(|txn| txn.insert([1], [1])).unwrap();
+
+  let read_txn = db.read_txn();
+  db.with_write_txn(|txn| {
+    txn.insert([1], [2])?;
+    txn.insert([2], [2])
+  })
+  .unwrap();
+  assert_eq!(read_txn.get([1]).unwrap().unwrap(), vec![1]);
+  assert!(read_txn.get([2]).unwrap().is_none());
+  drop(read_txn);
+
+  // The clones share the same data
+  let cloned_db = db.clone();
+  assert_eq!(cloned_db.read_txn().get([1]).unwrap().unwrap(), vec![2]);
+}
+
+#[test]
+fn memory_range_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  db.with_write_txn(|txn| {
+    for i in 0..5u8 {
+      txn.insert([1, i], [i])?;
+    }
+    txn.insert([2], [9])
+  })
+  .unwrap();
+
+  let txn = db.write_txn();
+  txn.remove(&[1, 4]).unwrap();
+  txn.insert([1, 5], [5]).unwrap();
+  assert_eq!(values(txn.range([1, 1]..[1, 6]).unwrap()), vec![1, 2, 3, 5]);
+  // The upper bound is never included
+  assert_eq!(values(txn.range([1, 1]..=[1, 5]).unwrap()), vec![1, 2, 3]);
+  assert!(txn.range([1, 3]..[1, 1]).unwrap().next().is_none());
+
+  let entry = txn.next_back_entry(&[1, 9]).unwrap().unwrap();
+  assert_eq!(entry.value(), &[5]);
+  txn.remove(&[1, 5]).unwrap();
+  // The removed entries are skipped
+  let entry = txn.next_back_entry(&[1, 9]).unwrap().unwrap();
+  assert_eq!(entry.value(), &[3]);
+  assert!(txn.next_back_entry(&[0]).unwrap().is_none());
+
+  txn.remove_range(&[1, 0], &[1, 3]).unwrap();
+  assert_eq!(values(txn.range([1, 0]..[3]).unwrap()), vec![3, 9]);
+  txn.commit_transaction().unwrap();
+  assert_eq!(
+    values(db.read_txn().range([1, 0]..[3]).unwrap()),
+    vec![3, 9]
+  );
+}
+
+#[test]
+fn memory_collab_doc_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  let doc = Doc::new();
+  db.with_write_txn(|txn| txn.create_new_doc(UID, WORKSPACE_ID, "d1", &doc.transact()))
+    .unwrap();
+  let text = doc.get_or_insert_text("text");
+  for s in ["hello", " world"] {
+    let mut txn = doc.transact_mut();
+    let len = text.len(&txn);
+    text.insert(&mut txn, len, s);
+    let update = txn.encode_update_v1();
+    drop(txn);
+    db.with_write_txn(|txn| txn.push_update(UID, WORKSPACE_ID, "d1", &update))
+      .unwrap();
+  }
+  assert_eq!(db.read_txn().number_of_updates(UID, WORKSPACE_ID, "d1"), 2);
+
+  let loaded = Doc::new();
+  db.read_txn()
+    .load_doc(UID, WORKSPACE_ID, "d1", &loaded)
+    .unwrap();
+  let text = loaded.get_or_insert_text("text");
+  assert_eq!(text.get_string(&loaded.transact()), "hello world");
+
+  db.with_write_txn(|txn| txn.delete_doc(UID, WORKSPACE_ID, "d1"))
+    .unwrap();
+  assert!(!db.read_txn().is_exist(UID, WORKSPACE_ID, "d1"));
+}
+
+#[test]
+fn memory_write_conflict_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  let doc = Doc::new();
+  db.with_write_txn(|txn| txn.create_new_doc(UID, WORKSPACE_ID, "d1", &doc.transact()))
+    .unwrap();
+  let text = doc.get_or_insert_text("text");
+  let mut updates = vec![];
+  for s in ["hello", " world"] {
+    let mut txn = doc.transact_mut();
+    let len = text.len(&txn);
+    text.insert(&mut txn, len, s);
+    updates.push(txn.encode_update_v1());
+  }
+
+  // Both transactions append their update after the same last clock
+  let txn_1 = db.write_txn();
+  let txn_2 = db.write_txn();
+  txn_1
+    .push_update(UID, WORKSPACE_ID, "d1", &updates[0])
+    .unwrap();
+  txn_2
+    .push_update(UID, WORKSPACE_ID, "d1", &updates[1])
+    .unwrap();
+  txn_1.commit_transaction().unwrap();
+  assert!(matches!(
+    txn_2.commit_transaction(),
+    Err(PersistenceError::WriteConflict(_))
+  ));
+  assert_eq!(db.read_txn().number_of_updates(UID, WORKSPACE_ID, "d1"), 1);
+
+  // The transactions that change other keys don't conflict
+  let txn_1 = db.write_txn();
+  let txn_2 = db.write_txn();
+  txn_1.insert([1], [1]).unwrap();
+  txn_2.insert([2], [2]).unwrap();
+  txn_1.commit_transaction().unwrap();
+  txn_2.commit_transaction().unwrap();
+  assert_eq!(db.read_txn().get([2]).unwrap().unwrap(), vec![2]);
+}
+
+#[test]
+fn memory_concurrent_push_and_compaction_test() {
+  let db = Arc::new(KVTransactionDBMemoryImpl::new());
+  db.with_write_txn(|txn| txn.create_new_doc(UID, WORKSPACE_ID, "d1", &Doc::new().transact()))
+    .unwrap();
+
+  // Two editors push their updates while the doc is compacted
+  let pushers = (0..2)
+    .map(|editor| {
+      let db = db.clone();
+      std::thread::spawn(move || {
+        let doc = Doc::new();
+        let map = doc.get_or_insert_map("map");
+        for i in 0..50 {
+          let mut txn = doc.transact_mut();
+          map.insert(&mut txn, format!("{}-{}", editor, i), i);
+          let update = txn.encode_update_v1();
+          drop(txn);
+          db.with_retried_write_txn(|txn| txn.push_update(UID, WORKSPACE_ID, "d1", &update))
+            .unwrap();
+        }
+      })
+    })
+    .collect::<Vec<_>>();
+  let done = Arc::new(AtomicBool::new(false));
+  let compactor = {
+    let db = db.clone();
+    let done = done.clone();
+    std::thread::spawn(move || {
+      while !done.load(SeqCst) {
+        let _ = db.with_retried_write_txn(|txn| txn.compact_updates(UID, WORKSPACE_ID, "d1"));
+      }
+    })
+  };
+  for pusher in pushers {
+    pusher.join().unwrap();
+  }
+  done.store(true, SeqCst);
+  compactor.join().unwrap();
+
+  let doc = Doc::new();
+  db.read_txn()
+    .load_doc(UID, WORKSPACE_ID, "d1", &doc)
+    .unwrap();
+  let map = doc.get_or_insert_map("map");
+  assert_eq!(map.len(&doc.transact()), 100);
+}
+
+#[test]
+fn memory_snapshot_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  for i in 0..3u8 {
+    db.with_write_txn(|txn| txn.create_snapshot_with_data(UID, WORKSPACE_ID, "d1", vec![i]))
+      .unwrap();
+  }
+  let snapshots = db.read_txn().get_snapshots(UID, WORKSPACE_ID, "d1");
+  let data = snapshots
+    .iter()
+    .map(|snapshot| snapshot.data.clone())
+    .collect::<Vec<_>>();
+  assert_eq!(data, vec![vec![0], vec![1], vec![2]]);
+
+  db.with_write_txn(|txn| txn.delete_all_snapshots(UID, "d1"))
+    .unwrap();
+  assert!(
+    db.read_txn()
+      .get_snapshots(UID, WORKSPACE_ID, "d1")
+      .is_empty()
+  );
+}
+
+fn values<E: KVEntry>(range: impl Iterator<Item = E>) -> Vec<u8> {
+  range.map(|entry| entry.value()[0]).collect()
+}
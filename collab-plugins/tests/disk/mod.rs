@@ -1,7 +1,14 @@
+mod archive_test;
+mod compact_test;
 mod delete_test;
+mod encryption_test;
 mod insert_test;
+mod integrity_test;
+mod kv_txn_test;
+mod memory_test;
 mod range_test;
 mod restore_test;
 mod script;
 mod undo_test;
 mod util;
+mod version_test;
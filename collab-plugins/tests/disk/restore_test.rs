@@ -205,3 +205,36 @@ async fn test_migrate_old_keys() {
     assert_eq!(oid, object_id);
   }
 }
+
+#[tokio::test]
+async fn object_and_workspace_ids_of_other_users_test() {
+  let (_, db) = rocks_db();
+  let workspace_ids = [Uuid::new_v4().to_string(), Uuid::new_v4().to_string()];
+  // Every document is written before the ids are read, so the keys of the other users and
+  // workspaces follow the ones that are read.
+  for uid in [1, 2] {
+    for workspace_id in workspace_ids.iter() {
+      for i in 0..3 {
+        let object_id = format!("{}-{}", uid, i);
+        let doc = Doc::new();
+        let txn = doc.transact();
+        db.with_write_txn(|store| store.create_new_doc(uid, workspace_id, &object_id, &txn))
+          .unwrap();
+      }
+    }
+  }
+
+  let mut object_ids = db
+    .read_txn()
+    .get_all_object_ids(1, &workspace_ids[0])
+    .unwrap()
+    .collect::<Vec<String>>();
+  object_ids.sort();
+  assert_eq!(object_ids, vec!["1-0", "1-1", "1-2"]);
+
+  let mut ids = db.read_txn().get_all_workspace_ids().unwrap();
+  ids.sort();
+  let mut expected = workspace_ids.to_vec();
+  expected.sort();
+  assert_eq!(ids, expected);
+}
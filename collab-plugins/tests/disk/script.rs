@@ -10,10 +10,10 @@ use collab::preclude::*;
 use collab_entity::CollabType;
 use collab_plugins::CollabKVDB;
 use collab_plugins::local_storage::CollabPersistenceConfig;
+use collab_plugins::local_storage::disk::rocksdb_plugin::RocksdbDiskPlugin;
+use collab_plugins::local_storage::disk::util::KVDBCollabPersistenceImpl;
 use collab_plugins::local_storage::kv::KVTransactionDB;
 use collab_plugins::local_storage::kv::doc::CollabKVAction;
-use collab_plugins::local_storage::rocksdb::rocksdb_plugin::RocksdbDiskPlugin;
-use collab_plugins::local_storage::rocksdb::util::KVDBCollabPersistenceImpl;
 use tempfile::TempDir;
 use uuid::Uuid;
 
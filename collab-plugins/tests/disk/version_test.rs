@@ -0,0 +1,154 @@
+use crate::disk::script::CollabPersistenceTest;
+use collab::core::collab::{CollabOptions, default_client_id};
+use collab::core::collab_version::CollabVersionStorage;
+use collab::core::origin::CollabOrigin;
+use collab::error::CollabError;
+use collab::preclude::Collab;
+use collab_plugins::local_storage::CollabPersistenceConfig;
+use collab_plugins::local_storage::disk::version_storage::RocksdbVersionStorage;
+use serde_json::json;
+use std::sync::Arc;
+
+fn collab_with_version_storage(test: &CollabPersistenceTest, object_id: &str) -> Collab {
+  let storage = RocksdbVersionStorage::new(
+    test.uid,
+    test.workspace_id.clone(),
+    Arc::downgrade(&test.db),
+  );
+  let options = CollabOptions::new(object_id.to_string(), default_client_id())
+    .with_skip_gc(true)
+    .with_version_storage(Arc::new(storage));
+  Collab::new_with_options(CollabOrigin::Empty, options).unwrap()
+}
+
+#[tokio::test]
+async fn create_and_open_version_test() {
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::default());
+  let mut collab = collab_with_version_storage(&test, "1");
+
+  collab.insert("title", "first draft");
+  let v1 = collab.create_version("v1").unwrap();
+
+  collab.insert("title", "second draft");
+  collab.insert("body", "hello");
+  let v2 = collab.create_version("v2").unwrap();
+
+  collab.remove("body");
+
+  let versions = collab.list_versions().unwrap();
+  assert_eq!(versions, vec![v1.clone(), v2.clone()]);
+
+  let view = collab.open_version(&v1.version_id).unwrap();
+  assert_eq!(view.version().label, "v1");
+  assert_eq!(view.to_json_value(), json!({ "title": "first draft" }));
+  let title: Option<String> = view.get_with_path(["title"]);
+  assert_eq!(title.as_deref(), Some("first draft"));
+
+  let view = collab.open_version(&v2.version_id).unwrap();
+  assert_eq!(
+    view.to_json_value(),
+    json!({ "title": "second draft", "body": "hello" })
+  );
+
+  // The current state is not affected by opening a version
+  assert_eq!(collab.to_json_value(), json!({ "title": "second draft" }));
+}
+
+#[tokio::test]
+async fn versions_are_scoped_by_object_test() {
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::default());
+  let mut collab_1 = collab_with_version_storage(&test, "1");
+  let mut collab_2 = collab_with_version_storage(&test, "2");
+  collab_1.insert("a", "1");
+  collab_2.insert("b", "2");
+  let version = collab_1.create_version("v1").unwrap();
+
+  assert_eq!(collab_1.list_versions().unwrap().len(), 1);
+  assert!(collab_2.list_versions().unwrap().is_empty());
+  assert!(matches!(
+    collab_2.open_version(&version.version_id),
+    Err(CollabError::VersionNotFound(_))
+  ));
+}
+
+#[tokio::test]
+async fn version_requires_skip_gc_test() {
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::default());
+  let storage = RocksdbVersionStorage::new(
+    test.uid,
+    test.workspace_id.clone(),
+    Arc::downgrade(&test.db),
+  );
+  let options = CollabOptions::new("1".to_string(), default_client_id())
+    .with_version_storage(Arc::new(storage));
+  let collab = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  assert!(matches!(
+    collab.create_version("v1"),
+    Err(CollabError::VersionRequiresSkipGc)
+  ));
+}
+
+#[tokio::test]
+async fn compact_collab_with_versions_test() {
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::default());
+  let mut collab = collab_with_version_storage(&test, "1");
+  collab.insert("title", "first draft");
+  let version = collab.create_version("v1").unwrap();
+  collab.remove("title");
+  assert!(matches!(
+    collab.compact(),
+    Err(CollabError::CompactionWithVersions(_))
+  ));
+
+  RocksdbVersionStorage::new(
+    test.uid,
+    test.workspace_id.clone(),
+    Arc::downgrade(&test.db),
+  )
+  .delete_version("1", &version.version_id)
+  .unwrap();
+  assert!(collab.compact().is_ok());
+}
+
+#[tokio::test]
+async fn get_and_delete_version_by_id_test() {
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::default());
+  let storage = RocksdbVersionStorage::new(
+    test.uid,
+    test.workspace_id.clone(),
+    Arc::downgrade(&test.db),
+  );
+  let mut collab = collab_with_version_storage(&test, "1");
+  collab.insert("title", "first draft");
+  let v1 = collab.create_version("v1").unwrap();
+  collab.insert("title", "second draft");
+  let v2 = collab.create_version("v2").unwrap();
+
+  assert_eq!(
+    storage.get_version("1", &v1.version_id).unwrap(),
+    Some(v1.clone())
+  );
+  assert_eq!(
+    storage.get_version("1", &v2.version_id).unwrap(),
+    Some(v2.clone())
+  );
+  assert_eq!(storage.get_version("1", "unknown").unwrap(), None);
+
+  // Saving a version again replaces it, and moves it last
+  let mut renamed = v1.clone();
+  renamed.label = "renamed".to_string();
+  storage.save_version("1", &renamed).unwrap();
+  assert_eq!(
+    storage.list_versions("1").unwrap(),
+    vec![v2.clone(), renamed.clone()]
+  );
+  assert_eq!(
+    storage.get_version("1", &v1.version_id).unwrap(),
+    Some(renamed)
+  );
+
+  storage.delete_version("1", &v1.version_id).unwrap();
+  assert_eq!(storage.get_version("1", &v1.version_id).unwrap(), None);
+  assert_eq!(storage.list_versions("1").unwrap(), vec![v2.clone()]);
+  assert_eq!(storage.get_version("1", &v2.version_id).unwrap(), Some(v2));
+}
@@ -1,14 +1,10 @@
-use std::collections::HashMap;
-
 use collab::preclude::encoding::serde::from_any;
 use collab::preclude::{
-  Any, Array, ArrayRef, Change, DeepObservable, Event, Map, MapPrelim, MapRef, Out, ReadTxn,
-  Subscription, ToJson, TransactionMut, YrsValue,
-};
-use collab_entity::reminder::{
-  REMINDER_ID, REMINDER_IS_ACK, REMINDER_IS_READ, REMINDER_MESSAGE, REMINDER_META,
-  REMINDER_OBJECT_ID, REMINDER_SCHEDULED_AT, REMINDER_TITLE, REMINDER_TY, Reminder,
+  Any, Array, ArrayRef, Change, DeepObservable, Event, Map, MapPrelim, Out, ReadTxn, Subscription,
+  ToJson, TransactionMut, YrsValue,
 };
+pub use collab_entity::reminder::ReminderUpdate;
+use collab_entity::reminder::{REMINDER_ID, Reminder};
 use tokio::sync::broadcast;
 
 pub type RemindersChangeSender = broadcast::Sender<ReminderChange>;
@@ -64,12 +60,8 @@ impl Reminders {
   where
     F: FnOnce(ReminderUpdate),
   {
-    if let Some((_, Out::YMap(mut map))) = self.find(txn, reminder_id) {
-      let update = ReminderUpdate {
-        map_ref: &mut map,
-        txn,
-      };
-      f(update)
+    if let Some((_, Out::YMap(map))) = self.find(txn, reminder_id) {
+      f(ReminderUpdate::new(txn, &map))
     }
   }
 
@@ -137,58 +129,3 @@ fn subscribe_reminder_change(
     }
   })
 }
-
-pub struct ReminderUpdate<'a, 'b> {
-  map_ref: &'a mut MapRef,
-  txn: &'a mut TransactionMut<'b>,
-}
-
-impl ReminderUpdate<'_, '_> {
-  pub fn set_object_id<T: AsRef<str>>(self, value: T) -> Self {
-    self
-      .map_ref
-      .try_update(self.txn, REMINDER_OBJECT_ID, value.as_ref());
-    self
-  }
-
-  pub fn set_title<T: AsRef<str>>(self, value: T) -> Self {
-    self
-      .map_ref
-      .try_update(self.txn, REMINDER_TITLE, value.as_ref());
-    self
-  }
-
-  pub fn set_message<T: AsRef<str>>(self, value: T) -> Self {
-    self
-      .map_ref
-      .try_update(self.txn, REMINDER_MESSAGE, value.as_ref());
-    self
-  }
-
-  pub fn set_is_ack(self, value: bool) -> Self {
-    self.map_ref.try_update(self.txn, REMINDER_IS_ACK, value);
-    self
-  }
-
-  pub fn set_is_read(self, value: bool) -> Self {
-    self.map_ref.try_update(self.txn, REMINDER_IS_READ, value);
-    self
-  }
-
-  pub fn set_type<T: Into<i64>>(self, value: T) -> Self {
-    self.map_ref.try_update(self.txn, REMINDER_TY, value.into());
-    self
-  }
-
-  pub fn set_scheduled_at<T: Into<i64>>(self, value: T) -> Self {
-    self
-      .map_ref
-      .try_update(self.txn, REMINDER_SCHEDULED_AT, value.into());
-    self
-  }
-
-  pub fn set_meta(self, value: HashMap<String, String>) -> Self {
-    self.map_ref.try_update(self.txn, REMINDER_META, value);
-    self
-  }
-}
@@ -8,7 +8,7 @@ use collab::core::origin::{CollabClient, CollabOrigin};
 use collab::preclude::Collab;
 use collab_entity::CollabType;
 use collab_plugins::CollabKVDB;
-use collab_plugins::local_storage::rocksdb::rocksdb_plugin::RocksdbDiskPlugin;
+use collab_plugins::local_storage::disk::rocksdb_plugin::RocksdbDiskPlugin;
 use collab_user::core::{RemindersChangeSender, UserAwareness, UserAwarenessNotifier};
 use tempfile::TempDir;
 use tokio::sync::broadcast::Receiver;
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 pub use std::fmt::Display;
 use std::fmt::{Debug, Formatter};
 use std::ops::{Deref, DerefMut};
@@ -5,9 +7,10 @@ use std::panic;
 use std::panic::AssertUnwindSafe;
 
 use arc_swap::ArcSwapOption;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::vec::IntoIter;
 
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
 use tokio_stream::wrappers::WatchStream;
@@ -23,8 +26,17 @@ use yrs::{
 };
 
 use crate::core::awareness::Awareness;
+use crate::core::collab_attribution::{AttributionIndex, CollabAttribution};
+use crate::core::collab_migration::CollabMigrations;
+use crate::core::collab_permission::{ChangedPaths, CollabPermission, PermissionCheck};
 use crate::core::collab_plugin::{CollabPersistence, CollabPlugin, CollabPluginType, Plugins};
+use crate::core::collab_presence::{CollabPresence, set_local_awareness_state};
+use crate::core::collab_schema::CollabSchema;
+use crate::core::collab_search::{CollabSearch, CollabSearchIndex, SearchHit};
+use crate::core::collab_snapshot::SnapshotCache;
 use crate::core::collab_state::{InitState, SnapshotState, State, SyncState};
+use crate::core::collab_undo::UndoScope;
+use crate::core::collab_version::CollabVersionStorage;
 use crate::core::origin::{CollabClient, CollabOrigin};
 use crate::core::transaction::DocTransactionExtension;
 
@@ -62,6 +74,19 @@ pub struct Collab {
   /// A list of plugins that are used to extend the functionality of the [Collab].
   plugins: Plugins,
   pub index_json_sender: IndexContentSender,
+  /// The full-text search index of the [Collab::data] section. By default, the search is
+  /// disabled. To enable it, call [Collab::enable_search].
+  search: Option<CollabSearch>,
+  /// The presence of the other peers. By default, it's not tracked. To track it, call
+  /// [Collab::enable_presence].
+  pub(crate) presence: Option<CollabPresence>,
+  /// The storage of the named versions of this [Collab]. See [Collab::create_version].
+  pub(crate) version_storage: Option<Arc<dyn CollabVersionStorage>>,
+  /// The updates of the migrations that ran before the update observer was installed. They are
+  /// sent to the plugins by [Collab::initialize].
+  pub(crate) migration_updates: Vec<Vec<u8>>,
+  /// The encoded document shared by the snapshots returned by [Collab::read_snapshot].
+  pub(crate) snapshot_cache: Arc<SnapshotCache>,
 
   // EXPLANATION: context, meta and data are often used within the same context: &mut context
   //  used to obtain TransactionMut, which is then used by &data and &meta. This is why they are
@@ -69,8 +94,7 @@ pub struct Collab {
   //  will be able to infere that &mut context and &data/&meta don't overlap.
   /// Every [Collab] instance has a data section that can be used to store
   pub data: MapRef,
-  #[allow(dead_code)]
-  meta: MapRef,
+  pub(crate) meta: MapRef,
   /// This is an inner collab state that requires mut access in order to modify it.
   pub context: CollabContext,
 }
@@ -92,37 +116,74 @@ pub struct CollabContext {
   /// The [UndoManager] is used to undo and redo changes. By default, the [UndoManager]
   /// is disabled. To enable it, call [Collab::enable_undo_manager].
   undo_manager: Option<UndoManager>,
+  /// The named [UndoScope]s added by [Collab::add_undo_scope].
+  pub(crate) undo_scopes: HashMap<String, UndoScope>,
+  /// Reverts the transactions rejected by a [CollabPlugin::before_commit] hook or by the
+  /// [CollabPermission]. It's created by the first transaction that needs it.
+  commit_guard: Option<CommitGuard>,
+  permission: CollabPermission,
+  /// Records the paths changed by the local transactions while the [CollabPermission] restricts
+  /// them.
+  changed_paths: Option<ChangedPaths>,
+  /// Records the changes of the local transactions, see [Collab::enable_attribution].
+  pub(crate) attribution: Option<CollabAttribution>,
+  /// The records of the attribution by path, created when they are first read or written.
+  pub(crate) attribution_index: OnceLock<Arc<AttributionIndex>>,
+  /// Holds the update of the current transaction until it's approved, see [UpdateGate].
+  pub(crate) update_gate: Arc<UpdateGate>,
+  object_id: String,
+  plugins: Plugins,
 
   /// The current transaction that is being executed.
   current_txn: Option<TransactionMut<'static>>,
+  /// Whether the current transaction runs the [CollabPlugin::before_commit] hooks.
+  guarded: bool,
 }
 
 unsafe impl Send for CollabContext {}
 unsafe impl Sync for CollabContext {}
 
 impl CollabContext {
-  fn new(origin: CollabOrigin, awareness: Awareness) -> Self {
+  fn new(origin: CollabOrigin, awareness: Awareness, object_id: &str, plugins: Plugins) -> Self {
     CollabContext {
       origin,
       awareness,
       undo_manager: None,
+      undo_scopes: HashMap::new(),
+      commit_guard: None,
+      permission: CollabPermission::default(),
+      changed_paths: None,
+      attribution: None,
+      attribution_index: OnceLock::new(),
+      update_gate: Arc::new(UpdateGate::default()),
+      object_id: object_id.to_string(),
+      plugins,
       current_txn: None,
+      guarded: false,
     }
   }
 
+  /// Executes `f` within the current transaction, or a new one if there is none. The call that
+  /// creates the transaction commits it, after the [CollabPlugin::before_commit] hooks and the
+  /// [CollabPermission] approved it. If the transaction is rejected, its changes are reverted and
+  /// the error is returned.
+  ///
+  /// yrs can't abort a transaction: the rejected changes are committed, then deleted by a second
+  /// transaction. The observers and the undo managers see both transactions, and the next
+  /// approved update sent to the plugins carries the rejected items as deleted content, so they
+  /// still reach the peers and the disk as tombstones.
   pub fn with_txn<F, T>(&mut self, f: F) -> Result<T, CollabError>
   where
     F: FnOnce(&mut TransactionMut) -> T,
   {
-    let mut cleanup = false;
-    if self.current_txn.is_none() {
-      let txn: TransactionMut<'_> = self.transact_mut();
-      self.current_txn = Some(unsafe {
-        std::mem::transmute::<yrs::TransactionMut<'_>, yrs::TransactionMut<'static>>(txn)
-      });
-      cleanup = true;
-    }
+    self.with_txn_inner(true, f)
+  }
 
+  fn with_txn_inner<F, T>(&mut self, run_hooks: bool, f: F) -> Result<T, CollabError>
+  where
+    F: FnOnce(&mut TransactionMut) -> T,
+  {
+    let cleanup = self.begin_txn(run_hooks, false);
     let txn = self.current_txn.as_mut().unwrap();
 
     // if we let panics happen, we might not be able to cleanup broken transaction
@@ -131,11 +192,112 @@ impl CollabContext {
 
     if cleanup {
       // the call which initialized the transaction is responsible for cleaning it up
-      self.current_txn = None;
+      self.commit_txn(result.is_ok(), false)?;
+    }
+    result
+  }
+
+  /// Like [CollabContext::with_txn], but the transaction is also reverted when `f` fails, e.g. a
+  /// migration that fails halfway, and the error of `f` is returned.
+  pub fn try_with_txn<F, T>(&mut self, f: F) -> Result<T, CollabError>
+  where
+    F: FnOnce(&mut TransactionMut) -> Result<T, CollabError>,
+  {
+    let cleanup = self.begin_txn(true, true);
+    let txn = self.current_txn.as_mut().unwrap();
+    let result = std::panic::catch_unwind(AssertUnwindSafe(|| f(txn)))
+      .map_err(|_| CollabError::YrsTransactionError("failed to execute transaction".to_string()))
+      .and_then(|result| result);
+
+    if cleanup {
+      let failed = result.is_err();
+      let verdict = self.commit_txn(!failed, failed);
+      if !failed {
+        verdict?;
+      }
     }
     result
   }
 
+  /// Starts a new transaction, unless there is already one. Returns true if it was started. A
+  /// `revertible` transaction can be reverted even if no hook or permission guards it.
+  fn begin_txn(&mut self, run_hooks: bool, revertible: bool) -> bool {
+    if self.current_txn.is_some() {
+      return false;
+    }
+    self.guarded = revertible
+      || (run_hooks && (self.plugins.has_commit_hooks() || !self.permission.is_read_write()));
+    if self.guarded && self.commit_guard.is_none() {
+      self.commit_guard = Some(CommitGuard::new(self.doc(), &self.origin));
+    }
+    if let Some(changed_paths) = &self.changed_paths {
+      // Drop the paths changed by the transactions that weren't checked
+      changed_paths.take();
+    }
+    let txn = self.doc().transact_mut_with(self.origin.clone());
+    self.current_txn = Some(unsafe {
+      std::mem::transmute::<yrs::TransactionMut<'_>, yrs::TransactionMut<'static>>(txn)
+    });
+    true
+  }
+
+  /// Commits the current transaction. The update of a guarded transaction is only sent to the
+  /// plugins if the [CollabPlugin::before_commit] hooks and the [CollabPermission] approved it. A
+  /// rejected transaction is reverted. The hooks are skipped if `run_hooks` is false, e.g. when
+  /// the transaction panicked. A guarded transaction is always reverted if `revert` is true.
+  fn commit_txn(&mut self, run_hooks: bool, revert: bool) -> Result<(), CollabError> {
+    let Some(txn) = self.current_txn.take() else {
+      return Ok(());
+    };
+    if !self.guarded {
+      drop(txn);
+      return Ok(());
+    }
+
+    let verdict = if revert {
+      Err(CollabError::TransactionRejected(
+        "the transaction failed".to_string(),
+      ))
+    } else if run_hooks {
+      let object_id = self.object_id.as_str();
+      self
+        .plugins
+        .try_each(|plugin| plugin.before_commit(object_id, &txn))
+    } else {
+      Ok(())
+    };
+    // The changed paths are only known once the events of the transaction were emitted, so
+    // they are checked by the update observer, right before the update is sent.
+    let permission_check = match &self.changed_paths {
+      Some(changed_paths) if run_hooks => Some(changed_paths.check(self.permission.clone())),
+      _ => None,
+    };
+    let before_state = txn.before_state().clone();
+    self.update_gate.check(PendingCheck {
+      verdict,
+      permission_check,
+    });
+    drop(txn);
+    let verdict = self.update_gate.verdict();
+
+    match &verdict {
+      Ok(()) => {
+        if let Some(guard) = self.commit_guard.as_mut() {
+          guard.rollback.clear();
+        }
+      },
+      Err(_) => {
+        self.update_gate.close(before_state.get(&self.client_id()));
+        if let Some(guard) = self.commit_guard.as_mut() {
+          guard.rollback.undo_blocking();
+          guard.rollback.clear();
+        }
+        self.update_gate.open();
+      },
+    }
+    verdict
+  }
+
   #[inline]
   pub fn doc(&self) -> &Doc {
     self.awareness.doc()
@@ -153,6 +315,19 @@ impl CollabContext {
     &mut self.awareness
   }
 
+  pub fn permission(&self) -> &CollabPermission {
+    &self.permission
+  }
+
+  /// Replaces the [CollabPermission], e.g. when a page is locked or unlocked. It applies to the
+  /// transactions created after the call.
+  pub fn set_permission(&mut self, permission: CollabPermission) {
+    if !permission.is_read_write() && self.changed_paths.is_none() {
+      self.changed_paths = Some(ChangedPaths::new(self.doc(), self.origin.clone()));
+    }
+    self.permission = permission;
+  }
+
   pub fn undo_manager(&self) -> Result<&UndoManager, CollabError> {
     match &self.undo_manager {
       None => Err(CollabError::UndoManagerNotEnabled),
@@ -167,6 +342,9 @@ impl CollabContext {
     }
   }
 
+  /// Returns a raw transaction. It bypasses the [CollabPlugin::before_commit] hooks and the
+  /// [CollabPermission]: its changes are always kept. Use [CollabContext::with_txn] or
+  /// [CollabContext::try_with_txn] for the changes that must be approved.
   pub fn transact_mut(&mut self) -> TransactionMut {
     self.doc().transact_mut_with(self.origin.clone())
   }
@@ -181,8 +359,10 @@ impl CollabContext {
     Ok(undo_manager.redo_blocking())
   }
 
+  /// Applies the update without running the [CollabPlugin::before_commit] hooks: the update
+  /// contains changes that were already committed elsewhere.
   pub fn apply_update(&mut self, update: Update) -> Result<(), CollabError> {
-    self.with_txn(|tx| tx.apply_update(update))??;
+    self.with_txn_inner(false, |tx| tx.apply_update(update))??;
     Ok(())
   }
 
@@ -197,7 +377,7 @@ impl CollabContext {
       None
     };
     if let Some(state) = state {
-      if let Err(e) = self.awareness.set_local_state(state) {
+      if let Err(e) = set_local_awareness_state(&self.awareness, state) {
         tracing::warn!("Failed to set awareness state: {}", e);
       }
     }
@@ -226,6 +406,150 @@ impl CollabContext {
   }
 }
 
+/// Reverts the local transactions rejected by a [CollabPlugin::before_commit] hook. yrs can't
+/// abort a transaction, so the rejected transaction is committed and then undone with a dedicated
+/// [UndoManager] that only tracks the transactions of the local origin.
+struct CommitGuard {
+  rollback: UndoManager,
+}
+
+impl CommitGuard {
+  fn new(doc: &Doc, origin: &CollabOrigin) -> Self {
+    // get_or_insert_map would commit an empty transaction
+    let root_map = |name| {
+      let map = doc.transact().get_map(name);
+      map.unwrap_or_else(|| doc.get_or_insert_map(name))
+    };
+    let data = root_map(DATA_SECTION);
+    let meta = root_map(META_SECTION);
+    let options = yrs::undo::Options {
+      // Every transaction is a separate stack item
+      capture_timeout_millis: 0,
+      ..Default::default()
+    };
+    let mut rollback = UndoManager::with_scope_and_options(doc, &data, options);
+    rollback.expand_scope(&meta);
+    rollback.include_origin(origin.clone());
+    Self { rollback }
+  }
+}
+
+/// Decides what the update observer of a [Collab] sends to the plugins.
+///
+/// The update of a guarded transaction is only sent if the transaction is approved. A rejected
+/// transaction and the transaction that reverts it send nothing, but their changes can't be
+/// aborted: the local client has a gap in its history that the peers don't know about. The next
+/// local update is encoded from the start of the gap, otherwise the peers couldn't integrate it.
+#[derive(Default)]
+pub(crate) struct UpdateGate(Mutex<GateState>);
+
+#[derive(Default)]
+struct GateState {
+  mode: GateMode,
+  /// The clock of the local client before the first change that wasn't sent.
+  gap: Option<u32>,
+}
+
+impl GateState {
+  /// Runs the pending check, if any, and returns true unless the current transaction was
+  /// rejected or the gate is closed.
+  fn run_check(&mut self) -> bool {
+    if matches!(self.mode, GateMode::Check(_)) {
+      if let GateMode::Check(pending) = std::mem::take(&mut self.mode) {
+        self.mode = GateMode::Checked(pending.run());
+      }
+    }
+    !matches!(self.mode, GateMode::Closed | GateMode::Checked(Err(_)))
+  }
+}
+
+#[derive(Default)]
+enum GateMode {
+  #[default]
+  Open,
+  /// The update of the current transaction is sent if it passes the check.
+  Check(PendingCheck),
+  /// The verdict of the current transaction.
+  Checked(Result<(), CollabError>),
+  /// Nothing is sent, e.g. while a rejected transaction is reverted.
+  Closed,
+}
+
+struct PendingCheck {
+  /// The verdict of the [CollabPlugin::before_commit] hooks.
+  verdict: Result<(), CollabError>,
+  permission_check: Option<PermissionCheck>,
+}
+
+impl PendingCheck {
+  fn run(self) -> Result<(), CollabError> {
+    self.verdict?;
+    match self.permission_check {
+      Some(permission_check) => permission_check.run(),
+      None => Ok(()),
+    }
+  }
+}
+
+impl UpdateGate {
+  fn check(&self, pending: PendingCheck) {
+    self.0.lock().unwrap().mode = GateMode::Check(pending);
+  }
+
+  /// Returns the verdict of the checked transaction and opens the gate. The check runs here if
+  /// the transaction didn't emit any update.
+  fn verdict(&self) -> Result<(), CollabError> {
+    match std::mem::take(&mut self.0.lock().unwrap().mode) {
+      GateMode::Check(pending) => pending.run(),
+      GateMode::Checked(verdict) => verdict,
+      _ => Ok(()),
+    }
+  }
+
+  /// Stops sending updates. `clock` is the clock of the local client before the rejected
+  /// transaction.
+  fn close(&self, clock: u32) {
+    let mut state = self.0.lock().unwrap();
+    state.mode = GateMode::Closed;
+    state.gap.get_or_insert(clock);
+  }
+
+  fn open(&self) {
+    self.0.lock().unwrap().mode = GateMode::Open;
+  }
+
+  /// Returns true if the changes of the current transaction are kept. It can be called before
+  /// the update is emitted, e.g. by an after transaction observer.
+  pub(crate) fn approved(&self) -> bool {
+    self.0.lock().unwrap().run_check()
+  }
+
+  /// Returns the update to send for the transaction, or None if it must not be sent.
+  fn filter<'a>(
+    &self,
+    txn: &TransactionMut,
+    update: &'a [u8],
+    local_origin: &CollabOrigin,
+  ) -> Option<Cow<'a, [u8]>> {
+    let mut state = self.0.lock().unwrap();
+    if !state.run_check() {
+      return None;
+    }
+
+    if &CollabOrigin::from(txn) != local_origin {
+      return Some(Cow::Borrowed(update));
+    }
+    match state.gap.take() {
+      None => Some(Cow::Borrowed(update)),
+      Some(clock) => {
+        let mut state_vector = txn.before_state().clone();
+        state_vector.set_min(txn.doc().client_id(), clock);
+        Some(Cow::Owned(txn.encode_state_as_update_v1(&state_vector)))
+      },
+    }
+  }
+}
+
 pub fn default_client_id() -> ClientID {
   let mut rng = fastrand::Rng::new();
   let client_id: u32 = rng.u32(0..u32::MAX);
@@ -251,6 +575,19 @@ pub struct CollabOptions {
   pub object_id: String,
   pub data_source: Option<DataSource>,
   pub client_id: ClientID,
+  /// Keep the deleted content of the document. It's required to read the document back at a
+  /// previous [crate::core::collab_version::CollabVersion]. Default is [false].
+  pub skip_gc: bool,
+  pub version_storage: Option<Arc<dyn CollabVersionStorage>>,
+  /// When set, the data loaded from the [DataSource] is validated against the schema and
+  /// [Collab::new_with_options] fails with [CollabError::SchemaViolations] on mismatch.
+  pub schema: Option<CollabSchema>,
+  /// Migrations applied to the data loaded from the [DataSource], before it's validated
+  /// against the [CollabOptions::schema].
+  pub migrations: Option<CollabMigrations>,
+  /// Restricts the local changes. It's applied once the data is loaded from the [DataSource] and
+  /// migrated. Default is [CollabPermission::ReadWrite].
+  pub permission: CollabPermission,
 }
 
 impl Display for CollabOptions {
@@ -259,6 +596,7 @@ impl Display for CollabOptions {
       .field("object_id", &self.object_id)
       .field("client_id", &self.client_id)
       .field("data_source", &self.data_source)
+      .field("skip_gc", &self.skip_gc)
       .finish()
   }
 }
@@ -269,6 +607,11 @@ impl CollabOptions {
       object_id,
       data_source: None,
       client_id,
+      skip_gc: false,
+      version_storage: None,
+      schema: None,
+      migrations: None,
+      permission: CollabPermission::default(),
     }
   }
 
@@ -276,6 +619,31 @@ impl CollabOptions {
     self.data_source = Some(data_source);
     self
   }
+
+  pub fn with_skip_gc(mut self, skip_gc: bool) -> Self {
+    self.skip_gc = skip_gc;
+    self
+  }
+
+  pub fn with_version_storage(mut self, version_storage: Arc<dyn CollabVersionStorage>) -> Self {
+    self.version_storage = Some(version_storage);
+    self
+  }
+
+  pub fn with_schema(mut self, schema: CollabSchema) -> Self {
+    self.schema = Some(schema);
+    self
+  }
+
+  pub fn with_migrations(mut self, migrations: CollabMigrations) -> Self {
+    self.migrations = Some(migrations);
+    self
+  }
+
+  pub fn with_permission(mut self, permission: CollabPermission) -> Self {
+    self.permission = permission;
+    self
+  }
 }
 
 impl Collab {
@@ -295,15 +663,15 @@ impl Collab {
     options: CollabOptions,
   ) -> Result<Self, CollabError> {
     let object_id = options.object_id;
-    let doc = make_yrs_doc(&object_id, false, options.client_id);
+    let doc = make_yrs_doc(&object_id, options.skip_gc, options.client_id);
     let data = doc.get_or_insert_map(DATA_SECTION);
     let meta = doc.get_or_insert_map(META_SECTION);
     let plugins = Plugins::new(vec![]);
     let state = Arc::new(State::new(&object_id));
     let awareness = Awareness::new(doc);
     let mut this = Self {
+      context: CollabContext::new(origin, awareness, &object_id, plugins.clone()),
       object_id,
-      context: CollabContext::new(origin, awareness),
       state,
       data,
       meta,
@@ -312,6 +680,11 @@ impl Collab {
       after_txn_subscription: Default::default(),
       awareness_subscription: Default::default(),
       index_json_sender: tokio::sync::broadcast::channel(100).0,
+      search: None,
+      presence: None,
+      version_storage: options.version_storage,
+      migration_updates: vec![],
+      snapshot_cache: Default::default(),
     };
 
     if let Some(data_source) = options.data_source {
@@ -333,9 +706,24 @@ impl Collab {
             this.context.apply_update(update)?;
           }
         },
+        DataSource::DocStateV1Stream(reader) => {
+          this.apply_doc_state_stream(reader)?;
+        },
+      }
+
+      // An empty document hasn't been initialized yet, so there is nothing to migrate or
+      // validate.
+      if this.data.len(&this.transact()) > 0 {
+        if let Some(migrations) = &options.migrations {
+          this.run_migrations(migrations)?;
+        }
+        if let Some(schema) = &options.schema {
+          this.validate_schema(schema)?;
+        }
       }
     }
 
+    this.context.set_permission(options.permission);
     Ok(this)
   }
 
@@ -364,19 +752,25 @@ impl Collab {
     let meta = doc.get_or_insert_map(META_SECTION);
     let state = Arc::new(State::new(&object_id));
     let awareness = Awareness::new(doc);
+    let plugins = Plugins::default();
     Self {
-      object_id,
       // if not the fact that we need origin here, it would be
       // not necessary either
-      context: CollabContext::new(origin, awareness),
+      context: CollabContext::new(origin, awareness, &object_id, plugins.clone()),
+      object_id,
       state,
       data,
       meta,
-      plugins: Plugins::default(),
+      plugins,
       update_subscription: Default::default(),
       after_txn_subscription: Default::default(),
       awareness_subscription: Default::default(),
       index_json_sender: tokio::sync::broadcast::channel(100).0,
+      search: None,
+      presence: None,
+      version_storage: None,
+      migration_updates: vec![],
+      snapshot_cache: Default::default(),
     }
   }
 
@@ -408,6 +802,26 @@ impl Collab {
         .plugins
         .each(|plugin| plugin.did_init(self, &self.object_id));
     }
+    self.send_migration_updates();
+  }
+
+  /// Sends the updates of the migrations that ran when the [Collab] was opened, before the
+  /// update observer was installed. Otherwise the plugins, e.g. the disk plugin, would never
+  /// store them, and the later updates that depend on them couldn't be integrated.
+  fn send_migration_updates(&mut self) {
+    let updates = std::mem::take(&mut self.migration_updates);
+    if updates.is_empty() {
+      return;
+    }
+    let origin = self.origin();
+    // The changes were already committed, the transaction only gives the plugins the origin
+    let txn = self.context.doc().transact_mut_with(origin.clone());
+    for update in &updates {
+      self.plugins.each(|plugin| {
+        plugin.receive_update(&self.object_id, &txn, update);
+        plugin.receive_local_update(origin, &self.object_id, update);
+      });
+    }
   }
 
   pub fn observe_update(&mut self) {
@@ -421,6 +835,7 @@ impl Collab {
       self.object_id.clone(),
       self.plugins.clone(),
       self.origin().clone(),
+      self.context.update_gate.clone(),
     );
 
     let awareness_subscription = observe_awareness(
@@ -506,6 +921,12 @@ impl Collab {
     });
   }
 
+  /// Inserts the value in the data section.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the transaction is rejected, e.g. by the [CollabPermission] of a read-only collab.
+  /// Use [Collab::try_insert] to get the error instead.
   pub fn insert<P>(&mut self, key: &str, value: P) -> P::Return
   where
     P: Prelim,
@@ -525,6 +946,12 @@ impl Collab {
     V::try_from(value).ok()
   }
 
+  /// Removes the value from the data section.
+  ///
+  /// # Panics
+  ///
+  /// Panics if the transaction is rejected, e.g. by the [CollabPermission] of a read-only collab.
+  /// Use [Collab::try_remove] to get the error instead.
   pub fn remove(&mut self, key: &str) -> Option<Out> {
     self
       .context
@@ -532,6 +959,25 @@ impl Collab {
       .unwrap()
   }
 
+  /// Like [Collab::insert], but returns the error of a rejected transaction, e.g.
+  /// [CollabError::PermissionDenied], instead of panicking. The [CollabPermission] is checked
+  /// before anything is inserted.
+  pub fn try_insert<P>(&mut self, key: &str, value: P) -> Result<P::Return, CollabError>
+  where
+    P: Prelim,
+  {
+    self.context.permission.check(&[Path::from(vec![key])])?;
+    self.context.with_txn(|tx| self.data.insert(tx, key, value))
+  }
+
+  /// Like [Collab::remove], but returns the error of a rejected transaction, e.g.
+  /// [CollabError::PermissionDenied], instead of panicking. The [CollabPermission] is checked
+  /// before anything is removed.
+  pub fn try_remove(&mut self, key: &str) -> Result<Option<Out>, CollabError> {
+    self.context.permission.check(&[Path::from(vec![key])])?;
+    self.context.with_txn(|tx| self.data.remove(tx, key))
+  }
+
   pub fn enable_undo_redo(&mut self) {
     if self.context.undo_manager.is_some() {
       return;
@@ -548,6 +994,28 @@ impl Collab {
     self.context.undo_manager = Some(undo_manager);
   }
 
+  /// Builds a full-text search index over the [Collab::data] section. The index is kept up to
+  /// date with every subsequent local or remote change.
+  pub fn enable_search(&mut self) {
+    if self.search.is_some() {
+      return;
+    }
+    self.search = Some(CollabSearch::new(self));
+  }
+
+  pub fn search_index(&self) -> Result<&CollabSearchIndex, CollabError> {
+    match &self.search {
+      None => Err(CollabError::SearchNotEnabled),
+      Some(search) => Ok(&search.index),
+    }
+  }
+
+  /// Returns at most `limit` ranked hits for the given query. Each hit contains the object id
+  /// and the path of the matching value.
+  pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, CollabError> {
+    Ok(self.search_index()?.search(query, limit))
+  }
+
   /// Returns the doc state and the state vector.
   pub fn encode_collab_v1<F, E>(&self, validate: F) -> Result<EncodedCollab, E>
   where
@@ -610,26 +1078,30 @@ fn observe_doc(
   oid: String,
   plugins: Plugins,
   local_origin: CollabOrigin,
+  update_gate: Arc<UpdateGate>,
 ) -> (Subscription, Option<AfterTransactionSubscription>) {
   let cloned_oid = oid.clone();
   let cloned_plugins = plugins.clone();
   let update_sub = doc
     .observe_update_v1(move |txn, event| {
+      let Some(update) = update_gate.filter(txn, &event.update, &local_origin) else {
+        return;
+      };
       // If the origin of the txn is none, it means that the update is coming from a remote source.
       cloned_plugins.each(|plugin| {
         #[cfg(all(debug_assertions, feature = "verbose_log"))]
         {
-          if let Ok(update) = Update::decode_v1(&event.update) {
+          if let Ok(update) = Update::decode_v1(&update) {
             tracing::trace!("Collab {} apply update: {:#?}", cloned_oid, update);
           } else {
             tracing::warn!("Failed to decode update for Collab {}", cloned_oid);
           }
         }
 
-        plugin.receive_update(&cloned_oid, txn, &event.update);
+        plugin.receive_update(&cloned_oid, txn, &update);
         let remote_origin = CollabOrigin::from(txn);
         if remote_origin == local_origin {
-          plugin.receive_local_update(&local_origin, &cloned_oid, &event.update);
+          plugin.receive_local_update(&local_origin, &cloned_oid, &update);
         } else {
           #[cfg(feature = "verbose_log")]
           tracing::trace!("{} did apply remote {} update", local_origin, remote_origin);
@@ -655,6 +1127,9 @@ pub enum DataSource {
   Disk(Option<Box<dyn CollabPersistence>>),
   DocStateV1(Vec<u8>),
   DocStateV2(Vec<u8>),
+  /// A doc state written by [Collab::write_doc_state]. It's applied one chunk at a time, so the
+  /// encoded doc state is never read at once, see [Collab::apply_doc_state_stream].
+  DocStateV1Stream(Box<dyn std::io::Read + Send + Sync>),
 }
 
 impl Debug for DataSource {
@@ -663,6 +1138,7 @@ impl Debug for DataSource {
       DataSource::Disk(_) => f.write_str("Disk"),
       DataSource::DocStateV1(_) => f.write_str("DocStateV1"),
       DataSource::DocStateV2(_) => f.write_str("DocStateV2"),
+      DataSource::DocStateV1Stream(_) => f.write_str("DocStateV1Stream"),
     }
   }
 }
@@ -682,6 +1158,7 @@ impl DataSource {
       DataSource::Disk(d) => d.is_none(),
       DataSource::DocStateV1(d) => d.is_empty(),
       DataSource::DocStateV2(d) => d.is_empty(),
+      DataSource::DocStateV1Stream(_) => false,
     }
   }
   pub fn as_update(&self) -> Result<Option<Update>, CollabError> {
@@ -692,13 +1169,29 @@ impl DataSource {
       DataSource::DocStateV2(doc_state) if !doc_state.is_empty() => {
         Ok(Some(Update::decode_v2(doc_state)?))
       },
+      DataSource::DocStateV1Stream(_) => Err(CollabError::InvalidDocStateStream(
+        "a stream can't be decoded as a single update".to_string(),
+      )),
       _ => Ok(None),
     }
   }
 }
-#[derive(Clone)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Path(Vec<String>);
 
+impl Path {
+  /// Returns true if this path is equal to or nested under the given `prefix`.
+  pub fn starts_with(&self, prefix: &Path) -> bool {
+    self.0.starts_with(&prefix.0)
+  }
+}
+
+impl Display for Path {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.write_str(&self.0.join("/"))
+  }
+}
+
 impl IntoIterator for Path {
   type Item = String;
   type IntoIter = IntoIter<Self::Item>;
@@ -730,6 +1223,21 @@ impl From<Vec<String>> for Path {
   }
 }
 
+/// Converts a yrs event path into a [Path]. Array indexes are stored as their decimal string
+/// representation.
+impl From<yrs::types::Path> for Path {
+  fn from(path: yrs::types::Path) -> Self {
+    let values = path
+      .into_iter()
+      .map(|segment| match segment {
+        yrs::types::PathSegment::Key(key) => key.to_string(),
+        yrs::types::PathSegment::Index(index) => index.to_string(),
+      })
+      .collect::<Vec<String>>();
+    Self(values)
+  }
+}
+
 impl Deref for Path {
   type Target = Vec<String>;
 
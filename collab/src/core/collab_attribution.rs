@@ -0,0 +1,352 @@
+//! Records who changed each value of the [Collab::data] section, and when.
+//!
+//! Once [Collab::enable_attribution] is called, the paths changed by the local transactions are
+//! recorded with the [ClientID] of the collab and a timestamp. The records are written by the
+//! transaction that made the changes, once it's approved, so they are synced with the other peers
+//! like any other change. The [ClientID]s are mapped to the [CollabClient] that owns them in the
+//! same way.
+//!
+//! A change is attributed when it creates content: the transactions that only remove values are
+//! not recorded. The records are compacted when they are written: a record is dropped when its
+//! value is removed or when one of its ancestors is replaced, so there is at most one record per
+//! value of the document.
+//!
+//! [Collab::attribution_at] reads the records, it doesn't require the attribution to be enabled:
+//! a peer that doesn't record its own changes can still read the records of the other peers.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use yrs::block::ClientID;
+use yrs::encoding::serde::from_any;
+use yrs::types::{EntryChange, Event};
+use yrs::{
+  DeepObservable, Doc, Map, MapRef, Observable, Out, ReadTxn, Subscription, TransactionMut,
+};
+
+use crate::core::collab::{Collab, CollabContext, Path, UpdateGate};
+use crate::core::collab_permission::collect_paths;
+use crate::core::origin::{CollabClient, CollabOrigin};
+use crate::error::CollabError;
+use crate::util::json_value_to_any;
+
+/// Maps the [ClientID]s to their [CollabClient].
+pub const ATTRIBUTION_USERS_SECTION: &str = "attribution_users";
+/// Maps the changed paths to their last change.
+pub const ATTRIBUTION_EDITS_SECTION: &str = "attribution_edits";
+
+/// The last change of the value at [Attribution::path].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attribution {
+  pub path: Path,
+  pub client_id: ClientID,
+  /// None if the client that made the change is unknown.
+  pub user: Option<CollabClient>,
+  /// The time of the change, in milliseconds since the unix epoch.
+  pub timestamp: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Edit {
+  path: Path,
+  client_id: ClientID,
+  timestamp: i64,
+}
+
+impl Edit {
+  fn from_out(value: &Out) -> Option<Self> {
+    match value {
+      Out::Any(value) => from_any(value).ok(),
+      _ => None,
+    }
+  }
+}
+
+/// The records of the [ATTRIBUTION_EDITS_SECTION] by path. The records of the other peers are
+/// added by the observer of the section, the local ones by [CollabAttribution].
+pub(crate) struct AttributionIndex {
+  edits: Arc<Mutex<BTreeMap<Path, Edit>>>,
+  _subscription: Subscription,
+}
+
+impl AttributionIndex {
+  fn new<T: ReadTxn>(txn: &T, section: &MapRef) -> Self {
+    let edits = section
+      .iter(txn)
+      .filter_map(|(_, value)| Edit::from_out(&value))
+      .map(|edit| (edit.path.clone(), edit))
+      .collect::<BTreeMap<_, _>>();
+    let edits = Arc::new(Mutex::new(edits));
+    let subscription = {
+      let edits = edits.clone();
+      section.observe(move |txn, event| {
+        let mut edits = edits.lock().unwrap();
+        for change in event.keys(txn).values() {
+          let (old, new) = match change {
+            EntryChange::Inserted(new) => (None, Some(new)),
+            EntryChange::Updated(old, new) => (Some(old), Some(new)),
+            EntryChange::Removed(old) => (Some(old), None),
+          };
+          if let Some(old) = old.and_then(Edit::from_out) {
+            edits.remove(&old.path);
+          }
+          if let Some(new) = new.and_then(Edit::from_out) {
+            edits.insert(new.path.clone(), new);
+          }
+        }
+      })
+    };
+    Self {
+      edits,
+      _subscription: subscription,
+    }
+  }
+
+  /// Returns the last edit of the value at `path`, of its ancestors, or of the values nested
+  /// under it.
+  fn latest(&self, path: &Path) -> Option<Edit> {
+    let edits = self.edits.lock().unwrap();
+    let mut ancestor = Path::default();
+    let ancestors = path
+      .clone()
+      .into_iter()
+      .filter_map(|key| {
+        ancestor.push(key);
+        edits.get(&ancestor)
+      })
+      .collect::<Vec<_>>();
+    let nested = edits
+      .range(path..)
+      .take_while(|(nested, _)| nested.starts_with(path))
+      .map(|(_, edit)| edit);
+    ancestors
+      .into_iter()
+      .chain(nested)
+      .max_by_key(|edit| edit.timestamp)
+      .cloned()
+  }
+
+  /// Removes the edits of the values nested under `path`, and of `path` itself if `inclusive`.
+  /// Returns their paths.
+  fn remove_nested(&self, path: &Path, inclusive: bool) -> Vec<Path> {
+    let mut edits = self.edits.lock().unwrap();
+    let nested = edits
+      .range(path..)
+      .map(|(nested, _)| nested)
+      .take_while(|nested| nested.starts_with(path))
+      .filter(|nested| inclusive || *nested != path)
+      .cloned()
+      .collect::<Vec<_>>();
+    for nested in &nested {
+      edits.remove(nested);
+    }
+    nested
+  }
+
+  fn insert(&self, edit: Edit) {
+    self.edits.lock().unwrap().insert(edit.path.clone(), edit);
+  }
+}
+
+/// Returns the [AttributionIndex] of the collab, or None if there are no records yet.
+fn attribution_index<T: ReadTxn>(
+  context: &CollabContext,
+  txn: &T,
+) -> Option<Arc<AttributionIndex>> {
+  if let Some(index) = context.attribution_index.get() {
+    return Some(index.clone());
+  }
+  let section = txn.get_map(ATTRIBUTION_EDITS_SECTION)?;
+  let index = context
+    .attribution_index
+    .get_or_init(|| Arc::new(AttributionIndex::new(txn, &section)));
+  Some(index.clone())
+}
+
+/// The changes of the current transaction, and the removed values whose records weren't dropped
+/// yet.
+#[derive(Default)]
+struct PendingChanges {
+  changed: Vec<Path>,
+  /// The values replaced by the current transaction.
+  replaced: Vec<Path>,
+  removed: BTreeSet<Path>,
+}
+
+pub(crate) struct CollabAttribution {
+  _data_subscription: Subscription,
+  _txn_subscription: Subscription,
+}
+
+impl CollabAttribution {
+  fn new(
+    data: &MapRef,
+    doc: &Doc,
+    user: CollabClient,
+    index: Arc<AttributionIndex>,
+    update_gate: Arc<UpdateGate>,
+  ) -> Result<Self, CollabError> {
+    let client_id = doc.client_id();
+    let users = doc.get_or_insert_map(ATTRIBUTION_USERS_SECTION);
+    let edits = doc.get_or_insert_map(ATTRIBUTION_EDITS_SECTION);
+    let pending = Arc::new(Mutex::new(PendingChanges::default()));
+    let local_origin = CollabOrigin::Client(user.clone());
+
+    let data_subscription = {
+      let pending = pending.clone();
+      let local_origin = local_origin.clone();
+      data.observe_deep(move |txn, events| {
+        // The remote updates are applied with the local origin too, but only the local changes
+        // create items of the local client.
+        let created = txn.state_vector().get(&client_id) > txn.before_state().get(&client_id);
+        let local = created && CollabOrigin::from(txn) == local_origin;
+        let mut pending = pending.lock().unwrap();
+        for event in events.iter() {
+          if let Event::Map(event) = event {
+            let parent = Path::from(event.path());
+            for (key, change) in event.keys(txn) {
+              let mut path = parent.clone();
+              path.push(key.to_string());
+              match change {
+                EntryChange::Removed(_) => {
+                  pending.removed.insert(path);
+                },
+                EntryChange::Updated(_, _) if local => pending.replaced.push(path),
+                _ => {},
+              }
+            }
+          }
+          if local {
+            collect_paths(txn, event, &mut pending.changed);
+          }
+        }
+      })
+    };
+
+    let data = data.clone();
+    let txn_subscription = doc
+      .observe_after_transaction(move |txn| {
+        let mut pending = pending.lock().unwrap();
+        let changed = std::mem::take(&mut pending.changed);
+        let replaced = std::mem::take(&mut pending.replaced);
+        // The changes of a rejected transaction are reverted, they must not be recorded
+        if changed.is_empty() || !update_gate.approved() {
+          return;
+        }
+        let client_key = client_id.to_string();
+        if users.get(txn, &client_key).is_none() {
+          match serde_json::to_value(&user).map(json_value_to_any) {
+            Ok(Ok(user)) => {
+              users.insert(txn, client_key, user);
+            },
+            _ => tracing::warn!("Failed to record the user of client {}", client_id),
+          }
+        }
+
+        for path in std::mem::take(&mut pending.removed) {
+          if !value_exists(txn, &data, &path) {
+            for removed in index.remove_nested(&path, true) {
+              edits.remove(txn, &removed.to_string());
+            }
+          }
+        }
+        for path in replaced {
+          for removed in index.remove_nested(&path, true) {
+            edits.remove(txn, &removed.to_string());
+          }
+        }
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        for path in changed {
+          // Replacing a value replaces the values nested under it too
+          for removed in index.remove_nested(&path, false) {
+            edits.remove(txn, &removed.to_string());
+          }
+          let edit = Edit {
+            path,
+            client_id,
+            timestamp,
+          };
+          match serde_json::to_value(&edit).map(json_value_to_any) {
+            Ok(Ok(value)) => {
+              edits.insert(txn, edit.path.to_string(), value);
+              index.insert(edit);
+            },
+            _ => tracing::warn!("Failed to record the attribution of {}", edit.path),
+          }
+        }
+      })
+      .map_err(|_| CollabError::AcquiredWriteTxnFail)?;
+
+    Ok(Self {
+      _data_subscription: data_subscription,
+      _txn_subscription: txn_subscription,
+    })
+  }
+}
+
+fn value_exists(txn: &TransactionMut, data: &MapRef, path: &Path) -> bool {
+  let mut map = data.clone();
+  let mut keys = path.clone().into_iter().peekable();
+  while let Some(key) = keys.next() {
+    match map.get(txn, &key) {
+      None => return false,
+      Some(Out::YMap(nested)) => map = nested,
+      Some(_) => return keys.peek().is_none(),
+    }
+  }
+  true
+}
+
+impl Collab {
+  /// Starts recording the changes of the local transactions, see
+  /// [crate::core::collab_attribution]. Requires a [CollabOrigin::Client] origin, which
+  /// identifies the user.
+  pub fn enable_attribution(&mut self) -> Result<(), CollabError> {
+    if self.context.attribution.is_some() {
+      return Ok(());
+    }
+    let CollabOrigin::Client(user) = self.origin().clone() else {
+      return Err(CollabError::AttributionRequiresClientOrigin);
+    };
+    self
+      .context
+      .doc()
+      .get_or_insert_map(ATTRIBUTION_EDITS_SECTION);
+    let index = {
+      let txn = self.transact();
+      attribution_index(&self.context, &txn).ok_or(CollabError::AcquiredReadTxnFail)?
+    };
+    let attribution = CollabAttribution::new(
+      &self.data,
+      self.context.doc(),
+      user,
+      index,
+      self.context.update_gate.clone(),
+    )?;
+    self.context.attribution = Some(attribution);
+    Ok(())
+  }
+
+  /// Returns the last change of the value at `path`, of the values nested under it, or of its
+  /// ancestors: replacing an ancestor replaces the value too. The path is relative to the
+  /// [Collab::data] section.
+  pub fn attribution_at<P: Into<Path>>(&self, path: P) -> Option<Attribution> {
+    let path = path.into();
+    let txn = self.transact();
+    let edit = attribution_index(&self.context, &txn)?.latest(&path)?;
+    let user = txn
+      .get_map(ATTRIBUTION_USERS_SECTION)
+      .and_then(|users| users.get(&txn, &edit.client_id.to_string()))
+      .and_then(|user| match user {
+        Out::Any(user) => from_any::<CollabClient>(&user).ok(),
+        _ => None,
+      });
+    Some(Attribution {
+      path: edit.path,
+      client_id: edit.client_id,
+      user,
+      timestamp: edit.timestamp,
+    })
+  }
+}
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use yrs::types::{Change as YrsChange, EntryChange, Event, Events, ToJson};
+use yrs::{Any, Array, DeepObservable, Map, MapRef, Out, ReadTxn, Subscription, TransactionMut};
+
+use crate::core::collab::{Collab, DATA_SECTION, Path};
+use crate::core::origin::CollabOrigin;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeKind {
+  Insert,
+  Update,
+  Delete,
+}
+
+/// A structured change of a value stored in the [Collab::data] section.
+///
+/// Shared types (maps, arrays and texts) are converted into their [Any] representation. A text
+/// change is reported as an update of the whole text, with the full old and new content.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+  /// The path of the changed value, relative to the [Collab::data] section. Array elements use
+  /// their index as path segment: the index in the new array for an insertion, and the index in
+  /// the old array for a removal.
+  pub path: Path,
+  pub kind: ChangeKind,
+  pub old: Option<Any>,
+  pub new: Option<Any>,
+  pub origin: CollabOrigin,
+}
+
+impl Collab {
+  /// Observes the changes of the subtree at the given `path`. An empty path observes the whole
+  /// [Collab::data] section.
+  ///
+  /// The callback receives every change of a transaction at once. A change is reported when its
+  /// path is nested under `path`, or when one of the ancestors of `path` is replaced or removed.
+  /// The callback is only invoked while the returned [Subscription] is alive.
+  ///
+  /// yrs doesn't keep the content removed from an array or a text, so the subscription keeps a
+  /// copy of the subtree at `path` to report the old values, and updates the changed values of
+  /// the copy after each transaction. The old value of a replaced or removed ancestor of `path`
+  /// is only reported when it's not a shared type.
+  pub fn observe_changes<P, F>(&self, path: P, f: F) -> Subscription
+  where
+    P: Into<Path>,
+    F: Fn(&TransactionMut, Vec<Change>) + Send + Sync + 'static,
+  {
+    let prefix = path.into();
+    let subtree = Arc::new(Mutex::new(get_subtree(
+      &self.transact(),
+      &self.data,
+      &prefix,
+    )));
+    self.data.observe_deep(move |txn, events| {
+      let origin = CollabOrigin::from(txn);
+      let events = events
+        .iter()
+        .filter(|event| {
+          let path = Path::from(event.path());
+          path.starts_with(&prefix) || prefix.starts_with(&path)
+        })
+        .collect::<Vec<_>>();
+      if events.is_empty() {
+        return;
+      }
+
+      let mut subtree = subtree.lock().unwrap();
+      let mut changes = vec![];
+      {
+        let old_data = OldData::new(&prefix, subtree.as_ref(), txn, &events);
+        for event in &events {
+          collect_changes(txn, event, &origin, &old_data, &mut changes);
+        }
+      }
+      update_subtree(&mut subtree, &prefix, txn, &events);
+      changes.retain(|change| change.path.starts_with(&prefix) || prefix.starts_with(&change.path));
+      if !changes.is_empty() {
+        f(txn, changes);
+      }
+    })
+  }
+}
+
+/// An edit of an array, as reported by yrs.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ArrayDelta {
+  Retain(u32),
+  Added(u32),
+  Removed(u32),
+}
+
+impl ArrayDelta {
+  pub(crate) fn from_delta(delta: &[YrsChange]) -> Vec<Self> {
+    delta
+      .iter()
+      .map(|change| match change {
+        YrsChange::Added(values) => ArrayDelta::Added(values.len() as u32),
+        YrsChange::Removed(len) => ArrayDelta::Removed(*len),
+        YrsChange::Retain(len) => ArrayDelta::Retain(*len),
+      })
+      .collect()
+  }
+}
+
+/// The subtree observed by [Collab::observe_changes] before a transaction.
+struct OldData<'a> {
+  prefix: &'a Path,
+  subtree: Option<&'a Any>,
+  /// The edits of the arrays changed by the transaction, by their path in the new data.
+  arrays: HashMap<Path, Vec<ArrayDelta>>,
+}
+
+impl<'a> OldData<'a> {
+  fn new(
+    prefix: &'a Path,
+    subtree: Option<&'a Any>,
+    txn: &TransactionMut,
+    events: &[&Event],
+  ) -> Self {
+    let arrays = events
+      .iter()
+      .filter_map(|event| match event {
+        Event::Array(event) => Some((
+          Path::from(event.path()),
+          ArrayDelta::from_delta(event.delta(txn)),
+        )),
+        _ => None,
+      })
+      .collect();
+    Self {
+      prefix,
+      subtree,
+      arrays,
+    }
+  }
+
+  /// Returns the old value at a path of the new data, `None` if the path isn't nested under the
+  /// observed subtree. The indexes of the arrays changed by the transaction are mapped to their
+  /// old indexes.
+  fn get(&self, path: &Path) -> Option<&'a Any> {
+    let segments = path.strip_prefix(self.prefix.as_slice())?;
+    let mut value = self.subtree?;
+    let mut current = self.prefix.clone();
+    for segment in segments {
+      value = match value {
+        Any::Map(map) => map.get(segment)?,
+        Any::Array(array) => {
+          let index = segment.parse::<u32>().ok()?;
+          let index = match self.arrays.get(&current) {
+            Some(delta) => old_index(delta, index)?,
+            None => index,
+          };
+          array.get(index as usize)?
+        },
+        _ => return None,
+      };
+      current.push(segment.clone());
+    }
+    Some(value)
+  }
+}
+
+/// Maps the index of an element in the new array to its index in the old one, `None` if the
+/// element was inserted.
+fn old_index(delta: &[ArrayDelta], index: u32) -> Option<u32> {
+  let (mut new_index, mut old_index) = (0, 0);
+  for change in delta {
+    match *change {
+      ArrayDelta::Added(len) => {
+        if index < new_index + len {
+          return None;
+        }
+        new_index += len;
+      },
+      ArrayDelta::Removed(len) => old_index += len,
+      ArrayDelta::Retain(len) => {
+        if index < new_index + len {
+          return Some(old_index + index - new_index);
+        }
+        new_index += len;
+        old_index += len;
+      },
+    }
+  }
+  Some(old_index + index - new_index)
+}
+
+/// Returns the value at `path` in the [Collab::data] section.
+fn get_subtree<T: ReadTxn>(txn: &T, data: &MapRef, path: &Path) -> Option<Any> {
+  let mut value = Out::YMap(data.clone());
+  for segment in path.iter() {
+    value = match value {
+      Out::YMap(map) => map.get(txn, segment)?,
+      Out::YArray(array) => array.get(txn, segment.parse().ok()?)?,
+      _ => return None,
+    };
+  }
+  Some(to_any(txn, &value))
+}
+
+/// Copies the values changed by the transaction into the copy of the observed subtree.
+fn update_subtree(
+  subtree: &mut Option<Any>,
+  prefix: &Path,
+  txn: &TransactionMut,
+  events: &[&Event],
+) {
+  let mut targets = events
+    .iter()
+    .map(|event| (Path::from(event.path()), *event))
+    .collect::<Vec<_>>();
+  // One of the ancestors of the subtree changed: it's copied again
+  let replaced = targets
+    .iter()
+    .any(|(path, event)| replaces_subtree(txn, prefix, path, event));
+  if subtree.is_none() || replaced {
+    *subtree = txn
+      .get_map(DATA_SECTION)
+      .and_then(|data| get_subtree(txn, &data, prefix));
+    return;
+  }
+  let Some(subtree) = subtree.as_mut() else {
+    return;
+  };
+
+  // A value nested under another changed value is copied with it
+  targets.sort_by_key(|(path, _)| path.len());
+  let mut copied: Vec<Path> = vec![];
+  for (path, event) in targets {
+    if !path.starts_with(prefix) || copied.iter().any(|copied| path.starts_with(copied)) {
+      continue;
+    }
+    let value = match event {
+      Event::Map(event) => event.target().to_json(txn),
+      Event::Array(event) => event.target().to_json(txn),
+      Event::Text(event) => event.target().to_json(txn),
+      _ => continue,
+    };
+    replace(subtree, &path[prefix.len()..], value);
+    copied.push(path);
+  }
+}
+
+/// Returns true if the event of an ancestor of the subtree at `prefix` may have replaced, moved
+/// or removed it.
+fn replaces_subtree(txn: &TransactionMut, prefix: &Path, path: &Path, event: &Event) -> bool {
+  if path.len() >= prefix.len() {
+    return false;
+  }
+  match event {
+    Event::Map(event) => event.keys(txn).contains_key(prefix[path.len()].as_str()),
+    _ => true,
+  }
+}
+
+/// Replaces the value at `path`. The maps and arrays on the path are only cloned when they are
+/// shared, e.g. with the old values of the reported changes.
+fn replace(data: &mut Any, path: &[String], value: Any) {
+  let Some((segment, rest)) = path.split_first() else {
+    *data = value;
+    return;
+  };
+  match data {
+    Any::Map(map) => {
+      let child = Arc::make_mut(map)
+        .entry(segment.clone())
+        .or_insert(Any::Null);
+      replace(child, rest, value);
+    },
+    Any::Array(array) => {
+      let Ok(index) = segment.parse::<usize>() else {
+        return;
+      };
+      if Arc::get_mut(array).is_none() {
+        *array = array.to_vec().into();
+      }
+      if let Some(child) = Arc::get_mut(array).and_then(|array| array.get_mut(index)) {
+        replace(child, rest, value);
+      }
+    },
+    _ => {},
+  }
+}
+
+fn collect_changes(
+  txn: &TransactionMut,
+  event: &Event,
+  origin: &CollabOrigin,
+  old_data: &OldData,
+  changes: &mut Vec<Change>,
+) {
+  let parent = Path::from(event.path());
+  match event {
+    Event::Map(event) => {
+      let mut keys = event.keys(txn).iter().collect::<Vec<_>>();
+      // The keys are stored in a hash map, sort them to report the changes in a stable order.
+      keys.sort_by(|(a, _), (b, _)| a.cmp(b));
+      for (key, entry) in keys {
+        let mut path = parent.clone();
+        path.push(key.to_string());
+        let (kind, old, new) = match entry {
+          EntryChange::Inserted(new) => (ChangeKind::Insert, None, Some(to_any(txn, new))),
+          EntryChange::Updated(old, new) => (
+            ChangeKind::Update,
+            Some(old_value(txn, old_data, &path, old)),
+            Some(to_any(txn, new)),
+          ),
+          EntryChange::Removed(old) => (
+            ChangeKind::Delete,
+            Some(old_value(txn, old_data, &path, old)),
+            None,
+          ),
+        };
+        changes.push(Change {
+          path,
+          kind,
+          old,
+          new,
+          origin: origin.clone(),
+        });
+      }
+    },
+    Event::Array(event) => {
+      // Insertions are reported at their index in the new array, and removals at their index in
+      // the old array.
+      let old_array = match old_data.get(&parent) {
+        Some(Any::Array(array)) => Some(array),
+        _ => None,
+      };
+      let mut new_index = 0;
+      let mut old_index = 0;
+      for delta in event.delta(txn) {
+        match delta {
+          YrsChange::Added(values) => {
+            for value in values {
+              changes.push(Change {
+                path: child_path(&parent, new_index),
+                kind: ChangeKind::Insert,
+                old: None,
+                new: Some(to_any(txn, value)),
+                origin: origin.clone(),
+              });
+              new_index += 1;
+            }
+          },
+          YrsChange::Removed(len) => {
+            for _ in 0..*len {
+              changes.push(Change {
+                path: child_path(&parent, old_index),
+                kind: ChangeKind::Delete,
+                old: old_array.and_then(|array| array.get(old_index as usize).cloned()),
+                new: None,
+                origin: origin.clone(),
+              });
+              old_index += 1;
+            }
+          },
+          YrsChange::Retain(len) => {
+            new_index += len;
+            old_index += len;
+          },
+        }
+      }
+    },
+    Event::Text(event) => {
+      changes.push(Change {
+        path: parent,
+        kind: ChangeKind::Update,
+        old: old_data.get(&parent).cloned(),
+        new: Some(to_any(txn, &Out::YText(event.target().clone()))),
+        origin: origin.clone(),
+      });
+    },
+    _ => {},
+  }
+}
+
+fn child_path(parent: &Path, index: u32) -> Path {
+  let mut path = parent.clone();
+  path.push(index.to_string());
+  path
+}
+
+/// The yrs value of a replaced or removed shared type has lost its content, so the old value is
+/// read from the copy of the data.
+fn old_value<T: ReadTxn>(txn: &T, old_data: &OldData, path: &Path, old: &Out) -> Any {
+  match old {
+    Out::Any(any) => any.clone(),
+    other => old_data
+      .get(path)
+      .cloned()
+      .unwrap_or_else(|| other.to_json(txn)),
+  }
+}
+
+fn to_any<T: ReadTxn>(txn: &T, value: &Out) -> Any {
+  match value {
+    Out::Any(any) => any.clone(),
+    other => other.to_json(txn),
+  }
+}
@@ -0,0 +1,76 @@
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{ReadTxn, StateVector, Transact, Update};
+
+use crate::core::collab::{Collab, default_client_id, make_yrs_doc};
+use crate::entity::EncodedCollab;
+use crate::error::CollabError;
+
+/// The size in bytes of a document before and after [Collab::compact].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactionReport {
+  pub before: usize,
+  pub after: usize,
+}
+
+impl CompactionReport {
+  pub fn saved(&self) -> usize {
+    self.before.saturating_sub(self.after)
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompactedCollab {
+  pub encoded_collab: EncodedCollab,
+  pub report: CompactionReport,
+}
+
+impl Collab {
+  /// Rebuilds the doc state with garbage collection applied, i.e. the content of the deleted
+  /// items is dropped. The ids of the items are kept, so the compacted doc has the same state
+  /// vector and the peers can keep syncing with it.
+  ///
+  /// The collab itself is left untouched: open a new collab from
+  /// [CompactedCollab::encoded_collab] to release the memory. When the collab is persisted, compact
+  /// the store instead and reopen the collab from the doc state it returns, so the live doc and
+  /// the stored one stay the same.
+  ///
+  /// The versions of the collab need the deleted items, see [crate::core::collab_version], so
+  /// [CollabError::CompactionWithVersions] is returned as long as its version storage has versions
+  /// of the collab.
+  pub fn compact(&self) -> Result<CompactedCollab, CollabError> {
+    if let Some(storage) = &self.version_storage {
+      if !storage.list_versions(self.object_id())?.is_empty() {
+        return Err(CollabError::CompactionWithVersions(
+          self.object_id().to_string(),
+        ));
+      }
+    }
+    compact_doc_state(&self.transact())
+  }
+}
+
+/// Compacts the doc state of `txn`, see [Collab::compact].
+pub fn compact_doc_state<T: ReadTxn>(txn: &T) -> Result<CompactedCollab, CollabError> {
+  let doc_state = txn.encode_state_as_update_v1(&StateVector::default());
+  let before = doc_state.len();
+
+  // The deleted items of the applied update are collected when the transaction is committed.
+  // The client id is never used as the doc is only read.
+  let doc = make_yrs_doc("", false, default_client_id());
+  doc
+    .transact_mut()
+    .apply_update(Update::decode_v1(&doc_state)?)?;
+
+  let txn = doc.transact();
+  let doc_state = txn.encode_state_as_update_v1(&StateVector::default());
+  let state_vector = txn.state_vector().encode_v1();
+  let report = CompactionReport {
+    before,
+    after: doc_state.len(),
+  };
+  Ok(CompactedCollab {
+    encoded_collab: EncodedCollab::new_v1(state_vector, doc_state),
+    report,
+  })
+}
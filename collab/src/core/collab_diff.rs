@@ -0,0 +1,554 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use yrs::block::ClientID;
+use yrs::types::text::{ChangeKind as TextChangeKind, YChange};
+use yrs::types::{Event, ToJson};
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{
+  Any, Array, DeepObservable, DeleteSet, Doc, Map, Options, Out, ReadTxn, Snapshot, StateVector,
+  Text, Transact, TransactionMut, Update,
+};
+
+use crate::core::collab::{Collab, DATA_SECTION, Path};
+use crate::core::collab_change::ArrayDelta;
+use crate::core::collab_version::encode_state_from_snapshot;
+use crate::entity::{EncodedCollab, EncoderVersion};
+use crate::error::CollabError;
+use crate::preclude::JsonValue;
+
+/// A structural diff of the [Collab::data] section between two states of the same object.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CollabDiff {
+  pub changes: Vec<DiffEntry>,
+  /// The clients that contributed operations to the newer state.
+  pub authors: Vec<ClientID>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffEntry {
+  /// The path of the changed value, relative to the [Collab::data] section. Array elements use
+  /// their index in the newer array as path segment, a removed element the index it was removed
+  /// at.
+  pub path: Path,
+  pub change: DiffChange,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DiffChange {
+  Added { value: JsonValue },
+  Removed { value: JsonValue },
+  Changed { old: JsonValue, new: JsonValue },
+  Text { ops: Vec<TextOp> },
+}
+
+/// An edit of a [yrs::TextRef]. The index is the offset in the newer text, in UTF-16 code units.
+///
+/// yrs only records the client that inserted the content, so the author of a deletion is not
+/// known.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TextOp {
+  Insert {
+    index: u32,
+    text: String,
+    author: ClientID,
+  },
+  Delete {
+    index: u32,
+    text: String,
+  },
+}
+
+impl CollabDiff {
+  /// Returns the diff between two encoded states of the same object. If `new` doesn't contain
+  /// every change of `old`, the diff describes what merging `new` into `old` changes.
+  pub fn between(old: &EncodedCollab, new: &EncodedCollab) -> Result<Self, CollabError> {
+    let doc = Doc::with_options(Options {
+      skip_gc: true,
+      ..Options::default()
+    });
+    doc.transact_mut().apply_update(decode_update(old)?)?;
+    let old_data = data_to_any(&doc);
+    let lo = doc.transact().snapshot();
+    doc.transact_mut().apply_update(decode_update(new)?)?;
+    let hi = doc.transact().snapshot();
+    let arrays = array_deltas(decode_update(old)?, decode_update(new)?)?;
+    Ok(diff_doc(&doc, &lo, &hi, &old_data, &arrays))
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.changes.is_empty()
+  }
+
+  pub fn to_json(&self) -> JsonValue {
+    serde_json::to_value(self).unwrap_or_default()
+  }
+}
+
+impl Collab {
+  /// Returns the diff between an older encoded state of this object and its current state.
+  pub fn diff_from(&self, old: &EncodedCollab) -> Result<CollabDiff, CollabError> {
+    let new = self.encode_collab_v1(|_| Ok::<_, CollabError>(()))?;
+    CollabDiff::between(old, &new)
+  }
+
+  /// Returns the diff between the state of this object at the given [StateVector] and its
+  /// current state. Materialising the old state requires the deleted content, so the [Collab]
+  /// must be created with `skip_gc` enabled.
+  ///
+  /// A [StateVector] doesn't record deletions, so content deleted before `state_vector` is
+  /// reported as removed too. Use [Collab::diff_from] with the old [EncodedCollab] when an exact
+  /// diff is needed.
+  pub fn diff_since(&self, state_vector: &StateVector) -> Result<CollabDiff, CollabError> {
+    if !self.context.doc().skip_gc() {
+      return Err(CollabError::DiffRequiresSkipGc);
+    }
+    let doc_state = self
+      .transact()
+      .encode_state_as_update_v1(&StateVector::default());
+    let doc = Doc::with_options(Options {
+      skip_gc: true,
+      ..Options::default()
+    });
+    doc
+      .transact_mut()
+      .apply_update(Update::decode_v1(&doc_state)?)?;
+
+    let lo = Snapshot::new(state_vector.clone(), DeleteSet::new());
+    let old_doc = Doc::new();
+    let old_state = encode_state_from_snapshot(&doc.transact(), &lo)?;
+    old_doc
+      .transact_mut()
+      .apply_update(Update::decode_v1(&old_state)?)?;
+    let old_data = data_to_any(&old_doc);
+
+    let hi = doc.transact().snapshot();
+    let arrays = array_deltas(
+      Update::decode_v1(&old_state)?,
+      Update::decode_v1(&doc_state)?,
+    )?;
+    Ok(diff_doc(&doc, &lo, &hi, &old_data, &arrays))
+  }
+}
+
+/// A three-way merge report of two states derived from the same base state of an object: the
+/// changes each of them made to the base, the changes of merging both into the base, and the
+/// values both of them changed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CollabMergeReport {
+  pub ours: CollabDiff,
+  pub theirs: CollabDiff,
+  pub merged: CollabDiff,
+  pub conflicts: Vec<MergeConflict>,
+}
+
+/// A value changed by both sides of a merge. When one side changed a value nested under the
+/// value changed by the other side, the path is the one of the outer value.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MergeConflict {
+  pub path: Path,
+  pub ours: DiffEntry,
+  pub theirs: DiffEntry,
+  /// The change of the merged state at the path, `None` if the merged value is the base one.
+  pub merged: Option<DiffChange>,
+}
+
+impl CollabMergeReport {
+  pub fn between(
+    base: &EncodedCollab,
+    ours: &EncodedCollab,
+    theirs: &EncodedCollab,
+  ) -> Result<Self, CollabError> {
+    let doc = Doc::new();
+    doc.transact_mut().apply_update(decode_update(ours)?)?;
+    doc.transact_mut().apply_update(decode_update(theirs)?)?;
+    let txn = doc.transact();
+    let merged = EncodedCollab::new_v1(
+      txn.state_vector().encode_v1(),
+      txn.encode_state_as_update_v1(&StateVector::default()),
+    );
+
+    let ours = CollabDiff::between(base, ours)?;
+    let theirs = CollabDiff::between(base, theirs)?;
+    let merged = CollabDiff::between(base, &merged)?;
+    let mut conflicts = vec![];
+    for our in ours.changes.iter() {
+      for their in theirs.changes.iter() {
+        let path = if our.path.starts_with(&their.path) {
+          their.path.clone()
+        } else if their.path.starts_with(&our.path) {
+          our.path.clone()
+        } else {
+          continue;
+        };
+        if our == their {
+          continue;
+        }
+        let merged = merged
+          .changes
+          .iter()
+          .find(|entry| entry.path == path)
+          .map(|entry| entry.change.clone());
+        conflicts.push(MergeConflict {
+          path,
+          ours: our.clone(),
+          theirs: their.clone(),
+          merged,
+        });
+      }
+    }
+    Ok(Self {
+      ours,
+      theirs,
+      merged,
+      conflicts,
+    })
+  }
+
+  pub fn has_conflicts(&self) -> bool {
+    !self.conflicts.is_empty()
+  }
+
+  pub fn to_json(&self) -> JsonValue {
+    serde_json::to_value(self).unwrap_or_default()
+  }
+}
+
+impl Display for CollabMergeReport {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "ours:")?;
+    write!(f, "{}", self.ours)?;
+    writeln!(f, "theirs:")?;
+    write!(f, "{}", self.theirs)?;
+    writeln!(f, "merged:")?;
+    write!(f, "{}", self.merged)?;
+    if self.conflicts.is_empty() {
+      return writeln!(f, "no conflicts");
+    }
+    for conflict in self.conflicts.iter() {
+      writeln!(f, "! {}:", conflict.path)?;
+      write!(f, "  ours:   ")?;
+      write_change(f, &conflict.ours.path, &conflict.ours.change)?;
+      write!(f, "  theirs: ")?;
+      write_change(f, &conflict.theirs.path, &conflict.theirs.change)?;
+      write!(f, "  merged: ")?;
+      match &conflict.merged {
+        Some(change) => write_change(f, &conflict.path, change)?,
+        None => writeln!(f, "unchanged")?,
+      }
+    }
+    Ok(())
+  }
+}
+
+impl Display for CollabDiff {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    if self.changes.is_empty() {
+      return writeln!(f, "no changes");
+    }
+    for entry in self.changes.iter() {
+      write_change(f, &entry.path, &entry.change)?;
+    }
+    let authors = self
+      .authors
+      .iter()
+      .map(|author| author.to_string())
+      .collect::<Vec<_>>();
+    writeln!(f, "authors: {}", authors.join(", "))
+  }
+}
+
+fn write_change(f: &mut Formatter<'_>, path: &Path, change: &DiffChange) -> std::fmt::Result {
+  match change {
+    DiffChange::Added { value } => writeln!(f, "+ {}: {}", path, value),
+    DiffChange::Removed { value } => writeln!(f, "- {}: {}", path, value),
+    DiffChange::Changed { old, new } => writeln!(f, "~ {}: {} -> {}", path, old, new),
+    DiffChange::Text { ops } => {
+      writeln!(f, "~ {}:", path)?;
+      for op in ops {
+        match op {
+          TextOp::Insert {
+            index,
+            text,
+            author,
+          } => writeln!(f, "    + [{}] {:?} by {}", index, text, author)?,
+          TextOp::Delete { index, text } => writeln!(f, "    - [{}] {:?}", index, text)?,
+        }
+      }
+      Ok(())
+    },
+  }
+}
+
+fn decode_update(encoded_collab: &EncodedCollab) -> Result<Update, CollabError> {
+  let update = match encoded_collab.version {
+    EncoderVersion::V1 => Update::decode_v1(&encoded_collab.doc_state)?,
+    EncoderVersion::V2 => Update::decode_v2(&encoded_collab.doc_state)?,
+  };
+  Ok(update)
+}
+
+type ArrayDeltas = HashMap<Path, Vec<ArrayDelta>>;
+
+/// Applies `new` to a document holding `old` and returns the edits of every array. yrs computes
+/// them from the ids of the elements, so inserting an element doesn't shift the ones after it.
+fn array_deltas(old: Update, new: Update) -> Result<ArrayDeltas, CollabError> {
+  let doc = Doc::new();
+  let data = doc.get_or_insert_map(DATA_SECTION);
+  doc.transact_mut().apply_update(old)?;
+
+  let deltas = Arc::new(Mutex::new(ArrayDeltas::new()));
+  let subscription = {
+    let deltas = deltas.clone();
+    data.observe_deep(move |txn, events| {
+      for event in events.iter() {
+        if let Event::Array(event) = event {
+          let delta = ArrayDelta::from_delta(event.delta(txn));
+          deltas
+            .lock()
+            .unwrap()
+            .insert(Path::from(event.path()), delta);
+        }
+      }
+    })
+  };
+  doc.transact_mut().apply_update(new)?;
+  drop(subscription);
+  let deltas = std::mem::take(&mut *deltas.lock().unwrap());
+  Ok(deltas)
+}
+
+fn data_to_any(doc: &Doc) -> Any {
+  let data = doc.get_or_insert_map(DATA_SECTION);
+  data.to_json(&doc.transact())
+}
+
+/// The states the diff is computed between.
+struct DiffStates<'a> {
+  lo: &'a Snapshot,
+  hi: &'a Snapshot,
+  arrays: &'a ArrayDeltas,
+}
+
+fn diff_doc(
+  doc: &Doc,
+  lo: &Snapshot,
+  hi: &Snapshot,
+  old_data: &Any,
+  arrays: &ArrayDeltas,
+) -> CollabDiff {
+  let data = doc.get_or_insert_map(DATA_SECTION);
+  let mut txn = doc.transact_mut();
+  let mut changes = vec![];
+  let states = DiffStates { lo, hi, arrays };
+  diff_value(
+    &mut txn,
+    &mut Path::default(),
+    old_data,
+    Out::YMap(data),
+    &states,
+    &mut changes,
+  );
+
+  let mut authors = hi
+    .state_map
+    .iter()
+    .filter(|(client, clock)| lo.state_map.get(client) < **clock)
+    .map(|(client, _)| *client)
+    .collect::<Vec<_>>();
+  authors.sort();
+  CollabDiff { changes, authors }
+}
+
+fn diff_value(
+  txn: &mut TransactionMut,
+  path: &mut Path,
+  old: &Any,
+  new: Out,
+  states: &DiffStates,
+  changes: &mut Vec<DiffEntry>,
+) {
+  match (old, new) {
+    (Any::Map(old_map), Out::YMap(map)) => {
+      let mut entries = map
+        .iter(&*txn)
+        .map(|(key, value)| (key.to_string(), value))
+        .collect::<Vec<_>>();
+      entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+      for (key, value) in entries.iter() {
+        path.push(key.clone());
+        match old_map.get(key) {
+          None => push_change(
+            changes,
+            path,
+            DiffChange::Added {
+              value: to_json_value(&value.to_json(&*txn)),
+            },
+          ),
+          Some(old) => diff_value(txn, path, old, value.clone(), states, changes),
+        }
+        path.pop();
+      }
+
+      let mut removed = old_map
+        .iter()
+        .filter(|(key, _)| !entries.iter().any(|(k, _)| k == *key))
+        .collect::<Vec<_>>();
+      removed.sort_by(|(a, _), (b, _)| a.cmp(b));
+      for (key, value) in removed {
+        path.push(key.clone());
+        push_change(
+          changes,
+          path,
+          DiffChange::Removed {
+            value: to_json_value(value),
+          },
+        );
+        path.pop();
+      }
+    },
+    (Any::Array(old_array), Out::YArray(array)) => {
+      let elements = array.iter(&*txn).collect::<Vec<_>>();
+      // The elements after the last edit are retained
+      let mut deltas = states.arrays.get(path).cloned().unwrap_or_default();
+      deltas.push(ArrayDelta::Retain(u32::MAX));
+      let (mut old_index, mut new_index) = (0, 0);
+      for delta in deltas {
+        match delta {
+          ArrayDelta::Retain(len) => {
+            for _ in 0..len {
+              let (Some(old), Some(new)) = (old_array.get(old_index), elements.get(new_index))
+              else {
+                break;
+              };
+              path.push(new_index.to_string());
+              diff_value(txn, path, old, new.clone(), states, changes);
+              path.pop();
+              old_index += 1;
+              new_index += 1;
+            }
+          },
+          ArrayDelta::Added(len) => {
+            for new in elements.iter().skip(new_index).take(len as usize) {
+              path.push(new_index.to_string());
+              push_change(
+                changes,
+                path,
+                DiffChange::Added {
+                  value: to_json_value(&new.to_json(&*txn)),
+                },
+              );
+              path.pop();
+              new_index += 1;
+            }
+          },
+          ArrayDelta::Removed(len) => {
+            for old in old_array.iter().skip(old_index).take(len as usize) {
+              path.push(new_index.to_string());
+              push_change(
+                changes,
+                path,
+                DiffChange::Removed {
+                  value: to_json_value(old),
+                },
+              );
+              path.pop();
+              old_index += 1;
+            }
+          },
+        }
+      }
+    },
+    (Any::String(_), Out::YText(text)) => {
+      let ops = diff_text(txn, &text, states.lo, states.hi);
+      if !ops.is_empty() {
+        push_change(changes, path, DiffChange::Text { ops });
+      }
+    },
+    (old, new) => {
+      let new = new.to_json(&*txn);
+      if old != &new {
+        push_change(
+          changes,
+          path,
+          DiffChange::Changed {
+            old: to_json_value(old),
+            new: to_json_value(&new),
+          },
+        );
+      }
+    },
+  }
+}
+
+fn diff_text(
+  txn: &mut TransactionMut,
+  text: &yrs::TextRef,
+  lo: &Snapshot,
+  hi: &Snapshot,
+) -> Vec<TextOp> {
+  let mut ops: Vec<TextOp> = vec![];
+  let mut index = 0;
+  for chunk in text.diff_range(txn, Some(hi), Some(lo), YChange::identity) {
+    let content = match &chunk.insert {
+      Out::Any(Any::String(content)) => content.to_string(),
+      _ => String::new(),
+    };
+    let len = content.encode_utf16().count().max(1) as u32;
+    match chunk.ychange {
+      None => index += len,
+      Some(YChange {
+        kind: TextChangeKind::Added,
+        id,
+      }) => {
+        match ops.last_mut() {
+          Some(TextOp::Insert {
+            index: last_index,
+            text,
+            author,
+          }) if *author == id.client
+            && *last_index + text.encode_utf16().count() as u32 == index =>
+          {
+            text.push_str(&content)
+          },
+          _ => ops.push(TextOp::Insert {
+            index,
+            text: content,
+            author: id.client,
+          }),
+        }
+        index += len;
+      },
+      Some(YChange {
+        kind: TextChangeKind::Removed,
+        ..
+      }) => match ops.last_mut() {
+        Some(TextOp::Delete {
+          index: last_index,
+          text,
+        }) if *last_index == index => text.push_str(&content),
+        _ => ops.push(TextOp::Delete {
+          index,
+          text: content,
+        }),
+      },
+    }
+  }
+  ops
+}
+
+fn push_change(changes: &mut Vec<DiffEntry>, path: &Path, change: DiffChange) {
+  changes.push(DiffEntry {
+    path: path.clone(),
+    change,
+  });
+}
+
+fn to_json_value(any: &Any) -> JsonValue {
+  serde_json::to_value(any).unwrap_or_default()
+}
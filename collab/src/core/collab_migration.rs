@@ -0,0 +1,132 @@
+use std::sync::Arc;
+
+use yrs::{Any, Map, MapRef, Out, ReadTxn, TransactionMut};
+
+use crate::core::collab::Collab;
+use crate::error::CollabError;
+
+/// The key of the schema version in the [crate::core::collab::META_SECTION].
+pub const SCHEMA_VERSION: &str = "schema_version";
+
+/// A migration of the [Collab::data] section from one schema version to another.
+///
+/// Migrations run when a [Collab] is opened, possibly on several clients at the same time, and
+/// the resulting updates are merged. A migration must therefore be idempotent: running it on
+/// data that was already migrated by another client must leave the data unchanged. For example,
+/// only insert a value when the key is missing, and copy a deprecated key before removing it.
+pub trait CollabMigration: Send + Sync + 'static {
+  fn source_version(&self) -> u32;
+
+  fn target_version(&self) -> u32;
+
+  fn migrate(&self, txn: &mut TransactionMut, data: &MapRef) -> Result<(), CollabError>;
+}
+
+/// An ordered chain of migrations for a single collab type. The chains of all the collab types are
+/// kept by the `MigrationRegistry` of `collab-entity`.
+#[derive(Clone, Default)]
+pub struct CollabMigrations {
+  migrations: Vec<Arc<dyn CollabMigration>>,
+}
+
+impl CollabMigrations {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds a migration to the chain. Each version can only be migrated by one migration, and a
+  /// migration must upgrade the version.
+  pub fn push(&mut self, migration: Arc<dyn CollabMigration>) -> Result<(), CollabError> {
+    let (from, to) = (migration.source_version(), migration.target_version());
+    if to <= from {
+      return Err(CollabError::InvalidMigration(format!(
+        "migration must upgrade the version, from:{} to:{}",
+        from, to
+      )));
+    }
+    if self.migrations.iter().any(|m| m.source_version() == from) {
+      return Err(CollabError::InvalidMigration(format!(
+        "duplicate migration from version:{}",
+        from
+      )));
+    }
+    self.migrations.push(migration);
+    Ok(())
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.migrations.is_empty()
+  }
+
+  /// The version reached after applying every migration.
+  pub fn latest_version(&self) -> u32 {
+    self
+      .migrations
+      .iter()
+      .map(|m| m.target_version())
+      .max()
+      .unwrap_or_default()
+  }
+
+  fn find(&self, from: u32) -> Option<&Arc<dyn CollabMigration>> {
+    self.migrations.iter().find(|m| m.source_version() == from)
+  }
+}
+
+/// Returns the schema version recorded in the meta section. A document without a recorded
+/// version is at version 0.
+pub fn schema_version<T: ReadTxn>(txn: &T, meta: &MapRef) -> u32 {
+  match meta.get(txn, SCHEMA_VERSION) {
+    Some(Out::Any(Any::BigInt(version))) => version as u32,
+    Some(Out::Any(Any::Number(version))) => version as u32,
+    _ => 0,
+  }
+}
+
+impl Collab {
+  pub fn schema_version(&self) -> u32 {
+    schema_version(&self.transact(), &self.meta)
+  }
+
+  /// Applies the migrations that upgrade the current schema version and returns the schema
+  /// version after the migrations. The migrations run in a single transaction, which also records
+  /// the version they upgrade to in the meta section.
+  ///
+  /// A document whose version is newer than the migrations, e.g. when it was written by a newer
+  /// client, is left untouched. If a migration fails, the whole transaction is reverted, so the
+  /// document keeps its version and none of the migrations is applied. They are retried the next
+  /// time the document is opened.
+  pub fn run_migrations(&mut self, migrations: &CollabMigrations) -> Result<u32, CollabError> {
+    let version = self.schema_version();
+    if migrations.is_empty() || version >= migrations.latest_version() {
+      return Ok(version);
+    }
+
+    let data = self.data.clone();
+    let meta = self.meta.clone();
+    let state_vector = self.transact().state_vector();
+    let version = self.context.try_with_txn(|txn| {
+      let mut version = version;
+      while let Some(migration) = migrations.find(version) {
+        migration.migrate(txn, &data)?;
+        version = migration.target_version();
+      }
+      // Another client may have recorded a newer version in the meantime, never downgrade it.
+      if version > schema_version(txn, &meta) {
+        meta.insert(txn, SCHEMA_VERSION, Any::BigInt(version as i64));
+      }
+      Ok(version)
+    })?;
+
+    // Without the update observer, the plugins would never receive the changes
+    if self.get_state().is_uninitialized() {
+      let txn = self.transact();
+      if txn.state_vector() != state_vector {
+        let update = txn.encode_state_as_update_v1(&state_vector);
+        drop(txn);
+        self.migration_updates.push(update);
+      }
+    }
+    Ok(version)
+  }
+}
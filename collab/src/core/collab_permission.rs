@@ -0,0 +1,191 @@
+use std::sync::{Arc, Mutex};
+
+use yrs::types::{EntryChange, Event};
+use yrs::{DeepObservable, Doc, Map, MapRef, Out, Subscription, TransactionMut};
+
+use crate::core::collab::{DATA_SECTION, META_SECTION, Path};
+use crate::core::origin::CollabOrigin;
+use crate::error::CollabError;
+
+/// Restricts the local changes of a [crate::core::collab::Collab], see
+/// [crate::core::collab::CollabOptions::with_permission].
+///
+/// [crate::core::collab::Collab::try_insert] and [crate::core::collab::Collab::try_remove] check
+/// the permission before changing anything and return [CollabError::PermissionDenied]. The
+/// transactions of [crate::core::collab::CollabContext::with_txn] and
+/// [crate::core::collab::CollabContext::try_with_txn] can change any value, so they are checked
+/// when they are committed: a transaction that changes a value it's not allowed to is reverted
+/// and [CollabError::PermissionDenied] is returned. The raw transactions of
+/// [crate::core::collab::CollabContext::transact_mut] aren't checked. The updates applied with
+/// [crate::core::collab::CollabContext::apply_update] come from other peers, so they are always
+/// applied.
+///
+/// The paths are relative to the [crate::core::collab::Collab::data] section. The meta section
+/// can only be changed with [CollabPermission::ReadWrite].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CollabPermission {
+  #[default]
+  ReadWrite,
+  ReadOnly,
+  /// Only the values nested under one of the paths can be changed.
+  WriteRestricted(Vec<Path>),
+}
+
+impl CollabPermission {
+  pub fn write_restricted<I, P>(prefixes: I) -> Self
+  where
+    I: IntoIterator<Item = P>,
+    P: Into<Path>,
+  {
+    Self::WriteRestricted(prefixes.into_iter().map(Into::into).collect())
+  }
+
+  pub fn is_read_write(&self) -> bool {
+    matches!(self, CollabPermission::ReadWrite)
+  }
+
+  pub fn can_write(&self, path: &Path) -> bool {
+    match self {
+      CollabPermission::ReadWrite => true,
+      CollabPermission::ReadOnly => false,
+      CollabPermission::WriteRestricted(prefixes) => {
+        prefixes.iter().any(|prefix| path.starts_with(prefix))
+      },
+    }
+  }
+
+  /// Returns an error if the meta section was changed without [CollabPermission::ReadWrite], or
+  /// if one of the changed paths of the data section can't be written.
+  pub(crate) fn check_changes(&self, changes: &LocalChanges) -> Result<(), CollabError> {
+    if changes.meta_changed && !self.is_read_write() {
+      return Err(CollabError::PermissionDenied(
+        "the meta of the collab is not writable".to_string(),
+      ));
+    }
+    self.check(&changes.data)
+  }
+
+  /// Returns an error for the first of the `changed` paths that can't be written.
+  pub(crate) fn check(&self, changed: &[Path]) -> Result<(), CollabError> {
+    match changed.iter().find(|path| !self.can_write(path)) {
+      None => Ok(()),
+      Some(_) if matches!(self, CollabPermission::ReadOnly) => Err(CollabError::PermissionDenied(
+        "the collab is read-only".to_string(),
+      )),
+      Some(path) => Err(CollabError::PermissionDenied(format!(
+        "{} is not writable",
+        path
+      ))),
+    }
+  }
+}
+
+/// The changes of the local transactions recorded by [ChangedPaths].
+#[derive(Debug, Default)]
+pub(crate) struct LocalChanges {
+  /// The changed paths of the data section.
+  pub(crate) data: Vec<Path>,
+  pub(crate) meta_changed: bool,
+}
+
+/// Records the paths changed by the local transactions in the data and meta sections. The events
+/// of a transaction are only emitted when it's committed, so the paths are checked against the
+/// [CollabPermission] after the commit, and the transaction is reverted if needed.
+pub(crate) struct ChangedPaths {
+  changes: Arc<Mutex<LocalChanges>>,
+  _data_subscription: Subscription,
+  _meta_subscription: Subscription,
+}
+
+impl ChangedPaths {
+  pub(crate) fn new(doc: &Doc, local_origin: CollabOrigin) -> Self {
+    let changes = Arc::new(Mutex::new(LocalChanges::default()));
+    let data = doc.get_or_insert_map(DATA_SECTION);
+    let cloned_changes = changes.clone();
+    let cloned_origin = local_origin.clone();
+    let data_subscription = data.observe_deep(move |txn, events| {
+      if CollabOrigin::from(txn) != cloned_origin {
+        return;
+      }
+      let mut changes = cloned_changes.lock().unwrap();
+      for event in events.iter() {
+        collect_paths(txn, event, &mut changes.data);
+      }
+    });
+    let meta = doc.get_or_insert_map(META_SECTION);
+    let cloned_changes = changes.clone();
+    let meta_subscription = meta.observe_deep(move |txn, _| {
+      if CollabOrigin::from(txn) == local_origin {
+        cloned_changes.lock().unwrap().meta_changed = true;
+      }
+    });
+    Self {
+      changes,
+      _data_subscription: data_subscription,
+      _meta_subscription: meta_subscription,
+    }
+  }
+
+  pub(crate) fn take(&self) -> LocalChanges {
+    std::mem::take(&mut *self.changes.lock().unwrap())
+  }
+
+  /// Returns the check of the changes of the current transaction, to run once its events were
+  /// emitted.
+  pub(crate) fn check(&self, permission: CollabPermission) -> PermissionCheck {
+    PermissionCheck {
+      permission,
+      changes: self.changes.clone(),
+    }
+  }
+}
+
+/// Checks the changes recorded by [ChangedPaths] against a [CollabPermission].
+pub(crate) struct PermissionCheck {
+  permission: CollabPermission,
+  changes: Arc<Mutex<LocalChanges>>,
+}
+
+impl PermissionCheck {
+  pub(crate) fn run(self) -> Result<(), CollabError> {
+    let changes = std::mem::take(&mut *self.changes.lock().unwrap());
+    self.permission.check_changes(&changes)
+  }
+}
+
+/// A changed key of a map is reported with its own path. An array or a text is reported as a
+/// whole. A map created by the transaction doesn't emit events of its own, so its keys are
+/// reported instead.
+pub(crate) fn collect_paths(txn: &TransactionMut, event: &Event, paths: &mut Vec<Path>) {
+  let parent = Path::from(event.path());
+  match event {
+    Event::Map(event) => {
+      for (key, change) in event.keys(txn) {
+        let mut path = parent.clone();
+        path.push(key.to_string());
+        match change {
+          EntryChange::Inserted(Out::YMap(map)) | EntryChange::Updated(_, Out::YMap(map)) => {
+            collect_map_paths(txn, map, path, paths)
+          },
+          _ => paths.push(path),
+        }
+      }
+    },
+    _ => paths.push(parent),
+  }
+}
+
+fn collect_map_paths(txn: &TransactionMut, map: &MapRef, parent: Path, paths: &mut Vec<Path>) {
+  if map.len(txn) == 0 {
+    paths.push(parent);
+    return;
+  }
+  for (key, value) in map.iter(txn) {
+    let mut path = parent.clone();
+    path.push(key.to_string());
+    match value {
+      Out::YMap(map) => collect_map_paths(txn, &map, path, paths),
+      _ => paths.push(path),
+    }
+  }
+}
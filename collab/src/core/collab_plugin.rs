@@ -2,10 +2,13 @@ use crate::core::awareness::{AwarenessUpdate, Event};
 
 use arc_swap::ArcSwapOption;
 use async_trait::async_trait;
+use bytes::Bytes;
 
-use std::sync::Arc;
-use tracing::trace;
-use yrs::{Doc, TransactionMut};
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use tokio::sync::{Notify, watch};
+use tracing::{error, trace, warn};
+use yrs::{Doc, ReadTxn, StateVector, TransactionMut};
 
 use crate::core::origin::CollabOrigin;
 use crate::error::CollabError;
@@ -58,6 +61,27 @@ pub trait CollabPlugin: Send + Sync + 'static {
   ) {
   }
 
+  /// Called before a local [TransactionMut] created by [crate::core::collab::CollabContext::with_txn]
+  /// or [crate::core::collab::CollabContext::try_with_txn] is committed. Returning an error rejects
+  /// the transaction: its changes are reverted and the error is returned to the caller. The hooks
+  /// are called in the order of [Plugins::each], the first error stops the iteration.
+  ///
+  /// The rejected changes are reverted by a second transaction, so the peers still receive them
+  /// as deleted content, see [crate::core::collab::CollabContext::with_txn]. The raw transactions
+  /// of [crate::core::collab::CollabContext::transact_mut] don't go through the hooks, and the
+  /// updates applied with [crate::core::collab::CollabContext::apply_update] were already
+  /// committed by another peer, so they can't be rejected.
+  fn before_commit(&self, _object_id: &str, _txn: &TransactionMut) -> Result<(), CollabError> {
+    Ok(())
+  }
+
+  /// Returns true if the plugin implements [CollabPlugin::before_commit]. Reverting a rejected
+  /// transaction requires tracking every local transaction, so the hooks only run while one of
+  /// the plugins returns true.
+  fn has_commit_hook(&self) -> bool {
+    false
+  }
+
   /// Called after each [TransactionMut]
   fn after_transaction(&self, _object_id: &str, _txn: &mut TransactionMut) {}
 
@@ -107,6 +131,14 @@ where
     (**self).receive_local_state(origin, object_id, event, update)
   }
 
+  fn before_commit(&self, object_id: &str, txn: &TransactionMut) -> Result<(), CollabError> {
+    (**self).before_commit(object_id, txn)
+  }
+
+  fn has_commit_hook(&self) -> bool {
+    (**self).has_commit_hook()
+  }
+
   fn after_transaction(&self, object_id: &str, txn: &mut TransactionMut) {
     (**self).after_transaction(object_id, txn)
   }
@@ -123,6 +155,365 @@ where
   }
 }
 
+/// A plugin whose callbacks run on a background task instead of the yrs observer, so heavy I/O,
+/// e.g. writing to the disk, doesn't block the editing thread. Use [AsyncPluginAdapter] to add it
+/// to a [Collab].
+#[async_trait]
+pub trait AsyncCollabPlugin: Send + Sync + 'static {
+  /// Called on the editing thread, like [CollabPlugin::did_init].
+  fn did_init(&self, _collab: &Collab, _object_id: &str) {}
+
+  /// Called with the updates of the document, in the order they were committed. Depending on the
+  /// [BackpressurePolicy], an update may be the merge of several consecutive updates, or the whole
+  /// document.
+  async fn receive_update(&self, object_id: &str, update: Bytes);
+
+  /// Called once every queued update was received, after the adapter was destroyed.
+  async fn flush(&self) {}
+
+  /// Whether the plugin can recover from missing updates, e.g. by syncing the whole document
+  /// later. Only such a plugin can be used with [BackpressurePolicy::DropOldest].
+  fn tolerates_missing_updates(&self) -> bool {
+    false
+  }
+
+  fn plugin_type(&self) -> CollabPluginType;
+}
+
+/// What to do when an update is committed while the queue of an [AsyncPluginAdapter] is full.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BackpressurePolicy {
+  /// Blocks the editing thread until the plugin catches up. A thread of a tokio runtime is never
+  /// blocked, as it would stall the other tasks of the runtime, or deadlock if the plugin task
+  /// runs on the same thread: the update is queued over the capacity instead and counted by
+  /// [AsyncPluginHandle::overflowed]. Editors running on a runtime wait with
+  /// [AsyncPluginHandle::wait_for_capacity] before editing.
+  Block,
+  /// Drops the oldest queued update. Only accepted for the plugins that
+  /// [AsyncCollabPlugin::tolerates_missing_updates], e.g. not for a persistence plugin.
+  DropOldest,
+  /// Merges the queued updates of the same object into a single update. If they can't be merged,
+  /// they are replaced by the whole document, so no change is lost. If the queue is still full,
+  /// e.g. it's shared by more objects than its capacity, the editing thread is blocked, or the
+  /// update is queued over the capacity, like [BackpressurePolicy::Block].
+  Coalesce,
+}
+
+#[derive(Debug, Clone)]
+pub struct AsyncPluginConfig {
+  /// The maximum number of queued updates. It's only a soft limit for the updates committed on a
+  /// thread of a tokio runtime, which is never blocked, see [BackpressurePolicy::Block].
+  pub capacity: usize,
+  pub policy: BackpressurePolicy,
+}
+
+impl Default for AsyncPluginConfig {
+  fn default() -> Self {
+    Self {
+      capacity: 1024,
+      policy: BackpressurePolicy::Coalesce,
+    }
+  }
+}
+
+impl AsyncPluginConfig {
+  pub fn new(capacity: usize, policy: BackpressurePolicy) -> Self {
+    Self {
+      capacity: capacity.max(1),
+      policy,
+    }
+  }
+}
+
+/// Adapts an [AsyncCollabPlugin] to a [CollabPlugin]. The updates are pushed into a bounded queue
+/// and received by a task spawned on the current tokio runtime, one at a time, so their order is
+/// preserved.
+///
+/// [CollabPlugin::destroy], or dropping the adapter, closes the queue. The updates that are
+/// already queued are still received, then [AsyncCollabPlugin::flush] is called. Use
+/// [AsyncPluginHandle::flushed] to wait for it.
+pub struct AsyncPluginAdapter {
+  plugin: Arc<dyn AsyncCollabPlugin>,
+  queue: Arc<UpdateQueue>,
+}
+
+impl AsyncPluginAdapter {
+  /// Spawns the task of the plugin on the current tokio runtime. Returns an error outside of a
+  /// runtime, or if the plugin can't use the [BackpressurePolicy] of the config.
+  pub fn new<P>(plugin: P, config: AsyncPluginConfig) -> Result<Self, CollabError>
+  where
+    P: AsyncCollabPlugin,
+  {
+    let runtime = tokio::runtime::Handle::try_current().map_err(|_| CollabError::NoTokioRuntime)?;
+    if config.policy == BackpressurePolicy::DropOldest && !plugin.tolerates_missing_updates() {
+      return Err(CollabError::InvalidAsyncPlugin(format!(
+        "{:?} can't drop updates",
+        plugin.plugin_type()
+      )));
+    }
+    let plugin: Arc<dyn AsyncCollabPlugin> = Arc::new(plugin);
+    let queue = Arc::new(UpdateQueue::new(config));
+    runtime.spawn(run_async_plugin(plugin.clone(), queue.clone()));
+    Ok(Self { plugin, queue })
+  }
+
+  pub fn handle(&self) -> AsyncPluginHandle {
+    AsyncPluginHandle {
+      queue: self.queue.clone(),
+    }
+  }
+}
+
+impl CollabPlugin for AsyncPluginAdapter {
+  fn did_init(&self, collab: &Collab, object_id: &str) {
+    self.plugin.did_init(collab, object_id);
+  }
+
+  fn receive_update(&self, object_id: &str, txn: &TransactionMut, update: &[u8]) {
+    let update = QueuedUpdate {
+      object_id: object_id.to_string(),
+      update: Bytes::copy_from_slice(update),
+    };
+    self.queue.push(update, txn);
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    self.plugin.plugin_type()
+  }
+
+  fn destroy(&self) {
+    self.queue.close();
+  }
+}
+
+impl Drop for AsyncPluginAdapter {
+  fn drop(&mut self) {
+    self.queue.close();
+  }
+}
+
+/// Observes the queue of an [AsyncPluginAdapter] after it was added to a [Collab].
+#[derive(Clone)]
+pub struct AsyncPluginHandle {
+  queue: Arc<UpdateQueue>,
+}
+
+impl AsyncPluginHandle {
+  /// The number of updates waiting to be received by the plugin.
+  pub fn pending(&self) -> usize {
+    self.queue.state.lock().unwrap().updates.len()
+  }
+
+  /// The number of updates dropped by [BackpressurePolicy::DropOldest].
+  pub fn dropped(&self) -> u64 {
+    self.queue.state.lock().unwrap().dropped
+  }
+
+  /// The number of times [BackpressurePolicy::Coalesce] replaced the queued updates of an object
+  /// by the whole document.
+  pub fn resynced(&self) -> u64 {
+    self.queue.state.lock().unwrap().resynced
+  }
+
+  /// The number of updates queued over the capacity because they were committed on a thread of a
+  /// tokio runtime, which can't be blocked.
+  pub fn overflowed(&self) -> u64 {
+    self.queue.state.lock().unwrap().overflowed
+  }
+
+  /// Waits until the queue has room for an update, or is closed. An editor running on a tokio
+  /// runtime calls it before editing, so the queue stays within its capacity.
+  pub async fn wait_for_capacity(&self) {
+    loop {
+      let notified = self.queue.not_full_async.notified();
+      {
+        let state = self.queue.state.lock().unwrap();
+        if state.updates.len() < self.queue.config.capacity || state.closed {
+          return;
+        }
+      }
+      notified.await;
+    }
+  }
+
+  /// Waits until the adapter was destroyed and the plugin was flushed.
+  pub async fn flushed(&self) {
+    let mut rx = self.queue.flushed.subscribe();
+    let _ = rx.wait_for(|flushed| *flushed).await;
+  }
+}
+
+struct QueuedUpdate {
+  object_id: String,
+  update: Bytes,
+}
+
+#[derive(Default)]
+struct QueueState {
+  updates: VecDeque<QueuedUpdate>,
+  closed: bool,
+  dropped: u64,
+  resynced: u64,
+  overflowed: u64,
+}
+
+struct UpdateQueue {
+  config: AsyncPluginConfig,
+  state: Mutex<QueueState>,
+  /// Wakes the editing threads blocked by [BackpressurePolicy::Block].
+  not_full: Condvar,
+  /// Wakes the editors waiting in [AsyncPluginHandle::wait_for_capacity].
+  not_full_async: Notify,
+  /// Wakes the task of the plugin.
+  not_empty: Notify,
+  flushed: watch::Sender<bool>,
+}
+
+impl UpdateQueue {
+  fn new(config: AsyncPluginConfig) -> Self {
+    Self {
+      config,
+      state: Mutex::new(QueueState::default()),
+      not_full: Condvar::new(),
+      not_full_async: Notify::new(),
+      not_empty: Notify::new(),
+      flushed: watch::Sender::new(false),
+    }
+  }
+
+  fn push(&self, mut update: QueuedUpdate, txn: &TransactionMut) {
+    let capacity = self.config.capacity;
+    let mut state = self.state.lock().unwrap();
+    if state.updates.len() >= capacity {
+      match self.config.policy {
+        BackpressurePolicy::Block => {},
+        BackpressurePolicy::DropOldest => {
+          state.updates.pop_front();
+          state.dropped += 1;
+        },
+        BackpressurePolicy::Coalesce => {
+          coalesce_updates(&mut state.updates);
+          if state.updates.len() >= capacity {
+            // The document contains every queued update of the object
+            state
+              .updates
+              .retain(|queued| queued.object_id != update.object_id);
+            update.update = Bytes::from(txn.encode_state_as_update_v1(&StateVector::default()));
+            state.resynced += 1;
+          }
+        },
+      }
+      if state.updates.len() >= capacity && !state.closed {
+        if can_block() {
+          while state.updates.len() >= capacity && !state.closed {
+            state = self.not_full.wait(state).unwrap();
+          }
+        } else {
+          // Reported once per overflow, the queue is drained all at once
+          if state.updates.len() == capacity {
+            error!(
+              "Async plugin queue is full on a runtime thread, queue the update of:{} over the capacity",
+              update.object_id
+            );
+          } else {
+            trace!(
+              "Async plugin queue is full, queue the update of:{} over the capacity",
+              update.object_id
+            );
+          }
+          state.overflowed += 1;
+        }
+      }
+    }
+    if state.closed {
+      warn!(
+        "Async plugin is destroyed, drop update of:{}",
+        update.object_id
+      );
+      return;
+    }
+    state.updates.push_back(update);
+    drop(state);
+    self.not_empty.notify_one();
+  }
+
+  /// Takes every queued update, and whether the queue was closed.
+  fn take(&self) -> (VecDeque<QueuedUpdate>, bool) {
+    let mut state = self.state.lock().unwrap();
+    let updates = std::mem::take(&mut state.updates);
+    let closed = state.closed;
+    drop(state);
+    self.not_full.notify_all();
+    self.not_full_async.notify_waiters();
+    (updates, closed)
+  }
+
+  fn close(&self) {
+    self.state.lock().unwrap().closed = true;
+    self.not_full.notify_all();
+    self.not_full_async.notify_waiters();
+    self.not_empty.notify_one();
+  }
+}
+
+/// Whether the editing thread can wait for the plugin. Blocking a thread of a tokio runtime
+/// stalls the other tasks of the runtime, and deadlocks if the plugin task runs on it.
+fn can_block() -> bool {
+  tokio::runtime::Handle::try_current().is_err()
+}
+
+/// Merges the consecutive updates of the same object.
+fn coalesce_updates(updates: &mut VecDeque<QueuedUpdate>) {
+  let mut merged = VecDeque::with_capacity(updates.len());
+  let mut run: Vec<QueuedUpdate> = vec![];
+  for update in updates.drain(..) {
+    if run
+      .last()
+      .is_some_and(|last| last.object_id != update.object_id)
+    {
+      merged.extend(merge_run(std::mem::take(&mut run)));
+    }
+    run.push(update);
+  }
+  merged.extend(merge_run(run));
+  *updates = merged;
+}
+
+fn merge_run(run: Vec<QueuedUpdate>) -> Vec<QueuedUpdate> {
+  if run.len() < 2 {
+    return run;
+  }
+  let object_id = run[0].object_id.clone();
+  let updates = run.iter().map(|u| u.update.as_ref()).collect::<Vec<_>>();
+  match yrs::merge_updates_v1(&updates) {
+    Ok(update) => vec![QueuedUpdate {
+      object_id,
+      update: Bytes::from(update),
+    }],
+    Err(err) => {
+      warn!("Failed to coalesce updates of:{}, {}", object_id, err);
+      run
+    },
+  }
+}
+
+async fn run_async_plugin(plugin: Arc<dyn AsyncCollabPlugin>, queue: Arc<UpdateQueue>) {
+  loop {
+    let (updates, closed) = queue.take();
+    for update in updates {
+      plugin
+        .receive_update(&update.object_id, update.update)
+        .await;
+    }
+    if closed {
+      break;
+    }
+    queue.not_empty.notified().await;
+  }
+  plugin.flush().await;
+  queue.flushed.send_replace(true);
+}
+
 #[derive(Clone, Default)]
 pub struct Plugins(pub(crate) Arc<PluginsInner>);
 #[derive(Default)]
@@ -238,6 +629,35 @@ impl Plugins {
       curr = node.next.load_full();
     }
   }
+
+  // Iterate over each plugin in the same order as [Plugins::each], stopping at the first error
+  pub fn try_each<F, E>(&self, mut f: F) -> Result<(), E>
+  where
+    F: FnMut(&Box<dyn CollabPlugin>) -> Result<(), E>,
+  {
+    let mut curr = self.0.head.load_full();
+    while let Some(node) = curr {
+      f(&node.value)?;
+      curr = node.next.load_full();
+    }
+    Ok(())
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.head.load().is_none()
+  }
+
+  /// Returns true if one of the plugins implements [CollabPlugin::before_commit].
+  pub fn has_commit_hooks(&self) -> bool {
+    let mut curr = self.0.head.load_full();
+    while let Some(node) = curr {
+      if node.value.has_commit_hook() {
+        return true;
+      }
+      curr = node.next.load_full();
+    }
+    false
+  }
 }
 
 pub struct RemovedPluginsIter {
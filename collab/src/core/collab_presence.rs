@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+use tokio::sync::broadcast;
+use yrs::Subscription;
+use yrs::block::ClientID;
+
+use crate::core::awareness::Awareness;
+use crate::core::collab::{Collab, Path};
+use crate::error::CollabError;
+
+pub type PresenceReceiver = broadcast::Receiver<PresenceEvent>;
+
+/// The key of the [PresenceState] in the awareness state of a peer. The rest of the awareness
+/// state is left to the other users of the awareness, e.g. the document awareness state.
+pub const PRESENCE_KEY: &str = "presence";
+
+/// The presence of a peer, shared with the other peers through the awareness of the [Collab].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PresenceState {
+  pub user: PresenceUser,
+  /// The cursor or the selection of the user in the [Collab::data] section.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub cursor: Option<PresenceCursor>,
+  /// What the user is looking at, e.g. a row of a database or a view of a folder.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub viewing: Option<PresenceViewing>,
+  /// Application specific data, e.g. the color of the selection.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub metadata: Option<serde_json::Value>,
+}
+
+impl PresenceState {
+  pub fn new(user: PresenceUser) -> Self {
+    Self {
+      user,
+      cursor: None,
+      viewing: None,
+      metadata: None,
+    }
+  }
+
+  pub fn with_cursor(mut self, cursor: PresenceCursor) -> Self {
+    self.cursor = Some(cursor);
+    self
+  }
+
+  pub fn with_viewing(mut self, viewing: PresenceViewing) -> Self {
+    self.viewing = Some(viewing);
+    self
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PresenceUser {
+  pub uid: i64,
+  pub device_id: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub name: Option<String>,
+}
+
+/// A selection from `anchor` to `head`. A cursor is a selection whose anchor and head are equal.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PresenceCursor {
+  pub anchor: PresencePosition,
+  pub head: PresencePosition,
+}
+
+impl PresenceCursor {
+  pub fn at(position: PresencePosition) -> Self {
+    Self {
+      anchor: position.clone(),
+      head: position,
+    }
+  }
+
+  pub fn is_collapsed(&self) -> bool {
+    self.anchor == self.head
+  }
+}
+
+/// A position in the value at `path`, relative to the [Collab::data] section. The offset is the
+/// index in a text or an array.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PresencePosition {
+  pub path: Path,
+  pub offset: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PresenceViewing {
+  /// A row of a database.
+  Row { row_id: String },
+  /// A view of a folder or a database.
+  View { view_id: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PresenceEvent {
+  Join {
+    client_id: ClientID,
+    state: PresenceState,
+  },
+  Update {
+    client_id: ClientID,
+    state: PresenceState,
+  },
+  /// The peer cleared its presence or its awareness state, or it expired after
+  /// [PresenceOptions::timeout].
+  Leave {
+    client_id: ClientID,
+    state: PresenceState,
+  },
+}
+
+#[derive(Debug, Clone)]
+pub struct PresenceOptions {
+  /// A peer whose presence wasn't updated within this duration is removed by
+  /// [Collab::expire_stale_presence]. Peers must renew their presence, see
+  /// [Collab::renew_presence], more often than that.
+  pub timeout: Duration,
+}
+
+impl Default for PresenceOptions {
+  fn default() -> Self {
+    Self {
+      timeout: Duration::from_secs(30),
+    }
+  }
+}
+
+struct Peer {
+  state: PresenceState,
+  last_seen: Instant,
+}
+
+/// Tracks the presence of the remote peers from the awareness updates.
+pub(crate) struct CollabPresence {
+  options: PresenceOptions,
+  peers: Arc<Mutex<HashMap<ClientID, Peer>>>,
+  sender: broadcast::Sender<PresenceEvent>,
+  _subscription: Subscription,
+}
+
+impl CollabPresence {
+  fn new(awareness: &Awareness, options: PresenceOptions) -> Self {
+    let peers = Arc::new(Mutex::new(HashMap::<ClientID, Peer>::new()));
+    let (sender, _) = broadcast::channel(100);
+    let local_client_id = awareness.client_id();
+    let subscription = {
+      let peers = peers.clone();
+      let sender = sender.clone();
+      awareness.on_update(move |awareness, event, _| {
+        let changes = event
+          .all_changes()
+          .into_iter()
+          .filter(|client_id| *client_id != local_client_id)
+          .collect::<Vec<_>>();
+        let Ok(update) = awareness.update_with_clients(changes.clone()) else {
+          return;
+        };
+
+        let mut peers = peers.lock().unwrap();
+        for client_id in changes {
+          let event = match update
+            .clients
+            .get(&client_id)
+            .map(|entry| parse_presence(&entry.json))
+            .unwrap_or(ParsedPresence::Cleared)
+          {
+            ParsedPresence::Set(state) => {
+              let peer = Peer {
+                state: state.clone(),
+                last_seen: Instant::now(),
+              };
+              match peers.insert(client_id, peer) {
+                None => PresenceEvent::Join { client_id, state },
+                // A renewal only refreshes the last seen time.
+                Some(old) if old.state == state => continue,
+                Some(_) => PresenceEvent::Update { client_id, state },
+              }
+            },
+            // Another part of the awareness state changed, the peer is still there
+            ParsedPresence::Missing => {
+              if let Some(peer) = peers.get_mut(&client_id) {
+                peer.last_seen = Instant::now();
+              }
+              continue;
+            },
+            ParsedPresence::Cleared => match peers.remove(&client_id) {
+              Some(peer) => PresenceEvent::Leave {
+                client_id,
+                state: peer.state,
+              },
+              None => continue,
+            },
+          };
+          let _ = sender.send(event);
+        }
+      })
+    };
+
+    Self {
+      options,
+      peers,
+      sender,
+      _subscription: subscription,
+    }
+  }
+}
+
+enum ParsedPresence {
+  Set(PresenceState),
+  /// The awareness state doesn't contain a presence, e.g. it was replaced by a document awareness
+  /// state.
+  Missing,
+  /// The presence, or the whole awareness state, was removed.
+  Cleared,
+}
+
+fn parse_presence(json: &str) -> ParsedPresence {
+  match serde_json::from_str::<JsonValue>(json) {
+    Ok(JsonValue::Object(mut state)) => match state.remove(PRESENCE_KEY) {
+      None => ParsedPresence::Missing,
+      Some(JsonValue::Null) => ParsedPresence::Cleared,
+      Some(presence) => match serde_json::from_value(presence) {
+        Ok(presence) => ParsedPresence::Set(presence),
+        Err(_) => ParsedPresence::Missing,
+      },
+    },
+    Ok(JsonValue::Null) => ParsedPresence::Cleared,
+    _ => ParsedPresence::Missing,
+  }
+}
+
+/// Returns the local awareness state as an object, to add or replace one of its keys.
+fn local_state_object(awareness: &Awareness) -> Map<String, JsonValue> {
+  match awareness.local_state::<JsonValue>() {
+    Some(JsonValue::Object(state)) => state,
+    _ => Map::new(),
+  }
+}
+
+/// See [Collab::set_local_awareness_state].
+pub(crate) fn set_local_awareness_state<S: Serialize>(
+  awareness: &Awareness,
+  state: S,
+) -> Result<(), CollabError> {
+  let mut state = match serde_json::to_value(state)? {
+    JsonValue::Object(state) => state,
+    state => {
+      return Err(CollabError::Internal(anyhow::anyhow!(
+        "awareness state must be an object: {}",
+        state
+      )));
+    },
+  };
+  if let Some(presence) = local_state_object(awareness).remove(PRESENCE_KEY) {
+    state.insert(PRESENCE_KEY.to_string(), presence);
+  }
+  awareness.set_local_state(state)?;
+  Ok(())
+}
+
+impl Collab {
+  /// Starts tracking the presence of the other peers. Peers that were already known by the
+  /// awareness are reported when they update their presence.
+  pub fn enable_presence(&mut self, options: PresenceOptions) {
+    if self.presence.is_some() {
+      return;
+    }
+    self.presence = Some(CollabPresence::new(self.get_awareness(), options));
+  }
+
+  fn presence_tracker(&self) -> Result<&CollabPresence, CollabError> {
+    self
+      .presence
+      .as_ref()
+      .ok_or(CollabError::PresenceNotEnabled)
+  }
+
+  /// Sets the presence of the local peer and broadcasts it to the other peers. The rest of the
+  /// awareness state is kept.
+  pub fn set_presence(&mut self, state: PresenceState) -> Result<(), CollabError> {
+    let presence = serde_json::to_value(state)?;
+    self.set_local_awareness_key(PRESENCE_KEY, presence)
+  }
+
+  /// Removes the presence of the local peer. The other peers receive a [PresenceEvent::Leave].
+  pub fn clear_presence(&mut self) -> Result<(), CollabError> {
+    if self.local_presence().is_some() {
+      self.set_local_awareness_key(PRESENCE_KEY, JsonValue::Null)?;
+    }
+    Ok(())
+  }
+
+  pub fn local_presence(&self) -> Option<PresenceState> {
+    let presence = local_state_object(self.get_awareness()).remove(PRESENCE_KEY)?;
+    serde_json::from_value(presence).ok()
+  }
+
+  /// Replaces the local awareness state, keeping the presence of the local peer. Use it instead
+  /// of setting the local state of the awareness directly, which would make the other peers lose
+  /// the presence.
+  pub fn set_local_awareness_state<S: Serialize>(&self, state: S) -> Result<(), CollabError> {
+    set_local_awareness_state(self.get_awareness(), state)
+  }
+
+  fn set_local_awareness_key(&self, key: &str, value: JsonValue) -> Result<(), CollabError> {
+    let mut state = local_state_object(self.get_awareness());
+    state.insert(key.to_string(), value);
+    self.get_awareness().set_local_state(state)?;
+    Ok(())
+  }
+
+  /// Broadcasts the presence of the local peer again, so the other peers don't expire it.
+  pub fn renew_presence(&mut self) -> Result<(), CollabError> {
+    if let Some(state) = self.local_presence() {
+      self.set_presence(state)?;
+    }
+    Ok(())
+  }
+
+  /// Returns the presence of the other peers.
+  pub fn presences(&self) -> Result<HashMap<ClientID, PresenceState>, CollabError> {
+    let peers = self.presence_tracker()?.peers.lock().unwrap();
+    Ok(
+      peers
+        .iter()
+        .map(|(client_id, peer)| (*client_id, peer.state.clone()))
+        .collect(),
+    )
+  }
+
+  /// Subscribes to the join, update and leave events of the other peers. Use
+  /// `tokio_stream::wrappers::BroadcastStream` to consume the events as a stream.
+  pub fn subscribe_presence(&self) -> Result<PresenceReceiver, CollabError> {
+    Ok(self.presence_tracker()?.sender.subscribe())
+  }
+
+  /// Removes the peers whose presence wasn't updated within [PresenceOptions::timeout] and
+  /// returns their client ids. A [PresenceEvent::Leave] is sent for each of them.
+  pub fn expire_stale_presence(&mut self) -> Result<Vec<ClientID>, CollabError> {
+    let presence = self.presence_tracker()?;
+    let expired = {
+      let mut peers = presence.peers.lock().unwrap();
+      let stale = peers
+        .iter()
+        .filter(|(_, peer)| peer.last_seen.elapsed() >= presence.options.timeout)
+        .map(|(client_id, _)| *client_id)
+        .collect::<Vec<_>>();
+      stale
+        .into_iter()
+        .filter_map(|client_id| peers.remove(&client_id).map(|peer| (client_id, peer)))
+        .collect::<Vec<_>>()
+    };
+
+    let mut client_ids = Vec::with_capacity(expired.len());
+    for (client_id, peer) in expired {
+      let _ = presence.sender.send(PresenceEvent::Leave {
+        client_id,
+        state: peer.state,
+      });
+      client_ids.push(client_id);
+    }
+    // The peers are already forgotten, so removing their awareness state doesn't emit a second
+    // leave event.
+    for client_id in client_ids.iter() {
+      self.get_mut_awareness().remove_state(*client_id);
+    }
+    Ok(client_ids)
+  }
+}
@@ -0,0 +1,331 @@
+use std::fmt::{Display, Formatter};
+
+use yrs::{Any, Array, Map, MapRef, Out, ReadTxn};
+
+use crate::core::collab::{Collab, Path};
+use crate::error::CollabError;
+
+/// The expected kind of a value in the [Collab] data tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueSchema {
+  /// Accepts any value.
+  Any,
+  String,
+  /// A floating point number or a big integer.
+  Number,
+  Bool,
+  /// A [yrs::TextRef].
+  Text,
+  /// A [yrs::MapRef] or an [Any::Map] with the given fields.
+  Map(MapSchema),
+  /// A [yrs::MapRef] or an [Any::Map] with arbitrary keys, every value must match the schema.
+  /// For example, the blocks of a document keyed by block id.
+  MapOf(Box<ValueSchema>),
+  /// A [yrs::ArrayRef] or an [Any::Array], every element must match the schema.
+  Array(Box<ValueSchema>),
+}
+
+impl ValueSchema {
+  pub fn map_of(value: ValueSchema) -> Self {
+    Self::MapOf(Box::new(value))
+  }
+
+  pub fn array_of(value: ValueSchema) -> Self {
+    Self::Array(Box::new(value))
+  }
+
+  fn name(&self) -> &'static str {
+    match self {
+      ValueSchema::Any => "any",
+      ValueSchema::String => "string",
+      ValueSchema::Number => "number",
+      ValueSchema::Bool => "bool",
+      ValueSchema::Text => "text",
+      ValueSchema::Map(_) | ValueSchema::MapOf(_) => "map",
+      ValueSchema::Array(_) => "array",
+    }
+  }
+}
+
+impl From<MapSchema> for ValueSchema {
+  fn from(value: MapSchema) -> Self {
+    Self::Map(value)
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSchema {
+  pub key: String,
+  pub value: ValueSchema,
+  pub required: bool,
+}
+
+/// Describes the fields of a map. Keys that are not described are allowed unless
+/// [MapSchema::deny_unknown_keys] is called.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MapSchema {
+  pub fields: Vec<FieldSchema>,
+  pub deny_unknown_keys: bool,
+}
+
+impl MapSchema {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn required(mut self, key: impl Into<String>, value: impl Into<ValueSchema>) -> Self {
+    self.fields.push(FieldSchema {
+      key: key.into(),
+      value: value.into(),
+      required: true,
+    });
+    self
+  }
+
+  pub fn optional(mut self, key: impl Into<String>, value: impl Into<ValueSchema>) -> Self {
+    self.fields.push(FieldSchema {
+      key: key.into(),
+      value: value.into(),
+      required: false,
+    });
+    self
+  }
+
+  pub fn deny_unknown_keys(mut self) -> Self {
+    self.deny_unknown_keys = true;
+    self
+  }
+}
+
+/// A declarative description of the [Collab::data] section.
+///
+/// ```
+/// use collab::core::collab_schema::{CollabSchema, MapSchema, ValueSchema};
+///
+/// let schema = CollabSchema::new(
+///   MapSchema::new().required(
+///     "folder",
+///     MapSchema::new()
+///       .required("meta", MapSchema::new().required("current_workspace", ValueSchema::String))
+///       .optional("views", ValueSchema::map_of(ValueSchema::Any)),
+///   ),
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CollabSchema {
+  pub data: MapSchema,
+}
+
+impl CollabSchema {
+  pub fn new(data: MapSchema) -> Self {
+    Self { data }
+  }
+
+  /// Returns every violation of the given [Collab]. An empty list means the data matches the
+  /// schema.
+  pub fn validate(&self, collab: &Collab) -> Vec<SchemaViolation> {
+    self.validate_with_txn(&collab.transact(), &collab.data)
+  }
+
+  pub fn validate_with_txn<T: ReadTxn>(&self, txn: &T, data: &MapRef) -> Vec<SchemaViolation> {
+    let mut violations = vec![];
+    let mut path = Path::default();
+    validate_map(
+      txn,
+      map_entries(txn, &Out::YMap(data.clone())).unwrap_or_default(),
+      &self.data,
+      &mut path,
+      &mut violations,
+    );
+    violations
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ViolationKind {
+  MissingKey,
+  UnknownKey,
+  UnexpectedKind {
+    expected: &'static str,
+    found: &'static str,
+  },
+}
+
+/// A single mismatch between the data and a [CollabSchema].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaViolation {
+  /// The path of the value, relative to the [Collab::data] section.
+  pub path: Path,
+  pub kind: ViolationKind,
+}
+
+impl Display for SchemaViolation {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match &self.kind {
+      ViolationKind::MissingKey => write!(f, "{}: missing required key", self.path),
+      ViolationKind::UnknownKey => write!(f, "{}: unknown key", self.path),
+      ViolationKind::UnexpectedKind { expected, found } => {
+        write!(f, "{}: expected {}, found {}", self.path, expected, found)
+      },
+    }
+  }
+}
+
+/// Joins the violations into a single line, used by the errors that carry them.
+pub fn display_violations(violations: &[SchemaViolation]) -> String {
+  violations
+    .iter()
+    .map(|violation| violation.to_string())
+    .collect::<Vec<_>>()
+    .join(", ")
+}
+
+impl Collab {
+  /// Validates the [Collab::data] section against the given schema. All the violations are
+  /// returned in [CollabError::SchemaViolations].
+  pub fn validate_schema(&self, schema: &CollabSchema) -> Result<(), CollabError> {
+    let violations = schema.validate(self);
+    if violations.is_empty() {
+      Ok(())
+    } else {
+      Err(CollabError::SchemaViolations(violations))
+    }
+  }
+}
+
+fn validate_value<T: ReadTxn>(
+  txn: &T,
+  value: Out,
+  schema: &ValueSchema,
+  path: &mut Path,
+  violations: &mut Vec<SchemaViolation>,
+) {
+  let matched = match (schema, &value) {
+    (ValueSchema::Any, _) => true,
+    (ValueSchema::String, Out::Any(Any::String(_))) => true,
+    (ValueSchema::Number, Out::Any(Any::Number(_) | Any::BigInt(_))) => true,
+    (ValueSchema::Bool, Out::Any(Any::Bool(_))) => true,
+    (ValueSchema::Text, Out::YText(_)) => true,
+    (ValueSchema::Map(map_schema), _) => match map_entries(txn, &value) {
+      Some(entries) => {
+        validate_map(txn, entries, map_schema, path, violations);
+        true
+      },
+      None => false,
+    },
+    (ValueSchema::MapOf(value_schema), _) => match map_entries(txn, &value) {
+      Some(entries) => {
+        for (key, value) in entries {
+          path.push(key);
+          validate_value(txn, value, value_schema, path, violations);
+          path.pop();
+        }
+        true
+      },
+      None => false,
+    },
+    (ValueSchema::Array(value_schema), _) => match array_elements(txn, &value) {
+      Some(elements) => {
+        for (i, value) in elements.into_iter().enumerate() {
+          path.push(i.to_string());
+          validate_value(txn, value, value_schema, path, violations);
+          path.pop();
+        }
+        true
+      },
+      None => false,
+    },
+    _ => false,
+  };
+
+  if !matched {
+    violations.push(SchemaViolation {
+      path: path.clone(),
+      kind: ViolationKind::UnexpectedKind {
+        expected: schema.name(),
+        found: kind_name(&value),
+      },
+    });
+  }
+}
+
+fn validate_map<T: ReadTxn>(
+  txn: &T,
+  mut entries: Vec<(String, Out)>,
+  schema: &MapSchema,
+  path: &mut Path,
+  violations: &mut Vec<SchemaViolation>,
+) {
+  for field in schema.fields.iter() {
+    path.push(field.key.clone());
+    match entries.iter().position(|(key, _)| key == &field.key) {
+      Some(index) => {
+        let (_, value) = entries.swap_remove(index);
+        validate_value(txn, value, &field.value, path, violations);
+      },
+      None if field.required => violations.push(SchemaViolation {
+        path: path.clone(),
+        kind: ViolationKind::MissingKey,
+      }),
+      None => {},
+    }
+    path.pop();
+  }
+
+  if schema.deny_unknown_keys {
+    // Sort the remaining keys so the violations are reported in a stable order.
+    let mut unknown_keys = entries.into_iter().map(|(key, _)| key).collect::<Vec<_>>();
+    unknown_keys.sort();
+    for key in unknown_keys {
+      path.push(key);
+      violations.push(SchemaViolation {
+        path: path.clone(),
+        kind: ViolationKind::UnknownKey,
+      });
+      path.pop();
+    }
+  }
+}
+
+fn map_entries<T: ReadTxn>(txn: &T, value: &Out) -> Option<Vec<(String, Out)>> {
+  match value {
+    Out::YMap(map) => Some(
+      map
+        .iter(txn)
+        .map(|(key, value)| (key.to_string(), value))
+        .collect(),
+    ),
+    Out::Any(Any::Map(map)) => Some(
+      map
+        .iter()
+        .map(|(key, value)| (key.clone(), Out::Any(value.clone())))
+        .collect(),
+    ),
+    _ => None,
+  }
+}
+
+fn array_elements<T: ReadTxn>(txn: &T, value: &Out) -> Option<Vec<Out>> {
+  match value {
+    Out::YArray(array) => Some(array.iter(txn).collect()),
+    Out::Any(Any::Array(array)) => Some(array.iter().cloned().map(Out::Any).collect()),
+    _ => None,
+  }
+}
+
+fn kind_name(value: &Out) -> &'static str {
+  match value {
+    Out::Any(Any::Null) => "null",
+    Out::Any(Any::Undefined) => "undefined",
+    Out::Any(Any::Bool(_)) => "bool",
+    Out::Any(Any::Number(_) | Any::BigInt(_)) => "number",
+    Out::Any(Any::String(_)) => "string",
+    Out::Any(Any::Buffer(_)) => "buffer",
+    Out::Any(Any::Array(_)) => "array",
+    Out::Any(Any::Map(_)) => "map",
+    Out::YText(_) => "text",
+    Out::YArray(_) => "array",
+    Out::YMap(_) => "map",
+    _ => "other",
+  }
+}
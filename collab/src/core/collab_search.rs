@@ -1 +1,336 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
 
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+use yrs::types::Event;
+use yrs::{Any, Array, DeepObservable, GetString, Map, MapRef, Out, ReadTxn, Subscription};
+
+use crate::core::collab::{Collab, Path};
+
+/// A single ranked result returned by [CollabSearchIndex::search].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchHit {
+  /// The object id of the [Collab] that contains the hit.
+  pub object_id: String,
+  /// The path of the matching value, relative to the [Collab::data] section.
+  pub path: Path,
+  pub score: f32,
+}
+
+/// An incremental inverted index over the content of one or more [Collab]s.
+///
+/// Every string value, [yrs::TextRef] and string stored inside an [Any] map or array is indexed
+/// under the path it lives at. The index is cheap to clone and can be shared by multiple collabs,
+/// so a workspace can answer a single query across documents, databases and folders.
+#[derive(Clone, Default)]
+pub struct CollabSearchIndex {
+  inner: Arc<RwLock<InvertedIndex>>,
+}
+
+impl CollabSearchIndex {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Indexes the current content of the given [Collab] and keeps the index up to date with
+  /// subsequent changes. The index is only updated while the returned [Subscription] is alive.
+  pub fn index_collab(&self, collab: &Collab) -> Subscription {
+    let object_id: Arc<str> = Arc::from(collab.object_id());
+    {
+      let txn = collab.transact();
+      let mut index = self.inner.write().unwrap();
+      index.remove_object(&object_id);
+      index.index_map(&txn, &object_id, &mut vec![], &collab.data);
+    }
+    subscribe_data_change(&collab.data, object_id, self.inner.clone())
+  }
+
+  /// Removes every indexed entry that belongs to the given object.
+  pub fn remove_object(&self, object_id: &str) {
+    self.inner.write().unwrap().remove_object(object_id);
+  }
+
+  /// Returns at most `limit` hits ordered by descending score. Every term of the query must
+  /// appear in the matching value.
+  pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+    self.inner.read().unwrap().search(query, limit)
+  }
+
+  /// Returns the number of indexed values.
+  pub fn len(&self) -> usize {
+    self.inner.read().unwrap().entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+}
+
+pub(crate) struct CollabSearch {
+  pub(crate) index: CollabSearchIndex,
+  _subscription: Subscription,
+}
+
+impl CollabSearch {
+  pub(crate) fn new(collab: &Collab) -> Self {
+    let index = CollabSearchIndex::new();
+    let subscription = index.index_collab(collab);
+    Self {
+      index,
+      _subscription: subscription,
+    }
+  }
+}
+
+fn subscribe_data_change(
+  data: &MapRef,
+  object_id: Arc<str>,
+  inner: Arc<RwLock<InvertedIndex>>,
+) -> Subscription {
+  data.observe_deep(move |txn, events| {
+    let mut index = inner.write().unwrap();
+    for event in events.iter() {
+      let mut path: Vec<String> = Path::from(event.path()).to_vec();
+      match event {
+        Event::Map(event) => {
+          // Only the changed keys need to be re-indexed
+          let map = event.target();
+          for key in event.keys(txn).keys() {
+            path.push(key.to_string());
+            index.remove_prefix(&object_id, &path);
+            if let Some(value) = map.get(txn, key) {
+              index.index_out(txn, &object_id, &mut path, value);
+            }
+            path.pop();
+          }
+        },
+        // Array events may shift the index of every following element, so the whole array is
+        // re-indexed.
+        Event::Array(_) | Event::Text(_) => {
+          index.remove_prefix(&object_id, &path);
+          index.index_out(txn, &object_id, &mut path, event.target());
+        },
+        _ => {},
+      }
+    }
+  })
+}
+
+type EntryKey = (Arc<str>, Vec<String>);
+
+#[derive(Default)]
+struct InvertedIndex {
+  /// term => (object id, path) => term frequency
+  postings: HashMap<String, HashMap<EntryKey, u32>>,
+  /// (object id, path) => terms of the entry. Ordered so that a subtree can be removed with a
+  /// range scan.
+  entries: BTreeMap<EntryKey, Vec<String>>,
+  total_terms: usize,
+}
+
+impl InvertedIndex {
+  fn index_map<T: ReadTxn>(
+    &mut self,
+    txn: &T,
+    object_id: &Arc<str>,
+    path: &mut Vec<String>,
+    map: &MapRef,
+  ) {
+    for (key, value) in map.iter(txn) {
+      path.push(key.to_string());
+      self.index_out(txn, object_id, path, value);
+      path.pop();
+    }
+  }
+
+  fn index_out<T: ReadTxn>(
+    &mut self,
+    txn: &T,
+    object_id: &Arc<str>,
+    path: &mut Vec<String>,
+    value: Out,
+  ) {
+    match value {
+      Out::Any(any) => self.index_any(object_id, path, &any),
+      Out::YText(text) => self.insert_entry(object_id, path, &text.get_string(txn)),
+      Out::YMap(map) => self.index_map(txn, object_id, path, &map),
+      Out::YArray(array) => {
+        for (i, value) in array.iter(txn).enumerate() {
+          path.push(i.to_string());
+          self.index_out(txn, object_id, path, value);
+          path.pop();
+        }
+      },
+      _ => {},
+    }
+  }
+
+  fn index_any(&mut self, object_id: &Arc<str>, path: &mut Vec<String>, any: &Any) {
+    match any {
+      Any::String(value) => self.insert_entry(object_id, path, value),
+      Any::Map(map) => {
+        for (key, value) in map.iter() {
+          path.push(key.clone());
+          self.index_any(object_id, path, value);
+          path.pop();
+        }
+      },
+      Any::Array(array) => {
+        for (i, value) in array.iter().enumerate() {
+          path.push(i.to_string());
+          self.index_any(object_id, path, value);
+          path.pop();
+        }
+      },
+      _ => {},
+    }
+  }
+
+  fn insert_entry(&mut self, object_id: &Arc<str>, path: &[String], content: &str) {
+    let terms = tokenize(content);
+    if terms.is_empty() {
+      return;
+    }
+    let key: EntryKey = (object_id.clone(), path.to_vec());
+    if let Some(old) = self.entries.remove(&key) {
+      // The same path was indexed twice, drop the stale terms.
+      self.remove_terms(&key, &old);
+    }
+    for term in terms.iter() {
+      *self
+        .postings
+        .entry(term.clone())
+        .or_default()
+        .entry(key.clone())
+        .or_default() += 1;
+    }
+    self.total_terms += terms.len();
+    self.entries.insert(key, terms);
+  }
+
+  fn remove_object(&mut self, object_id: &str) {
+    let keys = self
+      .entries
+      .keys()
+      .filter(|(oid, _)| &**oid == object_id)
+      .cloned()
+      .collect::<Vec<_>>();
+    for key in keys {
+      if let Some(terms) = self.entries.remove(&key) {
+        self.remove_terms(&key, &terms);
+      }
+    }
+  }
+
+  fn remove_prefix(&mut self, object_id: &Arc<str>, prefix: &[String]) {
+    let start: EntryKey = (object_id.clone(), prefix.to_vec());
+    let keys = self
+      .entries
+      .range(start..)
+      .map(|(key, _)| key)
+      .take_while(|(oid, path)| oid == object_id && path.starts_with(prefix))
+      .cloned()
+      .collect::<Vec<_>>();
+    for key in keys {
+      if let Some(terms) = self.entries.remove(&key) {
+        self.remove_terms(&key, &terms);
+      }
+    }
+  }
+
+  fn remove_terms(&mut self, key: &EntryKey, terms: &[String]) {
+    self.total_terms = self.total_terms.saturating_sub(terms.len());
+    for term in terms {
+      if let Some(posting) = self.postings.get_mut(term) {
+        posting.remove(key);
+        if posting.is_empty() {
+          self.postings.remove(term);
+        }
+      }
+    }
+  }
+
+  fn search(&self, query: &str, limit: usize) -> Vec<SearchHit> {
+    let terms = tokenize(query)
+      .into_iter()
+      .collect::<HashSet<_>>()
+      .into_iter()
+      .collect::<Vec<_>>();
+    if terms.is_empty() || self.entries.is_empty() {
+      return vec![];
+    }
+
+    let num_entries = self.entries.len() as f32;
+    let avg_len = self.total_terms as f32 / num_entries;
+    let mut scores: HashMap<&EntryKey, (f32, usize)> = HashMap::new();
+    for term in terms.iter() {
+      let Some(posting) = self.postings.get(term) else {
+        // Every term must match, so a missing term means there is no result.
+        return vec![];
+      };
+      let idf =
+        (1.0 + (num_entries - posting.len() as f32 + 0.5) / (posting.len() as f32 + 0.5)).ln();
+      for (key, tf) in posting.iter() {
+        let len = self.entries.get(key).map(|terms| terms.len()).unwrap_or(1) as f32;
+        let tf = *tf as f32;
+        // BM25 with the default k1 = 1.2 and b = 0.75
+        let score = idf * (tf * 2.2) / (tf + 1.2 * (0.25 + 0.75 * len / avg_len));
+        let entry = scores.entry(key).or_insert((0.0, 0));
+        entry.0 += score;
+        entry.1 += 1;
+      }
+    }
+
+    let mut hits = scores
+      .into_iter()
+      .filter(|(_, (_, matched))| *matched == terms.len())
+      .map(|((object_id, path), (score, _))| SearchHit {
+        object_id: object_id.to_string(),
+        path: Path::from(path.clone()),
+        score,
+      })
+      .collect::<Vec<_>>();
+    hits.sort_by(|a, b| {
+      b.score
+        .total_cmp(&a.score)
+        .then_with(|| a.object_id.cmp(&b.object_id))
+        .then_with(|| a.path.cmp(&b.path))
+    });
+    hits.truncate(limit);
+    hits
+  }
+}
+
+fn tokenize(content: &str) -> Vec<String> {
+  content
+    .unicode_words()
+    .map(|word| word.to_lowercase())
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn tokenize_lowercases_and_splits_words() {
+    assert_eq!(
+      tokenize("Hello, World! It's AppFlowy."),
+      vec!["hello", "world", "it's", "appflowy"]
+    );
+  }
+
+  #[test]
+  fn remove_prefix_only_removes_subtree() {
+    let mut index = InvertedIndex::default();
+    let oid: Arc<str> = Arc::from("1");
+    index.insert_entry(&oid, &["a".to_string(), "b".to_string()], "hello");
+    index.insert_entry(&oid, &["a".to_string(), "c".to_string()], "hello");
+    index.insert_entry(&oid, &["ab".to_string()], "hello");
+    index.remove_prefix(&oid, &["a".to_string()]);
+    let hits = index.search("hello", 10);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].path, Path::from(vec!["ab"]));
+  }
+}
@@ -0,0 +1,385 @@
+//! A deterministic harness to check that several peers editing the same document converge.
+//! Enabled by the `simulation` feature, for tests.
+//!
+//! Every peer is a [Collab] with its own [ClientID] and [CollabOrigin]. The local updates of a
+//! peer are sent to every other peer through a simulated network, which can deliver them out of
+//! order or drop them. All the random choices are made by a [fastrand::Rng] seeded with
+//! [SimulationConfig::seed], so a failing run is replayed by running it again with the seed
+//! reported by [Simulation::assert_converged].
+//!
+//! The peers can wrap their [Collab], e.g. in a `Document` or a `Folder`, see
+//! [Simulation::with_peers].
+//!
+//! ```
+//! use collab::core::collab_simulation::{Simulation, SimulationConfig};
+//!
+//! let config = SimulationConfig::new(3, 42)
+//!   .with_reorder(true)
+//!   .with_drop_rate(0.2);
+//! let mut simulation = Simulation::new(config).unwrap();
+//! simulation.run(100, |collab, rng| {
+//!   collab.insert(&rng.u8(0..10).to_string(), rng.i64(..));
+//! });
+//! simulation.converge();
+//! simulation.assert_converged();
+//! ```
+
+use std::borrow::BorrowMut;
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use yrs::block::ClientID;
+use yrs::updates::decoder::Decode;
+use yrs::{ReadTxn, StateVector, Transact, Update};
+
+use crate::core::collab::{Collab, CollabOptions, DataSource, TransactionMutExt};
+use crate::core::collab_plugin::{CollabPlugin, CollabPluginType};
+use crate::core::origin::{CollabClient, CollabOrigin};
+use crate::error::CollabError;
+use crate::preclude::JsonValue;
+
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+  pub object_id: String,
+  pub peers: usize,
+  pub seed: u64,
+  /// Deliver the updates in a random order instead of the order they were sent in.
+  pub reorder: bool,
+  /// The probability, between 0 and 1, that an update is dropped instead of being delivered.
+  pub drop_rate: f64,
+  /// The doc state every peer is opened with.
+  pub doc_state: Option<Vec<u8>>,
+}
+
+impl SimulationConfig {
+  pub fn new(peers: usize, seed: u64) -> Self {
+    Self {
+      object_id: "simulation".to_string(),
+      peers,
+      seed,
+      reorder: false,
+      drop_rate: 0.0,
+      doc_state: None,
+    }
+  }
+
+  pub fn with_object_id(mut self, object_id: impl ToString) -> Self {
+    self.object_id = object_id.to_string();
+    self
+  }
+
+  pub fn with_reorder(mut self, reorder: bool) -> Self {
+    self.reorder = reorder;
+    self
+  }
+
+  pub fn with_drop_rate(mut self, drop_rate: f64) -> Self {
+    debug_assert!((0.0..=1.0).contains(&drop_rate));
+    self.drop_rate = drop_rate;
+    self
+  }
+
+  pub fn with_doc_state(mut self, doc_state: Vec<u8>) -> Self {
+    self.doc_state = Some(doc_state);
+    self
+  }
+}
+
+/// The number of messages handled by the network. A message is an update sent by a peer to
+/// another one.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkStats {
+  pub sent: usize,
+  pub delivered: usize,
+  pub dropped: usize,
+}
+
+struct Message {
+  from: usize,
+  to: usize,
+  update: Vec<u8>,
+}
+
+struct Peer<T> {
+  inner: T,
+  origin: CollabOrigin,
+  outbox: Outbox,
+}
+
+type Outbox = Arc<Mutex<Vec<Vec<u8>>>>;
+
+/// Collects the local updates of a peer, to be sent when the edit is done.
+struct OutboxPlugin {
+  outbox: Outbox,
+}
+
+impl CollabPlugin for OutboxPlugin {
+  fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, update: &[u8]) {
+    self.outbox.lock().unwrap().push(update.to_vec());
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::Other("SimulationOutbox".to_string())
+  }
+}
+
+pub struct Simulation<T = Collab> {
+  config: SimulationConfig,
+  rng: fastrand::Rng,
+  peers: Vec<Peer<T>>,
+  in_flight: VecDeque<Message>,
+  stats: NetworkStats,
+}
+
+impl Simulation<Collab> {
+  pub fn new(config: SimulationConfig) -> Result<Self, CollabError> {
+    Self::with_peers(config, |collab| collab)
+  }
+}
+
+impl<T> Simulation<T>
+where
+  T: BorrowMut<Collab>,
+{
+  /// Creates the peers of the simulation. `open` wraps the [Collab] of each peer, which is
+  /// already initialized with the doc state of the config.
+  pub fn with_peers<F>(config: SimulationConfig, mut open: F) -> Result<Self, CollabError>
+  where
+    F: FnMut(Collab) -> T,
+  {
+    let mut rng = fastrand::Rng::with_seed(config.seed);
+    let mut client_ids = HashSet::new();
+    let mut peers = Vec::with_capacity(config.peers);
+    for index in 0..config.peers {
+      let client_id = loop {
+        let client_id = rng.u32(..) as ClientID;
+        if client_ids.insert(client_id) {
+          break client_id;
+        }
+      };
+      let origin = CollabOrigin::Client(CollabClient::new(
+        index as i64 + 1,
+        format!("simulation-peer-{}", index),
+      ));
+      let mut options = CollabOptions::new(config.object_id.clone(), client_id);
+      if let Some(doc_state) = &config.doc_state {
+        options = options.with_data_source(DataSource::DocStateV1(doc_state.clone()));
+      }
+      let mut collab = Collab::new_with_options(origin.clone(), options)?;
+      let outbox = Outbox::default();
+      collab.add_plugin(Box::new(OutboxPlugin {
+        outbox: outbox.clone(),
+      }));
+      collab.initialize();
+      peers.push(Peer {
+        inner: open(collab),
+        origin,
+        outbox,
+      });
+    }
+
+    let mut simulation = Self {
+      config,
+      rng,
+      peers,
+      in_flight: VecDeque::new(),
+      stats: NetworkStats::default(),
+    };
+    // Wrapping the collab may have changed it
+    for index in 0..simulation.peers.len() {
+      simulation.send(index);
+    }
+    Ok(simulation)
+  }
+
+  pub fn seed(&self) -> u64 {
+    self.config.seed
+  }
+
+  pub fn peer_count(&self) -> usize {
+    self.peers.len()
+  }
+
+  pub fn peer(&self, index: usize) -> &T {
+    &self.peers[index].inner
+  }
+
+  pub fn peers(&self) -> impl Iterator<Item = &T> {
+    self.peers.iter().map(|peer| &peer.inner)
+  }
+
+  pub fn origin(&self, index: usize) -> &CollabOrigin {
+    &self.peers[index].origin
+  }
+
+  /// The random number generator of the simulation. Use it to generate the edits, to keep the
+  /// simulation deterministic.
+  pub fn rng(&mut self) -> &mut fastrand::Rng {
+    &mut self.rng
+  }
+
+  pub fn stats(&self) -> NetworkStats {
+    self.stats
+  }
+
+  /// The number of messages that were sent but neither delivered nor dropped yet.
+  pub fn in_flight(&self) -> usize {
+    self.in_flight.len()
+  }
+
+  /// Runs `f` on the peer at `index` and sends its local updates to every other peer.
+  pub fn edit<F, R>(&mut self, index: usize, f: F) -> R
+  where
+    F: FnOnce(&mut T) -> R,
+  {
+    let result = f(&mut self.peers[index].inner);
+    self.send(index);
+    result
+  }
+
+  /// Runs `f` on a random peer, see [Simulation::edit].
+  pub fn edit_random<F, R>(&mut self, f: F) -> R
+  where
+    F: FnOnce(&mut T, &mut fastrand::Rng) -> R,
+  {
+    let index = self.rng.usize(..self.peers.len());
+    let result = f(&mut self.peers[index].inner, &mut self.rng);
+    self.send(index);
+    result
+  }
+
+  /// Runs `steps` random edits with `f`. After each edit, a random number of the messages in
+  /// flight are delivered.
+  pub fn run<F>(&mut self, steps: usize, mut f: F)
+  where
+    F: FnMut(&mut T, &mut fastrand::Rng),
+  {
+    for _ in 0..steps {
+      self.edit_random(&mut f);
+      let count = self.rng.usize(..=self.in_flight.len());
+      self.deliver(count);
+    }
+  }
+
+  /// Delivers, or drops, the next message. Returns false if there is no message in flight.
+  pub fn deliver_next(&mut self) -> bool {
+    if self.in_flight.is_empty() {
+      return false;
+    }
+    let index = if self.config.reorder {
+      self.rng.usize(..self.in_flight.len())
+    } else {
+      0
+    };
+    let message = self.in_flight.remove(index).unwrap();
+    if self.rng.f64() < self.config.drop_rate {
+      self.stats.dropped += 1;
+    } else {
+      self.stats.delivered += 1;
+      self.apply(message.from, message.to, &message.update);
+    }
+    true
+  }
+
+  pub fn deliver(&mut self, count: usize) {
+    for _ in 0..count {
+      if !self.deliver_next() {
+        break;
+      }
+    }
+  }
+
+  pub fn deliver_all(&mut self) {
+    while self.deliver_next() {}
+  }
+
+  /// Syncs every pair of peers with their state vectors, as the peers do when they reconnect.
+  /// The network is bypassed, so nothing is dropped.
+  pub fn sync_all(&mut self) {
+    for from in 0..self.peers.len() {
+      for to in 0..self.peers.len() {
+        if from == to {
+          continue;
+        }
+        let state_vector = self.collab(to).transact().state_vector();
+        let update = self
+          .collab(from)
+          .transact()
+          .encode_state_as_update_v1(&state_vector);
+        self.apply(from, to, &update);
+      }
+    }
+  }
+
+  /// Delivers the messages in flight, then syncs the peers to recover the dropped ones.
+  pub fn converge(&mut self) {
+    self.deliver_all();
+    self.sync_all();
+  }
+
+  pub fn is_converged(&self) -> bool {
+    let states = (0..self.peers.len())
+      .map(|index| self.state_of(index))
+      .collect::<Vec<_>>();
+    states.windows(2).all(|pair| pair[0] == pair[1])
+  }
+
+  /// Panics with the seed of the simulation and the content of every peer if the peers don't
+  /// have the same content.
+  pub fn assert_converged(&self) {
+    if self.is_converged() {
+      return;
+    }
+    let peers = (0..self.peers.len())
+      .map(|index| format!("peer {}: {}", index, self.state_of(index).0))
+      .collect::<Vec<_>>()
+      .join("\n");
+    panic!(
+      "peers did not converge, seed: {}, {:?}\n{}",
+      self.config.seed, self.stats, peers
+    );
+  }
+
+  fn collab(&self, index: usize) -> &Collab {
+    self.peers[index].inner.borrow()
+  }
+
+  fn state_of(&self, index: usize) -> (JsonValue, StateVector) {
+    let collab = self.collab(index);
+    (collab.to_json_value(), collab.transact().state_vector())
+  }
+
+  fn send(&mut self, from: usize) {
+    let updates = std::mem::take(&mut *self.peers[from].outbox.lock().unwrap());
+    for update in updates {
+      for to in (0..self.peers.len()).filter(|to| *to != from) {
+        self.stats.sent += 1;
+        self.in_flight.push_back(Message {
+          from,
+          to,
+          update: update.clone(),
+        });
+      }
+    }
+  }
+
+  /// Applies the update with the origin of the sender, so it isn't sent again by the receiver.
+  fn apply(&mut self, from: usize, to: usize, update: &[u8]) {
+    let origin = self.peers[from].origin.clone();
+    let collab: &mut Collab = self.peers[to].inner.borrow_mut();
+    let result = Update::decode_v1(update)
+      .map_err(CollabError::from)
+      .and_then(|update| {
+        collab
+          .doc()
+          .transact_mut_with(origin)
+          .try_apply_update(update)
+      });
+    if let Err(err) = result {
+      panic!(
+        "seed: {}, peer {} failed to apply an update of peer {}: {}",
+        self.config.seed, to, from, err
+      );
+    }
+  }
+}
@@ -0,0 +1,224 @@
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+
+use yrs::block::ClientID;
+use yrs::types::ToJson;
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{
+  Any, Doc, MapRef, ReadTxn, StateVector, Transact, Transaction, Update, merge_updates_v1,
+};
+
+use crate::core::collab::{Collab, DATA_SECTION, META_SECTION, make_yrs_doc};
+use crate::entity::EncodedCollab;
+use crate::error::CollabError;
+use crate::preclude::JsonValue;
+
+/// The number of segments after which [Collab::read_snapshot] encodes the whole document again
+/// if none of its snapshots has been read in the meantime.
+const MAX_SNAPSHOT_SEGMENTS: usize = 64;
+
+/// An immutable copy of a [Collab] at the state vector it was taken at. It doesn't borrow the
+/// [Collab], so it can be sent to a background task, e.g. to export or index the document, while
+/// the [Collab] keeps being edited.
+///
+/// The snapshot only holds the encoded document, as the segments shared with the previous
+/// snapshots of the same [Collab] followed by the changes since the last of them. The read-only
+/// [Doc] is built the first time the snapshot is read, outside of any lock around the [Collab],
+/// and shared by its clones.
+///
+/// The snapshot exposes the same sections as the [Collab]. Read them with [MapExt] and [ArrayExt]
+/// using the transaction returned by [CollabReadSnapshot::transact]:
+///
+/// ```
+/// use collab::core::collab::default_client_id;
+/// use collab::preclude::{Collab, MapExt};
+///
+/// let mut collab = Collab::new(1, "1", "1", default_client_id());
+/// collab.insert("title", "hello");
+/// let snapshot = collab.read_snapshot();
+/// std::thread::spawn(move || {
+///   let txn = snapshot.transact().unwrap();
+///   let title: Option<String> = snapshot.data().unwrap().get_with_txn(&txn, "title");
+///   assert_eq!(title.as_deref(), Some("hello"));
+/// })
+/// .join()
+/// .unwrap();
+/// ```
+///
+/// [MapExt]: crate::preclude::MapExt
+/// [ArrayExt]: crate::util::ArrayExt
+#[derive(Clone)]
+pub struct CollabReadSnapshot {
+  object_id: Arc<str>,
+  client_id: ClientID,
+  /// Whether the [Collab] keeps the deleted items, so the snapshot keeps them too.
+  skip_gc: bool,
+  state_vector: StateVector,
+  segments: Arc<[Arc<[u8]>]>,
+  cache: Arc<SnapshotCache>,
+  doc: Arc<OnceLock<Result<SnapshotDoc, String>>>,
+}
+
+struct SnapshotDoc {
+  doc: Doc,
+  data: MapRef,
+  meta: MapRef,
+}
+
+impl CollabReadSnapshot {
+  pub fn object_id(&self) -> &str {
+    &self.object_id
+  }
+
+  /// The state vector of the [Collab] when the snapshot was taken.
+  pub fn state_vector(&self) -> &StateVector {
+    &self.state_vector
+  }
+
+  pub fn data(&self) -> Result<&MapRef, CollabError> {
+    Ok(&self.snapshot_doc()?.data)
+  }
+
+  pub fn meta(&self) -> Result<&MapRef, CollabError> {
+    Ok(&self.snapshot_doc()?.meta)
+  }
+
+  /// Returns a read-only transaction. The snapshot never changes, so the transaction never
+  /// blocks.
+  pub fn transact(&self) -> Result<Transaction, CollabError> {
+    Ok(self.snapshot_doc()?.doc.transact())
+  }
+
+  pub fn to_json(&self) -> Result<Any, CollabError> {
+    let snapshot_doc = self.snapshot_doc()?;
+    Ok(snapshot_doc.data.to_json(&snapshot_doc.doc.transact()))
+  }
+
+  pub fn to_json_value(&self) -> Result<JsonValue, CollabError> {
+    Ok(serde_json::to_value(self.to_json()?)?)
+  }
+
+  /// Returns the encoded document without building the [Doc]. The segments are merged into one
+  /// doc state, which the next snapshots of the [Collab] then share.
+  pub fn encode_collab_v1(&self) -> Result<EncodedCollab, CollabError> {
+    let doc_state = match &*self.segments {
+      [doc_state] => doc_state.to_vec(),
+      segments => {
+        let updates = segments
+          .iter()
+          .map(|segment| &**segment)
+          .collect::<Vec<_>>();
+        let doc_state = merge_updates_v1(&updates)?;
+        self
+          .cache
+          .compact(&self.segments, Arc::from(doc_state.as_slice()));
+        doc_state
+      },
+    };
+    Ok(EncodedCollab::new_v1(
+      self.state_vector.encode_v1(),
+      doc_state,
+    ))
+  }
+
+  fn snapshot_doc(&self) -> Result<&SnapshotDoc, CollabError> {
+    self
+      .doc
+      .get_or_init(|| self.build_doc().map_err(|err| err.to_string()))
+      .as_ref()
+      .map_err(|err| {
+        CollabError::InvalidReadSnapshot(format!(
+          "failed to build the read snapshot of {}: {}",
+          self.object_id, err
+        ))
+      })
+  }
+
+  fn build_doc(&self) -> Result<SnapshotDoc, CollabError> {
+    let doc = make_yrs_doc(&self.object_id, self.skip_gc, self.client_id);
+    let data = doc.get_or_insert_map(DATA_SECTION);
+    let meta = doc.get_or_insert_map(META_SECTION);
+    {
+      let mut txn = doc.transact_mut();
+      for segment in self.segments.iter() {
+        txn.apply_update(Update::decode_v1(segment)?)?;
+      }
+    }
+    if self.segments.len() > 1 {
+      let doc_state = doc
+        .transact()
+        .encode_state_as_update_v1(&StateVector::default());
+      self.cache.compact(&self.segments, Arc::from(doc_state));
+    }
+    Ok(SnapshotDoc { doc, data, meta })
+  }
+}
+
+/// The encoded document shared by the snapshots of a [Collab], so that taking a snapshot only
+/// encodes the changes since the previous one.
+#[derive(Default)]
+pub(crate) struct SnapshotCache(Mutex<SnapshotSegments>);
+
+#[derive(Default)]
+struct SnapshotSegments {
+  /// The state vector of the [Collab] when the last segment was encoded.
+  state_vector: StateVector,
+  segments: Vec<Arc<[u8]>>,
+}
+
+impl SnapshotCache {
+  fn lock(&self) -> MutexGuard<'_, SnapshotSegments> {
+    self.0.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+
+  /// Appends the changes of the document since the last segment and returns all the segments.
+  fn segments<T: ReadTxn>(&self, txn: &T, state_vector: &StateVector) -> Arc<[Arc<[u8]>]> {
+    let mut cache = self.lock();
+    if cache.segments.len() >= MAX_SNAPSHOT_SEGMENTS {
+      *cache = SnapshotSegments::default();
+    }
+    // The delete set is always encoded in full, so the changes are never empty. Only keep them
+    // when they differ from the last segment.
+    let changes = txn.encode_state_as_update_v1(&cache.state_vector);
+    if cache.segments.last().map(|segment| &**segment) != Some(changes.as_slice()) {
+      cache.segments.push(Arc::from(changes));
+    }
+    cache.state_vector = state_vector.clone();
+    Arc::from(cache.segments.as_slice())
+  }
+
+  /// Replaces the given segments with the doc state they were merged into, unless they were
+  /// already replaced.
+  fn compact(&self, segments: &[Arc<[u8]>], doc_state: Arc<[u8]>) {
+    let mut cache = self.lock();
+    let is_prefix = cache.segments.len() >= segments.len()
+      && cache
+        .segments
+        .iter()
+        .zip(segments)
+        .all(|(cached, segment)| Arc::ptr_eq(cached, segment));
+    if is_prefix {
+      cache.segments.splice(..segments.len(), [doc_state]);
+    }
+  }
+}
+
+impl Collab {
+  /// Takes an immutable snapshot of the document. Only the changes since the previous snapshot
+  /// are encoded, so a lock around the [Collab] is only held for as long as it takes to encode
+  /// them. The snapshot is decoded when it's first read.
+  pub fn read_snapshot(&self) -> CollabReadSnapshot {
+    let txn = self.transact();
+    let state_vector = txn.state_vector();
+    let segments = self.snapshot_cache.segments(&txn, &state_vector);
+    CollabReadSnapshot {
+      object_id: Arc::from(self.object_id()),
+      client_id: self.context.doc().client_id(),
+      skip_gc: self.context.doc().skip_gc(),
+      state_vector,
+      segments,
+      cache: self.snapshot_cache.clone(),
+      doc: Arc::new(OnceLock::new()),
+    }
+  }
+}
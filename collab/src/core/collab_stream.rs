@@ -0,0 +1,517 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+
+use bytes::Bytes;
+use tracing::warn;
+use yrs::block::{
+  BLOCK_GC_REF_NUMBER, BLOCK_SKIP_REF_NUMBER, ClientID, HAS_ORIGIN, HAS_PARENT_SUB,
+  HAS_RIGHT_ORIGIN, ItemContent,
+};
+use yrs::encoding::read::Read as _;
+use yrs::encoding::write::Write as _;
+use yrs::updates::decoder::{Decode, Decoder, DecoderV1};
+use yrs::updates::encoder::{Encoder, EncoderV1};
+use yrs::{DeleteSet, OffsetKind, ReadTxn, StateVector, Transaction, Update};
+
+use crate::core::collab::Collab;
+use crate::core::collab_snapshot::CollabReadSnapshot;
+use crate::entity::MAX_FRAME_BODY_SIZE;
+use crate::error::CollabError;
+
+/// Starts every chunked doc state, followed by chunks prefixed with their length as a
+/// little-endian u32. A zero length ends the stream.
+const CHUNKS_MAGIC: [u8; 8] = *b"CLBCHUNK";
+
+/// The default target size of a chunk, see [DocStateChunks].
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The maximum size of a chunk in a stream, so a corrupted or malicious length can't exhaust the
+/// memory. Only a chunk with a single block can exceed the target size, see [DocStateChunks].
+pub const MAX_CHUNK_SIZE: usize = MAX_FRAME_BODY_SIZE;
+
+impl Collab {
+  /// Splits the doc state into several updates, see [DocStateChunks] for how `chunk_size` is
+  /// applied. The iterator holds a read transaction of the collab, use
+  /// [CollabReadSnapshot::doc_state_chunks] to iterate without locking the collab.
+  pub fn doc_state_chunks(&self, chunk_size: usize) -> DocStateChunks<'_> {
+    DocStateChunks::new(self.transact(), chunk_size)
+  }
+
+  /// Writes the doc state to `writer` in the chunks of [Collab::doc_state_chunks]. Only one chunk
+  /// is encoded at a time. Returns the number of bytes written, or an error if a chunk is bigger
+  /// than [MAX_CHUNK_SIZE].
+  ///
+  /// The chunks are encoded from a [Collab::read_snapshot], so the collab is only locked while
+  /// the snapshot is taken, not while writing.
+  pub fn write_doc_state<W: Write>(
+    &self,
+    writer: W,
+    chunk_size: usize,
+  ) -> Result<u64, CollabError> {
+    self.read_snapshot().write_doc_state(writer, chunk_size)
+  }
+
+  /// Applies a doc state written by [Collab::write_doc_state], one chunk at a time.
+  ///
+  /// The blocks of a chunk that depend on blocks of a later chunk are kept pending by yrs until
+  /// that chunk is applied, so the memory used while applying the stream is only bounded by the
+  /// size of the doc state.
+  pub fn apply_doc_state_stream<R: Read>(&mut self, reader: R) -> Result<(), CollabError> {
+    for update in DocStateChunkReader::new(reader) {
+      self.context.apply_update(update?)?;
+    }
+    Ok(())
+  }
+}
+
+impl CollabReadSnapshot {
+  /// Like [Collab::doc_state_chunks], on the snapshot instead of the live collab.
+  pub fn doc_state_chunks(&self, chunk_size: usize) -> Result<DocStateChunks<'_>, CollabError> {
+    Ok(DocStateChunks::new(self.transact()?, chunk_size))
+  }
+
+  /// Like [Collab::write_doc_state], on the snapshot instead of the live collab.
+  pub fn write_doc_state<W: Write>(
+    &self,
+    mut writer: W,
+    chunk_size: usize,
+  ) -> Result<u64, CollabError> {
+    let mut written = 0;
+    for frame in self.doc_state_chunks(chunk_size)?.framed() {
+      if frame.len() > MAX_CHUNK_SIZE + 4 {
+        return Err(CollabError::InvalidDocStateStream(format!(
+          "chunk of {} bytes exceeds the maximum of {} bytes",
+          frame.len() - 4,
+          MAX_CHUNK_SIZE
+        )));
+      }
+      writer.write_all(&frame)?;
+      written += frame.len() as u64;
+    }
+    writer.flush()?;
+    Ok(written)
+  }
+}
+
+/// An iterator over the doc state of a [Collab], split into several updates that can be applied
+/// in any order.
+///
+/// The clients are grouped into batches whose total clock is at most the chunk size, and the
+/// blocks of a batch are encoded together. The encoded blocks are then split into chunks of at
+/// most the chunk size in bytes, so the content of a client that wrote more than that is split
+/// too. A single block bigger than the chunk size, e.g. a long string inserted at once, is never
+/// split. The delete set is sent once, in the last chunk.
+///
+/// The batches are ordered by client id, not causally: a chunk can depend on the blocks of a
+/// later chunk, and stays pending in the receiving doc until they are applied.
+///
+/// The blocks of a batch are encoded into one update before being split, so the memory used by
+/// the iterator is bounded by the size of a batch, i.e. by the content of the client with the
+/// biggest clock when it exceeds the chunk size.
+pub struct DocStateChunks<'a> {
+  txn: Transaction<'a>,
+  state_vector: StateVector,
+  chunk_size: usize,
+  batches: std::vec::IntoIter<Vec<ClientID>>,
+  /// The chunks of the current batch that were not returned yet.
+  pending: VecDeque<Bytes>,
+  delete_set_sent: bool,
+}
+
+impl<'a> DocStateChunks<'a> {
+  fn new(txn: Transaction<'a>, chunk_size: usize) -> Self {
+    let state_vector = txn.state_vector();
+    let mut clients = state_vector
+      .iter()
+      .map(|(client, clock)| (*client, *clock as usize))
+      .collect::<Vec<_>>();
+    clients.sort();
+
+    let mut batches = vec![];
+    let mut batch = vec![];
+    let mut batch_size = 0;
+    for (client, clock) in clients {
+      if !batch.is_empty() && batch_size + clock > chunk_size {
+        batches.push(std::mem::take(&mut batch));
+        batch_size = 0;
+      }
+      batch.push(client);
+      batch_size += clock;
+    }
+    if !batch.is_empty() {
+      batches.push(batch);
+    }
+
+    Self {
+      txn,
+      state_vector,
+      chunk_size: chunk_size.max(1),
+      batches: batches.into_iter(),
+      pending: VecDeque::new(),
+      delete_set_sent: false,
+    }
+  }
+
+  /// Frames the chunks in the format read by [DocStateChunkReader] and [DocStateChunkDecoder],
+  /// e.g. to send them over an async byte stream.
+  pub fn framed(self) -> impl Iterator<Item = Bytes> + 'a {
+    std::iter::once(Bytes::from_static(&CHUNKS_MAGIC))
+      .chain(self.map(|chunk| {
+        let mut frame = Vec::with_capacity(4 + chunk.len());
+        frame.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        frame.extend_from_slice(&chunk);
+        Bytes::from(frame)
+      }))
+      .chain(std::iter::once(Bytes::from_static(&[0; 4])))
+  }
+
+  fn encode_batch(&self, batch: &[ClientID]) -> Vec<u8> {
+    // Every client that is not part of the batch is marked as fully known, so only the blocks
+    // of the batch are encoded.
+    let mut state_vector = StateVector::default();
+    for (client, clock) in self.state_vector.iter() {
+      if !batch.contains(client) {
+        state_vector.set_max(*client, *clock);
+      }
+    }
+    self.txn.encode_state_as_update_v1(&state_vector)
+  }
+}
+
+impl Iterator for DocStateChunks<'_> {
+  type Item = Bytes;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      if let Some(chunk) = self.pending.pop_front() {
+        return Some(chunk);
+      }
+      if let Some(batch) = self.batches.next() {
+        let update = self.encode_batch(&batch);
+        match split_blocks(&update, self.chunk_size) {
+          Ok(chunks) => self.pending.extend(chunks),
+          Err(err) => {
+            // The whole batch is still a valid chunk, encoded by yrs itself.
+            warn!("failed to split the doc state into chunks: {}", err);
+            self.pending.push_back(Bytes::from(update));
+          },
+        }
+        continue;
+      }
+      if self.delete_set_sent {
+        return None;
+      }
+      self.delete_set_sent = true;
+      // Nothing is missing from the state vector, so only the delete set is encoded
+      let delete_set = self.txn.encode_state_as_update_v1(&self.state_vector);
+      if delete_set != Update::EMPTY_V1 {
+        return Some(Bytes::from(delete_set));
+      }
+    }
+  }
+}
+
+/// Splits the blocks of a v1 update into updates of at most `chunk_size` bytes. The delete set of
+/// the update is dropped.
+///
+/// yrs doesn't expose the blocks of an update, so they are read with [read_block]. The yrs version
+/// is pinned in the workspace manifest for that reason. In case the encoding of yrs changes
+/// anyway, the chunks are checked against the update: they must decode and cover the same clocks,
+/// and the blocks must be followed by the delete set.
+fn split_blocks(update: &[u8], chunk_size: usize) -> Result<Vec<Bytes>, CollabError> {
+  let chunks = read_chunks(update, chunk_size)?;
+  let mut state_vector = StateVector::default();
+  for chunk in chunks.iter() {
+    state_vector.merge(Update::decode_v1(chunk)?.state_vector());
+  }
+  if state_vector != Update::decode_v1(update)?.state_vector() {
+    return Err(CollabError::InvalidDocStateStream(
+      "the chunks don't cover the blocks of the update".to_string(),
+    ));
+  }
+  Ok(chunks)
+}
+
+fn read_chunks(update: &[u8], chunk_size: usize) -> Result<Vec<Bytes>, CollabError> {
+  let mut decoder = DecoderV1::from(update);
+  let mut chunks = vec![];
+  let mut chunk = ChunkBuilder::default();
+  let clients: u32 = decoder.read_var()?;
+  for _ in 0..clients {
+    let blocks: u32 = decoder.read_var()?;
+    let client = decoder.read_client()?;
+    let mut clock: u32 = decoder.read_var()?;
+    for _ in 0..blocks {
+      let start = update.len() - decoder.read_to_end()?.len();
+      let len = read_block(&mut decoder)?;
+      let end = update.len() - decoder.read_to_end()?.len();
+      if !chunk.is_empty() && chunk.size_with(client, end - start) > chunk_size {
+        chunks.push(chunk.finish());
+      }
+      chunk.push(client, clock, &update[start..end]);
+      clock += len;
+    }
+  }
+  if !chunk.is_empty() {
+    chunks.push(chunk.finish());
+  }
+  DeleteSet::decode(&mut decoder)?;
+  if !decoder.read_to_end()?.is_empty() {
+    return Err(CollabError::InvalidDocStateStream(
+      "unexpected bytes after the delete set".to_string(),
+    ));
+  }
+  Ok(chunks)
+}
+
+/// Reads an encoded block and returns its length. Mirrors the decoding of the blocks in the
+/// `yrs::block` module of yrs 0.23, which must be checked when yrs is upgraded.
+fn read_block(decoder: &mut DecoderV1) -> Result<u32, yrs::encoding::read::Error> {
+  match decoder.read_info()? {
+    BLOCK_SKIP_REF_NUMBER => decoder.read_var(),
+    BLOCK_GC_REF_NUMBER => decoder.read_len(),
+    info => {
+      let cant_copy_parent_info = info & (HAS_ORIGIN | HAS_RIGHT_ORIGIN) == 0;
+      if info & HAS_ORIGIN != 0 {
+        decoder.read_left_id()?;
+      }
+      if info & HAS_RIGHT_ORIGIN != 0 {
+        decoder.read_right_id()?;
+      }
+      if cant_copy_parent_info {
+        if decoder.read_parent_info()? {
+          decoder.read_string()?;
+        } else {
+          decoder.read_left_id()?;
+        }
+        if info & HAS_PARENT_SUB != 0 {
+          decoder.read_string()?;
+        }
+      }
+      Ok(ItemContent::decode(decoder, info)?.len(OffsetKind::Utf16))
+    },
+  }
+}
+
+/// The maximum size of the number of clients of a chunk and of its empty delete set.
+const CHUNK_HEADER_SIZE: usize = 5 + 1;
+/// The maximum size of the number of blocks, the client and the clock written before the blocks
+/// of a client.
+const CLIENT_HEADER_SIZE: usize = 5 + 10 + 5;
+
+/// The blocks of a chunk, grouped by client. The blocks of a client are consecutive.
+#[derive(Default)]
+struct ChunkBuilder {
+  /// The client, the clock of its first block, the number of blocks and the encoded blocks.
+  clients: Vec<(ClientID, u32, u32, Vec<u8>)>,
+  /// The maximum size of the encoded chunk.
+  size: usize,
+}
+
+impl ChunkBuilder {
+  fn is_empty(&self) -> bool {
+    self.clients.is_empty()
+  }
+
+  /// The maximum size of the encoded chunk once the block is pushed.
+  fn size_with(&self, client: ClientID, block_len: usize) -> usize {
+    let mut size = self.size + block_len;
+    if self.is_empty() {
+      size += CHUNK_HEADER_SIZE;
+    }
+    if self.clients.last().is_none_or(|(last, ..)| *last != client) {
+      size += CLIENT_HEADER_SIZE;
+    }
+    size
+  }
+
+  fn push(&mut self, client: ClientID, clock: u32, block: &[u8]) {
+    self.size = self.size_with(client, block.len());
+    match self.clients.last_mut() {
+      Some((last, _, blocks, encoded)) if *last == client => {
+        *blocks += 1;
+        encoded.extend_from_slice(block);
+      },
+      _ => self.clients.push((client, clock, 1, block.to_vec())),
+    }
+  }
+
+  fn finish(&mut self) -> Bytes {
+    let mut encoder = EncoderV1::new();
+    encoder.write_var(self.clients.len());
+    for (client, clock, blocks, encoded) in self.clients.drain(..) {
+      encoder.write_var(blocks);
+      encoder.write_client(client);
+      encoder.write_var(clock);
+      encoder.write_all(&encoded);
+    }
+    // An empty delete set
+    encoder.write_var(0u32);
+    self.size = 0;
+    Bytes::from(encoder.to_vec())
+  }
+}
+
+/// Reads the chunks written by [Collab::write_doc_state] from a [Read].
+pub struct DocStateChunkReader<R> {
+  reader: R,
+  started: bool,
+  finished: bool,
+}
+
+impl<R: Read> DocStateChunkReader<R> {
+  pub fn new(reader: R) -> Self {
+    Self {
+      reader,
+      started: false,
+      finished: false,
+    }
+  }
+
+  fn read_chunk(&mut self) -> Result<Option<Update>, CollabError> {
+    if !self.started {
+      let mut magic = [0; CHUNKS_MAGIC.len()];
+      self.reader.read_exact(&mut magic).map_err(truncated)?;
+      if magic != CHUNKS_MAGIC {
+        return Err(CollabError::InvalidDocStateStream(
+          "missing chunks magic".to_string(),
+        ));
+      }
+      self.started = true;
+    }
+
+    let mut len = [0; 4];
+    self.reader.read_exact(&mut len).map_err(truncated)?;
+    let len = u32::from_le_bytes(len) as usize;
+    if len == 0 {
+      return Ok(None);
+    }
+    check_chunk_len(len)?;
+    // The buffer grows with the bytes actually read, not with the length of the header
+    let mut chunk = vec![];
+    (&mut self.reader)
+      .take(len as u64)
+      .read_to_end(&mut chunk)?;
+    if chunk.len() < len {
+      return Err(truncated(std::io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(Some(Update::decode_v1(&chunk)?))
+  }
+}
+
+impl<R: Read> Iterator for DocStateChunkReader<R> {
+  type Item = Result<Update, CollabError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.finished {
+      return None;
+    }
+    let result = self.read_chunk().transpose();
+    if !matches!(result, Some(Ok(_))) {
+      self.finished = true;
+    }
+    result
+  }
+}
+
+fn check_chunk_len(len: usize) -> Result<(), CollabError> {
+  if len > MAX_CHUNK_SIZE {
+    return Err(CollabError::InvalidDocStateStream(format!(
+      "chunk of {} bytes exceeds the maximum of {} bytes",
+      len, MAX_CHUNK_SIZE
+    )));
+  }
+  Ok(())
+}
+
+fn truncated(err: std::io::Error) -> CollabError {
+  if err.kind() == std::io::ErrorKind::UnexpectedEof {
+    CollabError::InvalidDocStateStream("stream ended before the last chunk".to_string())
+  } else {
+    CollabError::Io(err)
+  }
+}
+
+/// Decodes the chunks written by [Collab::write_doc_state] from bytes pushed in arbitrary
+/// pieces, e.g. the items of an async stream. Only the incomplete chunk is buffered.
+///
+/// ```no_run
+/// # use collab::core::collab_stream::DocStateChunkDecoder;
+/// # use collab::preclude::Collab;
+/// # fn f(collab: &mut Collab, pieces: Vec<Vec<u8>>) -> Result<(), collab::error::CollabError> {
+/// let mut decoder = DocStateChunkDecoder::default();
+/// for piece in pieces {
+///   for update in decoder.push(&piece)? {
+///     collab.apply_update(update)?;
+///   }
+/// }
+/// decoder.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct DocStateChunkDecoder {
+  buf: Vec<u8>,
+  started: bool,
+  finished: bool,
+}
+
+impl DocStateChunkDecoder {
+  /// Pushes the next bytes of the stream and returns the updates of the completed chunks.
+  pub fn push(&mut self, bytes: &[u8]) -> Result<Vec<Update>, CollabError> {
+    if self.finished {
+      return Err(CollabError::InvalidDocStateStream(
+        "bytes after the last chunk".to_string(),
+      ));
+    }
+    self.buf.extend_from_slice(bytes);
+
+    let mut updates = vec![];
+    let mut offset = 0;
+    if !self.started {
+      if self.buf.len() < CHUNKS_MAGIC.len() {
+        return Ok(updates);
+      }
+      if self.buf[..CHUNKS_MAGIC.len()] != CHUNKS_MAGIC {
+        return Err(CollabError::InvalidDocStateStream(
+          "missing chunks magic".to_string(),
+        ));
+      }
+      self.started = true;
+      offset = CHUNKS_MAGIC.len();
+    }
+
+    while self.buf.len() >= offset + 4 {
+      let len = u32::from_le_bytes(self.buf[offset..offset + 4].try_into().unwrap()) as usize;
+      if len == 0 {
+        self.finished = true;
+        offset += 4;
+        if offset != self.buf.len() {
+          return Err(CollabError::InvalidDocStateStream(
+            "bytes after the last chunk".to_string(),
+          ));
+        }
+        break;
+      }
+      check_chunk_len(len)?;
+      if self.buf.len() < offset + 4 + len {
+        break;
+      }
+      updates.push(Update::decode_v1(&self.buf[offset + 4..offset + 4 + len])?);
+      offset += 4 + len;
+    }
+    self.buf.drain(..offset);
+    Ok(updates)
+  }
+
+  /// Returns an error if the stream ended before the last chunk.
+  pub fn finish(self) -> Result<(), CollabError> {
+    if self.finished {
+      Ok(())
+    } else {
+      Err(CollabError::InvalidDocStateStream(
+        "stream ended before the last chunk".to_string(),
+      ))
+    }
+  }
+}
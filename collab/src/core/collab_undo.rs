@@ -0,0 +1,260 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use yrs::undo::{Options, UndoManager};
+use yrs::{Doc, Out, Subscription};
+
+use crate::core::collab::{Collab, Path};
+use crate::core::origin::CollabOrigin;
+use crate::error::CollabError;
+use crate::preclude::{JsonValue, MapExt};
+
+/// The metadata attached to every step of an [UndoScope]. It's up to the UI to decide what to
+/// store in it, for example a label ("move block") and the cursor before the change.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UndoStackMeta {
+  pub label: Option<String>,
+  pub cursor: Option<JsonValue>,
+}
+
+impl UndoStackMeta {
+  pub fn new(label: impl Into<String>) -> Self {
+    Self {
+      label: Some(label.into()),
+      cursor: None,
+    }
+  }
+
+  pub fn with_cursor(mut self, cursor: JsonValue) -> Self {
+    self.cursor = Some(cursor);
+    self
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct UndoScopeOptions {
+  /// Changes made within this duration are merged into a single undo step.
+  pub capture_timeout: Duration,
+  /// Only the changes made by one of these origins are tracked. When empty, the origin of the
+  /// [Collab] is used, so remote changes are never undone.
+  pub tracked_origins: Vec<CollabOrigin>,
+}
+
+impl Default for UndoScopeOptions {
+  fn default() -> Self {
+    Self {
+      capture_timeout: Duration::from_millis(500),
+      tracked_origins: vec![],
+    }
+  }
+}
+
+impl UndoScopeOptions {
+  pub fn with_capture_timeout(mut self, capture_timeout: Duration) -> Self {
+    self.capture_timeout = capture_timeout;
+    self
+  }
+
+  pub fn with_tracked_origin(mut self, origin: CollabOrigin) -> Self {
+    self.tracked_origins.push(origin);
+    self
+  }
+}
+
+/// An undo/redo stack that only tracks the changes of the subtree at [UndoScope::path].
+pub struct UndoScope {
+  path: Path,
+  undo_manager: UndoManager<UndoStackMeta>,
+  /// The metadata of the next step, set by [UndoScope::set_meta].
+  pending_meta: Arc<Mutex<Option<UndoStackMeta>>>,
+  /// The metadata of the step being undone or redone, moved to the opposite stack.
+  carried_meta: Arc<Mutex<Option<UndoStackMeta>>>,
+  _subscriptions: Vec<Subscription>,
+}
+
+impl UndoScope {
+  fn new<T>(doc: &Doc, scope: &T, path: Path, origins: Vec<CollabOrigin>, timeout: Duration) -> Self
+  where
+    T: AsRef<yrs::branch::Branch>,
+  {
+    let options = Options {
+      capture_timeout_millis: timeout.as_millis() as u64,
+      ..Default::default()
+    };
+    let mut undo_manager = UndoManager::with_scope_and_options(doc, scope, options);
+    for origin in origins {
+      undo_manager.include_origin(origin);
+    }
+
+    let pending_meta = Arc::new(Mutex::new(None::<UndoStackMeta>));
+    let carried_meta = Arc::new(Mutex::new(None::<UndoStackMeta>));
+    let added = {
+      let pending_meta = pending_meta.clone();
+      let carried_meta = carried_meta.clone();
+      undo_manager.observe_item_added(move |_txn, event| {
+        // While undoing or redoing, the item is pushed onto the opposite stack and keeps the
+        // metadata of the step it reverts.
+        let meta = carried_meta
+          .lock()
+          .unwrap()
+          .take()
+          .or_else(|| pending_meta.lock().unwrap().take());
+        if let Some(meta) = meta {
+          *event.meta_mut() = meta;
+        }
+      })
+    };
+    let updated = {
+      let pending_meta = pending_meta.clone();
+      undo_manager.observe_item_updated(move |_txn, event| {
+        // The change was merged into the last step, a newer metadata overrides the old one.
+        if let Some(meta) = pending_meta.lock().unwrap().take() {
+          *event.meta_mut() = meta;
+        }
+      })
+    };
+
+    Self {
+      path,
+      undo_manager,
+      pending_meta,
+      carried_meta,
+      _subscriptions: vec![added, updated],
+    }
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Sets the metadata of the next undo step. If the next change is merged into the last step
+  /// because of the capture timeout, the metadata of the last step is replaced.
+  pub fn set_meta(&self, meta: UndoStackMeta) {
+    *self.pending_meta.lock().unwrap() = Some(meta);
+  }
+
+  /// Stops merging subsequent changes into the last undo step, regardless of the capture timeout.
+  pub fn stop_capturing(&mut self) {
+    self.undo_manager.reset();
+  }
+
+  pub fn can_undo(&self) -> bool {
+    self.undo_manager.can_undo()
+  }
+
+  pub fn can_redo(&self) -> bool {
+    self.undo_manager.can_redo()
+  }
+
+  /// Returns the metadata of the undo stack, from the oldest to the most recent step.
+  pub fn undo_stack(&self) -> Vec<UndoStackMeta> {
+    self
+      .undo_manager
+      .undo_stack()
+      .iter()
+      .map(|item| item.meta.clone())
+      .collect()
+  }
+
+  /// Returns the metadata of the redo stack, from the oldest to the most recent step.
+  pub fn redo_stack(&self) -> Vec<UndoStackMeta> {
+    self
+      .undo_manager
+      .redo_stack()
+      .iter()
+      .map(|item| item.meta.clone())
+      .collect()
+  }
+
+  /// Undoes the last step and returns its metadata, or `None` if there is nothing to undo.
+  pub fn undo(&mut self) -> Option<UndoStackMeta> {
+    let meta = self.undo_manager.undo_stack().last()?.meta.clone();
+    *self.carried_meta.lock().unwrap() = Some(meta.clone());
+    let undone = self.undo_manager.undo_blocking();
+    self.carried_meta.lock().unwrap().take();
+    undone.then_some(meta)
+  }
+
+  /// Redoes the last undone step and returns its metadata, or `None` if there is nothing to redo.
+  pub fn redo(&mut self) -> Option<UndoStackMeta> {
+    let meta = self.undo_manager.redo_stack().last()?.meta.clone();
+    *self.carried_meta.lock().unwrap() = Some(meta.clone());
+    let redone = self.undo_manager.redo_blocking();
+    self.carried_meta.lock().unwrap().take();
+    redone.then_some(meta)
+  }
+
+  pub fn clear(&mut self) {
+    self.undo_manager.clear();
+  }
+}
+
+impl Collab {
+  /// Adds a named undo scope that only tracks the changes of the subtree at the given `path`.
+  /// An empty path tracks the whole [Collab::data] section. The value at `path` must be a map,
+  /// an array or a text. Adding a scope with an existing name replaces the old scope.
+  pub fn add_undo_scope<P>(
+    &mut self,
+    name: impl Into<String>,
+    path: P,
+    options: UndoScopeOptions,
+  ) -> Result<(), CollabError>
+  where
+    P: Into<Path>,
+  {
+    let path = path.into();
+    let mut origins = options.tracked_origins;
+    if origins.is_empty() {
+      origins.push(self.origin().clone());
+    }
+
+    let doc = self.context.doc().clone();
+    let timeout = options.capture_timeout;
+    let scope = if path.is_empty() {
+      UndoScope::new(&doc, &self.data, path, origins, timeout)
+    } else {
+      let value = self
+        .data
+        .get_value_with_path(&self.transact(), path.clone());
+      match value {
+        Some(Out::YMap(map)) => UndoScope::new(&doc, &map, path, origins, timeout),
+        Some(Out::YArray(array)) => UndoScope::new(&doc, &array, path, origins, timeout),
+        Some(Out::YText(text)) => UndoScope::new(&doc, &text, path, origins, timeout),
+        _ => return Err(CollabError::UndoScopePathNotFound(path.to_string())),
+      }
+    };
+    self.context.undo_scopes.insert(name.into(), scope);
+    Ok(())
+  }
+
+  pub fn remove_undo_scope(&mut self, name: &str) -> Option<UndoScope> {
+    self.context.undo_scopes.remove(name)
+  }
+
+  pub fn undo_scope(&self, name: &str) -> Result<&UndoScope, CollabError> {
+    self
+      .context
+      .undo_scopes
+      .get(name)
+      .ok_or_else(|| CollabError::UndoScopeNotFound(name.to_string()))
+  }
+
+  pub fn undo_scope_mut(&mut self, name: &str) -> Result<&mut UndoScope, CollabError> {
+    self
+      .context
+      .undo_scopes
+      .get_mut(name)
+      .ok_or_else(|| CollabError::UndoScopeNotFound(name.to_string()))
+  }
+
+  /// Undoes the last step of the given scope and returns its metadata.
+  pub fn undo_in(&mut self, name: &str) -> Result<Option<UndoStackMeta>, CollabError> {
+    Ok(self.undo_scope_mut(name)?.undo())
+  }
+
+  /// Redoes the last undone step of the given scope and returns its metadata.
+  pub fn redo_in(&mut self, name: &str) -> Result<Option<UndoStackMeta>, CollabError> {
+    Ok(self.undo_scope_mut(name)?.redo())
+  }
+}
@@ -0,0 +1,159 @@
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+use yrs::{Out, ReadTxn, Snapshot, Transaction};
+
+use crate::core::collab::{Collab, CollabOptions, DataSource, Path, default_client_id};
+use crate::error::CollabError;
+use crate::preclude::{JsonValue, MapExt};
+
+/// A named point in time of a [Collab]. A version only stores the yrs [Snapshot] (the state
+/// vector and the delete set) of the document, so it is cheap to create. Materialising a version
+/// requires the document to be created with `skip_gc` enabled, otherwise the deleted content
+/// would be garbage collected. See [CollabOptions::with_skip_gc].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollabVersion {
+  pub version_id: String,
+  pub label: String,
+  /// Timestamp in seconds since the Unix epoch.
+  pub created_at: i64,
+  /// The [Snapshot] of the document, encoded with [Encode::encode_v1].
+  pub snapshot: Bytes,
+}
+
+impl CollabVersion {
+  pub fn new(label: String, snapshot: Bytes) -> Self {
+    Self {
+      version_id: format!("{:032x}", fastrand::u128(..)),
+      label,
+      created_at: chrono::Utc::now().timestamp(),
+      snapshot,
+    }
+  }
+
+  pub fn decode_snapshot(&self) -> Result<Snapshot, CollabError> {
+    Ok(Snapshot::decode_v1(&self.snapshot)?)
+  }
+}
+
+/// The storage used to persist the [CollabVersion]s of a [Collab].
+pub trait CollabVersionStorage: Send + Sync + 'static {
+  fn save_version(&self, object_id: &str, version: &CollabVersion) -> Result<(), CollabError>;
+
+  fn get_version(
+    &self,
+    object_id: &str,
+    version_id: &str,
+  ) -> Result<Option<CollabVersion>, CollabError>;
+
+  /// Returns the versions of the given object, ordered from the oldest to the newest.
+  fn list_versions(&self, object_id: &str) -> Result<Vec<CollabVersion>, CollabError>;
+
+  fn delete_version(&self, object_id: &str, version_id: &str) -> Result<(), CollabError>;
+}
+
+/// A read-only [Collab] materialised at a given [CollabVersion]. The underlying [Collab] is not
+/// exposed, only read accessors are, so the content can't be modified.
+pub struct CollabVersionView {
+  version: CollabVersion,
+  collab: Collab,
+}
+
+impl CollabVersionView {
+  pub fn version(&self) -> &CollabVersion {
+    &self.version
+  }
+
+  pub fn to_json_value(&self) -> JsonValue {
+    self.collab.to_json_value()
+  }
+
+  /// Returns the value at the given path, relative to the [Collab::data] section.
+  pub fn get_with_path<P, V>(&self, path: P) -> Option<V>
+  where
+    P: Into<Path>,
+    V: TryFrom<Out, Error = Out>,
+  {
+    let txn = self.collab.transact();
+    self.collab.data.get_with_path(&txn, path)
+  }
+
+  pub fn transact(&self) -> Transaction {
+    self.collab.transact()
+  }
+}
+
+/// Encodes the state of the document as it was when the given [Snapshot] was taken.
+pub fn encode_state_from_snapshot<T: ReadTxn>(
+  txn: &T,
+  snapshot: &Snapshot,
+) -> Result<Vec<u8>, CollabError> {
+  let result = panic::catch_unwind(AssertUnwindSafe(|| {
+    let mut encoder = EncoderV1::new();
+    txn
+      .encode_state_from_snapshot(snapshot, &mut encoder)
+      .map(|_| encoder.to_vec())
+  }));
+  match result {
+    Ok(Ok(doc_state)) => Ok(doc_state),
+    Ok(Err(err)) => Err(CollabError::YrsEncodeStateError(err.to_string())),
+    Err(err) => Err(CollabError::YrsEncodeStateError(format!("{:?}", err))),
+  }
+}
+
+impl Collab {
+  pub fn set_version_storage(&mut self, version_storage: Arc<dyn CollabVersionStorage>) {
+    self.version_storage = Some(version_storage);
+  }
+
+  fn version_storage(&self) -> Result<&dyn CollabVersionStorage, CollabError> {
+    match &self.version_storage {
+      None => Err(CollabError::VersionStorageNotSet),
+      Some(storage) => Ok(storage.as_ref()),
+    }
+  }
+
+  fn ensure_skip_gc(&self) -> Result<(), CollabError> {
+    if self.context.doc().skip_gc() {
+      Ok(())
+    } else {
+      Err(CollabError::VersionRequiresSkipGc)
+    }
+  }
+
+  /// Names the current state of the document and saves it with the [CollabVersionStorage] of
+  /// this [Collab].
+  pub fn create_version(&self, label: impl Into<String>) -> Result<CollabVersion, CollabError> {
+    self.ensure_skip_gc()?;
+    let storage = self.version_storage()?;
+    let snapshot = self.transact().snapshot();
+    let version = CollabVersion::new(label.into(), Bytes::from(snapshot.encode_v1()));
+    storage.save_version(self.object_id(), &version)?;
+    Ok(version)
+  }
+
+  /// Returns the versions of this [Collab], ordered from the oldest to the newest.
+  pub fn list_versions(&self) -> Result<Vec<CollabVersion>, CollabError> {
+    self.version_storage()?.list_versions(self.object_id())
+  }
+
+  /// Opens a read-only [Collab] with the content of the document at the given version.
+  pub fn open_version(&self, version_id: &str) -> Result<CollabVersionView, CollabError> {
+    self.ensure_skip_gc()?;
+    let version = self
+      .version_storage()?
+      .get_version(self.object_id(), version_id)?
+      .ok_or_else(|| CollabError::VersionNotFound(version_id.to_string()))?;
+    let snapshot = version.decode_snapshot()?;
+    let doc_state = encode_state_from_snapshot(&self.transact(), &snapshot)?;
+    let options = CollabOptions::new(self.object_id().to_string(), default_client_id())
+      .with_data_source(DataSource::DocStateV1(doc_state));
+    let collab = Collab::new_with_options(self.origin().clone(), options)?;
+    Ok(CollabVersionView { version, collab })
+  }
+}
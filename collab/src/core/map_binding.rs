@@ -0,0 +1,169 @@
+//! The runtime side of `#[derive(Collab)]` from collab-derive.
+//!
+//! The derive macro binds the fields of a plain struct to the keys of a [MapRef]. It generates
+//! an implementation of [MapBinding], an update builder with a `set_<field>` method per field
+//! and an `observe_<field>` observer per field, all implemented with the functions of this
+//! module. Fields are stored in one of three ways:
+//!
+//! * a value (the default): the field is serialized with serde and stored as an [yrs::Any], so it
+//!   is replaced as a whole when it's updated.
+//! * `#[collab(map)]`: the field is a struct deriving `Collab` too, stored in a nested [MapRef].
+//! * `#[collab(array)]`: the field is a `Vec` of structs deriving `Collab`, stored in a nested
+//!   [ArrayRef] of [MapRef]s.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value as JsonValue;
+use tracing::error;
+use yrs::encoding::serde::from_any;
+use yrs::types::{Event, Events, PathSegment, ToJson};
+use yrs::{
+  Any, Array, ArrayPrelim, ArrayRef, Map, MapPrelim, MapRef, Out, ReadTxn, TransactionMut,
+};
+
+use crate::error::CollabError;
+use crate::util::MapExt;
+
+/// A struct whose fields are stored in the keys of a [MapRef]. Implemented by
+/// `#[derive(Collab)]`.
+pub trait MapBinding: Sized {
+  /// Reads the struct from `map_ref`. Returns None when a field that is neither optional nor
+  /// marked with `#[collab(default)]` is missing or can't be decoded.
+  fn from_map_ref<T: ReadTxn>(txn: &T, map_ref: &MapRef) -> Option<Self>;
+
+  /// Writes every field of the struct to `map_ref`. The keys of the fields that are None are
+  /// removed.
+  fn fill_map_ref(self, txn: &mut TransactionMut, map_ref: &MapRef) -> Result<(), CollabError>;
+}
+
+pub fn read_value<T, V>(txn: &T, map_ref: &MapRef, key: &str) -> Option<V>
+where
+  T: ReadTxn,
+  V: DeserializeOwned,
+{
+  let value = map_ref.get(txn, key)?.to_json(txn);
+  from_any(&value).ok()
+}
+
+/// Writes `value` to `key`. The integers, including the enums serialized as their
+/// representation, are stored as [Any::BigInt] like the other setters of the repo do.
+pub fn write_value<V>(
+  txn: &mut TransactionMut,
+  map_ref: &MapRef,
+  key: &str,
+  value: &V,
+) -> Result<(), CollabError>
+where
+  V: Serialize + ?Sized,
+{
+  let value = json_to_any(serde_json::to_value(value)?);
+  map_ref.insert(txn, key, value);
+  Ok(())
+}
+
+fn json_to_any(value: JsonValue) -> Any {
+  match value {
+    JsonValue::Null => Any::Null,
+    JsonValue::Bool(value) => Any::Bool(value),
+    JsonValue::Number(number) => match number.as_i64() {
+      Some(value) => Any::BigInt(value),
+      None => Any::Number(number.as_f64().unwrap_or_default()),
+    },
+    JsonValue::String(value) => Any::from(value),
+    JsonValue::Array(values) => Any::from(values.into_iter().map(json_to_any).collect::<Vec<_>>()),
+    JsonValue::Object(map) => Any::from(
+      map
+        .into_iter()
+        .map(|(key, value)| (key, json_to_any(value)))
+        .collect::<HashMap<_, _>>(),
+    ),
+  }
+}
+
+pub fn read_map<T, V>(txn: &T, map_ref: &MapRef, key: &str) -> Option<V>
+where
+  T: ReadTxn,
+  V: MapBinding,
+{
+  match map_ref.get(txn, key)? {
+    Out::YMap(nested) => V::from_map_ref(txn, &nested),
+    _ => None,
+  }
+}
+
+/// Writes `value` to the nested map at `key`, creating the map if needed.
+pub fn write_map<V>(
+  txn: &mut TransactionMut,
+  map_ref: &MapRef,
+  key: &str,
+  value: V,
+) -> Result<(), CollabError>
+where
+  V: MapBinding,
+{
+  let nested = map_ref.get_or_init_map(txn, key);
+  value.fill_map_ref(txn, &nested)
+}
+
+pub fn read_array<T, V>(txn: &T, map_ref: &MapRef, key: &str) -> Option<Vec<V>>
+where
+  T: ReadTxn,
+  V: MapBinding,
+{
+  match map_ref.get(txn, key)? {
+    Out::YArray(array) => Some(
+      array
+        .iter(txn)
+        .filter_map(|value| match value {
+          Out::YMap(nested) => V::from_map_ref(txn, &nested),
+          _ => None,
+        })
+        .collect(),
+    ),
+    _ => None,
+  }
+}
+
+/// Replaces the nested array at `key` with `values`.
+pub fn write_array<V>(
+  txn: &mut TransactionMut,
+  map_ref: &MapRef,
+  key: &str,
+  values: Vec<V>,
+) -> Result<(), CollabError>
+where
+  V: MapBinding,
+{
+  let array: ArrayRef = map_ref.insert(txn, key, ArrayPrelim::default());
+  for value in values {
+    let nested = array.push_back(txn, MapPrelim::default());
+    value.fill_map_ref(txn, &nested)?;
+  }
+  Ok(())
+}
+
+pub fn remove_key(txn: &mut TransactionMut, map_ref: &MapRef, key: &str) {
+  map_ref.remove(txn, key);
+}
+
+/// Used by the update builders, whose setters can be chained and therefore don't return errors.
+pub fn log_update_error(key: &str, result: Result<(), CollabError>) {
+  if let Err(err) = result {
+    error!("Failed to update {}: {}", key, err);
+  }
+}
+
+/// Returns true if one of the `events` observed on a map changed the value at `key`, including
+/// the changes nested in that value.
+pub fn is_key_changed(txn: &TransactionMut, events: &Events, key: &str) -> bool {
+  events.iter().any(|event| match event.path().front() {
+    Some(PathSegment::Key(changed)) => &**changed == key,
+    Some(PathSegment::Index(_)) => false,
+    None => match event {
+      Event::Map(event) => event.keys(txn).contains_key(key),
+      _ => false,
+    },
+  })
+}
@@ -1,8 +1,8 @@
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 
-use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde::de::DeserializeOwned;
 use yrs::block::Prelim;
 use yrs::types::ToJson;
 use yrs::{
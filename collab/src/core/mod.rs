@@ -1,9 +1,24 @@
 pub use yrs::sync::awareness;
 pub mod collab;
+pub mod collab_attribution;
+pub mod collab_change;
+pub mod collab_compact;
+pub mod collab_diff;
+pub mod collab_migration;
+pub mod collab_permission;
 pub mod collab_plugin;
-mod collab_search;
+pub mod collab_presence;
+pub mod collab_schema;
+pub mod collab_search;
+#[cfg(feature = "simulation")]
+pub mod collab_simulation;
+pub mod collab_snapshot;
 pub mod collab_state;
+pub mod collab_stream;
+pub mod collab_undo;
+pub mod collab_version;
 pub mod fill;
+pub mod map_binding;
 pub mod origin;
 pub mod transaction;
 pub mod value;
@@ -1,8 +1,8 @@
 use crate::preclude::{CollabContext, YrsDelta};
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
-use yrs::types::text::{TextEvent, YChange};
 use yrs::types::Delta;
+use yrs::types::text::{TextEvent, YChange};
 use yrs::{In, ReadTxn, Subscription, Text, TextRef, Transaction, TransactionMut};
 pub type TextSubscriptionCallback = Arc<dyn Fn(&TransactionMut, &TextEvent)>;
 pub type TextSubscription = Subscription;
@@ -5,6 +5,8 @@ use std::fmt::{Debug, Formatter};
 use yrs::Update;
 use yrs::updates::decoder::Decode;
 
+use crate::error::EncodedCollabError;
+
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub struct EncodedCollab {
   pub state_vector: Bytes,
@@ -18,14 +20,15 @@ impl Debug for EncodedCollab {
     let doc_state = match self.version {
       EncoderVersion::V1 => Update::decode_v1(&self.doc_state),
       EncoderVersion::V2 => Update::decode_v2(&self.doc_state),
-    }
-    .map_err(|_| std::fmt::Error)?;
+    };
 
-    f.debug_struct("EncodedCollab")
-      .field("state_vector", &self.state_vector)
-      .field("doc_state", &doc_state)
-      .field("version", &self.version)
-      .finish()
+    let mut debug = f.debug_struct("EncodedCollab");
+    debug.field("state_vector", &self.state_vector);
+    match doc_state {
+      Ok(doc_state) => debug.field("doc_state", &doc_state),
+      Err(err) => debug.field("doc_state", &format!("<invalid: {}>", err)),
+    };
+    debug.field("version", &self.version).finish()
   }
 }
 
@@ -58,6 +61,111 @@ impl EncodedCollab {
     bincode::serialize(self)
   }
 
+  /// Encodes into the framed container format: a header with the object type and the
+  /// compression, an optionally compressed body and a CRC32 checksum of the whole frame.
+  pub fn encode_to_frame(
+    &self,
+    object_type: &str,
+    compression: Compression,
+  ) -> Result<Vec<u8>, EncodedCollabError> {
+    if object_type.len() > u8::MAX as usize {
+      return Err(EncodedCollabError::InvalidObjectType(format!(
+        "longer than {} bytes: {}",
+        u8::MAX,
+        object_type
+      )));
+    }
+    let mut body = Vec::with_capacity(4 + self.state_vector.len() + self.doc_state.len());
+    body.extend_from_slice(&(self.state_vector.len() as u32).to_le_bytes());
+    body.extend_from_slice(&self.state_vector);
+    body.extend_from_slice(&self.doc_state);
+    let body = compression.compress(&body)?;
+
+    let mut frame = Vec::with_capacity(FRAME_MAGIC.len() + 16 + object_type.len() + body.len());
+    frame.extend_from_slice(&FRAME_MAGIC);
+    frame.push(FRAME_VERSION);
+    frame.push(self.version.clone() as u8);
+    frame.push(compression as u8);
+    frame.push(object_type.len() as u8);
+    frame.extend_from_slice(object_type.as_bytes());
+    frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&body);
+    let checksum = crc32fast::hash(&frame[FRAME_MAGIC.len()..]);
+    frame.extend_from_slice(&checksum.to_le_bytes());
+    Ok(frame)
+  }
+
+  /// Decodes a frame written by [EncodedCollab::encode_to_frame] and returns its header.
+  pub fn decode_frame(encoded: &[u8]) -> Result<(FrameHeader, EncodedCollab), EncodedCollabError> {
+    if !is_frame(encoded) {
+      return Err(EncodedCollabError::Corrupted(
+        "missing frame magic".to_string(),
+      ));
+    }
+    if encoded.len() < FRAME_MAGIC.len() + 4 {
+      return Err(EncodedCollabError::Corrupted("truncated frame".to_string()));
+    }
+    let (content, checksum) = encoded.split_at(encoded.len() - 4);
+    let expected = u32::from_le_bytes(checksum.try_into().unwrap());
+    let found = crc32fast::hash(&content[FRAME_MAGIC.len()..]);
+    if expected != found {
+      return Err(EncodedCollabError::ChecksumMismatch { expected, found });
+    }
+
+    let mut reader = FrameReader(&content[FRAME_MAGIC.len()..]);
+    let frame_version = reader.u8()?;
+    if frame_version != FRAME_VERSION {
+      return Err(EncodedCollabError::UnsupportedFrameVersion(frame_version));
+    }
+    let version = match reader.u8()? {
+      0 => EncoderVersion::V1,
+      1 => EncoderVersion::V2,
+      version => {
+        return Err(EncodedCollabError::Corrupted(format!(
+          "unknown encoder version: {}",
+          version
+        )));
+      },
+    };
+    let compression = Compression::try_from(reader.u8()?)?;
+    let type_len = reader.u8()? as usize;
+    let object_type = String::from_utf8(reader.take(type_len)?.to_vec())
+      .map_err(|err| EncodedCollabError::InvalidObjectType(err.to_string()))?;
+    let body_len = reader.u32()? as usize;
+    let body = compression.decompress(reader.take(body_len)?, MAX_FRAME_BODY_SIZE)?;
+    if !reader.0.is_empty() {
+      return Err(EncodedCollabError::Corrupted(
+        "trailing bytes after the body".to_string(),
+      ));
+    }
+
+    let mut reader = FrameReader(&body);
+    let state_vector_len = reader.u32()? as usize;
+    let state_vector = Bytes::copy_from_slice(reader.take(state_vector_len)?);
+    let doc_state = Bytes::copy_from_slice(reader.0);
+    let header = FrameHeader {
+      object_type,
+      compression,
+    };
+    let encoded_collab = EncodedCollab {
+      state_vector,
+      doc_state,
+      version,
+    };
+    Ok((header, encoded_collab))
+  }
+
+  /// Decodes the framed container format, or the legacy bincode formats handled by
+  /// [EncodedCollab::decode_from_bytes].
+  pub fn decode_from_frame_or_bytes(encoded: &[u8]) -> Result<EncodedCollab, EncodedCollabError> {
+    if is_frame(encoded) {
+      return Self::decode_frame(encoded).map(|(_, encoded_collab)| encoded_collab);
+    }
+    Ok(Self::decode_from_bytes(encoded)?)
+  }
+
+  /// Decodes the legacy bincode formats written by [EncodedCollab::encode_to_bytes] and
+  /// [EncodedCollabV0]. Use [EncodedCollab::decode_from_frame_or_bytes] to decode frames too.
   pub fn decode_from_bytes(encoded: &[u8]) -> Result<EncodedCollab, bincode::Error> {
     // The deserialize_encoded_collab function first tries to deserialize the data as EncodedCollab.
     // If it fails (presumably because the data was serialized with EncodedCollabV0), it then tries to deserialize as EncodedCollabV0.
@@ -77,6 +185,129 @@ impl EncodedCollab {
   }
 }
 
+/// Starts every frame. The legacy formats start with the length of the state vector as a
+/// little-endian u64, which can't have a non-zero most significant byte, so the formats can't be
+/// confused.
+const FRAME_MAGIC: [u8; 8] = *b"CLBFRAME";
+const FRAME_VERSION: u8 = 1;
+/// The maximum size of a decompressed body, so a corrupted or malicious frame can't exhaust the
+/// memory.
+pub const MAX_FRAME_BODY_SIZE: usize = 512 * 1024 * 1024;
+
+fn is_frame(encoded: &[u8]) -> bool {
+  encoded.starts_with(&FRAME_MAGIC)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FrameHeader {
+  /// The type of the encoded object, e.g. `CollabType::Document` as a string.
+  pub object_type: String,
+  pub compression: Compression,
+}
+
+/// The compression of the body of a frame. [Compression::Zstd] and [Compression::Lz4] require
+/// the `zstd` and `lz4` features.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+#[repr(u8)]
+pub enum Compression {
+  #[default]
+  None = 0,
+  Zstd = 1,
+  Lz4 = 2,
+}
+
+impl TryFrom<u8> for Compression {
+  type Error = EncodedCollabError;
+
+  fn try_from(value: u8) -> Result<Self, Self::Error> {
+    match value {
+      0 => Ok(Compression::None),
+      1 => Ok(Compression::Zstd),
+      2 => Ok(Compression::Lz4),
+      _ => Err(EncodedCollabError::Corrupted(format!(
+        "unknown compression: {}",
+        value
+      ))),
+    }
+  }
+}
+
+impl Compression {
+  fn compress(self, data: &[u8]) -> Result<Vec<u8>, EncodedCollabError> {
+    match self {
+      Compression::None => Ok(data.to_vec()),
+      #[cfg(feature = "zstd")]
+      Compression::Zstd => Ok(zstd::bulk::compress(data, 3)?),
+      #[cfg(feature = "lz4")]
+      Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(data)),
+      #[allow(unreachable_patterns)]
+      _ => Err(EncodedCollabError::UnsupportedCompression(format!(
+        "{:?}",
+        self
+      ))),
+    }
+  }
+
+  #[cfg_attr(not(any(feature = "zstd", feature = "lz4")), allow(unused_variables))]
+  fn decompress(self, data: &[u8], max_size: usize) -> Result<Vec<u8>, EncodedCollabError> {
+    match self {
+      Compression::None => Ok(data.to_vec()),
+      #[cfg(feature = "zstd")]
+      Compression::Zstd => {
+        use std::io::Read;
+        let mut body = vec![];
+        zstd::stream::read::Decoder::new(data)?
+          .take(max_size as u64 + 1)
+          .read_to_end(&mut body)
+          .map_err(|err| EncodedCollabError::Corrupted(err.to_string()))?;
+        if body.len() > max_size {
+          return Err(EncodedCollabError::BodyTooLarge { max: max_size });
+        }
+        Ok(body)
+      },
+      #[cfg(feature = "lz4")]
+      Compression::Lz4 => {
+        // The decompressed size is prepended, check it before it's allocated
+        let size = data
+          .get(..4)
+          .map(|size| u32::from_le_bytes(size.try_into().unwrap()) as usize)
+          .ok_or_else(|| EncodedCollabError::Corrupted("truncated frame".to_string()))?;
+        if size > max_size {
+          return Err(EncodedCollabError::BodyTooLarge { max: max_size });
+        }
+        lz4_flex::decompress_size_prepended(data)
+          .map_err(|err| EncodedCollabError::Corrupted(err.to_string()))
+      },
+      #[allow(unreachable_patterns)]
+      _ => Err(EncodedCollabError::UnsupportedCompression(format!(
+        "{:?}",
+        self
+      ))),
+    }
+  }
+}
+
+struct FrameReader<'a>(&'a [u8]);
+
+impl<'a> FrameReader<'a> {
+  fn take(&mut self, len: usize) -> Result<&'a [u8], EncodedCollabError> {
+    if self.0.len() < len {
+      return Err(EncodedCollabError::Corrupted("truncated frame".to_string()));
+    }
+    let (head, tail) = self.0.split_at(len);
+    self.0 = tail;
+    Ok(head)
+  }
+
+  fn u8(&mut self) -> Result<u8, EncodedCollabError> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn u32(&mut self) -> Result<u32, EncodedCollabError> {
+    Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+  }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct EncodedCollabV0 {
   pub state_vector: Bytes,
@@ -86,6 +317,8 @@ pub struct EncodedCollabV0 {
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::core::transaction::DocTransactionExtension;
+  use yrs::{Text, Transact};
   #[test]
   fn old_encoded_collab_decoded_into_new_encoded_collab() {
     let old_encoded_collab = EncodedCollabV0 {
@@ -124,4 +357,100 @@ mod tests {
       new_encoded_collab.state_vector
     );
   }
+
+  fn encoded_collab() -> EncodedCollab {
+    let doc = yrs::Doc::new();
+    let text = doc.get_or_insert_text("text");
+    text.insert(&mut doc.transact_mut(), 0, &"hello world ".repeat(100));
+    doc.transact().get_encoded_collab_v1()
+  }
+
+  #[test]
+  fn framed_encoded_collab_round_trip() {
+    let encoded_collab = encoded_collab();
+    let frame = encoded_collab
+      .encode_to_frame("Document", Compression::None)
+      .unwrap();
+    let (header, decoded) = EncodedCollab::decode_frame(&frame).unwrap();
+    assert_eq!(header.object_type, "Document");
+    assert_eq!(header.compression, Compression::None);
+    assert_eq!(decoded, encoded_collab);
+    assert_eq!(
+      EncodedCollab::decode_from_frame_or_bytes(&frame).unwrap(),
+      encoded_collab
+    );
+
+    // The legacy format is still decoded
+    let legacy = encoded_collab.encode_to_bytes().unwrap();
+    assert_eq!(
+      EncodedCollab::decode_from_frame_or_bytes(&legacy).unwrap(),
+      encoded_collab
+    );
+    assert_eq!(
+      EncodedCollab::decode_from_bytes(&legacy).unwrap(),
+      encoded_collab
+    );
+  }
+
+  #[cfg(feature = "zstd")]
+  #[test]
+  fn zstd_framed_encoded_collab_round_trip() {
+    let encoded_collab = encoded_collab();
+    let frame = encoded_collab
+      .encode_to_frame("Document", Compression::Zstd)
+      .unwrap();
+    assert!(frame.len() < encoded_collab.doc_state.len());
+    let decoded = EncodedCollab::decode_from_frame_or_bytes(&frame).unwrap();
+    assert_eq!(decoded, encoded_collab);
+  }
+
+  #[cfg(feature = "zstd")]
+  #[test]
+  fn zstd_decompressed_size_is_capped() {
+    let body = zstd::bulk::compress(&[0; 4096], 3).unwrap();
+    assert!(matches!(
+      Compression::Zstd.decompress(&body, 1024),
+      Err(EncodedCollabError::BodyTooLarge { max: 1024 })
+    ));
+    assert_eq!(
+      Compression::Zstd.decompress(&body, 4096).unwrap().len(),
+      4096
+    );
+  }
+
+  #[cfg(feature = "lz4")]
+  #[test]
+  fn lz4_framed_encoded_collab_round_trip() {
+    let encoded_collab = encoded_collab();
+    let frame = encoded_collab
+      .encode_to_frame("Document", Compression::Lz4)
+      .unwrap();
+    assert!(frame.len() < encoded_collab.doc_state.len());
+    let decoded = EncodedCollab::decode_from_frame_or_bytes(&frame).unwrap();
+    assert_eq!(decoded, encoded_collab);
+  }
+
+  #[test]
+  fn corrupted_frame_is_rejected() {
+    let frame = encoded_collab()
+      .encode_to_frame("Document", Compression::None)
+      .unwrap();
+
+    let mut corrupted = frame.clone();
+    let last = corrupted.len() - 10;
+    corrupted[last] ^= 0xff;
+    assert!(matches!(
+      EncodedCollab::decode_from_frame_or_bytes(&corrupted),
+      Err(EncodedCollabError::ChecksumMismatch { .. })
+    ));
+
+    assert!(matches!(
+      EncodedCollab::decode_from_frame_or_bytes(&frame[..frame.len() / 2]),
+      Err(EncodedCollabError::ChecksumMismatch { .. })
+    ));
+    assert!(matches!(
+      EncodedCollab::decode_from_frame_or_bytes(&frame[..10]),
+      Err(EncodedCollabError::Corrupted(_))
+    ));
+  }
 }
@@ -1,6 +1,8 @@
 use anyhow::anyhow;
 use yrs::TransactionAcqError;
 
+use crate::core::collab_schema::display_violations;
+
 #[derive(Debug, thiserror::Error)]
 pub enum CollabError {
   #[error(transparent)]
@@ -24,9 +26,66 @@ pub enum CollabError {
   #[error("UndoManager is not enabled")]
   UndoManagerNotEnabled,
 
+  #[error("Undo scope not found: {0}")]
+  UndoScopeNotFound(String),
+
+  #[error("Undo scope path must point to a map, an array or a text: {0}")]
+  UndoScopePathNotFound(String),
+
+  #[error("Search is not enabled")]
+  SearchNotEnabled,
+
+  #[error("Presence is not enabled")]
+  PresenceNotEnabled,
+
+  #[error("Version storage is not set")]
+  VersionStorageNotSet,
+
+  #[error("Versions require a collab created with skip_gc")]
+  VersionRequiresSkipGc,
+
+  #[error("Version not found: {0}")]
+  VersionNotFound(String),
+
+  #[error("Can't compact {0}, it has named versions")]
+  CompactionWithVersions(String),
+
+  #[error("Diff since a state vector requires a collab created with skip_gc")]
+  DiffRequiresSkipGc,
+
+  #[error("Invalid migration: {0}")]
+  InvalidMigration(String),
+
+  #[error("Transaction rejected: {0}")]
+  TransactionRejected(String),
+
+  #[error("Permission denied: {0}")]
+  PermissionDenied(String),
+
+  #[error("Attribution requires a collab with a client origin")]
+  AttributionRequiresClientOrigin,
+
+  #[error("Schema validation failed: {}", display_violations(.0))]
+  SchemaViolations(Vec<crate::core::collab_schema::SchemaViolation>),
+
   #[error(transparent)]
   DecodeUpdate(#[from] yrs::encoding::read::Error),
 
+  #[error("Async plugins must be created within a tokio runtime")]
+  NoTokioRuntime,
+
+  #[error("Invalid async plugin: {0}")]
+  InvalidAsyncPlugin(String),
+
+  #[error("Invalid read snapshot: {0}")]
+  InvalidReadSnapshot(String),
+
+  #[error("Invalid doc state stream: {0}")]
+  InvalidDocStateStream(String),
+
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+
   #[error("{0}")]
   NoRequiredData(String),
 
@@ -40,6 +99,35 @@ pub enum CollabError {
   Internal(#[from] anyhow::Error),
 }
 
+/// The errors of [crate::entity::EncodedCollab::decode_from_frame_or_bytes] and the framed
+/// container format.
+#[derive(Debug, thiserror::Error)]
+pub enum EncodedCollabError {
+  #[error("Encoded collab is corrupted: {0}")]
+  Corrupted(String),
+
+  #[error("Checksum mismatch, expected:{expected:#010x}, found:{found:#010x}")]
+  ChecksumMismatch { expected: u32, found: u32 },
+
+  #[error("Invalid object type: {0}")]
+  InvalidObjectType(String),
+
+  #[error("Unsupported frame version: {0}")]
+  UnsupportedFrameVersion(u8),
+
+  #[error("Unsupported compression: {0}")]
+  UnsupportedCompression(String),
+
+  #[error("Decompressed body is larger than {max} bytes")]
+  BodyTooLarge { max: usize },
+
+  #[error(transparent)]
+  Bincode(#[from] bincode::Error),
+
+  #[error(transparent)]
+  Io(#[from] std::io::Error),
+}
+
 impl From<TransactionAcqError> for CollabError {
   fn from(value: TransactionAcqError) -> Self {
     match value {
@@ -0,0 +1,583 @@
+//! Locks that record who holds them, enabled by the `lock_diagnostics` feature.
+//!
+//! Every acquisition of a [RwLock] or a [Mutex] is recorded in a process wide registry together
+//! with the holder, the time spent waiting for the lock and the backtrace of the acquisition.
+//! [snapshot] lists the locks that are currently held or waited for, which is usually enough to
+//! find the tasks involved in a deadlock.
+//!
+//! Locks are grouped in classes, the type name of the locked value by default, e.g.
+//! `collab::core::collab::Collab`. When a holder acquires a lock of class `B` while it holds a
+//! lock of class `A`, the order `A -> B` is recorded. Acquiring them in the opposite order later
+//! on is a lock-order inversion: two holders doing so concurrently can deadlock. Inversions are
+//! detected before waiting for the lock, so they are reported even when the deadlock doesn't
+//! happen, see [lock_inversions] and [assert_no_lock_inversions].
+//!
+//! The holder is the task running inside [instrument] or [spawn], or the current thread
+//! otherwise. Tasks of a multi-threaded runtime move between threads, so tasks that acquire
+//! several locks must be instrumented for the holders to be accurate.
+//!
+//! Backtraces are only captured when `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` is set, see
+//! [Backtrace::capture].
+
+use std::backtrace::Backtrace;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, warn};
+
+static REGISTRY: LazyLock<std::sync::Mutex<Registry>> = LazyLock::new(Default::default);
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+tokio::task_local! {
+  static TASK_HOLDER: u64;
+}
+
+fn next_id() -> u64 {
+  NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+fn registry() -> std::sync::MutexGuard<'static, Registry> {
+  // A panic while the registry is locked doesn't leave it in an inconsistent state.
+  REGISTRY.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Runs `future` as a distinct lock holder.
+pub async fn instrument<F: Future>(future: F) -> F::Output {
+  TASK_HOLDER.scope(next_id(), future).await
+}
+
+/// Spawns `future` on the current runtime as a distinct lock holder, see [instrument].
+pub fn spawn<F>(future: F) -> tokio::task::JoinHandle<F::Output>
+where
+  F: Future + Send + 'static,
+  F::Output: Send + 'static,
+{
+  tokio::spawn(instrument(future))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HolderId {
+  /// A task running inside [instrument].
+  Task(u64),
+  Thread(String),
+}
+
+impl HolderId {
+  pub fn current() -> Self {
+    match TASK_HOLDER.try_with(|id| *id) {
+      Ok(id) => HolderId::Task(id),
+      Err(_) => {
+        let thread = std::thread::current();
+        match thread.name() {
+          Some(name) => HolderId::Thread(format!("{} ({:?})", name, thread.id())),
+          None => HolderId::Thread(format!("{:?}", thread.id())),
+        }
+      },
+    }
+  }
+}
+
+impl Display for HolderId {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      HolderId::Task(id) => write!(f, "task {}", id),
+      HolderId::Thread(thread) => write!(f, "thread {}", thread),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+  Read,
+  Write,
+}
+
+#[derive(Debug, Clone)]
+pub struct LockSnapshot {
+  pub lock_id: u64,
+  pub class: &'static str,
+  pub holders: Vec<HolderSnapshot>,
+  pub waiters: Vec<WaiterSnapshot>,
+}
+
+impl LockSnapshot {
+  pub fn is_contended(&self) -> bool {
+    !self.waiters.is_empty()
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct HolderSnapshot {
+  pub holder: HolderId,
+  pub mode: LockMode,
+  pub held_for: Duration,
+  /// The time the holder waited before acquiring the lock.
+  pub waited: Duration,
+  pub backtrace: Arc<Backtrace>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WaiterSnapshot {
+  pub holder: HolderId,
+  pub mode: LockMode,
+  pub waiting_for: Duration,
+  pub backtrace: Arc<Backtrace>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WaitStats {
+  pub acquisitions: u64,
+  pub total_wait: Duration,
+  pub max_wait: Duration,
+}
+
+/// A lock of class `second` was acquired while holding a lock of class `first`, after the
+/// opposite order had been recorded.
+#[derive(Debug, Clone)]
+pub struct LockInversion {
+  pub first: &'static str,
+  pub second: &'static str,
+  pub holder: HolderId,
+  pub backtrace: Arc<Backtrace>,
+  /// The backtrace of the acquisition that recorded the order `second -> first`.
+  pub established_by: Arc<Backtrace>,
+}
+
+impl Display for LockInversion {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(
+      f,
+      "{} acquired {} while holding {}, but {} was previously acquired while holding {}",
+      self.holder, self.second, self.first, self.first, self.second
+    )
+  }
+}
+
+/// Returns the locks that are currently held or waited for.
+pub fn snapshot() -> Vec<LockSnapshot> {
+  let registry = registry();
+  let now = Instant::now();
+  let mut locks = registry
+    .locks
+    .iter()
+    .map(|(lock_id, entry)| LockSnapshot {
+      lock_id: *lock_id,
+      class: entry.class,
+      holders: entry
+        .holders
+        .iter()
+        .map(|holder| HolderSnapshot {
+          holder: holder.holder.clone(),
+          mode: holder.mode,
+          held_for: now.saturating_duration_since(holder.since),
+          waited: holder.waited,
+          backtrace: holder.backtrace.clone(),
+        })
+        .collect(),
+      waiters: entry
+        .waiters
+        .iter()
+        .map(|waiter| WaiterSnapshot {
+          holder: waiter.holder.clone(),
+          mode: waiter.mode,
+          waiting_for: now.saturating_duration_since(waiter.since),
+          backtrace: waiter.backtrace.clone(),
+        })
+        .collect(),
+    })
+    .collect::<Vec<_>>();
+  locks.sort_by_key(|lock| lock.lock_id);
+  locks
+}
+
+/// Returns the wait statistics of every lock class acquired so far.
+pub fn wait_stats() -> HashMap<&'static str, WaitStats> {
+  registry().wait_stats.clone()
+}
+
+/// Returns the lock-order inversions detected so far. Each pair of classes is reported once.
+pub fn lock_inversions() -> Vec<LockInversion> {
+  registry().inversions.clone()
+}
+
+/// Panics if a lock-order inversion was detected.
+pub fn assert_no_lock_inversions() {
+  let inversions = lock_inversions();
+  if !inversions.is_empty() {
+    let details = inversions
+      .iter()
+      .map(|inversion| format!("{}\n{}", inversion, inversion.backtrace))
+      .collect::<Vec<_>>()
+      .join("\n");
+    panic!("lock-order inversions detected:\n{}", details);
+  }
+}
+
+/// Forgets the recorded lock orders, inversions and wait statistics. The locks that are
+/// currently held are kept.
+pub fn reset() {
+  let mut registry = registry();
+  registry.orders.clear();
+  registry.inversions.clear();
+  registry.wait_stats.clear();
+}
+
+#[derive(Default)]
+struct Registry {
+  /// The locks that are held or waited for.
+  locks: HashMap<u64, LockEntry>,
+  /// The first acquisition of each order between two lock classes.
+  orders: HashMap<(&'static str, &'static str), Arc<Backtrace>>,
+  inversions: Vec<LockInversion>,
+  wait_stats: HashMap<&'static str, WaitStats>,
+}
+
+struct LockEntry {
+  class: &'static str,
+  holders: Vec<Acquisition>,
+  waiters: Vec<Acquisition>,
+}
+
+struct Acquisition {
+  token: u64,
+  holder: HolderId,
+  mode: LockMode,
+  since: Instant,
+  waited: Duration,
+  backtrace: Arc<Backtrace>,
+}
+
+impl Registry {
+  fn entry(&mut self, lock: &LockInfo) -> &mut LockEntry {
+    self.locks.entry(lock.id).or_insert_with(|| LockEntry {
+      class: lock.class,
+      holders: vec![],
+      waiters: vec![],
+    })
+  }
+
+  fn remove_if_unused(&mut self, lock_id: u64) {
+    if let Some(entry) = self.locks.get(&lock_id) {
+      if entry.holders.is_empty() && entry.waiters.is_empty() {
+        self.locks.remove(&lock_id);
+      }
+    }
+  }
+
+  /// Records the order between the classes held by `holder` and `class`.
+  fn record_order(&mut self, holder: &HolderId, class: &'static str, backtrace: &Arc<Backtrace>) {
+    let mut held_classes = self
+      .locks
+      .values()
+      .filter(|entry| entry.class != class)
+      .filter(|entry| entry.holders.iter().any(|h| &h.holder == holder))
+      .map(|entry| entry.class)
+      .collect::<Vec<_>>();
+    held_classes.sort_unstable();
+    held_classes.dedup();
+
+    for held in held_classes {
+      if let Some(established_by) = self.orders.get(&(class, held)) {
+        let reported = self
+          .inversions
+          .iter()
+          .any(|i| (i.first, i.second) == (held, class) || (i.first, i.second) == (class, held));
+        if !reported {
+          let inversion = LockInversion {
+            first: held,
+            second: class,
+            holder: holder.clone(),
+            backtrace: backtrace.clone(),
+            established_by: established_by.clone(),
+          };
+          warn!("lock-order inversion: {}", inversion);
+          self.inversions.push(inversion);
+        }
+      } else {
+        self
+          .orders
+          .entry((held, class))
+          .or_insert_with(|| backtrace.clone());
+      }
+    }
+  }
+}
+
+#[derive(Debug)]
+struct LockInfo {
+  id: u64,
+  class: &'static str,
+}
+
+impl LockInfo {
+  fn new(class: &'static str) -> Self {
+    Self {
+      id: next_id(),
+      class,
+    }
+  }
+
+  async fn acquire<G>(&self, mode: LockMode, acquire: impl Future<Output = G>) -> (G, Held) {
+    let holder = HolderId::current();
+    let backtrace = Arc::new(Backtrace::capture());
+    let token = next_id();
+    let since = Instant::now();
+    {
+      let mut registry = registry();
+      registry.record_order(&holder, self.class, &backtrace);
+      registry.entry(self).waiters.push(Acquisition {
+        token,
+        holder: holder.clone(),
+        mode,
+        since,
+        waited: Duration::ZERO,
+        backtrace: backtrace.clone(),
+      });
+    }
+    // Removes the waiter when the acquisition is cancelled.
+    let waiting = Waiting {
+      lock_id: self.id,
+      token,
+    };
+    let guard = acquire.await;
+    let waited = since.elapsed();
+    std::mem::forget(waiting);
+
+    let mut registry = registry();
+    let entry = registry.entry(self);
+    entry.waiters.retain(|waiter| waiter.token != token);
+    entry.holders.push(Acquisition {
+      token,
+      holder,
+      mode,
+      since: Instant::now(),
+      waited,
+      backtrace,
+    });
+    let stats = registry.wait_stats.entry(self.class).or_default();
+    stats.acquisitions += 1;
+    stats.total_wait += waited;
+    stats.max_wait = stats.max_wait.max(waited);
+    drop(registry);
+
+    let held = Held {
+      lock_id: self.id,
+      token,
+    };
+    (guard, held)
+  }
+}
+
+struct Waiting {
+  lock_id: u64,
+  token: u64,
+}
+
+impl Drop for Waiting {
+  fn drop(&mut self) {
+    let mut registry = registry();
+    if let Some(entry) = registry.locks.get_mut(&self.lock_id) {
+      entry.waiters.retain(|waiter| waiter.token != self.token);
+    }
+    registry.remove_if_unused(self.lock_id);
+  }
+}
+
+/// Removes the holder from the registry when the guard is dropped.
+struct Held {
+  lock_id: u64,
+  token: u64,
+}
+
+impl Drop for Held {
+  fn drop(&mut self) {
+    let mut registry = registry();
+    if let Some(entry) = registry.locks.get_mut(&self.lock_id) {
+      entry.holders.retain(|holder| holder.token != self.token);
+    }
+    registry.remove_if_unused(self.lock_id);
+  }
+}
+
+#[derive(Debug)]
+pub struct RwLock<T: ?Sized> {
+  info: LockInfo,
+  inner: tokio::sync::RwLock<T>,
+}
+
+impl<T: ?Sized> RwLock<T> {
+  pub fn new(inner: T) -> Self
+  where
+    T: Sized,
+  {
+    Self::with_class(inner, std::any::type_name::<T>())
+  }
+
+  /// Creates a lock whose order is tracked under `class` instead of the type name of `T`.
+  pub fn with_class(inner: T, class: &'static str) -> Self
+  where
+    T: Sized,
+  {
+    Self {
+      info: LockInfo::new(class),
+      inner: tokio::sync::RwLock::new(inner),
+    }
+  }
+
+  pub fn class(&self) -> &'static str {
+    self.info.class
+  }
+
+  pub async fn read(&self) -> RwLockReadGuard<'_, T> {
+    let (inner, held) = self.info.acquire(LockMode::Read, self.inner.read()).await;
+    RwLockReadGuard { inner, _held: held }
+  }
+
+  pub async fn write(&self) -> RwLockWriteGuard<'_, T> {
+    let (inner, held) = self.info.acquire(LockMode::Write, self.inner.write()).await;
+    RwLockWriteGuard { inner, _held: held }
+  }
+
+  pub async fn write_with_reason(&self, reason: &str) -> RwLockWriteGuard<'_, T> {
+    debug!("Acquiring write lock for reason: {}", reason);
+    self.write().await
+  }
+}
+
+impl<T: ?Sized> Deref for RwLock<T> {
+  type Target = tokio::sync::RwLock<T>;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.inner
+  }
+}
+
+impl<T> From<T> for RwLock<T> {
+  fn from(value: T) -> Self {
+    Self::new(value)
+  }
+}
+
+impl<T: Default> Default for RwLock<T> {
+  fn default() -> Self {
+    Self::new(T::default())
+  }
+}
+
+pub struct RwLockReadGuard<'a, T: ?Sized> {
+  inner: tokio::sync::RwLockReadGuard<'a, T>,
+  _held: Held,
+}
+
+impl<T: ?Sized> Deref for RwLockReadGuard<'_, T> {
+  type Target = T;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.inner
+  }
+}
+
+pub struct RwLockWriteGuard<'a, T: ?Sized> {
+  inner: tokio::sync::RwLockWriteGuard<'a, T>,
+  _held: Held,
+}
+
+impl<T: ?Sized> Deref for RwLockWriteGuard<'_, T> {
+  type Target = T;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.inner
+  }
+}
+
+impl<T: ?Sized> DerefMut for RwLockWriteGuard<'_, T> {
+  #[inline]
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.inner
+  }
+}
+
+#[derive(Debug)]
+pub struct Mutex<T: ?Sized> {
+  info: LockInfo,
+  inner: tokio::sync::Mutex<T>,
+}
+
+impl<T: ?Sized> Mutex<T> {
+  pub fn new(inner: T) -> Self
+  where
+    T: Sized,
+  {
+    Self::with_class(inner, std::any::type_name::<T>())
+  }
+
+  /// Creates a lock whose order is tracked under `class` instead of the type name of `T`.
+  pub fn with_class(inner: T, class: &'static str) -> Self
+  where
+    T: Sized,
+  {
+    Self {
+      info: LockInfo::new(class),
+      inner: tokio::sync::Mutex::new(inner),
+    }
+  }
+
+  pub fn class(&self) -> &'static str {
+    self.info.class
+  }
+
+  pub async fn lock(&self) -> MutexGuard<'_, T> {
+    let (inner, held) = self.info.acquire(LockMode::Write, self.inner.lock()).await;
+    MutexGuard { inner, _held: held }
+  }
+}
+
+impl<T: ?Sized> Deref for Mutex<T> {
+  type Target = tokio::sync::Mutex<T>;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.inner
+  }
+}
+
+impl<T> From<T> for Mutex<T> {
+  fn from(value: T) -> Self {
+    Self::new(value)
+  }
+}
+
+impl<T: Default> Default for Mutex<T> {
+  fn default() -> Self {
+    Self::new(T::default())
+  }
+}
+
+pub struct MutexGuard<'a, T: ?Sized> {
+  inner: tokio::sync::MutexGuard<'a, T>,
+  _held: Held,
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+  type Target = T;
+
+  #[inline]
+  fn deref(&self) -> &Self::Target {
+    &self.inner
+  }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+  #[inline]
+  fn deref_mut(&mut self) -> &mut Self::Target {
+    &mut self.inner
+  }
+}
@@ -1,15 +1,23 @@
-#[cfg(not(feature = "lock_timeout"))]
+#[cfg(not(any(feature = "lock_timeout", feature = "lock_diagnostics")))]
 mod rwlock;
 
-#[cfg(not(feature = "lock_timeout"))]
+#[cfg(not(any(feature = "lock_timeout", feature = "lock_diagnostics")))]
 pub type Mutex<T> = tokio::sync::Mutex<T>;
-#[cfg(not(feature = "lock_timeout"))]
+#[cfg(not(any(feature = "lock_timeout", feature = "lock_diagnostics")))]
 pub type RwLock<T> = rwlock::RwLock<T>;
 
-#[cfg(feature = "lock_timeout")]
+#[cfg(all(feature = "lock_timeout", not(feature = "lock_diagnostics")))]
 mod lock_timeout;
 
-#[cfg(feature = "lock_timeout")]
+#[cfg(all(feature = "lock_timeout", not(feature = "lock_diagnostics")))]
 pub use lock_timeout::Mutex;
-#[cfg(feature = "lock_timeout")]
+#[cfg(all(feature = "lock_timeout", not(feature = "lock_diagnostics")))]
 pub use lock_timeout::RwLock;
+
+#[cfg(feature = "lock_diagnostics")]
+pub mod diagnostics;
+
+#[cfg(feature = "lock_diagnostics")]
+pub use diagnostics::Mutex;
+#[cfg(feature = "lock_diagnostics")]
+pub use diagnostics::RwLock;
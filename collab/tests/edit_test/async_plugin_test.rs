@@ -0,0 +1,315 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use collab::core::collab::default_client_id;
+use collab::core::collab_plugin::{
+  AsyncCollabPlugin, AsyncPluginAdapter, AsyncPluginConfig, BackpressurePolicy, CollabPluginType,
+};
+use collab::error::CollabError;
+use collab::preclude::{Collab, CollabPlugin};
+use serde_json::json;
+use yrs::types::ToJson;
+use yrs::updates::decoder::Decode;
+use yrs::{Doc, Map, Transact, TransactionMut, Update};
+
+/// Applies the received updates to its own document and records the number of keys after each
+/// update. yrs keeps an out-of-order update pending, so the counts show the delivery order.
+#[derive(Clone, Default)]
+struct ReplicaPlugin {
+  doc: Doc,
+  key_counts: Arc<Mutex<Vec<u32>>>,
+  flushed: Arc<AtomicBool>,
+}
+
+impl ReplicaPlugin {
+  fn to_json_value(&self) -> serde_json::Value {
+    let data = self.doc.get_or_insert_map("data");
+    serde_json::to_value(data.to_json(&self.doc.transact())).unwrap()
+  }
+}
+
+#[async_trait]
+impl AsyncCollabPlugin for ReplicaPlugin {
+  async fn receive_update(&self, _object_id: &str, update: Bytes) {
+    let data = self.doc.get_or_insert_map("data");
+    let mut txn = self.doc.transact_mut();
+    txn
+      .apply_update(Update::decode_v1(&update).unwrap())
+      .unwrap();
+    self.key_counts.lock().unwrap().push(data.len(&txn));
+  }
+
+  async fn flush(&self) {
+    self.flushed.store(true, Ordering::SeqCst);
+  }
+
+  // Only observes the delivery order
+  fn tolerates_missing_updates(&self) -> bool {
+    true
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::Other("ReplicaPlugin".to_string())
+  }
+}
+
+/// Applies the received updates to one document per object.
+#[derive(Clone, Default)]
+struct ObjectsReplicaPlugin {
+  docs: Arc<Mutex<HashMap<String, Doc>>>,
+}
+
+impl ObjectsReplicaPlugin {
+  fn to_json_value(&self, object_id: &str) -> serde_json::Value {
+    let docs = self.docs.lock().unwrap();
+    let doc = &docs[object_id];
+    let data = doc.get_or_insert_map("data");
+    serde_json::to_value(data.to_json(&doc.transact())).unwrap()
+  }
+}
+
+#[async_trait]
+impl AsyncCollabPlugin for ObjectsReplicaPlugin {
+  async fn receive_update(&self, object_id: &str, update: Bytes) {
+    let mut docs = self.docs.lock().unwrap();
+    let doc = docs.entry(object_id.to_string()).or_default();
+    doc
+      .transact_mut()
+      .apply_update(Update::decode_v1(&update).unwrap())
+      .unwrap();
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::Other("ObjectsReplicaPlugin".to_string())
+  }
+}
+
+/// Adds the same adapter to several collabs.
+#[derive(Clone)]
+struct SharedAdapter(Arc<AsyncPluginAdapter>);
+
+impl CollabPlugin for SharedAdapter {
+  fn receive_update(&self, object_id: &str, txn: &TransactionMut, update: &[u8]) {
+    self.0.receive_update(object_id, txn, update);
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    self.0.plugin_type()
+  }
+
+  fn destroy(&self) {
+    self.0.destroy();
+  }
+}
+
+#[tokio::test]
+async fn async_plugin_coalesce_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let replica = ReplicaPlugin::default();
+  let adapter = AsyncPluginAdapter::new(
+    replica.clone(),
+    AsyncPluginConfig::new(2, BackpressurePolicy::Coalesce),
+  )
+  .unwrap();
+  let handle = adapter.handle();
+  collab.add_plugin(Box::new(adapter));
+  collab.initialize();
+
+  // The plugin task doesn't run before the test yields, so the queue fills up.
+  for i in 0..10 {
+    collab.insert(&i.to_string(), i);
+  }
+  assert!(handle.pending() <= 2);
+  assert_eq!(handle.dropped(), 0);
+
+  collab.remove_all_plugins();
+  handle.flushed().await;
+  assert!(replica.flushed.load(Ordering::SeqCst));
+  assert_eq!(replica.to_json_value(), collab.to_json_value());
+  assert_eq!(replica.key_counts.lock().unwrap().last(), Some(&10));
+}
+
+#[tokio::test]
+async fn async_plugin_coalesce_resync_test() {
+  let replica = ObjectsReplicaPlugin::default();
+  let adapter = AsyncPluginAdapter::new(
+    replica.clone(),
+    AsyncPluginConfig::new(2, BackpressurePolicy::Coalesce),
+  )
+  .unwrap();
+  let handle = adapter.handle();
+  let adapter = SharedAdapter(Arc::new(adapter));
+  let mut collabs = ["a", "b"].map(|object_id| {
+    let mut collab = Collab::new(1, object_id, "1", default_client_id());
+    collab.add_plugin(Box::new(adapter.clone()));
+    collab.initialize();
+    collab
+  });
+
+  // The updates of the two objects alternate, they can't be merged.
+  for i in 0..10 {
+    for collab in collabs.iter_mut() {
+      collab.insert(&i.to_string(), i);
+    }
+  }
+  assert!(handle.pending() <= 2);
+  assert!(handle.resynced() > 0);
+  assert_eq!(handle.dropped(), 0);
+
+  adapter.destroy();
+  handle.flushed().await;
+  for collab in collabs.iter() {
+    assert_eq!(
+      replica.to_json_value(collab.object_id()),
+      collab.to_json_value()
+    );
+  }
+}
+
+#[tokio::test]
+async fn async_plugin_drop_oldest_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let replica = ReplicaPlugin::default();
+  let adapter = AsyncPluginAdapter::new(
+    replica.clone(),
+    AsyncPluginConfig::new(2, BackpressurePolicy::DropOldest),
+  )
+  .unwrap();
+  let handle = adapter.handle();
+  collab.add_plugin(Box::new(adapter));
+  collab.initialize();
+
+  for i in 0..5 {
+    collab.insert(&i.to_string(), i);
+  }
+  assert_eq!(handle.pending(), 2);
+  assert_eq!(handle.dropped(), 3);
+
+  collab.remove_all_plugins();
+  handle.flushed().await;
+  // The remaining updates depend on the dropped ones, the replica can't apply them.
+  assert_eq!(*replica.key_counts.lock().unwrap(), vec![0, 0]);
+  assert_eq!(replica.to_json_value(), json!({}));
+}
+
+#[tokio::test]
+async fn async_plugin_block_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let replica = ReplicaPlugin::default();
+  let adapter = AsyncPluginAdapter::new(
+    replica.clone(),
+    AsyncPluginConfig::new(1, BackpressurePolicy::Block),
+  )
+  .unwrap();
+  let handle = adapter.handle();
+  collab.add_plugin(Box::new(adapter));
+  collab.initialize();
+
+  // Edit on another thread, the test runtime drives the plugin task while the editor is blocked.
+  let editor = std::thread::spawn(move || {
+    for i in 0..20 {
+      collab.insert(&i.to_string(), i);
+    }
+    collab.remove_all_plugins();
+    collab.to_json_value()
+  });
+
+  handle.flushed().await;
+  let expected = editor.join().unwrap();
+  assert_eq!(replica.to_json_value(), expected);
+  assert_eq!(
+    *replica.key_counts.lock().unwrap(),
+    (1..=20).collect::<Vec<u32>>()
+  );
+  assert_eq!(handle.dropped(), 0);
+}
+
+#[tokio::test]
+async fn async_plugin_flush_on_drop_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let replica = ReplicaPlugin::default();
+  let adapter = AsyncPluginAdapter::new(replica.clone(), AsyncPluginConfig::default()).unwrap();
+  let handle = adapter.handle();
+  collab.add_plugin(Box::new(adapter));
+  collab.initialize();
+  collab.insert("title", "hello");
+
+  drop(collab);
+  handle.flushed().await;
+  assert!(replica.flushed.load(Ordering::SeqCst));
+  assert_eq!(replica.to_json_value(), json!({ "title": "hello" }));
+}
+
+#[tokio::test]
+async fn async_plugin_block_on_runtime_thread_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let replica = ReplicaPlugin::default();
+  let adapter = AsyncPluginAdapter::new(
+    replica.clone(),
+    AsyncPluginConfig::new(1, BackpressurePolicy::Block),
+  )
+  .unwrap();
+  let handle = adapter.handle();
+  collab.add_plugin(Box::new(adapter));
+  collab.initialize();
+
+  // The plugin task runs on the same thread, blocking it would deadlock
+  for i in 0..5 {
+    collab.insert(&i.to_string(), i);
+  }
+  assert_eq!(handle.pending(), 5);
+  assert_eq!(handle.overflowed(), 4);
+
+  collab.remove_all_plugins();
+  handle.flushed().await;
+  assert_eq!(replica.to_json_value(), collab.to_json_value());
+  assert_eq!(handle.dropped(), 0);
+}
+
+#[tokio::test]
+async fn async_plugin_wait_for_capacity_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let replica = ReplicaPlugin::default();
+  let adapter = AsyncPluginAdapter::new(
+    replica.clone(),
+    AsyncPluginConfig::new(2, BackpressurePolicy::Block),
+  )
+  .unwrap();
+  let handle = adapter.handle();
+  collab.add_plugin(Box::new(adapter));
+  collab.initialize();
+
+  // Waiting yields to the plugin task, so the queue never exceeds its capacity
+  for i in 0..20 {
+    handle.wait_for_capacity().await;
+    collab.insert(&i.to_string(), i);
+    assert!(handle.pending() <= 2);
+  }
+  assert_eq!(handle.overflowed(), 0);
+
+  collab.remove_all_plugins();
+  handle.flushed().await;
+  assert_eq!(replica.to_json_value(), collab.to_json_value());
+  assert_eq!(
+    *replica.key_counts.lock().unwrap(),
+    (1..=20).collect::<Vec<u32>>()
+  );
+}
+
+#[tokio::test]
+async fn async_plugin_drop_oldest_requires_tolerant_plugin_test() {
+  let result = AsyncPluginAdapter::new(
+    ObjectsReplicaPlugin::default(),
+    AsyncPluginConfig::new(2, BackpressurePolicy::DropOldest),
+  );
+  assert!(matches!(result, Err(CollabError::InvalidAsyncPlugin(_))));
+}
+
+#[test]
+fn async_plugin_requires_runtime_test() {
+  let result = AsyncPluginAdapter::new(ReplicaPlugin::default(), AsyncPluginConfig::default());
+  assert!(matches!(result, Err(CollabError::NoTokioRuntime)));
+}
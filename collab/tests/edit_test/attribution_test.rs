@@ -0,0 +1,194 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use collab::core::collab::{CollabOptions, DataSource, default_client_id};
+use collab::core::collab_attribution::ATTRIBUTION_EDITS_SECTION;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::error::CollabError;
+use collab::preclude::{Collab, MapExt};
+use yrs::updates::decoder::Decode;
+use yrs::{Map, MapPrelim, ReadTxn, StateVector, Update};
+
+fn peer(uid: i64, device_id: &str) -> Collab {
+  let options = CollabOptions::new("1".to_string(), default_client_id());
+  let origin = CollabOrigin::Client(CollabClient::new(uid, device_id));
+  let mut collab = Collab::new_with_options(origin, options).unwrap();
+  collab.enable_attribution().unwrap();
+  collab
+}
+
+fn sync(from: &Collab, to: &mut Collab) {
+  let sv = to.transact().state_vector();
+  let update = from.transact().encode_state_as_update_v1(&sv);
+  to.apply_update(Update::decode_v1(&update).unwrap())
+    .unwrap();
+}
+
+#[test]
+fn local_change_is_attributed_test() {
+  let mut collab = peer(1, "1");
+  let start = chrono::Utc::now().timestamp_millis();
+  collab.insert("title", "hello");
+
+  let attribution = collab.attribution_at(["title"]).unwrap();
+  assert_eq!(attribution.path, ["title"].into());
+  assert_eq!(attribution.client_id, collab.client_id());
+  assert_eq!(attribution.user, Some(CollabClient::new(1, "1")));
+  assert!(attribution.timestamp >= start);
+  assert!(collab.attribution_at(["icon"]).is_none());
+}
+
+#[test]
+fn nested_change_is_attributed_to_its_ancestors_test() {
+  let mut collab = peer(1, "1");
+  collab
+    .context
+    .with_txn(|txn| {
+      let rows = collab.data.get_or_init_map(txn, "rows");
+      rows.insert(txn, "r1", "a");
+    })
+    .unwrap();
+
+  let path = ["rows", "r1"].into();
+  assert_eq!(collab.attribution_at(["rows"]).unwrap().path, path);
+  assert_eq!(collab.attribution_at(["rows", "r1"]).unwrap().path, path);
+}
+
+#[test]
+fn raw_transaction_is_attributed_test() {
+  let mut collab = peer(1, "1");
+  {
+    let mut txn = collab.context.transact_mut();
+    collab.data.insert(&mut txn, "title", "hello");
+  }
+  let attribution = collab.attribution_at(["title"]).unwrap();
+  assert_eq!(attribution.user, Some(CollabClient::new(1, "1")));
+}
+
+#[test]
+fn attribution_is_synced_test() {
+  let mut a = peer(1, "1");
+  let mut b = peer(2, "2");
+  a.insert("title", "hello");
+  b.insert("icon", "🚀");
+  sync(&a, &mut b);
+  sync(&b, &mut a);
+
+  for collab in [&a, &b] {
+    let title = collab.attribution_at(["title"]).unwrap();
+    assert_eq!(title.client_id, a.client_id());
+    assert_eq!(title.user, Some(CollabClient::new(1, "1")));
+    let icon = collab.attribution_at(["icon"]).unwrap();
+    assert_eq!(icon.user, Some(CollabClient::new(2, "2")));
+  }
+
+  // A peer that doesn't record its own changes can read the records of the others
+  let doc_state = a
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  let options = CollabOptions::new("1".to_string(), default_client_id())
+    .with_data_source(DataSource::DocStateV1(doc_state));
+  let reader = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  let icon = reader.attribution_at(["icon"]).unwrap();
+  assert_eq!(icon.user, Some(CollabClient::new(2, "2")));
+}
+
+#[test]
+fn remote_change_is_not_attributed_to_receiver_test() {
+  let mut a = peer(1, "1");
+  let mut b = Collab::new(2, "1", "2", default_client_id());
+  b.insert("title", "hello");
+  sync(&b, &mut a);
+  assert_eq!(a.to_json_value()["title"], "hello");
+  assert!(a.attribution_at(["title"]).is_none());
+}
+
+#[test]
+fn last_change_wins_test() {
+  let mut a = peer(1, "1");
+  let mut b = peer(2, "2");
+  a.insert("title", "hello");
+  sync(&a, &mut b);
+  std::thread::sleep(std::time::Duration::from_millis(5));
+  b.insert("title", "world");
+  sync(&b, &mut a);
+
+  let attribution = a.attribution_at(["title"]).unwrap();
+  assert_eq!(attribution.user, Some(CollabClient::new(2, "2")));
+}
+
+#[test]
+fn attribution_is_written_by_the_local_transaction_test() {
+  let mut collab = peer(1, "1");
+  let updates = Arc::new(AtomicUsize::new(0));
+  let cloned_updates = updates.clone();
+  let _subscription = collab
+    .context
+    .doc()
+    .observe_update_v1(move |_, _| {
+      cloned_updates.fetch_add(1, Ordering::SeqCst);
+    })
+    .unwrap();
+  collab.insert("title", "hello");
+  assert_eq!(updates.load(Ordering::SeqCst), 1);
+
+  // The update of the transaction carries the records
+  let mut b = Collab::new(2, "1", "2", default_client_id());
+  sync(&collab, &mut b);
+  assert_eq!(
+    b.attribution_at(["title"]).unwrap().client_id,
+    collab.client_id()
+  );
+}
+
+#[test]
+fn attribution_is_compacted_test() {
+  let mut collab = peer(1, "1");
+  collab
+    .context
+    .with_txn(|txn| {
+      let rows = collab.data.get_or_init_map(txn, "rows");
+      rows.insert(txn, "r1", "a");
+      rows.insert(txn, "r2", "b");
+    })
+    .unwrap();
+  let edit_count = |collab: &Collab| {
+    let txn = collab.transact();
+    txn
+      .get_map(ATTRIBUTION_EDITS_SECTION)
+      .map(|edits| edits.len(&txn))
+      .unwrap_or_default()
+  };
+  assert_eq!(edit_count(&collab), 2);
+
+  // Replacing the rows drops the records of the previous rows
+  collab
+    .context
+    .with_txn(|txn| {
+      let rows = collab.data.insert(txn, "rows", MapPrelim::default());
+      rows.insert(txn, "r3", "c");
+    })
+    .unwrap();
+  assert_eq!(edit_count(&collab), 1);
+  assert_eq!(
+    collab.attribution_at(["rows"]).unwrap().path,
+    ["rows", "r3"].into()
+  );
+
+  // The record of a removed value is dropped with the next records
+  collab.remove("rows");
+  assert!(collab.attribution_at(["rows", "r3"]).is_some());
+  collab.insert("title", "hello");
+  assert!(collab.attribution_at(["rows", "r3"]).is_none());
+  assert_eq!(edit_count(&collab), 1);
+}
+
+#[test]
+fn attribution_requires_client_origin_test() {
+  let options = CollabOptions::new("1".to_string(), default_client_id());
+  let mut collab = Collab::new_with_options(CollabOrigin::Server, options).unwrap();
+  assert!(matches!(
+    collab.enable_attribution(),
+    Err(CollabError::AttributionRequiresClientOrigin)
+  ));
+}
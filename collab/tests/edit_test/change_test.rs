@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use collab::core::collab::{Path, default_client_id};
+use collab::core::collab_change::{Change, ChangeKind};
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::preclude::{Any, Collab, MapExt, TextPrelim, TextRef};
+use yrs::updates::decoder::Decode;
+use yrs::{
+  Array, ArrayPrelim, ArrayRef, Map, MapPrelim, MapRef, ReadTxn, StateVector, Text, Transact,
+  Update,
+};
+
+fn collect_changes(
+  collab: &Collab,
+  path: impl Into<Path>,
+) -> (Arc<Mutex<Vec<Change>>>, yrs::Subscription) {
+  let changes = Arc::new(Mutex::new(vec![]));
+  let cloned_changes = changes.clone();
+  let subscription = collab.observe_changes(path, move |_txn, changes| {
+    cloned_changes.lock().unwrap().extend(changes);
+  });
+  (changes, subscription)
+}
+
+#[tokio::test]
+async fn observe_map_changes_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let (changes, _sub) = collect_changes(&collab, Path::default());
+  let origin = CollabOrigin::Client(CollabClient::new(1, "1"));
+
+  collab.insert("name", "Nathan");
+  collab.insert("name", "Lucas");
+  collab.remove("name");
+
+  let changes = changes.lock().unwrap();
+  assert_eq!(
+    *changes,
+    vec![
+      Change {
+        path: Path::from(["name"]),
+        kind: ChangeKind::Insert,
+        old: None,
+        new: Some(Any::from("Nathan")),
+        origin: origin.clone(),
+      },
+      Change {
+        path: Path::from(["name"]),
+        kind: ChangeKind::Update,
+        old: Some(Any::from("Nathan")),
+        new: Some(Any::from("Lucas")),
+        origin: origin.clone(),
+      },
+      Change {
+        path: Path::from(["name"]),
+        kind: ChangeKind::Delete,
+        old: Some(Any::from("Lucas")),
+        new: None,
+        origin,
+      },
+    ]
+  );
+}
+
+#[tokio::test]
+async fn observe_subtree_changes_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let (changes, _sub) = collect_changes(&collab, ["blocks", "b1"]);
+
+  collab
+    .context
+    .with_txn(|txn| {
+      collab
+        .data
+        .insert_with_path(txn, ["blocks", "b1", "text"], "hello")
+        .unwrap();
+      collab
+        .data
+        .insert_with_path(txn, ["blocks", "b2", "text"], "world")
+        .unwrap();
+      collab.data.insert(txn, "title", "page");
+    })
+    .unwrap();
+  // `blocks` is created by the transaction above, so the change of its ancestor is reported
+  let paths = changes
+    .lock()
+    .unwrap()
+    .drain(..)
+    .map(|change| change.path)
+    .collect::<Vec<_>>();
+  assert_eq!(paths, vec![Path::from(["blocks"])]);
+
+  collab
+    .context
+    .with_txn(|txn| {
+      collab
+        .data
+        .insert_with_path(txn, ["blocks", "b1", "text"], "hello world")
+        .unwrap();
+      collab
+        .data
+        .insert_with_path(txn, ["blocks", "b2", "text"], "foo")
+        .unwrap();
+    })
+    .unwrap();
+  let changes = changes.lock().unwrap();
+  assert_eq!(changes.len(), 1);
+  assert_eq!(changes[0].path, Path::from(["blocks", "b1", "text"]));
+  assert_eq!(changes[0].kind, ChangeKind::Update);
+  assert_eq!(changes[0].new, Some(Any::from("hello world")));
+}
+
+#[tokio::test]
+async fn observe_array_changes_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let array: ArrayRef = collab
+    .context
+    .with_txn(|txn| collab.data.insert(txn, "list", ArrayPrelim::default()))
+    .unwrap();
+  let (changes, _sub) = collect_changes(&collab, ["list"]);
+
+  collab
+    .context
+    .with_txn(|txn| {
+      array.push_back(txn, "a");
+      array.push_back(txn, "b");
+      array.push_back(txn, "c");
+    })
+    .unwrap();
+  collab.context.with_txn(|txn| array.remove(txn, 1)).unwrap();
+
+  let changes = changes
+    .lock()
+    .unwrap()
+    .iter()
+    .map(|change| (change.path.to_string(), change.kind, change.new.clone()))
+    .collect::<Vec<_>>();
+  assert_eq!(
+    changes,
+    vec![
+      (
+        "list/0".to_string(),
+        ChangeKind::Insert,
+        Some(Any::from("a"))
+      ),
+      (
+        "list/1".to_string(),
+        ChangeKind::Insert,
+        Some(Any::from("b"))
+      ),
+      (
+        "list/2".to_string(),
+        ChangeKind::Insert,
+        Some(Any::from("c"))
+      ),
+      ("list/1".to_string(), ChangeKind::Delete, None),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn observe_array_removal_after_insertion_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let array: ArrayRef = collab
+    .context
+    .with_txn(|txn| {
+      let array: ArrayRef = collab.data.insert(txn, "list", ArrayPrelim::default());
+      array.insert_range(txn, 0, ["a", "b", "c"]);
+      array
+    })
+    .unwrap();
+  let (changes, _sub) = collect_changes(&collab, ["list"]);
+
+  collab
+    .context
+    .with_txn(|txn| {
+      array.insert(txn, 0, "x");
+      // Removes "b", which was at index 1 before the insertion
+      array.remove(txn, 2);
+    })
+    .unwrap();
+
+  let changes = changes
+    .lock()
+    .unwrap()
+    .iter()
+    .map(|change| (change.path.to_string(), change.kind))
+    .collect::<Vec<_>>();
+  assert_eq!(
+    changes,
+    vec![
+      ("list/0".to_string(), ChangeKind::Insert),
+      ("list/1".to_string(), ChangeKind::Delete),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn observe_remote_changes_test() {
+  let mut remote = Collab::new(2, "1", "2", default_client_id());
+  remote.insert("name", "remote");
+  let update = remote
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+
+  let local = Collab::new(1, "1", "1", default_client_id());
+  let (changes, _sub) = collect_changes(&local, ["name"]);
+  {
+    let mut txn = local.context.doc().transact_mut_with(CollabOrigin::Server);
+    txn
+      .apply_update(Update::decode_v1(&update).unwrap())
+      .unwrap();
+  }
+
+  let changes = changes.lock().unwrap();
+  assert_eq!(changes.len(), 1);
+  assert_eq!(changes[0].kind, ChangeKind::Insert);
+  assert_eq!(changes[0].origin, CollabOrigin::Server);
+}
+
+#[tokio::test]
+async fn observe_old_values_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let (array, text) = collab
+    .context
+    .with_txn(|txn| {
+      let array: ArrayRef = collab.data.insert(txn, "list", ArrayPrelim::default());
+      array.insert_range(txn, 0, ["a", "b", "c"]);
+      let item: MapRef = array.push_back(txn, MapPrelim::default());
+      item.insert(txn, "name", "d");
+      let text: TextRef = collab
+        .data
+        .insert(txn, "title", TextPrelim::new("hello world"));
+      (array, text)
+    })
+    .unwrap();
+  let (changes, _sub) = collect_changes(&collab, Path::default());
+
+  collab
+    .context
+    .with_txn(|txn| {
+      array.insert(txn, 0, "x");
+      // Removes "b" and the map, which were at index 1 and 3 before the insertion
+      array.remove(txn, 2);
+      array.remove(txn, 3);
+    })
+    .unwrap();
+  collab
+    .context
+    .with_txn(|txn| text.remove_range(txn, 5, 6))
+    .unwrap();
+  // The copy of the data is updated after each transaction
+  collab.context.with_txn(|txn| array.remove(txn, 0)).unwrap();
+  collab
+    .context
+    .with_txn(|txn| text.insert(txn, 5, "!"))
+    .unwrap();
+
+  let changes = changes
+    .lock()
+    .unwrap()
+    .iter()
+    .map(|change| {
+      (
+        change.path.to_string(),
+        change.kind,
+        change.old.clone(),
+        change.new.clone(),
+      )
+    })
+    .collect::<Vec<_>>();
+  let d = Any::Map(Arc::new(HashMap::from([(
+    "name".to_string(),
+    Any::from("d"),
+  )])));
+  assert_eq!(
+    changes,
+    vec![
+      (
+        "list/0".to_string(),
+        ChangeKind::Insert,
+        None,
+        Some(Any::from("x"))
+      ),
+      (
+        "list/1".to_string(),
+        ChangeKind::Delete,
+        Some(Any::from("b")),
+        None
+      ),
+      ("list/3".to_string(), ChangeKind::Delete, Some(d), None),
+      (
+        "title".to_string(),
+        ChangeKind::Update,
+        Some(Any::from("hello world")),
+        Some(Any::from("hello"))
+      ),
+      (
+        "list/0".to_string(),
+        ChangeKind::Delete,
+        Some(Any::from("x")),
+        None
+      ),
+      (
+        "title".to_string(),
+        ChangeKind::Update,
+        Some(Any::from("hello")),
+        Some(Any::from("hello!"))
+      ),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn observe_subtree_old_values_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let blocks = collab
+    .context
+    .with_txn(|txn| {
+      let blocks = collab.data.get_or_init_map(txn, "blocks");
+      for id in ["b1", "b2"] {
+        let block: MapRef = blocks.insert(txn, id, MapPrelim::default());
+        let children: ArrayRef = block.insert(txn, "children", ArrayPrelim::default());
+        children.insert_range(txn, 0, ["a", "b"]);
+      }
+      blocks
+    })
+    .unwrap();
+  let (changes, _sub) = collect_changes(&collab, ["blocks", "b1"]);
+  let children = |txn: &yrs::TransactionMut, id: &str| -> ArrayRef {
+    blocks.get_with_path(txn, vec![id, "children"]).unwrap()
+  };
+
+  collab
+    .context
+    .with_txn(|txn| children(txn, "b1").remove(txn, 0))
+    .unwrap();
+  // The changes of the other blocks are not reported
+  collab
+    .context
+    .with_txn(|txn| children(txn, "b2").remove(txn, 0))
+    .unwrap();
+  // Replacing the block copies the subtree again
+  collab
+    .context
+    .with_txn(|txn| {
+      let block: MapRef = blocks.insert(txn, "b1", MapPrelim::default());
+      let children: ArrayRef = block.insert(txn, "children", ArrayPrelim::default());
+      children.insert_range(txn, 0, ["c"]);
+    })
+    .unwrap();
+  collab
+    .context
+    .with_txn(|txn| children(txn, "b1").remove(txn, 0))
+    .unwrap();
+
+  let changes = changes
+    .lock()
+    .unwrap()
+    .iter()
+    .map(|change| (change.path.to_string(), change.kind, change.old.clone()))
+    .collect::<Vec<_>>();
+  let old_block = Any::Map(Arc::new(HashMap::from([(
+    "children".to_string(),
+    Any::Array(vec![Any::from("b")].into()),
+  )])));
+  assert_eq!(
+    changes,
+    vec![
+      (
+        "blocks/b1/children/0".to_string(),
+        ChangeKind::Delete,
+        Some(Any::from("a"))
+      ),
+      ("blocks/b1".to_string(), ChangeKind::Update, Some(old_block)),
+      (
+        "blocks/b1/children/0".to_string(),
+        ChangeKind::Delete,
+        Some(Any::from("c"))
+      ),
+    ]
+  );
+}
@@ -0,0 +1,321 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use collab::core::collab::{CollabOptions, default_client_id};
+use collab::core::collab_plugin::{CollabPluginType, Plugins};
+use collab::core::origin::CollabOrigin;
+use collab::error::CollabError;
+use collab::preclude::{Collab, CollabPlugin, MapExt};
+use serde_json::{Value as JsonValue, json};
+use yrs::types::ToJson;
+use yrs::updates::decoder::Decode;
+use yrs::{Doc, Map, ReadTxn, StateVector, Transact, TransactionMut, Update, WriteTxn};
+
+use crate::util::CollabStateCachePlugin;
+
+/// Rejects every change made while the page is locked.
+#[derive(Clone, Default)]
+struct LockPagePlugin {
+  locked: Arc<AtomicBool>,
+  calls: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl CollabPlugin for LockPagePlugin {
+  fn before_commit(&self, _object_id: &str, _txn: &TransactionMut) -> Result<(), CollabError> {
+    self.calls.lock().unwrap().push("lock");
+    if self.locked.load(Ordering::SeqCst) {
+      return Err(CollabError::TransactionRejected(
+        "page is locked".to_string(),
+      ));
+    }
+    Ok(())
+  }
+
+  fn has_commit_hook(&self) -> bool {
+    true
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::Other("LockPagePlugin".to_string())
+  }
+}
+
+/// Rejects an empty title by inspecting the pending transaction.
+#[derive(Clone)]
+struct TitlePlugin {
+  calls: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl CollabPlugin for TitlePlugin {
+  fn before_commit(&self, _object_id: &str, txn: &TransactionMut) -> Result<(), CollabError> {
+    self.calls.lock().unwrap().push("title");
+    let title: Option<String> = txn
+      .get_map("data")
+      .and_then(|data| data.get_with_txn(txn, "title"));
+    match title {
+      Some(title) if title.is_empty() => Err(CollabError::TransactionRejected(
+        "title must not be empty".to_string(),
+      )),
+      _ => Ok(()),
+    }
+  }
+
+  fn has_commit_hook(&self) -> bool {
+    true
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::Other("TitlePlugin".to_string())
+  }
+}
+
+/// Applies the updates it receives to another document, and records the data after each one.
+#[derive(Clone, Default)]
+struct PeerPlugin {
+  doc: Arc<Doc>,
+  states: Arc<Mutex<Vec<JsonValue>>>,
+}
+
+impl CollabPlugin for PeerPlugin {
+  fn receive_update(&self, _object_id: &str, _txn: &TransactionMut, update: &[u8]) {
+    let mut txn = self.doc.transact_mut();
+    txn
+      .apply_update(Update::decode_v1(update).unwrap())
+      .unwrap();
+    let data = txn.get_or_insert_map("data").to_json(&txn);
+    self
+      .states
+      .lock()
+      .unwrap()
+      .push(serde_json::to_value(data).unwrap());
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::Other("PeerPlugin".to_string())
+  }
+}
+
+/// Records the callbacks of each transaction.
+#[derive(Clone, Default)]
+struct CallbackPlugin {
+  calls: Arc<Mutex<Vec<&'static str>>>,
+}
+
+impl CollabPlugin for CallbackPlugin {
+  fn receive_update(&self, _object_id: &str, _txn: &TransactionMut, _update: &[u8]) {
+    self.calls.lock().unwrap().push("receive_update");
+  }
+
+  fn after_transaction(&self, _object_id: &str, _txn: &mut TransactionMut) {
+    self.calls.lock().unwrap().push("after_transaction");
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::Other("CallbackPlugin".to_string())
+  }
+}
+
+#[tokio::test]
+async fn rejected_transaction_is_reverted_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let lock = LockPagePlugin::default();
+  collab.add_plugin(Box::new(lock.clone()));
+  collab
+    .context
+    .with_txn(|txn| collab.data.insert(txn, "title", "hello"))
+    .unwrap();
+
+  lock.locked.store(true, Ordering::SeqCst);
+  let result = collab.context.with_txn(|txn| {
+    collab.data.insert(txn, "title", "world");
+    collab.data.insert(txn, "icon", "🚀");
+  });
+  assert!(matches!(result, Err(CollabError::TransactionRejected(_))));
+  assert_eq!(collab.to_json_value(), json!({ "title": "hello" }));
+
+  lock.locked.store(false, Ordering::SeqCst);
+  collab
+    .context
+    .with_txn(|txn| collab.data.insert(txn, "title", "world"))
+    .unwrap();
+  assert_eq!(collab.to_json_value(), json!({ "title": "world" }));
+}
+
+#[tokio::test]
+async fn transaction_runs_hooks_once_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let lock = LockPagePlugin::default();
+  collab.add_plugin(Box::new(lock.clone()));
+
+  collab
+    .context
+    .with_txn(|txn| {
+      collab.data.insert(txn, "a", 1);
+      collab.data.insert(txn, "b", 2);
+    })
+    .unwrap();
+  assert_eq!(*lock.calls.lock().unwrap(), vec!["lock"]);
+}
+
+#[tokio::test]
+async fn hooks_follow_plugins_order_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let calls = Arc::new(Mutex::new(vec![]));
+  let lock = LockPagePlugin {
+    locked: Default::default(),
+    calls: calls.clone(),
+  };
+  let title = TitlePlugin {
+    calls: calls.clone(),
+  };
+  let plugins = || {
+    [
+      Box::new(lock.clone()) as Box<dyn CollabPlugin>,
+      Box::new(title.clone()) as Box<dyn CollabPlugin>,
+    ]
+  };
+  collab.add_plugins(plugins());
+
+  let mut expected = vec![];
+  Plugins::new(plugins()).each(|plugin| {
+    expected.push(match plugin.plugin_type() {
+      CollabPluginType::Other(name) if name == "LockPagePlugin" => "lock",
+      _ => "title",
+    })
+  });
+
+  // The first rejection stops the iteration
+  let result = collab
+    .context
+    .with_txn(|txn| collab.data.insert(txn, "title", ""));
+  assert!(matches!(result, Err(CollabError::TransactionRejected(_))));
+  assert_eq!(*calls.lock().unwrap(), expected[..1]);
+  assert_eq!(collab.to_json_value(), json!({}));
+
+  calls.lock().unwrap().clear();
+  collab
+    .context
+    .with_txn(|txn| collab.data.insert(txn, "title", "hello"))
+    .unwrap();
+  assert_eq!(*calls.lock().unwrap(), expected);
+}
+
+#[tokio::test]
+async fn remote_update_is_not_rejected_test() {
+  let mut remote = Collab::new(2, "1", "2", default_client_id());
+  remote
+    .context
+    .with_txn(|txn| remote.data.insert(txn, "title", "remote"))
+    .unwrap();
+  let update = remote
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let lock = LockPagePlugin::default();
+  lock.locked.store(true, Ordering::SeqCst);
+  collab.add_plugin(Box::new(lock.clone()));
+  collab
+    .apply_update(Update::decode_v1(&update).unwrap())
+    .unwrap();
+  assert_eq!(collab.to_json_value(), json!({ "title": "remote" }));
+  assert!(lock.calls.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn peers_converge_after_rejection_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let lock = LockPagePlugin::default();
+  let cache = CollabStateCachePlugin::new();
+  collab.add_plugins([
+    Box::new(lock.clone()) as Box<dyn CollabPlugin>,
+    Box::new(cache.clone()) as Box<dyn CollabPlugin>,
+  ]);
+  collab.initialize();
+  collab
+    .context
+    .with_txn(|txn| collab.data.insert(txn, "title", "hello"))
+    .unwrap();
+
+  lock.locked.store(true, Ordering::SeqCst);
+  let _ = collab
+    .context
+    .with_txn(|txn| collab.data.insert(txn, "title", "world"));
+
+  // The rejected update is never broadcast, peers end up in the same state.
+  let options = CollabOptions::new("1".to_string(), default_client_id())
+    .with_data_source(cache.get_doc_state().unwrap());
+  let peer = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  assert_eq!(peer.to_json_value(), json!({ "title": "hello" }));
+}
+
+#[tokio::test]
+async fn rejected_update_is_not_sent_to_plugins_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let lock = LockPagePlugin::default();
+  let peer = PeerPlugin::default();
+  collab.add_plugins([
+    Box::new(lock.clone()) as Box<dyn CollabPlugin>,
+    Box::new(peer.clone()) as Box<dyn CollabPlugin>,
+  ]);
+  collab.initialize();
+  collab.insert("title", "hello");
+
+  lock.locked.store(true, Ordering::SeqCst);
+  let result = collab.try_insert("title", "world");
+  assert!(matches!(result, Err(CollabError::TransactionRejected(_))));
+
+  // The peer never sees the rejected title
+  for state in peer.states.lock().unwrap().iter() {
+    assert_eq!(state, &json!({ "title": "hello" }));
+  }
+
+  // The next update carries the reverted history too, so the peer can integrate it
+  lock.locked.store(false, Ordering::SeqCst);
+  collab.insert("icon", "🚀");
+  let states = peer.states.lock().unwrap();
+  assert_eq!(states.last().unwrap(), &collab.to_json_value());
+}
+
+#[tokio::test]
+async fn approved_transaction_is_committed_once_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let callbacks = CallbackPlugin::default();
+  collab.add_plugins([
+    Box::new(LockPagePlugin::default()) as Box<dyn CollabPlugin>,
+    Box::new(callbacks.clone()) as Box<dyn CollabPlugin>,
+  ]);
+  collab.initialize();
+  collab.insert("title", "hello");
+  assert_eq!(
+    *callbacks.calls.lock().unwrap(),
+    vec!["after_transaction", "receive_update"]
+  );
+}
+
+#[tokio::test]
+async fn raw_transaction_skips_hooks_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let lock = LockPagePlugin::default();
+  collab.add_plugin(Box::new(lock.clone()));
+  collab.insert("title", "hello");
+
+  lock.locked.store(true, Ordering::SeqCst);
+  let result = collab
+    .context
+    .with_txn(|txn| collab.data.insert(txn, "title", "world"));
+  assert!(matches!(result, Err(CollabError::TransactionRejected(_))));
+  assert_eq!(collab.to_json_value(), json!({ "title": "hello" }));
+
+  // transact_mut returns a raw transaction, its changes are kept
+  lock.calls.lock().unwrap().clear();
+  {
+    let mut txn = collab.context.transact_mut();
+    collab.data.insert(&mut txn, "icon", "🚀");
+  }
+  assert!(lock.calls.lock().unwrap().is_empty());
+  assert_eq!(
+    collab.to_json_value(),
+    json!({ "title": "hello", "icon": "🚀" })
+  );
+}
@@ -0,0 +1,85 @@
+use collab::core::collab::{CollabOptions, DataSource, default_client_id};
+use collab::core::origin::CollabOrigin;
+use collab::preclude::{ClientID, Collab};
+use yrs::updates::decoder::Decode;
+use yrs::{ReadTxn, StateVector, Update};
+
+fn collab_with_history(client_id: ClientID) -> Collab {
+  let options = CollabOptions::new("1".to_string(), client_id).with_skip_gc(true);
+  let mut collab = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  for i in 0..50 {
+    collab.insert("text", format!("{}{}", i, "a".repeat(1024)));
+  }
+  collab.insert("title", "compact");
+  collab
+}
+
+fn open(client_id: ClientID, doc_state: Vec<u8>) -> Collab {
+  let options = CollabOptions::new("1".to_string(), client_id)
+    .with_data_source(DataSource::DocStateV1(doc_state));
+  Collab::new_with_options(CollabOrigin::Empty, options).unwrap()
+}
+
+fn sync(from: &Collab, to: &mut Collab) {
+  let sv = to.transact().state_vector();
+  let update = from.transact().encode_state_as_update_v1(&sv);
+  to.apply_update(Update::decode_v1(&update).unwrap())
+    .unwrap();
+}
+
+#[test]
+fn compact_drops_deleted_content_test() {
+  let collab = collab_with_history(default_client_id());
+  let compacted = collab.compact().unwrap();
+  let report = compacted.report;
+  assert!(report.before > 50 * 1024);
+  // Only the last text, about 1 KiB, is left
+  assert!(report.after < 2 * 1024);
+  assert_eq!(report.saved(), report.before - report.after);
+
+  // The collab itself is unchanged
+  let doc_state = collab
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  assert_eq!(doc_state.len(), report.before);
+
+  let reopened = open(
+    default_client_id(),
+    compacted.encoded_collab.doc_state.to_vec(),
+  );
+  assert_eq!(reopened.to_json_value(), collab.to_json_value());
+  assert_eq!(
+    reopened.transact().state_vector(),
+    collab.transact().state_vector()
+  );
+}
+
+#[test]
+fn sync_with_compacted_collab_test() {
+  let client_id = default_client_id();
+  let mut collab = collab_with_history(client_id);
+  let mut peer = open(
+    default_client_id(),
+    collab
+      .transact()
+      .encode_state_as_update_v1(&StateVector::default()),
+  );
+
+  // Reopen the collab from its compacted state, keeping its client id
+  let compacted = collab.compact().unwrap();
+  collab = open(client_id, compacted.encoded_collab.doc_state.to_vec());
+  collab.insert("text", "from compacted");
+  peer.insert("title", "from peer");
+
+  sync(&collab, &mut peer);
+  sync(&peer, &mut collab);
+  assert_eq!(collab.to_json_value(), peer.to_json_value());
+  assert_eq!(
+    collab.to_json_value()["text"],
+    serde_json::json!("from compacted")
+  );
+  assert_eq!(
+    collab.to_json_value()["title"],
+    serde_json::json!("from peer")
+  );
+}
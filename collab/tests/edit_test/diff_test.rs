@@ -0,0 +1,278 @@
+use collab::core::collab::{CollabOptions, Path, default_client_id};
+use collab::core::collab_diff::{CollabDiff, CollabMergeReport, DiffChange, TextOp};
+use collab::core::origin::CollabOrigin;
+use collab::error::CollabError;
+use collab::preclude::{Collab, MapExt, TextPrelim, TextRef};
+use serde_json::json;
+use yrs::{Array, ArrayPrelim, ArrayRef, Map, MapPrelim, MapRef, Out, ReadTxn, Text};
+
+fn encode(collab: &Collab) -> collab::entity::EncodedCollab {
+  collab
+    .encode_collab_v1(|_| Ok::<_, CollabError>(()))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn diff_map_changes_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  collab.insert("name", "page");
+  collab.insert("icon", "🚀");
+  let old = encode(&collab);
+
+  collab.insert("name", "my page");
+  collab.remove("icon");
+  collab
+    .context
+    .with_txn(|txn| {
+      collab
+        .data
+        .insert_with_path(txn, ["meta", "created_at"], 1)
+        .unwrap();
+    })
+    .unwrap();
+
+  let diff = CollabDiff::between(&old, &encode(&collab)).unwrap();
+  assert_eq!(diff.authors, vec![collab.context.client_id()]);
+  let changes = diff
+    .changes
+    .iter()
+    .map(|entry| (entry.path.clone(), entry.change.clone()))
+    .collect::<Vec<_>>();
+  assert_eq!(
+    changes,
+    vec![
+      (
+        Path::from(["meta"]),
+        DiffChange::Added {
+          value: json!({ "created_at": 1 })
+        }
+      ),
+      (
+        Path::from(["name"]),
+        DiffChange::Changed {
+          old: json!("page"),
+          new: json!("my page")
+        }
+      ),
+      (
+        Path::from(["icon"]),
+        DiffChange::Removed {
+          value: json!("🚀")
+        }
+      ),
+    ]
+  );
+
+  let json = diff.to_json();
+  assert_eq!(json["changes"][1]["change"]["type"], "changed");
+  let report = diff.to_string();
+  assert!(report.contains("~ name: \"page\" -> \"my page\""));
+  assert!(report.contains("- icon: \"🚀\""));
+}
+
+#[tokio::test]
+async fn diff_text_changes_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let text: TextRef = collab
+    .context
+    .with_txn(|txn| {
+      collab
+        .data
+        .insert_with_path(txn, ["title"], TextPrelim::new("hello world"))
+        .unwrap()
+    })
+    .unwrap();
+  let old = encode(&collab);
+
+  collab
+    .context
+    .with_txn(|txn| {
+      text.insert(txn, 5, " brave");
+      text.remove_range(txn, 12, 5);
+    })
+    .unwrap();
+
+  let diff = collab.diff_from(&old).unwrap();
+  let author = collab.context.client_id();
+  assert_eq!(diff.changes.len(), 1);
+  assert_eq!(diff.changes[0].path, Path::from(["title"]));
+  assert_eq!(
+    diff.changes[0].change,
+    DiffChange::Text {
+      ops: vec![
+        TextOp::Insert {
+          index: 5,
+          text: " brave".to_string(),
+          author,
+        },
+        TextOp::Delete {
+          index: 12,
+          text: "world".to_string(),
+        },
+      ],
+    }
+  );
+}
+
+#[tokio::test]
+async fn diff_without_changes_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  collab.insert("name", "page");
+  let old = encode(&collab);
+  let diff = collab.diff_from(&old).unwrap();
+  assert!(diff.is_empty());
+  assert!(diff.authors.is_empty());
+  assert_eq!(diff.to_string(), "no changes\n");
+}
+
+#[tokio::test]
+async fn diff_since_state_vector_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let sv = collab.transact().state_vector();
+  assert!(matches!(
+    collab.diff_since(&sv),
+    Err(CollabError::DiffRequiresSkipGc)
+  ));
+
+  let options = CollabOptions::new("1".to_string(), default_client_id()).with_skip_gc(true);
+  collab = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  collab.insert("a", "1");
+  let sv = collab.transact().state_vector();
+  collab.insert("b", "2");
+
+  let diff = collab.diff_since(&sv).unwrap();
+  assert_eq!(diff.changes.len(), 1);
+  assert_eq!(diff.changes[0].path, Path::from(["b"]));
+  assert_eq!(
+    diff.changes[0].change,
+    DiffChange::Added { value: json!("2") }
+  );
+}
+
+#[tokio::test]
+async fn diff_array_changes_by_element_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  let list: ArrayRef = collab
+    .context
+    .with_txn(|txn| collab.data.insert(txn, "list", ArrayPrelim::default()))
+    .unwrap();
+  collab
+    .context
+    .with_txn(|txn| {
+      for name in ["a", "b", "c"] {
+        let item: MapRef = list.push_back(txn, MapPrelim::default());
+        item.insert(txn, "name", name);
+      }
+    })
+    .unwrap();
+  let old = encode(&collab);
+
+  // Inserting in front doesn't shift the elements after it
+  collab
+    .context
+    .with_txn(|txn| {
+      let item: MapRef = list.insert(txn, 0, MapPrelim::default());
+      item.insert(txn, "name", "z");
+      let Some(Out::YMap(b)) = list.get(txn, 2) else {
+        panic!("expected a map");
+      };
+      b.insert(txn, "name", "bb");
+      list.remove(txn, 3);
+    })
+    .unwrap();
+
+  let diff = collab.diff_from(&old).unwrap();
+  let changes = diff
+    .changes
+    .iter()
+    .map(|entry| (entry.path.clone(), entry.change.clone()))
+    .collect::<Vec<_>>();
+  assert_eq!(
+    changes,
+    vec![
+      (
+        Path::from(["list", "0"]),
+        DiffChange::Added {
+          value: json!({ "name": "z" })
+        }
+      ),
+      (
+        Path::from(["list", "2", "name"]),
+        DiffChange::Changed {
+          old: json!("b"),
+          new: json!("bb")
+        }
+      ),
+      (
+        Path::from(["list", "3"]),
+        DiffChange::Removed {
+          value: json!({ "name": "c" })
+        }
+      ),
+    ]
+  );
+}
+
+#[tokio::test]
+async fn merge_report_test() {
+  let mut ours = Collab::new(1, "1", "1", default_client_id());
+  ours.insert("name", "page");
+  ours.insert("icon", "🚀");
+  let base = encode(&ours);
+  let options =
+    CollabOptions::new("1".to_string(), default_client_id()).with_data_source(base.clone().into());
+  let mut theirs = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+
+  ours.insert("name", "my page");
+  ours.insert("cover", "blue");
+  theirs.insert("name", "their page");
+  theirs.remove("icon");
+
+  let report = CollabMergeReport::between(&base, &encode(&ours), &encode(&theirs)).unwrap();
+  assert_eq!(report.ours.changes.len(), 2);
+  assert_eq!(report.theirs.changes.len(), 2);
+  // The merged state has the changes of both sides
+  assert_eq!(report.merged.changes.len(), 3);
+
+  assert!(report.has_conflicts());
+  assert_eq!(report.conflicts.len(), 1);
+  let conflict = &report.conflicts[0];
+  assert_eq!(conflict.path, Path::from(["name"]));
+  assert_eq!(
+    conflict.ours.change,
+    DiffChange::Changed {
+      old: json!("page"),
+      new: json!("my page")
+    }
+  );
+  assert_eq!(
+    conflict.theirs.change,
+    DiffChange::Changed {
+      old: json!("page"),
+      new: json!("their page")
+    }
+  );
+  // yrs picks one of the concurrent values
+  let Some(DiffChange::Changed { new, .. }) = &conflict.merged else {
+    panic!("unexpected merged change: {:?}", conflict.merged);
+  };
+  assert!(new == "my page" || new == "their page");
+
+  let json = report.to_json();
+  assert_eq!(json["conflicts"][0]["path"], json!(["name"]));
+  assert!(report.to_string().contains("! name:"));
+}
+
+#[tokio::test]
+async fn merge_report_without_conflicts_test() {
+  let mut ours = Collab::new(1, "1", "1", default_client_id());
+  ours.insert("name", "page");
+  let base = encode(&ours);
+  ours.insert("icon", "🚀");
+
+  let report = CollabMergeReport::between(&base, &encode(&ours), &base).unwrap();
+  assert!(!report.has_conflicts());
+  assert!(report.theirs.is_empty());
+  assert_eq!(report.merged, report.ours);
+  assert!(report.to_string().ends_with("no conflicts\n"));
+}
@@ -0,0 +1,112 @@
+#![cfg(feature = "lock_diagnostics")]
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use collab::core::collab::default_client_id;
+use collab::lock::RwLock;
+use collab::lock::diagnostics::{self, LockMode};
+use collab::preclude::Collab;
+
+// The registry is shared by the tests of this binary, so every test uses its own lock classes.
+
+#[tokio::test]
+async fn snapshot_held_and_contended_lock_test() {
+  let lock = Arc::new(RwLock::with_class(0, "snapshot_test::Counter"));
+  let guard = lock.write().await;
+
+  let waiter = {
+    let lock = lock.clone();
+    diagnostics::spawn(async move {
+      *lock.write().await += 1;
+    })
+  };
+  let contended = loop {
+    let snapshot = diagnostics::snapshot()
+      .into_iter()
+      .find(|snapshot| snapshot.class == lock.class())
+      .unwrap();
+    if snapshot.is_contended() {
+      break snapshot;
+    }
+    tokio::time::sleep(Duration::from_millis(5)).await;
+  };
+  assert_eq!(contended.holders.len(), 1);
+  assert_eq!(contended.holders[0].mode, LockMode::Write);
+  assert_eq!(contended.waiters.len(), 1);
+  assert_ne!(contended.waiters[0].holder, contended.holders[0].holder);
+
+  tokio::time::sleep(Duration::from_millis(20)).await;
+  drop(guard);
+  waiter.await.unwrap();
+  assert_eq!(*lock.read().await, 1);
+  assert!(
+    diagnostics::snapshot()
+      .iter()
+      .all(|snapshot| snapshot.class != lock.class())
+  );
+
+  let stats = diagnostics::wait_stats()[lock.class()].clone();
+  assert_eq!(stats.acquisitions, 3);
+  assert!(stats.max_wait >= Duration::from_millis(20));
+}
+
+#[tokio::test]
+async fn detect_lock_order_inversion_test() {
+  let collab = RwLock::with_class(
+    Collab::new(1, "1", "1", default_client_id()),
+    "inversion_test::Collab",
+  );
+  let row = RwLock::with_class((), "inversion_test::DatabaseRow");
+  let block = RwLock::with_class((), "inversion_test::Block");
+
+  // Collab -> DatabaseRow -> Block
+  diagnostics::instrument(async {
+    let _collab = collab.write().await;
+    let _row = row.write().await;
+    let _block = block.read().await;
+  })
+  .await;
+  assert!(inversions_of("inversion_test::").is_empty());
+
+  // Block -> Collab inverts the order recorded above. The inversion is reported even though
+  // the acquisitions don't deadlock.
+  diagnostics::instrument(async {
+    let _block = block.write().await;
+    let _collab = collab.read().await;
+  })
+  .await;
+  let inversions = inversions_of("inversion_test::");
+  assert_eq!(inversions.len(), 1);
+  assert_eq!(inversions[0].first, "inversion_test::Block");
+  assert_eq!(inversions[0].second, "inversion_test::Collab");
+}
+
+#[tokio::test]
+async fn separate_holders_do_not_record_order_test() {
+  let first = Arc::new(RwLock::with_class((), "holders_test::First"));
+  let second = Arc::new(RwLock::with_class((), "holders_test::Second"));
+
+  // The task acquires the second lock while the test holds the first one, on the same thread.
+  // They are different holders, so no order between the classes is recorded.
+  let first_guard = first.write().await;
+  let task = {
+    let second = second.clone();
+    diagnostics::spawn(async move {
+      let _second = second.write().await;
+    })
+  };
+  task.await.unwrap();
+  drop(first_guard);
+
+  let _second = second.write().await;
+  let _first = first.write().await;
+  assert!(inversions_of("holders_test::").is_empty());
+}
+
+fn inversions_of(prefix: &str) -> Vec<diagnostics::LockInversion> {
+  diagnostics::lock_inversions()
+    .into_iter()
+    .filter(|inversion| inversion.first.starts_with(prefix))
+    .collect()
+}
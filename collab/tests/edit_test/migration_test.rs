@@ -0,0 +1,265 @@
+use collab::core::collab::{CollabOptions, DataSource, default_client_id};
+use collab::core::collab_migration::{CollabMigration, CollabMigrations};
+use collab::core::collab_plugin::CollabPluginType;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::error::CollabError;
+use collab::preclude::{Collab, CollabPlugin, MapExt};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use yrs::updates::decoder::Decode;
+use yrs::{Map, MapPrelim, MapRef, ReadTxn, StateVector, TransactionMut, Update};
+
+/// Renames the `title` key to `name`.
+struct RenameTitle;
+
+impl CollabMigration for RenameTitle {
+  fn source_version(&self) -> u32 {
+    0
+  }
+
+  fn target_version(&self) -> u32 {
+    1
+  }
+
+  fn migrate(&self, txn: &mut TransactionMut, data: &MapRef) -> Result<(), CollabError> {
+    if let Some(title) = data.get_with_txn::<_, String>(txn, "title") {
+      if data.get(txn, "name").is_none() {
+        data.insert(txn, "name", title);
+      }
+      data.remove(txn, "title");
+    }
+    Ok(())
+  }
+}
+
+/// Adds an empty `settings` map.
+struct AddSettings;
+
+impl CollabMigration for AddSettings {
+  fn source_version(&self) -> u32 {
+    1
+  }
+
+  fn target_version(&self) -> u32 {
+    2
+  }
+
+  fn migrate(&self, txn: &mut TransactionMut, data: &MapRef) -> Result<(), CollabError> {
+    data.get_or_init_map(txn, "settings");
+    Ok(())
+  }
+}
+
+fn document_migrations() -> CollabMigrations {
+  let mut migrations = CollabMigrations::new();
+  migrations.push(Arc::new(AddSettings)).unwrap();
+  migrations.push(Arc::new(RenameTitle)).unwrap();
+  migrations
+}
+
+fn v0_doc_state() -> Vec<u8> {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  collab.insert("title", "my page");
+  collab
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default())
+}
+
+fn open(doc_state: Vec<u8>, uid: i64) -> Collab {
+  let options = CollabOptions::new("1".to_string(), default_client_id())
+    .with_data_source(DataSource::DocStateV1(doc_state))
+    .with_migrations(document_migrations());
+  Collab::new_with_options(CollabOrigin::Client(CollabClient::new(uid, "1")), options).unwrap()
+}
+
+#[tokio::test]
+async fn run_migrations_on_open_test() {
+  let collab = open(v0_doc_state(), 1);
+  assert_eq!(collab.schema_version(), 2);
+  assert_eq!(
+    collab.to_json_value(),
+    json!({ "name": "my page", "settings": {} })
+  );
+}
+
+#[tokio::test]
+async fn run_migrations_is_idempotent_test() {
+  let mut collab = open(v0_doc_state(), 1);
+  let migrations = document_migrations();
+  let sv = collab.transact().state_vector();
+  assert_eq!(collab.run_migrations(&migrations).unwrap(), 2);
+  // Nothing changed the second time
+  assert_eq!(collab.transact().state_vector(), sv);
+
+  // No migration is registered for the type
+  let migrations = CollabMigrations::new();
+  assert!(migrations.is_empty());
+  assert_eq!(collab.run_migrations(&migrations).unwrap(), 2);
+}
+
+#[tokio::test]
+async fn concurrent_migrations_converge_test() {
+  let doc_state = v0_doc_state();
+  let mut client_1 = open(doc_state.clone(), 1);
+  let mut client_2 = open(doc_state, 2);
+
+  let update_1 = client_1
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  let update_2 = client_2
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  client_1
+    .apply_update(Update::decode_v1(&update_2).unwrap())
+    .unwrap();
+  client_2
+    .apply_update(Update::decode_v1(&update_1).unwrap())
+    .unwrap();
+
+  assert_eq!(client_1.to_json_value(), client_2.to_json_value());
+  assert_eq!(
+    client_1.to_json_value(),
+    json!({ "name": "my page", "settings": {} })
+  );
+  assert_eq!(client_1.schema_version(), 2);
+  assert_eq!(client_2.schema_version(), 2);
+}
+
+#[tokio::test]
+async fn newer_document_is_not_migrated_test() {
+  let mut collab = open(v0_doc_state(), 1);
+  // Simulate a document written by a newer client
+  let mut migrations = document_migrations();
+  migrations.push(Arc::new(ReplaceSettings)).unwrap();
+  assert_eq!(collab.run_migrations(&migrations).unwrap(), 3);
+
+  let old_migrations = document_migrations();
+  assert_eq!(collab.run_migrations(&old_migrations).unwrap(), 3);
+  assert_eq!(collab.schema_version(), 3);
+}
+
+struct ReplaceSettings;
+
+impl CollabMigration for ReplaceSettings {
+  fn source_version(&self) -> u32 {
+    2
+  }
+
+  fn target_version(&self) -> u32 {
+    3
+  }
+
+  fn migrate(&self, txn: &mut TransactionMut, data: &MapRef) -> Result<(), CollabError> {
+    if data.get(txn, "config").is_none() {
+      data.insert(txn, "config", MapPrelim::default());
+    }
+    Ok(())
+  }
+}
+
+#[tokio::test]
+async fn failed_migration_is_reverted_test() {
+  /// Writes a value and then fails.
+  struct Fail;
+  impl CollabMigration for Fail {
+    fn source_version(&self) -> u32 {
+      1
+    }
+
+    fn target_version(&self) -> u32 {
+      2
+    }
+
+    fn migrate(&self, txn: &mut TransactionMut, data: &MapRef) -> Result<(), CollabError> {
+      data.insert(txn, "partial", true);
+      Err(CollabError::InvalidMigration("failed".to_string()))
+    }
+  }
+
+  let mut failing = CollabMigrations::new();
+  failing.push(Arc::new(RenameTitle)).unwrap();
+  failing.push(Arc::new(Fail)).unwrap();
+  let options = CollabOptions::new("1".to_string(), default_client_id())
+    .with_data_source(DataSource::DocStateV1(v0_doc_state()));
+  let mut collab =
+    Collab::new_with_options(CollabOrigin::Client(CollabClient::new(1, "1")), options).unwrap();
+  assert!(matches!(
+    collab.run_migrations(&failing),
+    Err(CollabError::InvalidMigration(_))
+  ));
+  // Neither the previous migration nor the partial writes of the failed one are kept
+  assert_eq!(collab.schema_version(), 0);
+  assert_eq!(collab.to_json_value(), json!({ "title": "my page" }));
+
+  // The migrations are retried from the original version
+  assert_eq!(collab.run_migrations(&document_migrations()).unwrap(), 2);
+  assert_eq!(
+    collab.to_json_value(),
+    json!({ "name": "my page", "settings": {} })
+  );
+}
+
+#[tokio::test]
+async fn migration_update_reaches_plugins_test() {
+  let doc_state = v0_doc_state();
+  let mut collab = open(doc_state.clone(), 1);
+  let plugin = UpdateRecorder::default();
+  collab.add_plugin(Box::new(plugin.clone()));
+  collab.initialize();
+
+  // A client that only receives the updates sent to the plugins sees the migrated document
+  let mut replica = Collab::new(2, "1", "1", default_client_id());
+  replica
+    .apply_update(Update::decode_v1(&doc_state).unwrap())
+    .unwrap();
+  for update in plugin.updates.lock().unwrap().iter() {
+    replica
+      .apply_update(Update::decode_v1(update).unwrap())
+      .unwrap();
+  }
+  assert_eq!(replica.to_json_value(), collab.to_json_value());
+  assert_eq!(replica.schema_version(), 2);
+}
+
+#[derive(Clone, Default)]
+struct UpdateRecorder {
+  updates: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl CollabPlugin for UpdateRecorder {
+  fn receive_update(&self, _object_id: &str, _txn: &TransactionMut, update: &[u8]) {
+    self.updates.lock().unwrap().push(update.to_vec());
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::Other("UpdateRecorder".to_string())
+  }
+}
+
+#[tokio::test]
+async fn invalid_migration_test() {
+  let mut migrations = document_migrations();
+  assert!(matches!(
+    migrations.push(Arc::new(RenameTitle)),
+    Err(CollabError::InvalidMigration(_))
+  ));
+
+  struct Downgrade;
+  impl CollabMigration for Downgrade {
+    fn source_version(&self) -> u32 {
+      5
+    }
+
+    fn target_version(&self) -> u32 {
+      4
+    }
+
+    fn migrate(&self, _txn: &mut TransactionMut, _data: &MapRef) -> Result<(), CollabError> {
+      Ok(())
+    }
+  }
+  assert!(matches!(
+    migrations.push(Arc::new(Downgrade)),
+    Err(CollabError::InvalidMigration(_))
+  ));
+}
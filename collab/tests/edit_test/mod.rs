@@ -1,5 +1,21 @@
+mod async_plugin_test;
+mod attribution_test;
 mod awareness_test;
+mod change_test;
+mod commit_hook_test;
+mod compact_test;
+mod diff_test;
 mod insert_test;
+mod lock_diagnostics_test;
+mod migration_test;
 mod observer_test;
+mod permission_test;
+mod presence_test;
+mod read_snapshot_test;
 mod restore_test;
+mod schema_test;
+mod search_test;
+mod simulation_test;
 mod state_vec_test;
+mod stream_test;
+mod undo_scope_test;
@@ -0,0 +1,213 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use collab::core::collab::{CollabOptions, DataSource, META_SECTION, default_client_id};
+use collab::core::collab_permission::CollabPermission;
+use collab::core::collab_plugin::CollabPluginType;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::error::CollabError;
+use collab::preclude::{Collab, CollabPlugin, MapExt};
+use serde_json::json;
+use yrs::updates::decoder::Decode;
+use yrs::{Map, ReadTxn, StateVector, TransactionMut, Update};
+
+/// Counts the updates handed to the plugins.
+#[derive(Clone, Default)]
+struct UpdateCounterPlugin {
+  updates: Arc<AtomicUsize>,
+}
+
+impl CollabPlugin for UpdateCounterPlugin {
+  fn receive_update(&self, _object_id: &str, _txn: &TransactionMut, _update: &[u8]) {
+    self.updates.fetch_add(1, Ordering::SeqCst);
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    CollabPluginType::Other("UpdateCounterPlugin".to_string())
+  }
+}
+
+fn page() -> Collab {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  collab.insert("title", "hello");
+  collab
+    .context
+    .with_txn(|txn| {
+      let rows = collab.data.get_or_init_map(txn, "rows");
+      rows.insert(txn, "r1", "a");
+    })
+    .unwrap();
+  collab
+}
+
+fn open(collab: &Collab, permission: CollabPermission) -> Collab {
+  let doc_state = collab
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  let options = CollabOptions::new("1".to_string(), default_client_id())
+    .with_data_source(DataSource::DocStateV1(doc_state))
+    .with_permission(permission);
+  let origin = CollabOrigin::Client(CollabClient::new(2, "2"));
+  Collab::new_with_options(origin, options).unwrap()
+}
+
+fn sync(from: &Collab, to: &mut Collab) {
+  let sv = to.transact().state_vector();
+  let update = from.transact().encode_state_as_update_v1(&sv);
+  to.apply_update(Update::decode_v1(&update).unwrap())
+    .unwrap();
+}
+
+#[test]
+fn read_only_rejects_local_changes_test() {
+  let page = page();
+  let mut read_only = open(&page, CollabPermission::ReadOnly);
+  let expected = json!({ "title": "hello", "rows": { "r1": "a" } });
+  assert_eq!(read_only.to_json_value(), expected);
+
+  assert!(matches!(
+    read_only.try_insert("title", "world"),
+    Err(CollabError::PermissionDenied(_))
+  ));
+  assert!(matches!(
+    read_only.try_remove("rows"),
+    Err(CollabError::PermissionDenied(_))
+  ));
+  let result = read_only.context.with_txn(|txn| {
+    let rows = read_only.data.get_or_init_map(txn, "rows");
+    rows.insert(txn, "r2", "b");
+  });
+  assert!(matches!(result, Err(CollabError::PermissionDenied(_))));
+  assert_eq!(read_only.to_json_value(), expected);
+}
+
+#[test]
+#[should_panic]
+fn read_only_insert_panics_test() {
+  let page = page();
+  let mut read_only = open(&page, CollabPermission::ReadOnly);
+  read_only.insert("title", "world");
+}
+
+#[test]
+fn transact_mut_is_not_checked_test() {
+  let page = page();
+  let mut read_only = open(&page, CollabPermission::ReadOnly);
+  let data = read_only.data.clone();
+  {
+    let mut txn = read_only.context.transact_mut();
+    data.insert(&mut txn, "title", "world");
+  }
+  assert_eq!(read_only.to_json_value()["title"], json!("world"));
+}
+
+#[test]
+fn only_read_write_changes_meta_test() {
+  let page = page();
+  let mut restricted = open(&page, CollabPermission::write_restricted([["rows"]]));
+  let meta = restricted.context.doc().get_or_insert_map(META_SECTION);
+  let result = restricted.context.with_txn(|txn| {
+    meta.insert(txn, "version", 2);
+  });
+  assert!(matches!(result, Err(CollabError::PermissionDenied(_))));
+  assert!(meta.get(&restricted.transact(), "version").is_none());
+
+  restricted.set_permission(CollabPermission::ReadWrite);
+  restricted
+    .context
+    .with_txn(|txn| {
+      meta.insert(txn, "version", 2);
+    })
+    .unwrap();
+  assert!(meta.get(&restricted.transact(), "version").is_some());
+}
+
+#[test]
+fn read_only_applies_remote_updates_test() {
+  let mut page = page();
+  let mut read_only = open(&page, CollabPermission::ReadOnly);
+  page.insert("title", "world");
+  sync(&page, &mut read_only);
+  assert_eq!(read_only.to_json_value(), page.to_json_value());
+  assert_eq!(read_only.to_json_value()["title"], json!("world"));
+}
+
+#[test]
+fn write_restricted_to_path_test() {
+  let page = page();
+  let mut restricted = open(&page, CollabPermission::write_restricted([["rows"]]));
+
+  restricted
+    .context
+    .with_txn(|txn| {
+      let rows = restricted.data.get_or_init_map(txn, "rows");
+      rows.insert(txn, "r2", "b");
+    })
+    .unwrap();
+  assert!(matches!(
+    restricted.try_insert("title", "world"),
+    Err(CollabError::PermissionDenied(_))
+  ));
+
+  // The allowed change of a rejected transaction is reverted too
+  let result = restricted.context.with_txn(|txn| {
+    let rows = restricted.data.get_or_init_map(txn, "rows");
+    rows.insert(txn, "r3", "c");
+    restricted.data.insert(txn, "icon", "🚀");
+  });
+  assert!(matches!(result, Err(CollabError::PermissionDenied(_))));
+  assert_eq!(
+    restricted.to_json_value(),
+    json!({ "title": "hello", "rows": { "r1": "a", "r2": "b" } })
+  );
+}
+
+#[test]
+fn change_permission_test() {
+  let mut collab = page();
+  assert!(collab.permission().is_read_write());
+
+  collab.set_permission(CollabPermission::ReadOnly);
+  assert!(matches!(
+    collab.try_insert("title", "locked"),
+    Err(CollabError::PermissionDenied(_))
+  ));
+
+  collab.set_permission(CollabPermission::ReadWrite);
+  collab.insert("title", "unlocked");
+  assert_eq!(collab.to_json_value()["title"], json!("unlocked"));
+}
+
+#[test]
+fn rejected_change_does_not_reach_peers_test() {
+  let page = page();
+  let mut read_only = open(&page, CollabPermission::ReadOnly);
+  let mut peer = open(&page, CollabPermission::ReadWrite);
+  assert!(read_only.try_insert("title", "world").is_err());
+
+  // The insert is rejected before it's applied, so there is nothing to sync
+  assert_eq!(
+    read_only.transact().state_vector(),
+    page.transact().state_vector()
+  );
+  sync(&read_only, &mut peer);
+  assert_eq!(peer.to_json_value(), page.to_json_value());
+}
+
+#[test]
+fn rejected_change_does_not_reach_plugins_test() {
+  let page = page();
+  let mut read_only = open(&page, CollabPermission::ReadOnly);
+  let counter = UpdateCounterPlugin::default();
+  read_only.add_plugin(Box::new(counter.clone()));
+  read_only.initialize();
+
+  assert!(read_only.try_insert("title", "world").is_err());
+  let result = read_only.context.with_txn(|txn| {
+    let rows = read_only.data.get_or_init_map(txn, "rows");
+    rows.insert(txn, "r2", "b");
+  });
+  assert!(matches!(result, Err(CollabError::PermissionDenied(_))));
+  assert_eq!(counter.updates.load(Ordering::SeqCst), 0);
+  assert_eq!(read_only.to_json_value(), page.to_json_value());
+}
@@ -0,0 +1,166 @@
+use std::time::Duration;
+
+use collab::core::collab::{Path, default_client_id};
+use collab::core::collab_presence::{
+  PresenceCursor, PresenceEvent, PresenceOptions, PresencePosition, PresenceState, PresenceUser,
+  PresenceViewing,
+};
+use collab::error::CollabError;
+use collab::preclude::Collab;
+use serde_json::{Value as JsonValue, json};
+
+fn user_state(uid: i64) -> PresenceState {
+  PresenceState::new(PresenceUser {
+    uid,
+    device_id: uid.to_string(),
+    name: Some(format!("user {}", uid)),
+  })
+}
+
+/// Sends the awareness state of `from` to `to`, including a cleared state.
+fn sync_awareness(from: &Collab, to: &mut Collab) {
+  let awareness = from.get_awareness();
+  let update = awareness
+    .update_with_clients([awareness.client_id()])
+    .unwrap();
+  to.get_mut_awareness().apply_update(update).unwrap();
+}
+
+#[tokio::test]
+async fn presence_join_update_leave_test() {
+  let mut c1 = Collab::new(1, "1", "1", default_client_id());
+  c1.enable_presence(PresenceOptions::default());
+  let mut rx = c1.subscribe_presence().unwrap();
+  let mut c2 = Collab::new(2, "1", "2", default_client_id());
+  let c2_id = c2.get_awareness().client_id();
+
+  let state = user_state(2).with_viewing(PresenceViewing::Row {
+    row_id: "row1".to_string(),
+  });
+  c2.set_presence(state.clone()).unwrap();
+  sync_awareness(&c2, &mut c1);
+  assert_eq!(
+    rx.try_recv().unwrap(),
+    PresenceEvent::Join {
+      client_id: c2_id,
+      state: state.clone(),
+    }
+  );
+
+  let position = PresencePosition {
+    path: Path::from(vec!["document", "title"]),
+    offset: 3,
+  };
+  let state = state.with_cursor(PresenceCursor::at(position));
+  c2.set_presence(state.clone()).unwrap();
+  sync_awareness(&c2, &mut c1);
+  assert_eq!(
+    rx.try_recv().unwrap(),
+    PresenceEvent::Update {
+      client_id: c2_id,
+      state: state.clone(),
+    }
+  );
+  assert_eq!(c1.presences().unwrap().get(&c2_id), Some(&state));
+
+  // Renewing an unchanged presence doesn't emit an event
+  c2.renew_presence().unwrap();
+  sync_awareness(&c2, &mut c1);
+  assert!(rx.try_recv().is_err());
+
+  c2.clean_awareness_state();
+  sync_awareness(&c2, &mut c1);
+  assert_eq!(
+    rx.try_recv().unwrap(),
+    PresenceEvent::Leave {
+      client_id: c2_id,
+      state,
+    }
+  );
+  assert!(c1.presences().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn presence_is_kept_with_other_awareness_state_test() {
+  let mut c1 = Collab::new(1, "1", "1", default_client_id());
+  c1.enable_presence(PresenceOptions::default());
+  let mut rx = c1.subscribe_presence().unwrap();
+  let mut c2 = Collab::new(2, "1", "2", default_client_id());
+  let c2_id = c2.get_awareness().client_id();
+
+  c2.set_local_awareness_state(json!({ "uid": 2, "selection": 1 }))
+    .unwrap();
+  sync_awareness(&c2, &mut c1);
+  assert!(rx.try_recv().is_err());
+
+  c2.set_presence(user_state(2)).unwrap();
+  sync_awareness(&c2, &mut c1);
+  assert!(matches!(rx.try_recv().unwrap(), PresenceEvent::Join { .. }));
+  assert_eq!(
+    c2.get_awareness().local_state::<JsonValue>().unwrap()["selection"],
+    1
+  );
+
+  // Replacing the rest of the awareness state keeps the presence
+  c2.emit_awareness_state();
+  c2.set_local_awareness_state(json!({ "uid": 2, "selection": 2 }))
+    .unwrap();
+  sync_awareness(&c2, &mut c1);
+  assert!(rx.try_recv().is_err());
+  assert_eq!(c2.local_presence(), Some(user_state(2)));
+  assert_eq!(c1.presences().unwrap().get(&c2_id), Some(&user_state(2)));
+
+  c2.clear_presence().unwrap();
+  sync_awareness(&c2, &mut c1);
+  assert!(matches!(
+    rx.try_recv().unwrap(),
+    PresenceEvent::Leave { client_id, .. } if client_id == c2_id
+  ));
+  assert_eq!(
+    c2.get_awareness().local_state::<JsonValue>().unwrap()["selection"],
+    2
+  );
+}
+
+#[tokio::test]
+async fn stale_presence_expires_test() {
+  let mut c1 = Collab::new(1, "1", "1", default_client_id());
+  c1.enable_presence(PresenceOptions {
+    timeout: Duration::from_millis(100),
+  });
+  let mut rx = c1.subscribe_presence().unwrap();
+  let mut c2 = Collab::new(2, "1", "2", default_client_id());
+  let c2_id = c2.get_awareness().client_id();
+  let mut c3 = Collab::new(3, "1", "3", default_client_id());
+  let c3_id = c3.get_awareness().client_id();
+
+  c2.set_presence(user_state(2)).unwrap();
+  sync_awareness(&c2, &mut c1);
+  std::thread::sleep(Duration::from_millis(150));
+  c3.set_presence(user_state(3)).unwrap();
+  sync_awareness(&c3, &mut c1);
+  assert_eq!(c1.presences().unwrap().len(), 2);
+
+  assert_eq!(c1.expire_stale_presence().unwrap(), vec![c2_id]);
+  let events = std::iter::from_fn(|| rx.try_recv().ok()).collect::<Vec<_>>();
+  assert!(matches!(
+    events.last(),
+    Some(PresenceEvent::Leave { client_id, .. }) if *client_id == c2_id
+  ));
+  let presences = c1.presences().unwrap();
+  assert_eq!(presences.len(), 1);
+  assert!(presences.contains_key(&c3_id));
+}
+
+#[tokio::test]
+async fn presence_not_enabled_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  assert!(matches!(
+    collab.subscribe_presence(),
+    Err(CollabError::PresenceNotEnabled)
+  ));
+
+  // The local presence doesn't require tracking the other peers
+  collab.set_presence(user_state(1)).unwrap();
+  assert_eq!(collab.local_presence(), Some(user_state(1)));
+}
@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use collab::core::collab::{CollabOptions, default_client_id};
+use collab::core::origin::CollabOrigin;
+use collab::lock::RwLock;
+use collab::preclude::{Collab, MapExt};
+use collab::util::ArrayExt;
+use serde_json::json;
+use yrs::{Array, ArrayPrelim, ArrayRef, Map, MapPrelim, MapRef, ReadTxn};
+
+#[tokio::test]
+async fn read_snapshot_is_isolated_from_edits_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  collab.insert("title", "hello");
+  let snapshot = collab.read_snapshot();
+  assert_eq!(snapshot.object_id(), "1");
+  assert_eq!(snapshot.state_vector(), &collab.transact().state_vector());
+
+  collab.insert("title", "world");
+  collab.insert("icon", "🚀");
+  assert_eq!(
+    snapshot.to_json_value().unwrap(),
+    json!({ "title": "hello" })
+  );
+  assert_eq!(
+    collab.to_json_value(),
+    json!({ "title": "world", "icon": "🚀" })
+  );
+
+  let options = CollabOptions::new("1".to_string(), default_client_id())
+    .with_data_source(snapshot.encode_collab_v1().unwrap().into());
+  let restored = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  assert_eq!(restored.to_json_value(), json!({ "title": "hello" }));
+}
+
+#[tokio::test]
+async fn read_snapshot_with_ext_helpers_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  collab
+    .context
+    .with_txn(|txn| {
+      let views: ArrayRef = collab
+        .data
+        .insert_with_path(txn, ["folder", "views"], ArrayPrelim::default())
+        .unwrap();
+      for id in ["v1", "v2"] {
+        let view: MapRef = views.push_back(txn, MapPrelim::default());
+        view.insert(txn, "id", id);
+        view.insert(txn, "name", format!("view {}", id));
+      }
+    })
+    .unwrap();
+
+  let snapshot = collab.read_snapshot();
+  let txn = snapshot.transact().unwrap();
+  let views: ArrayRef = snapshot
+    .data()
+    .unwrap()
+    .get_with_path(&txn, ["folder", "views"])
+    .unwrap();
+  assert_eq!(views.index_by_id(&txn, "v2"), Some(1));
+  let name: String = snapshot
+    .data()
+    .unwrap()
+    .get_json_with_path(&txn, ["folder", "views"])
+    .map(|views: Vec<serde_json::Value>| views[1]["name"].as_str().unwrap().to_string())
+    .unwrap();
+  assert_eq!(name, "view v2");
+}
+
+#[tokio::test]
+async fn read_snapshot_outlives_lock_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  collab.insert("title", "hello");
+  let collab = Arc::new(RwLock::new(collab));
+
+  let snapshot = collab.read().await.read_snapshot();
+  let export = tokio::spawn(async move { snapshot.to_json_value().unwrap() });
+
+  // Writers are not blocked by the export
+  collab.write().await.insert("title", "world");
+  assert_eq!(export.await.unwrap(), json!({ "title": "hello" }));
+  assert_eq!(
+    collab.read().await.to_json_value(),
+    json!({ "title": "world" })
+  );
+}
+
+#[tokio::test]
+async fn read_snapshots_share_the_encoded_document_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  collab.insert("title", "hello");
+  let first = collab.read_snapshot();
+  collab.insert("icon", "🚀");
+  collab.remove("title");
+  let second = collab.read_snapshot();
+  // Nothing changed since the second snapshot
+  let third = collab.read_snapshot();
+
+  assert_eq!(first.to_json_value().unwrap(), json!({ "title": "hello" }));
+  assert_eq!(second.to_json_value().unwrap(), json!({ "icon": "🚀" }));
+  assert_eq!(third.to_json_value().unwrap(), json!({ "icon": "🚀" }));
+
+  // The segments of the snapshots are merged once they are read
+  collab.insert("cover", "blue");
+  let fourth = collab.read_snapshot();
+  let options = CollabOptions::new("1".to_string(), default_client_id())
+    .with_data_source(fourth.encode_collab_v1().unwrap().into());
+  let restored = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  assert_eq!(restored.to_json_value(), collab.to_json_value());
+  assert_eq!(fourth.state_vector(), &restored.transact().state_vector());
+}
@@ -0,0 +1,167 @@
+use collab::core::collab::{CollabOptions, DataSource, Path, default_client_id};
+use collab::core::collab_schema::{
+  CollabSchema, MapSchema, SchemaViolation, ValueSchema, ViolationKind,
+};
+use collab::core::origin::CollabOrigin;
+use collab::error::CollabError;
+use collab::preclude::{Collab, MapExt, TextPrelim};
+use yrs::{ArrayPrelim, ReadTxn, StateVector};
+
+fn document_schema() -> CollabSchema {
+  let block = MapSchema::new()
+    .required("id", ValueSchema::String)
+    .required("ty", ValueSchema::String)
+    .optional("children", ValueSchema::array_of(ValueSchema::String))
+    .deny_unknown_keys();
+  CollabSchema::new(
+    MapSchema::new().required(
+      "document",
+      MapSchema::new()
+        .required("page_id", ValueSchema::String)
+        .required("blocks", ValueSchema::map_of(block.into()))
+        .optional("title", ValueSchema::Text),
+    ),
+  )
+}
+
+#[tokio::test]
+async fn valid_collab_schema_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  collab
+    .context
+    .with_txn(|txn| {
+      collab
+        .data
+        .insert_with_path(txn, ["document", "page_id"], "b1")
+        .unwrap();
+      collab
+        .data
+        .insert_with_path(txn, ["document", "title"], TextPrelim::new("Hello"))
+        .unwrap();
+      collab
+        .data
+        .insert_with_path(txn, ["document", "blocks", "b1", "id"], "b1")
+        .unwrap();
+      collab
+        .data
+        .insert_with_path(txn, ["document", "blocks", "b1", "ty"], "page")
+        .unwrap();
+      collab
+        .data
+        .insert_with_path(
+          txn,
+          ["document", "blocks", "b1", "children"],
+          ArrayPrelim::from_iter(["b2"]),
+        )
+        .unwrap();
+    })
+    .unwrap();
+
+  assert!(collab.validate_schema(&document_schema()).is_ok());
+}
+
+#[tokio::test]
+async fn collab_schema_violations_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  collab
+    .context
+    .with_txn(|txn| {
+      collab
+        .data
+        .insert_with_path(txn, ["document", "title"], "Hello")
+        .unwrap();
+      collab
+        .data
+        .insert_with_path(txn, ["document", "blocks", "b1", "id"], 1)
+        .unwrap();
+      collab
+        .data
+        .insert_with_path(txn, ["document", "blocks", "b1", "color"], "red")
+        .unwrap();
+      collab
+        .data
+        .insert_with_path(
+          txn,
+          ["document", "blocks", "b1", "children"],
+          ArrayPrelim::from_iter([true]),
+        )
+        .unwrap();
+    })
+    .unwrap();
+
+  let violations = document_schema().validate(&collab);
+  assert_eq!(
+    violations,
+    vec![
+      SchemaViolation {
+        path: Path::from(["document", "page_id"]),
+        kind: ViolationKind::MissingKey,
+      },
+      SchemaViolation {
+        path: Path::from(["document", "blocks", "b1", "id"]),
+        kind: ViolationKind::UnexpectedKind {
+          expected: "string",
+          found: "number",
+        },
+      },
+      SchemaViolation {
+        path: Path::from(["document", "blocks", "b1", "ty"]),
+        kind: ViolationKind::MissingKey,
+      },
+      SchemaViolation {
+        path: Path::from(["document", "blocks", "b1", "children", "0"]),
+        kind: ViolationKind::UnexpectedKind {
+          expected: "string",
+          found: "bool",
+        },
+      },
+      SchemaViolation {
+        path: Path::from(["document", "blocks", "b1", "color"]),
+        kind: ViolationKind::UnknownKey,
+      },
+      SchemaViolation {
+        path: Path::from(["document", "title"]),
+        kind: ViolationKind::UnexpectedKind {
+          expected: "text",
+          found: "string",
+        },
+      },
+    ]
+  );
+  assert_eq!(
+    violations[0].to_string(),
+    "document/page_id: missing required key"
+  );
+}
+
+#[tokio::test]
+async fn validate_schema_on_load_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  collab
+    .context
+    .with_txn(|txn| {
+      collab
+        .data
+        .insert_with_path(txn, ["document", "page_id"], 1)
+        .unwrap();
+    })
+    .unwrap();
+  let doc_state = collab
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+
+  let options = CollabOptions::new("1".to_string(), default_client_id())
+    .with_data_source(DataSource::DocStateV1(doc_state))
+    .with_schema(document_schema());
+  let result = Collab::new_with_options(CollabOrigin::Empty, options);
+  match result {
+    Err(CollabError::SchemaViolations(violations)) => assert_eq!(violations.len(), 2),
+    _ => panic!("expected schema violations"),
+  }
+
+  // An empty document is not validated
+  let options = CollabOptions::new("1".to_string(), default_client_id())
+    .with_data_source(DataSource::DocStateV1(vec![]))
+    .with_schema(document_schema());
+  assert!(Collab::new_with_options(CollabOrigin::Empty, options).is_ok());
+}
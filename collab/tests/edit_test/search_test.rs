@@ -0,0 +1,155 @@
+use collab::core::collab::{Path, default_client_id};
+use collab::core::collab_search::CollabSearchIndex;
+use collab::error::CollabError;
+use collab::preclude::{Collab, MapExt, MapPrelim, TextPrelim, TextRef};
+use yrs::updates::decoder::Decode;
+use yrs::{Array, ArrayPrelim, ArrayRef, Map, MapRef, ReadTxn, StateVector, Text, Update};
+
+#[tokio::test]
+async fn search_before_enable_test() {
+  let collab = Collab::new(1, "1", "1", default_client_id());
+  assert!(matches!(
+    collab.search("hello", 10),
+    Err(CollabError::SearchNotEnabled)
+  ));
+}
+
+#[tokio::test]
+async fn search_existing_content_test() {
+  let mut collab = Collab::new(1, "doc_1", "1", default_client_id());
+  collab.insert("title", "Quarterly planning");
+  collab
+    .context
+    .with_txn(|txn| {
+      let blocks: MapRef = collab.data.insert(txn, "blocks", MapPrelim::default());
+      let block: MapRef = blocks.insert(txn, "b1", MapPrelim::default());
+      block.insert(
+        txn,
+        "text",
+        TextPrelim::new("Planning the roadmap for the next quarter"),
+      );
+    })
+    .unwrap();
+
+  collab.enable_search();
+  let hits = collab.search("planning", 10).unwrap();
+  assert_eq!(hits.len(), 2);
+  // The shorter title ranks higher than the longer paragraph
+  assert_eq!(hits[0].object_id, "doc_1");
+  assert_eq!(hits[0].path, Path::from(["title"]));
+  assert_eq!(hits[1].path, Path::from(["blocks", "b1", "text"]));
+
+  let hits = collab.search("roadmap quarter", 10).unwrap();
+  assert_eq!(hits.len(), 1);
+  assert_eq!(hits[0].path, Path::from(["blocks", "b1", "text"]));
+
+  assert!(collab.search("roadmap unknown", 10).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn search_index_follows_local_changes_test() {
+  let mut collab = Collab::new(1, "doc_1", "1", default_client_id());
+  collab.enable_search();
+
+  let text: TextRef = collab.insert("text", TextPrelim::new("hello"));
+  assert_eq!(collab.search("hello", 10).unwrap().len(), 1);
+
+  collab
+    .context
+    .with_txn(|txn| {
+      text.remove_range(txn, 0, 5);
+      text.insert(txn, 0, "goodbye");
+    })
+    .unwrap();
+  assert!(collab.search("hello", 10).unwrap().is_empty());
+  assert_eq!(collab.search("goodbye", 10).unwrap().len(), 1);
+
+  let array: ArrayRef = collab.insert("tags", ArrayPrelim::default());
+  collab
+    .context
+    .with_txn(|txn| {
+      array.push_back(txn, "first tag");
+      array.insert(txn, 0, "zeroth tag");
+    })
+    .unwrap();
+  let hits = collab.search("first", 10).unwrap();
+  assert_eq!(hits[0].path, Path::from(["tags", "1"]));
+
+  collab.remove("tags");
+  assert!(collab.search("tag", 10).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn search_index_follows_map_key_changes_test() {
+  let mut collab = Collab::new(1, "doc_1", "1", default_client_id());
+  collab.enable_search();
+  let meta: MapRef = collab.insert("meta", MapPrelim::default());
+  collab
+    .context
+    .with_txn(|txn| {
+      meta.insert(txn, "name", "Weekly sync");
+      meta.insert(txn, "desc", "Agenda of the weekly sync");
+    })
+    .unwrap();
+  assert_eq!(collab.search("weekly", 10).unwrap().len(), 2);
+
+  collab
+    .context
+    .with_txn(|txn| {
+      meta.insert(txn, "name", "Daily sync");
+      meta.remove(txn, "desc");
+    })
+    .unwrap();
+  assert!(collab.search("weekly", 10).unwrap().is_empty());
+  let hits = collab.search("sync", 10).unwrap();
+  assert_eq!(hits.len(), 1);
+  assert_eq!(hits[0].path, Path::from(["meta", "name"]));
+}
+
+#[tokio::test]
+async fn search_index_follows_remote_changes_test() {
+  let mut remote = Collab::new(2, "doc_1", "2", default_client_id());
+  remote
+    .data
+    .insert_with_path(
+      &mut remote.context.transact_mut(),
+      ["meta", "name"],
+      "Remote page",
+    )
+    .unwrap();
+  let update = remote
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+
+  let mut local = Collab::new(1, "doc_1", "1", default_client_id());
+  local.enable_search();
+  local
+    .apply_update(Update::decode_v1(&update).unwrap())
+    .unwrap();
+
+  let hits = local.search("remote", 10).unwrap();
+  assert_eq!(hits.len(), 1);
+  assert_eq!(hits[0].path, Path::from(["meta", "name"]));
+}
+
+#[tokio::test]
+async fn shared_index_across_collabs_test() {
+  let index = CollabSearchIndex::new();
+  let mut doc = Collab::new(1, "doc", "1", default_client_id());
+  let mut folder = Collab::new(1, "folder", "1", default_client_id());
+  let _doc_sub = index.index_collab(&doc);
+  let _folder_sub = index.index_collab(&folder);
+
+  doc.insert("name", "Meeting notes");
+  folder.insert("name", "Notes");
+
+  let hits = index.search("notes", 10);
+  assert_eq!(hits.len(), 2);
+  assert_eq!(hits[0].object_id, "folder");
+  assert_eq!(hits[1].object_id, "doc");
+
+  index.remove_object("folder");
+  let hits = index.search("notes", 10);
+  assert_eq!(hits.len(), 1);
+  assert_eq!(hits[0].object_id, "doc");
+}
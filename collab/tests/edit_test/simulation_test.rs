@@ -0,0 +1,115 @@
+use collab::core::collab_simulation::{Simulation, SimulationConfig};
+use collab::preclude::{Array, Collab, MapExt, Text};
+use yrs::Map;
+
+/// Random edits of a map, a nested array and a nested text.
+fn random_edit(collab: &mut Collab, rng: &mut fastrand::Rng) {
+  let key = rng.u8(0..8).to_string();
+  let mut txn = collab.context.transact_mut();
+  let data = &collab.data;
+  match rng.u8(0..5) {
+    0 => {
+      data.insert(&mut txn, key, rng.i64(0..1000));
+    },
+    1 => {
+      data.remove(&mut txn, &key);
+    },
+    2 => {
+      let array = data.get_or_init_array(&mut txn, "array");
+      let index = rng.u32(..=array.len(&txn));
+      array.insert(&mut txn, index, key);
+    },
+    3 => {
+      let array = data.get_or_init_array(&mut txn, "array");
+      let len = array.len(&txn);
+      if len > 0 {
+        array.remove(&mut txn, rng.u32(..len));
+      }
+    },
+    _ => {
+      let text = data.get_or_init_text(&mut txn, "text");
+      let index = rng.u32(..=text.len(&txn));
+      text.insert(&mut txn, index, &key);
+    },
+  }
+}
+
+#[test]
+fn converge_in_order_test() {
+  let mut simulation = Simulation::new(SimulationConfig::new(3, 1)).unwrap();
+  simulation.run(200, random_edit);
+  simulation.deliver_all();
+  simulation.assert_converged();
+
+  let stats = simulation.stats();
+  assert_eq!(stats.sent, stats.delivered);
+  assert_eq!(stats.dropped, 0);
+}
+
+#[test]
+fn converge_with_reordered_updates_test() {
+  for seed in 0..20 {
+    let config = SimulationConfig::new(4, seed).with_reorder(true);
+    let mut simulation = Simulation::new(config).unwrap();
+    simulation.run(100, random_edit);
+    simulation.deliver_all();
+    simulation.assert_converged();
+  }
+}
+
+#[test]
+fn converge_with_dropped_updates_test() {
+  for seed in 0..20 {
+    let config = SimulationConfig::new(5, seed)
+      .with_reorder(true)
+      .with_drop_rate(0.3);
+    let mut simulation = Simulation::new(config).unwrap();
+    simulation.run(100, random_edit);
+    simulation.converge();
+    simulation.assert_converged();
+  }
+}
+
+#[test]
+fn dropped_updates_diverge_until_sync_test() {
+  let config = SimulationConfig::new(3, 7).with_drop_rate(1.0);
+  let mut simulation = Simulation::new(config).unwrap();
+  simulation.edit(0, |collab| collab.insert("0", "a"));
+  simulation.edit(1, |collab| collab.insert("1", "b"));
+  simulation.deliver_all();
+  assert_eq!(simulation.stats().dropped, 4);
+  assert!(!simulation.is_converged());
+
+  simulation.sync_all();
+  simulation.assert_converged();
+  let json = simulation.peer(2).to_json_value();
+  assert_eq!(json["0"], "a");
+  assert_eq!(json["1"], "b");
+}
+
+#[test]
+fn same_seed_same_result_test() {
+  let run = |seed| {
+    let config = SimulationConfig::new(3, seed)
+      .with_reorder(true)
+      .with_drop_rate(0.2);
+    let mut simulation = Simulation::new(config).unwrap();
+    simulation.run(50, random_edit);
+    simulation.converge();
+    (simulation.peer(0).to_json_value(), simulation.stats())
+  };
+  assert_eq!(run(3), run(3));
+}
+
+#[test]
+fn peers_have_distinct_ids_test() {
+  let simulation = Simulation::new(SimulationConfig::new(4, 0)).unwrap();
+  let mut client_ids = simulation
+    .peers()
+    .map(|collab| collab.client_id())
+    .collect::<Vec<_>>();
+  client_ids.sort_unstable();
+  client_ids.dedup();
+  assert_eq!(client_ids.len(), 4);
+  assert_ne!(simulation.origin(0), simulation.origin(1));
+}
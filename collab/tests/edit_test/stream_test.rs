@@ -0,0 +1,155 @@
+use std::io::Cursor;
+
+use collab::core::collab::{CollabOptions, DataSource, default_client_id};
+use collab::core::collab_stream::{DocStateChunkDecoder, DocStateChunkReader, MAX_CHUNK_SIZE};
+use collab::core::origin::CollabOrigin;
+use collab::error::CollabError;
+use collab::preclude::Collab;
+use yrs::updates::decoder::Decode;
+use yrs::{ReadTxn, StateVector, Update};
+
+/// Returns a collab edited by several clients.
+fn multi_client_collab() -> Collab {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  for client in 0..5 {
+    let mut peer = Collab::new(1, "1", client.to_string(), default_client_id());
+    for i in 0..20 {
+      peer.insert(&format!("{}-{}", client, i), "x".repeat(100));
+    }
+    let update = peer
+      .transact()
+      .encode_state_as_update_v1(&StateVector::default());
+    collab
+      .apply_update(Update::decode_v1(&update).unwrap())
+      .unwrap();
+  }
+  collab.insert("title", "hello");
+  collab
+}
+
+#[tokio::test]
+async fn restore_from_doc_state_stream_test() {
+  let collab = multi_client_collab();
+  let chunks = collab.doc_state_chunks(30).count();
+  assert!(chunks > 1, "expected several chunks, got {}", chunks);
+
+  let mut buf = vec![];
+  let written = collab.write_doc_state(&mut buf, 30).unwrap();
+  assert_eq!(written, buf.len() as u64);
+
+  let options = CollabOptions::new("1".to_string(), default_client_id())
+    .with_data_source(DataSource::DocStateV1Stream(Box::new(Cursor::new(buf))));
+  let restored = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  assert_eq!(restored.to_json_value(), collab.to_json_value());
+  assert_eq!(
+    restored.transact().state_vector(),
+    collab.transact().state_vector()
+  );
+}
+
+#[tokio::test]
+async fn split_single_client_by_size_test() {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  for i in 0..50 {
+    collab.insert(&i.to_string(), "x".repeat(100));
+  }
+  for i in 0..10 {
+    collab.remove(&i.to_string());
+  }
+
+  let chunks = collab.doc_state_chunks(1024).collect::<Vec<_>>();
+  assert!(
+    chunks.len() > 1,
+    "expected several chunks, got {}",
+    chunks.len()
+  );
+  let mut restored = Collab::new(1, "1", "2", default_client_id());
+  for (i, chunk) in chunks.iter().enumerate() {
+    assert!(chunk.len() <= 1024, "chunk {} has {} bytes", i, chunk.len());
+    let update = Update::decode_v1(chunk).unwrap();
+    // Only the last chunk carries the delete set
+    assert_eq!(update.delete_set().is_empty(), i + 1 < chunks.len());
+    restored.apply_update(update).unwrap();
+  }
+  assert_eq!(restored.to_json_value(), collab.to_json_value());
+  assert_eq!(
+    restored.transact().state_vector(),
+    collab.transact().state_vector()
+  );
+}
+
+#[tokio::test]
+async fn decode_doc_state_pushed_in_pieces_test() {
+  let collab = multi_client_collab();
+  let mut buf = vec![];
+  collab.write_doc_state(&mut buf, 30).unwrap();
+
+  let mut restored = Collab::new(1, "1", "2", default_client_id());
+  let mut decoder = DocStateChunkDecoder::default();
+  for piece in buf.chunks(7) {
+    for update in decoder.push(piece).unwrap() {
+      restored.apply_update(update).unwrap();
+    }
+  }
+  decoder.finish().unwrap();
+  assert_eq!(restored.to_json_value(), collab.to_json_value());
+}
+
+#[tokio::test]
+async fn truncated_doc_state_stream_test() {
+  let collab = multi_client_collab();
+  let mut buf = vec![];
+  collab.write_doc_state(&mut buf, 30).unwrap();
+  buf.truncate(buf.len() - 10);
+
+  let result = DocStateChunkReader::new(Cursor::new(buf.clone())).collect::<Result<Vec<_>, _>>();
+  assert!(matches!(result, Err(CollabError::InvalidDocStateStream(_))));
+
+  let mut decoder = DocStateChunkDecoder::default();
+  decoder.push(&buf).unwrap();
+  assert!(matches!(
+    decoder.finish(),
+    Err(CollabError::InvalidDocStateStream(_))
+  ));
+}
+
+#[tokio::test]
+async fn empty_doc_state_stream_test() {
+  let collab = Collab::new(1, "1", "1", default_client_id());
+  let mut buf = vec![];
+  collab.write_doc_state(&mut buf, 30).unwrap();
+  assert_eq!(DocStateChunkReader::new(Cursor::new(buf)).count(), 0);
+}
+
+#[tokio::test]
+async fn oversized_chunk_is_rejected_test() {
+  // The magic, then a chunk header with a length above the maximum
+  let mut buf = b"CLBCHUNK".to_vec();
+  buf.extend_from_slice(&(MAX_CHUNK_SIZE as u32 + 1).to_le_bytes());
+
+  let result = DocStateChunkReader::new(Cursor::new(buf.clone())).collect::<Result<Vec<_>, _>>();
+  assert!(matches!(result, Err(CollabError::InvalidDocStateStream(_))));
+
+  let mut decoder = DocStateChunkDecoder::default();
+  assert!(matches!(
+    decoder.push(&buf),
+    Err(CollabError::InvalidDocStateStream(_))
+  ));
+}
+
+#[tokio::test]
+async fn write_doc_state_from_read_snapshot_test() {
+  let mut collab = multi_client_collab();
+  let snapshot = collab.read_snapshot();
+  // The collab can be edited while the snapshot is written
+  collab.insert("title", "world");
+
+  let mut buf = vec![];
+  snapshot.write_doc_state(&mut buf, 30).unwrap();
+  let options = CollabOptions::new("1".to_string(), default_client_id())
+    .with_data_source(DataSource::DocStateV1Stream(Box::new(Cursor::new(buf))));
+  let restored = Collab::new_with_options(CollabOrigin::Empty, options).unwrap();
+  assert_eq!(restored.to_json_value(), snapshot.to_json_value().unwrap());
+  assert_eq!(&restored.transact().state_vector(), snapshot.state_vector());
+  assert_ne!(restored.to_json_value(), collab.to_json_value());
+}
@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use collab::core::collab::default_client_id;
+use collab::core::collab_undo::{UndoScopeOptions, UndoStackMeta};
+use collab::core::origin::CollabOrigin;
+use collab::error::CollabError;
+use collab::preclude::{Collab, MapExt, MapRef};
+use serde_json::json;
+use yrs::{Map, Transact};
+
+fn collab_with_blocks() -> Collab {
+  let mut collab = Collab::new(1, "1", "1", default_client_id());
+  collab
+    .context
+    .with_txn(|txn| {
+      collab
+        .data
+        .insert_with_path(txn, ["blocks", "b1", "text"], "a")
+        .unwrap();
+      collab
+        .data
+        .insert_with_path(txn, ["blocks", "b2", "text"], "a")
+        .unwrap();
+    })
+    .unwrap();
+  collab
+}
+
+fn insert_text(collab: &mut Collab, block_id: &str, text: &str) {
+  let path = vec![
+    "blocks".to_string(),
+    block_id.to_string(),
+    "text".to_string(),
+  ];
+  collab
+    .context
+    .with_txn(|txn| {
+      collab.data.insert_with_path(txn, path, text).unwrap();
+    })
+    .unwrap();
+}
+
+#[tokio::test]
+async fn undo_scope_only_reverts_its_subtree_test() {
+  let mut collab = collab_with_blocks();
+  collab
+    .add_undo_scope("b1", ["blocks", "b1"], UndoScopeOptions::default())
+    .unwrap();
+
+  insert_text(&mut collab, "b1", "b");
+  insert_text(&mut collab, "b2", "b");
+  assert!(collab.undo_scope("b1").unwrap().can_undo());
+
+  collab.undo_in("b1").unwrap();
+  assert_eq!(
+    collab.to_json_value(),
+    json!({ "blocks": { "b1": { "text": "a" }, "b2": { "text": "b" } } })
+  );
+  assert!(!collab.undo_scope("b1").unwrap().can_undo());
+
+  collab.redo_in("b1").unwrap();
+  assert_eq!(
+    collab.to_json_value(),
+    json!({ "blocks": { "b1": { "text": "b" }, "b2": { "text": "b" } } })
+  );
+}
+
+#[tokio::test]
+async fn undo_scope_stack_meta_test() {
+  let mut collab = collab_with_blocks();
+  collab
+    .add_undo_scope(
+      "blocks",
+      ["blocks"],
+      UndoScopeOptions::default().with_capture_timeout(Duration::from_secs(60)),
+    )
+    .unwrap();
+
+  collab
+    .undo_scope("blocks")
+    .unwrap()
+    .set_meta(UndoStackMeta::new("edit b1").with_cursor(json!({ "block": "b1" })));
+  insert_text(&mut collab, "b1", "b");
+  collab.undo_scope_mut("blocks").unwrap().stop_capturing();
+
+  collab
+    .undo_scope("blocks")
+    .unwrap()
+    .set_meta(UndoStackMeta::new("edit b2"));
+  insert_text(&mut collab, "b2", "b");
+  // Merged into the last step because of the capture timeout
+  insert_text(&mut collab, "b2", "c");
+
+  let labels = collab
+    .undo_scope("blocks")
+    .unwrap()
+    .undo_stack()
+    .into_iter()
+    .map(|meta| meta.label.unwrap())
+    .collect::<Vec<_>>();
+  assert_eq!(labels, vec!["edit b1", "edit b2"]);
+
+  let meta = collab.undo_in("blocks").unwrap().unwrap();
+  assert_eq!(meta.label.as_deref(), Some("edit b2"));
+  assert_eq!(
+    collab.to_json_value(),
+    json!({ "blocks": { "b1": { "text": "b" }, "b2": { "text": "a" } } })
+  );
+
+  let meta = collab.undo_in("blocks").unwrap().unwrap();
+  assert_eq!(
+    meta,
+    UndoStackMeta::new("edit b1").with_cursor(json!({ "block": "b1" }))
+  );
+  assert!(collab.undo_in("blocks").unwrap().is_none());
+
+  // The redo stack keeps the metadata of the undone steps
+  let labels = collab
+    .undo_scope("blocks")
+    .unwrap()
+    .redo_stack()
+    .into_iter()
+    .map(|meta| meta.label.unwrap())
+    .collect::<Vec<_>>();
+  assert_eq!(labels, vec!["edit b2", "edit b1"]);
+  let meta = collab.redo_in("blocks").unwrap().unwrap();
+  assert_eq!(meta.label.as_deref(), Some("edit b1"));
+}
+
+#[tokio::test]
+async fn undo_scope_ignores_untracked_origin_test() {
+  let mut collab = collab_with_blocks();
+  collab
+    .add_undo_scope("b1", ["blocks", "b1"], UndoScopeOptions::default())
+    .unwrap();
+
+  {
+    let block = collab
+      .data
+      .get_with_path::<_, _, MapRef>(&collab.transact(), ["blocks", "b1"])
+      .unwrap();
+    let mut txn = collab.context.doc().transact_mut_with(CollabOrigin::Server);
+    block.insert(&mut txn, "text", "remote");
+  }
+  assert!(!collab.undo_scope("b1").unwrap().can_undo());
+
+  // Track the server origin explicitly
+  collab
+    .add_undo_scope(
+      "b1",
+      ["blocks", "b1"],
+      UndoScopeOptions::default().with_tracked_origin(CollabOrigin::Server),
+    )
+    .unwrap();
+  {
+    let block = collab
+      .data
+      .get_with_path::<_, _, MapRef>(&collab.transact(), ["blocks", "b1"])
+      .unwrap();
+    let mut txn = collab.context.doc().transact_mut_with(CollabOrigin::Server);
+    block.insert(&mut txn, "text", "remote 2");
+  }
+  assert!(collab.undo_scope("b1").unwrap().can_undo());
+}
+
+#[tokio::test]
+async fn undo_scope_error_test() {
+  let mut collab = collab_with_blocks();
+  assert!(matches!(
+    collab.undo_in("unknown"),
+    Err(CollabError::UndoScopeNotFound(_))
+  ));
+  assert!(matches!(
+    collab.add_undo_scope("b3", ["blocks", "b3"], UndoScopeOptions::default()),
+    Err(CollabError::UndoScopePathNotFound(_))
+  ));
+}